@@ -28,6 +28,12 @@ contract DeployScript is Script {
         // 4. Wire everything up
         manager.setPaymentProcessor(address(processor));
         token.setSubscriptionManager(address(manager)); // If AccessToken logic requires it (currently doesn't use it but good for future)
+
+        // 5. Accept quotes signed by the proxy wallet
+        address quoteSigner = vm.envOr("QUOTE_SIGNER", address(0));
+        if (quoteSigner != address(0)) {
+            processor.setQuoteSigner(quoteSigner);
+        }
         
         vm.stopBroadcast();
     }
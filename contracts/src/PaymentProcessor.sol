@@ -4,17 +4,58 @@ pragma solidity ^0.8.20;
 import "@openzeppelin/contracts/access/Ownable.sol";
 import "@openzeppelin/contracts/utils/ReentrancyGuard.sol";
 import "@openzeppelin/contracts/utils/Pausable.sol";
+import "@openzeppelin/contracts/utils/cryptography/ECDSA.sol";
+import "@openzeppelin/contracts/utils/cryptography/EIP712.sol";
 import "./SubscriptionManager.sol";
 
 /// @title Payment Processor
 /// @notice Handles payments in native currency and triggers subscription updates.
-contract PaymentProcessor is Ownable, ReentrancyGuard, Pausable {
+contract PaymentProcessor is Ownable, ReentrancyGuard, Pausable, EIP712 {
+    /// @notice Quote as signed by the proxy, see docs/INTEGRATION.md.
+    struct Quote {
+        bytes16 id;
+        string serviceType;
+        uint8 tierId;
+        address user;
+        uint256 amount;
+        string currency;
+        address token;
+        uint64 duration;
+        uint64 expiry;
+        uint64 nonce;
+        uint256 rate;
+        uint64 rateUpdatedAt;
+        string promoCode;
+        uint256 discount;
+        uint8 fromTier;
+        uint64 fromExpiry;
+        uint256 credit;
+        uint64 bonusDuration;
+    }
+
+    bytes32 public constant QUOTE_TYPEHASH = keccak256(
+        "Quote(bytes16 id,string serviceType,uint8 tierId,address user,uint256 amount,string currency,"
+        "address token,uint64 duration,uint64 expiry,uint64 nonce,uint256 rate,uint64 rateUpdatedAt,"
+        "string promoCode,uint256 discount,uint8 fromTier,uint64 fromExpiry,uint256 credit,uint64 bonusDuration)"
+    );
+
     SubscriptionManager public subscriptionManager;
-    
+
+    /// @notice Address the proxy signs quotes with.
+    address public quoteSigner;
+
+    /// @notice Quotes already paid, by id.
+    mapping(bytes16 => bool) public quoteUsed;
+
     event PaymentReceived(address indexed user, uint256 amount, uint8 tierId);
+    event SubscriptionPurchased(address indexed user, uint256 amount, uint8 tierId, uint256 duration, bytes16 quoteId);
     event Withdrawal(address indexed to, uint256 amount);
+    event QuoteSignerUpdated(address quoteSigner);
 
-    constructor(address initialOwner, address _subscriptionManager) Ownable(initialOwner) {
+    constructor(address initialOwner, address _subscriptionManager)
+        Ownable(initialOwner)
+        EIP712("Seidar", "1")
+    {
         subscriptionManager = SubscriptionManager(_subscriptionManager);
     }
 
@@ -23,6 +64,12 @@ contract PaymentProcessor is Ownable, ReentrancyGuard, Pausable {
         subscriptionManager = SubscriptionManager(_subscriptionManager);
     }
 
+    /// @notice Update the address quotes must be signed by.
+    function setQuoteSigner(address _quoteSigner) external onlyOwner {
+        quoteSigner = _quoteSigner;
+        emit QuoteSignerUpdated(_quoteSigner);
+    }
+
     /// @notice Purchase a subscription with native currency (ETH/LIT).
     /// @param tierId The tier to purchase.
     function purchaseSubscription(uint8 tierId) external payable nonReentrant whenNotPaused {
@@ -39,6 +86,62 @@ contract PaymentProcessor is Ownable, ReentrancyGuard, Pausable {
         // Refund excess is optional, keeping it simple: user pays exact or overpays (tip)
     }
 
+    /// @notice Purchase a subscription for a duration without a quote.
+    /// @dev The price is the tier price, pro-rata per second of the tier
+    ///      duration. Discounts are only available through signed quotes.
+    /// @param tierId The tier to purchase.
+    /// @param duration Subscribed duration in seconds.
+    function purchaseSubscriptionFor(uint8 tierId, uint256 duration) external payable nonReentrant whenNotPaused {
+        SubscriptionManager.Tier memory tier = subscriptionManager.getTier(tierId);
+
+        require(tier.isActive, "Tier not active");
+        require(duration > 0, "Invalid duration");
+        require(msg.value >= (tier.price * duration) / tier.duration, "Insufficient payment");
+
+        subscriptionManager.processSubscriptionFor(msg.sender, tierId, duration);
+
+        emit SubscriptionPurchased(msg.sender, msg.value, tierId, duration, bytes16(0));
+    }
+
+    /// @notice Pay a subscription quote issued by the proxy.
+    /// @dev The quote must be signed by `quoteSigner` as EIP-712 typed data
+    ///      under this contract's domain, be addressed to the caller, payable
+    ///      in native currency and unexpired. Each quote can be paid once.
+    ///      A tier change quote, whose amount is already reduced by the
+    ///      credit for the replaced subscription, also grants its bonus time
+    ///      and is only accepted while that subscription is still active.
+    /// @param quote The quote as issued.
+    /// @param signature The proxy's 65-byte signature of the quote.
+    function purchaseQuotedSubscription(Quote calldata quote, bytes calldata signature)
+        external
+        payable
+        nonReentrant
+        whenNotPaused
+    {
+        require(quoteSigner != address(0), "Quotes not accepted");
+        require(ECDSA.recover(_hashTypedDataV4(_hashQuote(quote)), signature) == quoteSigner, "Invalid quote signature");
+        require(quote.user == msg.sender, "Quote issued to another user");
+        require(quote.token == address(0), "Quote not payable in native currency");
+        require(quote.duration > 0, "Not a subscription quote");
+        require(block.timestamp < quote.expiry, "Quote expired");
+        require(!quoteUsed[quote.id], "Quote already used");
+        require(msg.value >= quote.amount, "Insufficient payment");
+        if (quote.fromTier != 0) {
+            (uint256 expiry, uint8 tier, bool isActive) = subscriptionManager.subscriptions(msg.sender);
+            require(isActive && tier == quote.fromTier && expiry > block.timestamp, "Subscription changed since quote");
+        }
+
+        quoteUsed[quote.id] = true;
+        subscriptionManager.processSubscriptionFor(msg.sender, quote.tierId, uint256(quote.duration) + quote.bonusDuration);
+
+        emit SubscriptionPurchased(msg.sender, msg.value, quote.tierId, quote.duration, quote.id);
+    }
+
+    /// @notice EIP-712 digest the proxy signs for `quote`.
+    function quoteDigest(Quote calldata quote) external view returns (bytes32) {
+        return _hashTypedDataV4(_hashQuote(quote));
+    }
+
     /// @notice Withdraw accumulated funds.
     function withdraw(address payable to, uint256 amount) external onlyOwner nonReentrant {
         require(address(this).balance >= amount, "Insufficient funds");
@@ -56,4 +159,33 @@ contract PaymentProcessor is Ownable, ReentrancyGuard, Pausable {
     function unpause() external onlyOwner {
         _unpause();
     }
+
+    /// @dev EIP-712 struct hash of `quote`, encoded in two parts to stay
+    ///      within the stack limit.
+    function _hashQuote(Quote calldata quote) internal pure returns (bytes32) {
+        bytes memory head = abi.encode(
+            QUOTE_TYPEHASH,
+            quote.id,
+            keccak256(bytes(quote.serviceType)),
+            quote.tierId,
+            quote.user,
+            quote.amount,
+            keccak256(bytes(quote.currency)),
+            quote.token,
+            quote.duration,
+            quote.expiry
+        );
+        bytes memory tail = abi.encode(
+            quote.nonce,
+            quote.rate,
+            quote.rateUpdatedAt,
+            keccak256(bytes(quote.promoCode)),
+            quote.discount,
+            quote.fromTier,
+            quote.fromExpiry,
+            quote.credit,
+            quote.bonusDuration
+        );
+        return keccak256(bytes.concat(head, tail));
+    }
 }
@@ -65,6 +65,18 @@ contract SubscriptionManager is Ownable, Pausable {
     /// @param user The subscriber's address.
     /// @param tierId The tier ID.
     function processSubscription(address user, uint8 tierId) external onlyProcessor whenNotPaused {
+        _processSubscription(user, tierId, tiers[tierId].duration);
+    }
+
+    /// @notice Process a subscription for a quoted duration. Called by PaymentProcessor.
+    /// @param user The subscriber's address.
+    /// @param tierId The tier ID.
+    /// @param duration Subscribed duration in seconds.
+    function processSubscriptionFor(address user, uint8 tierId, uint256 duration) external onlyProcessor whenNotPaused {
+        _processSubscription(user, tierId, duration);
+    }
+
+    function _processSubscription(address user, uint8 tierId, uint256 duration) internal {
         Tier memory tier = tiers[tierId];
         if (!tier.isActive) revert InvalidTier();
 
@@ -73,11 +85,11 @@ contract SubscriptionManager is Ownable, Pausable {
         uint256 newExpiry;
         if (sub.expiry > block.timestamp && sub.tier == tierId) {
             // Renewal
-            newExpiry = sub.expiry + tier.duration;
+            newExpiry = sub.expiry + duration;
             emit SubscriptionRenewed(user, tierId, newExpiry);
         } else {
             // New or upgrade/downgrade (reset expiry for simplicity)
-            newExpiry = block.timestamp + tier.duration;
+            newExpiry = block.timestamp + duration;
             emit SubscriptionCreated(user, tierId, newExpiry);
         }
 
@@ -11,6 +11,7 @@ contract PaymentProcessorTest is Test {
     address public owner;
     address public user;
     uint256 public constant TIER_PRICE = 0.01 ether;
+    uint256 public constant SIGNER_KEY = 0xA11CE;
 
     function setUp() public {
         owner = address(this);
@@ -20,6 +21,27 @@ contract PaymentProcessorTest is Test {
         manager = new SubscriptionManager(owner);
         processor = new PaymentProcessor(owner, address(manager));
         manager.setPaymentProcessor(address(processor));
+        processor.setQuoteSigner(vm.addr(SIGNER_KEY));
+    }
+
+    /// A quote for 60 days of tier 1 at 20% off.
+    function _quote() internal view returns (PaymentProcessor.Quote memory quote) {
+        quote.id = bytes16(uint128(42));
+        quote.serviceType = "subscription_tier1";
+        quote.tierId = 1;
+        quote.user = user;
+        quote.amount = (TIER_PRICE * 2 * 8) / 10;
+        quote.currency = "ETH";
+        quote.duration = 60 days;
+        quote.expiry = uint64(block.timestamp + 300);
+        quote.nonce = 7;
+        quote.promoCode = "SPRING20";
+        quote.discount = (TIER_PRICE * 2 * 2) / 10;
+    }
+
+    function _sign(PaymentProcessor.Quote memory quote, uint256 key) internal view returns (bytes memory) {
+        (uint8 v, bytes32 r, bytes32 s) = vm.sign(key, processor.quoteDigest(quote));
+        return abi.encodePacked(r, s, v);
     }
 
     function testPurchaseSubscription() public {
@@ -39,6 +61,108 @@ contract PaymentProcessorTest is Test {
         processor.purchaseSubscription{value: TIER_PRICE - 1}(1);
     }
 
+    function testPurchaseForDuration() public {
+        // One year at the 30-day tier price, pro-rata
+        uint256 duration = 365 days;
+        uint256 price = (TIER_PRICE * duration) / 30 days;
+
+        vm.prank(user);
+        processor.purchaseSubscriptionFor{value: price}(1, duration);
+
+        (uint256 expiry,,) = manager.subscriptions(user);
+        assertEq(expiry, block.timestamp + duration);
+    }
+
+    function testPurchaseForRequiresFullPrice() public {
+        // Without a quote there is no discount
+        vm.prank(user);
+        vm.expectRevert("Insufficient payment");
+        processor.purchaseSubscriptionFor{value: TIER_PRICE * 2 - 1}(1, 60 days);
+    }
+
+    function testPurchaseQuoted() public {
+        PaymentProcessor.Quote memory quote = _quote();
+        bytes memory signature = _sign(quote, SIGNER_KEY);
+
+        vm.expectEmit(true, false, false, true);
+        emit PaymentProcessor.SubscriptionPurchased(user, quote.amount, 1, 60 days, quote.id);
+        vm.prank(user);
+        processor.purchaseQuotedSubscription{value: quote.amount}(quote, signature);
+
+        (uint256 expiry,,) = manager.subscriptions(user);
+        assertEq(expiry, block.timestamp + 60 days);
+        assertTrue(processor.quoteUsed(quote.id));
+
+        // Each quote is paid once
+        vm.prank(user);
+        vm.expectRevert("Quote already used");
+        processor.purchaseQuotedSubscription{value: quote.amount}(quote, signature);
+    }
+
+    function testPurchaseQuotedTierChange() public {
+        vm.prank(user);
+        processor.purchaseSubscription{value: TIER_PRICE}(1);
+
+        // Upgrade to tier 2, the unused tier 1 time paid out as a day of bonus
+        PaymentProcessor.Quote memory quote = _quote();
+        quote.serviceType = "subscription_tier2";
+        quote.tierId = 2;
+        quote.amount = TIER_PRICE;
+        quote.duration = 30 days;
+        quote.promoCode = "";
+        quote.discount = 0;
+        quote.fromTier = 1;
+        quote.fromExpiry = uint64(block.timestamp + 30 days);
+        quote.credit = TIER_PRICE;
+        quote.bonusDuration = 1 days;
+        bytes memory signature = _sign(quote, SIGNER_KEY);
+
+        vm.prank(user);
+        processor.purchaseQuotedSubscription{value: quote.amount}(quote, signature);
+        (uint256 expiry, uint8 tier,) = manager.subscriptions(user);
+        assertEq(tier, 2);
+        assertEq(expiry, block.timestamp + 31 days);
+
+        // The credit lapses once the replaced subscription changed
+        quote.id = bytes16(uint128(43));
+        signature = _sign(quote, SIGNER_KEY);
+        vm.prank(user);
+        vm.expectRevert("Subscription changed since quote");
+        processor.purchaseQuotedSubscription{value: quote.amount}(quote, signature);
+    }
+
+    function testPurchaseQuotedRejectsInvalidQuotes() public {
+        PaymentProcessor.Quote memory quote = _quote();
+
+        // Signed by someone else
+        vm.prank(user);
+        vm.expectRevert("Invalid quote signature");
+        processor.purchaseQuotedSubscription{value: quote.amount}(quote, _sign(quote, 0xB0B));
+
+        // Tampered after signing
+        bytes memory signature = _sign(quote, SIGNER_KEY);
+        quote.amount -= 1;
+        vm.prank(user);
+        vm.expectRevert("Invalid quote signature");
+        processor.purchaseQuotedSubscription{value: quote.amount}(quote, signature);
+        quote.amount += 1;
+
+        // Paid by another address
+        vm.deal(address(0x456), 1 ether);
+        vm.prank(address(0x456));
+        vm.expectRevert("Quote issued to another user");
+        processor.purchaseQuotedSubscription{value: quote.amount}(quote, signature);
+
+        vm.prank(user);
+        vm.expectRevert("Insufficient payment");
+        processor.purchaseQuotedSubscription{value: quote.amount - 1}(quote, signature);
+
+        vm.warp(quote.expiry);
+        vm.prank(user);
+        vm.expectRevert("Quote expired");
+        processor.purchaseQuotedSubscription{value: quote.amount}(quote, signature);
+    }
+
     function testWithdraw() public {
         // Fund processor
         vm.prank(user);
@@ -9,6 +9,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         service_type: "subscription_tier1".to_string(),
         user_address: "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266".to_string(),
         duration_seconds: Some(3600),
+        promo_code: None,
     };
 
     println!("Requesting quote for: {}", quote_req.service_type);
@@ -6,6 +6,8 @@ pub struct QuoteRequest {
     pub service_type: String, // Should be "subscription_tier1", "subscription_tier2", or "proof_generation"
     pub user_address: String,
     pub duration_seconds: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promo_code: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
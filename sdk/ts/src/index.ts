@@ -2,6 +2,7 @@ export interface QuoteRequest {
   service_type: string;
   user_address: string;
   duration_seconds: number;
+  promo_code?: string;
 }
 
 export interface QuoteResponse {
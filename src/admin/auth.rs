@@ -1,5 +1,6 @@
 use axum::{
     body::Body,
+    extract::State,
     http::{Request, StatusCode},
     middleware::Next,
     response::Response,
@@ -7,14 +8,10 @@ use axum::{
 use crate::http::server::AppState;
 
 pub async fn admin_auth_middleware(
+    State(state): State<AppState>,
     request: Request<Body>,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    let state = request
-        .extensions()
-        .get::<AppState>()
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-
     let inner = state.inner.load_full();
 
     let auth_header = request
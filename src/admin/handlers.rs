@@ -1,10 +1,24 @@
 use axum::{
-    extract::State,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
     Json,
 };
-use serde::Serialize;
+use alloy::primitives::Address;
+use serde::{Deserialize, Serialize};
 use std::sync::atomic::Ordering;
+use crate::blockchain::nonce::PendingTx;
+use crate::blockchain::BlockchainError;
+use crate::config::validation::validate_promo_code;
+use crate::config::PromoCode;
+use crate::http::account::{invoice, payment_history};
+use crate::http::org::{change_member, org_usage};
 use crate::http::server::AppState;
+use crate::payments::catalog::TierInfo;
+use crate::payments::ledger::SlaCredit;
+use crate::payments::treasury::{BalanceCheck, Sweep};
+use crate::quoting::{PromoError, PromoSource};
+use crate::security::orgs::MemberChange;
 
 #[derive(Serialize)]
 pub struct SystemStatus {
@@ -21,6 +35,38 @@ pub struct BackendStatus {
     pub active_connections: usize,
 }
 
+#[derive(Serialize)]
+pub struct TreasuryStatus {
+    pub enabled: bool,
+    pub address: String,
+    pub threshold: String,
+    /// Last PaymentProcessor balance read by the sweeper.
+    pub last_balance: Option<BalanceCheck>,
+    /// Most recent sweeps first.
+    pub sweeps: Vec<Sweep>,
+}
+
+#[derive(Deserialize)]
+pub struct SlaCreditQuery {
+    pub user: Option<Address>,
+    #[serde(default = "default_credit_limit")]
+    pub limit: usize,
+}
+
+fn default_credit_limit() -> usize {
+    100
+}
+
+#[derive(Serialize)]
+pub struct PromoStatus {
+    pub code: String,
+    pub source: PromoSource,
+    #[serde(flatten)]
+    pub promo: PromoCode,
+    /// Paid redemptions so far.
+    pub redemptions: u64,
+}
+
 #[derive(Serialize)]
 pub struct AnalyticsSummary {
     pub total_requests: usize,
@@ -79,3 +125,163 @@ pub async fn get_cache(
         "expired": expired,
     }))
 }
+
+pub async fn get_tiers(
+    State(state): State<AppState>,
+) -> Json<Vec<TierInfo>> {
+    let inner = state.inner.load();
+    Json(inner.tier_catalog.all())
+}
+
+pub async fn get_promos(
+    State(state): State<AppState>,
+) -> Response {
+    let inner = state.inner.load();
+    let mut promos = Vec::new();
+    for (code, source, promo) in inner.promo_book.all() {
+        let redemptions = match inner.quote_registry.promo_redemptions(&code) {
+            Ok(n) => n,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        promos.push(PromoStatus { code, source, promo, redemptions });
+    }
+    Json(promos).into_response()
+}
+
+/// Create or replace a runtime promo code. Codes in the config file cannot
+/// be overridden.
+pub async fn put_promo(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Json(promo): Json<PromoCode>,
+) -> Response {
+    let inner = state.inner.load();
+    let errors = validate_promo_code(&code, &promo, &inner.config.pricing);
+    if !errors.is_empty() {
+        let messages: Vec<_> = errors.into_iter().map(|e| e.0).collect();
+        return (StatusCode::BAD_REQUEST, messages.join("; ")).into_response();
+    }
+    match inner.promo_book.put(&code, promo) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e @ PromoError::Configured(_)) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn delete_promo(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+) -> Response {
+    let inner = state.inner.load();
+    match inner.promo_book.remove(&code) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Most recent SLA credits first, optionally for one `user`.
+pub async fn get_sla_credits(
+    State(state): State<AppState>,
+    Query(query): Query<SlaCreditQuery>,
+) -> Json<Vec<SlaCredit>> {
+    let inner = state.inner.load();
+    Json(inner.sla_ledger.recent(query.user, query.limit))
+}
+
+pub async fn get_treasury(
+    State(state): State<AppState>,
+) -> Json<TreasuryStatus> {
+    let inner = state.inner.load();
+    let treasury = &inner.config.payments.treasury;
+    Json(TreasuryStatus {
+        enabled: treasury.enabled,
+        address: treasury.address.clone(),
+        threshold: treasury.threshold.clone(),
+        last_balance: inner.sweep_log.last_balance(),
+        sweeps: inner.sweep_log.sweeps(),
+    })
+}
+
+/// Billing history of any subscriber.
+pub async fn get_account_payments(
+    State(state): State<AppState>,
+    Path(address): Path<Address>,
+) -> Response {
+    let inner = state.inner.load();
+    payment_history(&inner.billing, address)
+}
+
+/// Signed invoice for any subscriber's payment.
+pub async fn get_account_invoice(
+    State(state): State<AppState>,
+    Path((address, id)): Path<(Address, String)>,
+) -> Response {
+    let inner = state.inner.load();
+    invoice(&inner.billing, inner.invoice_signer.as_ref(), address, &id).await
+}
+
+/// Members and usage of any organization.
+pub async fn get_org(
+    State(state): State<AppState>,
+    Path(owner): Path<Address>,
+) -> Response {
+    org_usage(&state.inner.load().orgs, owner)
+}
+
+/// Add a member to an organization without the owner's signature.
+pub async fn put_org_member(
+    State(state): State<AppState>,
+    Path((owner, member)): Path<(Address, Address)>,
+) -> Response {
+    change_member(&state.inner.load().orgs, MemberChange::Add, owner, member)
+}
+
+/// Remove a member from an organization.
+pub async fn delete_org_member(
+    State(state): State<AppState>,
+    Path((owner, member)): Path<(Address, Address)>,
+) -> Response {
+    change_member(&state.inner.load().orgs, MemberChange::Remove, owner, member)
+}
+
+/// Webhook endpoints (without secrets), queue depth and dead letters.
+pub async fn get_webhooks(
+    State(state): State<AppState>,
+) -> Response {
+    let inner = state.inner.load();
+    match inner.webhooks.status() {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to read webhook outbox: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read webhook outbox").into_response()
+        }
+    }
+}
+
+/// Transactions sent by the proxy wallet and not yet mined, by nonce.
+pub async fn get_transactions(
+    State(state): State<AppState>,
+) -> Json<Vec<PendingTx>> {
+    let nonces = state.inner.load().nonces.clone();
+    Json(nonces.pending().await)
+}
+
+/// Replace a pending transaction with an empty transfer to the wallet.
+pub async fn cancel_transaction(
+    State(state): State<AppState>,
+    Path(nonce): Path<u64>,
+) -> Response {
+    let nonces = state.inner.load().nonces.clone();
+    match nonces.cancel(nonce).await {
+        Ok(tx_hash) => Json(serde_json::json!({ "nonce": nonce, "tx_hash": tx_hash })).into_response(),
+        Err(e) => {
+            let status = match &e {
+                BlockchainError::Nonce(_) => StatusCode::NOT_FOUND,
+                BlockchainError::NotAvailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+            (status, e.to_string()).into_response()
+        }
+    }
+}
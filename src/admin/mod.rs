@@ -2,7 +2,7 @@ pub mod handlers;
 pub mod auth;
 
 use axum::{
-    routing::get,
+    routing::{get, post, put},
     Router,
     middleware,
 };
@@ -16,6 +16,18 @@ pub fn setup_admin_router(state: AppState) -> Router {
         .route("/admin/backends", get(get_backends))
         .route("/admin/analytics", get(get_analytics))
         .route("/admin/cache", get(get_cache))
-        .layer(middleware::from_fn(admin_auth_middleware))
+        .route("/admin/tiers", get(get_tiers))
+        .route("/admin/promos", get(get_promos))
+        .route("/admin/promos/{code}", put(put_promo).delete(delete_promo))
+        .route("/admin/sla/credits", get(get_sla_credits))
+        .route("/admin/treasury", get(get_treasury))
+        .route("/admin/webhooks", get(get_webhooks))
+        .route("/admin/accounts/{address}/payments", get(get_account_payments))
+        .route("/admin/accounts/{address}/payments/{id}/invoice", get(get_account_invoice))
+        .route("/admin/orgs/{owner}", get(get_org))
+        .route("/admin/orgs/{owner}/members/{member}", put(put_org_member).delete(delete_org_member))
+        .route("/admin/transactions", get(get_transactions))
+        .route("/admin/transactions/{nonce}/cancel", post(cancel_transaction))
+        .layer(middleware::from_fn_with_state(state.clone(), admin_auth_middleware))
         .with_state(state)
 }
@@ -26,6 +26,8 @@ enum Commands {
     Analytics,
     /// Inspect subscription cache
     Cache,
+    /// List subscription tiers (pricing and limits)
+    Tiers,
 }
 
 #[tokio::main]
@@ -68,6 +70,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .await?;
             print_response(res).await?;
         }
+        Commands::Tiers => {
+            let res = client.get(format!("{}/admin/tiers", cli.url))
+                .headers(headers)
+                .send()
+                .await?;
+            print_response(res).await?;
+        }
     }
 
     Ok(())
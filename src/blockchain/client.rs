@@ -1,63 +1,75 @@
 //! Blockchain RPC client with timeout and error handling.
 //!
 //! # Responsibilities
-//! - Connect to JSON-RPC endpoint
+//! - Connect to JSON-RPC endpoints, routing each call to the healthiest
+//!   provider (see [`ProviderPool`])
 //! - Query chain state (block number, balances, receipts)
 //! - Handle timeouts and network errors gracefully
 //! - Provide health check for blockchain connectivity
 
-use alloy::primitives::{Address, TxHash, U256};
+use alloy::eips::BlockNumberOrTag;
+use alloy::primitives::{Address, Bytes, TxHash, B256, U256};
 use alloy::providers::{Provider, ProviderBuilder};
-use alloy::rpc::types::TransactionReceipt;
+use alloy::rpc::types::{FeeHistory, Filter, Log, TransactionReceipt, TransactionRequest};
+use alloy::transports::TransportError;
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::timeout;
 
+use crate::blockchain::pool::{DynProvider, ProviderPool};
 use crate::blockchain::types::{BlockchainConfig, BlockchainError, BlockchainResult, ChainId};
 use crate::observability::metrics;
 
 /// Blockchain RPC client wrapper with failover support.
 #[derive(Clone)]
 pub struct BlockchainClient {
-    /// List of providers (primary + failovers).
-    providers: Vec<Arc<dyn Provider + Send + Sync>>,
+    /// Providers (primary + failovers) with their health.
+    pool: Arc<ProviderPool>,
+    /// Provider every call goes to, if pinned (see [`pinned`](Self::pinned)).
+    pinned: Option<usize>,
     /// Configuration.
     config: BlockchainConfig,
-    /// Request timeout duration.
-    timeout_duration: Duration,
 }
 
 impl BlockchainClient {
     /// Create a new blockchain client.
     ///
+    /// Providers are probed every `pool.probe_interval_secs` for as long as
+    /// a clone of the client is alive.
+    ///
     /// # Arguments
     /// * `config` - Blockchain configuration
     ///
     /// # Returns
     /// A new client or error if connection fails
     pub async fn new(config: BlockchainConfig) -> BlockchainResult<Self> {
-        let timeout_duration = Duration::from_secs(config.rpc_timeout_secs);
         let mut providers = Vec::new();
 
         // 1. Add primary provider
         let primary_url: url::Url = config.rpc_url.parse().map_err(|e| {
             BlockchainError::Rpc(format!("Invalid RPC URL '{}': {}", config.rpc_url, e))
         })?;
-        providers.push(Arc::new(ProviderBuilder::new().connect_http(primary_url)) as Arc<dyn Provider + Send + Sync>);
+        providers.push((primary_url.clone(), Arc::new(ProviderBuilder::new().connect_http(primary_url)) as DynProvider));
 
         // 2. Add failover providers
         for url_str in &config.failover_urls {
-            if let Ok(url) = url_str.parse() {
-                providers.push(Arc::new(ProviderBuilder::new().connect_http(url)) as Arc<dyn Provider + Send + Sync>);
+            if let Ok(url) = url_str.parse::<url::Url>() {
+                providers.push((url.clone(), Arc::new(ProviderBuilder::new().connect_http(url)) as DynProvider));
             } else {
                 tracing::warn!(url = %url_str, "Ignoring invalid failover RPC URL");
             }
         }
 
-        let client = Self {
+        let pool = Arc::new(ProviderPool::new(
             providers,
+            config.pool.clone(),
+            Duration::from_secs(config.rpc_timeout_secs),
+        ));
+        ProviderPool::spawn_probes(&pool);
+        let client = Self {
+            pool,
+            pinned: None,
             config: config.clone(),
-            timeout_duration,
         };
 
         // Verify chain ID matches configuration
@@ -81,6 +93,29 @@ impl BlockchainClient {
         Ok(client)
     }
 
+    /// This client with every call sent to the provider ranked best now, so
+    /// reads that have to agree, e.g. a head and the logs up to it, come
+    /// from the same node. Calls of the pinned client fail rather than fail
+    /// over; take a new one to move on.
+    pub fn pinned(&self) -> Self {
+        Self {
+            pinned: self.pool.best(),
+            ..self.clone()
+        }
+    }
+
+    /// Run `call` on the pinned provider, or on the pool.
+    async fn execute<T, F, Fut>(&self, hedge: bool, call: F) -> Option<T>
+    where
+        F: Fn(DynProvider) -> Fut,
+        Fut: Future<Output = Result<T, TransportError>>,
+    {
+        match self.pinned {
+            Some(idx) => self.pool.execute_on(idx, call).await,
+            None => self.pool.execute(hedge, call).await,
+        }
+    }
+
     /// Verify the connected chain ID matches configuration.
     pub async fn verify_chain_id(&self) -> BlockchainResult<()> {
         let chain_id = self.get_chain_id().await?;
@@ -95,58 +130,66 @@ impl BlockchainClient {
 
     /// Get the chain ID from the RPC.
     pub async fn get_chain_id(&self) -> BlockchainResult<ChainId> {
-        for (i, provider) in self.providers.iter().enumerate() {
-            let fut = provider.get_chain_id();
-            match timeout(self.timeout_duration, fut).await {
-                Ok(Ok(result)) => return Ok(ChainId(result)),
-                Ok(Err(e)) => {
-                    tracing::warn!(provider_idx = i, error = %e, "RPC error, trying next provider");
-                }
-                Err(_) => {
-                    tracing::warn!(provider_idx = i, "RPC timeout, trying next provider");
-                }
-            }
-        }
-        Err(BlockchainError::Rpc("All RPC providers failed".to_string()))
+        self.execute(false, |provider: DynProvider| async move { provider.get_chain_id().await })
+            .await
+            .map(ChainId)
+            .ok_or_else(|| BlockchainError::Rpc("All RPC providers failed".to_string()))
     }
 
     /// Get the latest block number.
     pub async fn get_block_number(&self) -> BlockchainResult<u64> {
-        for (i, provider) in self.providers.iter().enumerate() {
-            let fut = provider.get_block_number();
-            match timeout(self.timeout_duration, fut).await {
-                Ok(Ok(result)) => return Ok(result),
-                Ok(Err(e)) => tracing::warn!(provider_idx = i, error = %e, "RPC error"),
-                Err(_) => tracing::warn!(provider_idx = i, "RPC timeout"),
-            }
-        }
-        Err(BlockchainError::Rpc("All providers failed to get block number".to_string()))
+        self.execute(true, |provider: DynProvider| async move { provider.get_block_number().await })
+            .await
+            .ok_or_else(|| BlockchainError::Rpc("All providers failed to get block number".to_string()))
+    }
+
+    /// Get the hash of a block by number.
+    ///
+    /// Returns `None` if the block does not exist (yet) on the connected chain.
+    pub async fn get_block_hash(&self, number: u64) -> BlockchainResult<Option<B256>> {
+        self.execute(true, |provider: DynProvider| async move {
+            provider.get_block_by_number(BlockNumberOrTag::Number(number)).await
+        })
+        .await
+        .map(|block| block.map(|block| block.header.hash))
+        .ok_or_else(|| BlockchainError::Rpc("All providers failed to get block".to_string()))
+    }
+
+    /// Get the timestamp of a block, in seconds.
+    pub async fn get_block_timestamp(&self, number: u64) -> BlockchainResult<Option<u64>> {
+        self.execute(true, |provider: DynProvider| async move {
+            provider.get_block_by_number(BlockNumberOrTag::Number(number)).await
+        })
+        .await
+        .map(|block| block.map(|block| block.header.timestamp))
+        .ok_or_else(|| BlockchainError::Rpc("All providers failed to get block".to_string()))
     }
 
     /// Get the balance of an address.
     pub async fn get_balance(&self, address: Address) -> BlockchainResult<U256> {
-        for (i, provider) in self.providers.iter().enumerate() {
-            let fut = provider.get_balance(address);
-            match timeout(self.timeout_duration, fut).await {
-                Ok(Ok(result)) => return Ok(result),
-                Ok(Err(e)) => tracing::warn!(provider_idx = i, error = %e, "RPC error"),
-                Err(_) => tracing::warn!(provider_idx = i, "RPC timeout"),
-            }
-        }
-        Err(BlockchainError::Rpc("All providers failed to get balance".to_string()))
+        self.execute(true, |provider: DynProvider| async move { provider.get_balance(address).await })
+            .await
+            .ok_or_else(|| BlockchainError::Rpc("All providers failed to get balance".to_string()))
     }
 
     /// Get the transaction count (nonce) for an address.
+    ///
+    /// Not hedged: nonces are compared with the pending count, which should
+    /// come from the same node (see [`pinned`](Self::pinned)).
     pub async fn get_transaction_count(&self, address: Address) -> BlockchainResult<u64> {
-        for (i, provider) in self.providers.iter().enumerate() {
-            let fut = provider.get_transaction_count(address);
-            match timeout(self.timeout_duration, fut).await {
-                Ok(Ok(result)) => return Ok(result),
-                Ok(Err(e)) => tracing::warn!(provider_idx = i, error = %e, "RPC error"),
-                Err(_) => tracing::warn!(provider_idx = i, "RPC timeout"),
-            }
-        }
-        Err(BlockchainError::Rpc("All providers failed to get transaction count".to_string()))
+        self.execute(false, |provider: DynProvider| async move { provider.get_transaction_count(address).await })
+            .await
+            .ok_or_else(|| BlockchainError::Rpc("All providers failed to get transaction count".to_string()))
+    }
+
+    /// Get the transaction count including transactions still in the
+    /// mempool, i.e. the next nonce the node would accept. Not hedged.
+    pub async fn get_pending_transaction_count(&self, address: Address) -> BlockchainResult<u64> {
+        self.execute(false, |provider: DynProvider| async move {
+            provider.get_transaction_count(address).pending().await
+        })
+        .await
+        .ok_or_else(|| BlockchainError::Rpc("All providers failed to get pending transaction count".to_string()))
     }
 
     /// Get a transaction receipt by hash.
@@ -154,28 +197,80 @@ impl BlockchainClient {
         &self,
         tx_hash: TxHash,
     ) -> BlockchainResult<Option<TransactionReceipt>> {
-        for (i, provider) in self.providers.iter().enumerate() {
-            let fut = provider.get_transaction_receipt(tx_hash);
-            match timeout(self.timeout_duration, fut).await {
-                Ok(Ok(result)) => return Ok(result),
-                Ok(Err(e)) => tracing::warn!(provider_idx = i, error = %e, "RPC error"),
-                Err(_) => tracing::warn!(provider_idx = i, "RPC timeout"),
-            }
-        }
-        Err(BlockchainError::Rpc("All providers failed to get receipt".to_string()))
+        self.execute(true, |provider: DynProvider| async move { provider.get_transaction_receipt(tx_hash).await })
+            .await
+            .ok_or_else(|| BlockchainError::Rpc("All providers failed to get receipt".to_string()))
+    }
+
+    /// Get the logs matching `filter` (`eth_getLogs`).
+    pub async fn get_logs(&self, filter: &Filter) -> BlockchainResult<Vec<Log>> {
+        self.execute(true, |provider: DynProvider| async move { provider.get_logs(filter).await })
+            .await
+            .ok_or_else(|| BlockchainError::Rpc("All providers failed to get logs".to_string()))
+    }
+
+    /// Execute a read-only contract call (`eth_call`) against the latest block.
+    pub async fn call(&self, tx: TransactionRequest) -> BlockchainResult<Bytes> {
+        self.execute(true, |provider: DynProvider| {
+            let tx = tx.clone();
+            async move { provider.call(tx).await }
+        })
+        .await
+        .ok_or_else(|| BlockchainError::Rpc("All providers failed to execute call".to_string()))
     }
 
     /// Get current gas price in wei.
     pub async fn get_gas_price(&self) -> BlockchainResult<u128> {
-        for (i, provider) in self.providers.iter().enumerate() {
-            let fut = provider.get_gas_price();
-            match timeout(self.timeout_duration, fut).await {
-                Ok(Ok(result)) => return Ok(result),
-                Ok(Err(e)) => tracing::warn!(provider_idx = i, error = %e, "RPC error"),
-                Err(_) => tracing::warn!(provider_idx = i, "RPC timeout"),
-            }
-        }
-        Err(BlockchainError::Rpc("All providers failed to get gas price".to_string()))
+        self.execute(true, |provider: DynProvider| async move { provider.get_gas_price().await })
+            .await
+            .ok_or_else(|| BlockchainError::Rpc("All providers failed to get gas price".to_string()))
+    }
+
+    /// Estimate the gas a transaction uses (`eth_estimateGas`).
+    ///
+    /// A transaction that would revert is reported as
+    /// [`BlockchainError::Reverted`] without trying other providers. Other
+    /// errors, e.g. rate limits or a node lagging behind, fail over.
+    pub async fn estimate_gas(&self, tx: TransactionRequest) -> BlockchainResult<u64> {
+        let estimate = self
+            .execute(false, |provider: DynProvider| {
+                let tx = tx.clone();
+                async move {
+                    match provider.estimate_gas(tx).await {
+                        Ok(gas) => Ok(Ok(gas)),
+                        // The node answered; the transaction is at fault
+                        Err(e) => match e.as_error_resp().filter(|resp| is_revert(resp.code, &resp.message)) {
+                            Some(resp) => Ok(Err(resp.message.to_string())),
+                            None => Err(e),
+                        },
+                    }
+                }
+            })
+            .await
+            .ok_or_else(|| BlockchainError::Rpc("All providers failed to estimate gas".to_string()))?;
+        estimate.map_err(BlockchainError::Reverted)
+    }
+
+    /// Base fees and priority fee percentiles of the last `block_count`
+    /// blocks (`eth_feeHistory`).
+    pub async fn get_fee_history(&self, block_count: u64, reward_percentiles: &[f64]) -> BlockchainResult<FeeHistory> {
+        self.execute(true, |provider: DynProvider| async move {
+            provider.get_fee_history(block_count, BlockNumberOrTag::Latest, reward_percentiles).await
+        })
+        .await
+        .ok_or_else(|| BlockchainError::Rpc("All providers failed to get fee history".to_string()))
+    }
+
+    /// Broadcast a signed transaction (`eth_sendRawTransaction`).
+    ///
+    /// Providers are tried best first; resending the same signed
+    /// transaction is harmless.
+    pub async fn send_raw_transaction(&self, raw: &[u8]) -> BlockchainResult<TxHash> {
+        self.execute(false, |provider: DynProvider| async move {
+            provider.send_raw_transaction(raw).await.map(|pending| *pending.tx_hash())
+        })
+        .await
+        .ok_or_else(|| BlockchainError::Rpc("All providers failed to send transaction".to_string()))
     }
 
     /// Check if the blockchain is reachable and healthy.
@@ -190,7 +285,7 @@ impl BlockchainClient {
 
     /// Get the underlying primary provider.
     pub fn provider(&self) -> &(dyn Provider + Send + Sync) {
-        self.providers[0].as_ref()
+        self.pool.primary().as_ref()
     }
 
     /// Get the configuration.
@@ -204,6 +299,12 @@ impl BlockchainClient {
     }
 }
 
+/// Whether a node's error says the transaction reverts, as opposed to the
+/// node failing to evaluate it.
+fn is_revert(code: i64, message: &str) -> bool {
+    code == 3 || (code == -32000 && message.contains("execution reverted"))
+}
+
 impl std::fmt::Debug for BlockchainClient {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("BlockchainClient")
@@ -223,11 +324,16 @@ mod tests {
             enabled: true,
             rpc_url: "http://localhost:8545".to_string(),
             failover_urls: Vec::new(),
+            ws_urls: Vec::new(),
             chain_id: 31337, // Anvil default
             rpc_timeout_secs: 5,
             confirmation_blocks: 1,
             gas_price_multiplier: 1.0,
             max_gas_price_gwei: 100,
+            gas_limit_multiplier: 1.2,
+            transactions: Default::default(),
+            remote_signer: Default::default(),
+            pool: Default::default(),
         }
     }
 
@@ -254,4 +360,14 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("All RPC providers failed"));
     }
+
+    #[test]
+    fn test_is_revert() {
+        assert!(is_revert(3, "execution reverted: not owner"));
+        assert!(is_revert(-32000, "execution reverted"));
+        // The node could not evaluate the transaction; another one may
+        assert!(!is_revert(-32000, "header not found"));
+        assert!(!is_revert(-32005, "limit exceeded"));
+        assert!(!is_revert(-32601, "the method eth_estimateGas does not exist"));
+    }
 }
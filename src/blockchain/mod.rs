@@ -4,17 +4,26 @@
 //! ```text
 //! Environment Variables (private key, RPC URL)
 //!     → wallet.rs (key loading, signing)
+//!       or remote_signer.rs (signing service holding the key)
 //!     → client.rs (RPC connection with timeouts)
+//!       over pool.rs (providers ranked by health, hedged reads)
+//!     → subscription.rs (optional WebSocket push notifications)
 //!     → transaction.rs (build, sign, broadcast, confirm)
+//!     → nonce.rs (nonce assignment, stuck transaction replacement)
 //! ```
 //!
 //! # Security Constraints
-//! - Private keys ONLY from environment variables
+//! - Private keys ONLY from environment variables, or kept off the host
+//!   entirely by a remote signer
 //! - Never log private keys or sensitive data
 //! - All RPC calls have configurable timeouts
 //! - Graceful degradation when blockchain unreachable
 
 pub mod client;
+pub mod nonce;
+pub mod pool;
+pub mod remote_signer;
+pub mod subscription;
 pub mod transaction;
 pub mod types;
 pub mod wallet;
@@ -0,0 +1,653 @@
+//! Nonce management for the proxy wallet.
+//!
+//! Every transaction the wallet sends goes through the [`NonceManager`],
+//! which hands out nonces under a lock so concurrent senders never collide
+//! and remembers each transaction until it is mined. Before each send the
+//! pending transactions are reconciled with the account's nonces on chain.
+//!
+//! Transactions left unmined for `stuck_after_secs` are replaced under the
+//! same nonce with higher fees, so a dropped or underpriced transaction
+//! cannot block the ones after it. An operator can also cancel a pending
+//! transaction by replacing it with an empty transfer to the wallet itself.
+//!
+//! Pending transactions are persisted, so replacements continue after a
+//! restart. The versions of recently mined transactions are kept in memory,
+//! so a caller waiting on a transaction still finds the replacement that
+//! was mined.
+
+use alloy::network::TransactionBuilder;
+use alloy::primitives::{Address, TxHash, U256};
+use alloy::rpc::types::TransactionRequest;
+use arc_swap::{ArcSwap, ArcSwapOption};
+use dashmap::DashMap;
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::blockchain::client::BlockchainClient;
+use crate::blockchain::transaction::WEI_PER_GWEI;
+use crate::blockchain::types::{BlockchainError, BlockchainResult};
+use crate::blockchain::wallet::Wallet;
+use crate::config::TransactionConfig;
+use crate::store::{backend, unix_now, StoreResult};
+
+/// Gas of a plain transfer, used by cancellations.
+const TRANSFER_GAS: u64 = 21_000;
+
+/// Mined transactions whose versions are remembered.
+const FINISHED_KEPT: usize = 256;
+
+/// One broadcast version of a pending transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxVersion {
+    pub tx_hash: TxHash,
+    pub sent_at: u64,
+    /// An empty transfer to the wallet replacing the original transaction.
+    pub cancel: bool,
+}
+
+/// A transaction sent by the proxy wallet and not yet mined.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTx {
+    pub nonce: u64,
+    pub from: Address,
+    /// Hash of the latest version.
+    pub tx_hash: TxHash,
+    /// When the latest version was sent.
+    pub sent_at: u64,
+    /// Whether the latest version is a cancellation.
+    pub cancelled: bool,
+    /// Earlier versions under this nonce, oldest first.
+    #[serde(default)]
+    pub replaced: Vec<TxVersion>,
+    /// The latest version as signed.
+    pub request: TransactionRequest,
+}
+
+impl PendingTx {
+    /// Every version broadcast under this nonce, latest last.
+    pub fn versions(&self) -> Vec<TxVersion> {
+        let mut versions = self.replaced.clone();
+        versions.push(TxVersion { tx_hash: self.tx_hash, sent_at: self.sent_at, cancel: self.cancelled });
+        versions
+    }
+
+    /// Record `request`, sent as `tx_hash`, as the latest version.
+    fn replace(&mut self, tx_hash: TxHash, request: TransactionRequest, cancel: bool, now: u64) {
+        self.replaced.push(TxVersion { tx_hash: self.tx_hash, sent_at: self.sent_at, cancel: self.cancelled });
+        self.tx_hash = tx_hash;
+        self.sent_at = now;
+        self.cancelled = cancel;
+        self.request = request;
+    }
+}
+
+/// Persistent storage for pending transactions.
+pub trait PendingTxStore: Send + Sync {
+    fn load(&self) -> StoreResult<Vec<PendingTx>>;
+
+    /// Insert or replace the transaction with `tx.nonce`.
+    fn put(&self, tx: &PendingTx) -> StoreResult<()>;
+
+    fn remove(&self, nonce: u64) -> StoreResult<()>;
+}
+
+/// In-memory store. Pending transactions are forgotten on restart.
+#[derive(Default)]
+pub struct MemoryPendingTxStore {
+    txs: DashMap<u64, PendingTx>,
+}
+
+impl PendingTxStore for MemoryPendingTxStore {
+    fn load(&self) -> StoreResult<Vec<PendingTx>> {
+        Ok(self.txs.iter().map(|e| e.value().clone()).collect())
+    }
+
+    fn put(&self, tx: &PendingTx) -> StoreResult<()> {
+        self.txs.insert(tx.nonce, tx.clone());
+        Ok(())
+    }
+
+    fn remove(&self, nonce: u64) -> StoreResult<()> {
+        self.txs.remove(&nonce);
+        Ok(())
+    }
+}
+
+/// JSON-encoded pending transactions by nonce.
+const PENDING: TableDefinition<u64, &[u8]> = TableDefinition::new("pending_transactions");
+
+/// Embedded on-disk store backed by redb.
+pub struct RedbPendingTxStore {
+    db: Database,
+}
+
+impl RedbPendingTxStore {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        let db = Database::create(path).map_err(backend)?;
+        let txn = db.begin_write().map_err(backend)?;
+        txn.open_table(PENDING).map_err(backend)?;
+        txn.commit().map_err(backend)?;
+        Ok(Self { db })
+    }
+}
+
+impl PendingTxStore for RedbPendingTxStore {
+    fn load(&self) -> StoreResult<Vec<PendingTx>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let table = txn.open_table(PENDING).map_err(backend)?;
+        let mut txs = Vec::new();
+        for entry in table.iter().map_err(backend)? {
+            let (_, json) = entry.map_err(backend)?;
+            txs.push(serde_json::from_slice(json.value())?);
+        }
+        Ok(txs)
+    }
+
+    fn put(&self, tx: &PendingTx) -> StoreResult<()> {
+        let json = serde_json::to_vec(tx)?;
+        let txn = self.db.begin_write().map_err(backend)?;
+        txn.open_table(PENDING).map_err(backend)?.insert(tx.nonce, json.as_slice()).map_err(backend)?;
+        txn.commit().map_err(backend)
+    }
+
+    fn remove(&self, nonce: u64) -> StoreResult<()> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        txn.open_table(PENDING).map_err(backend)?.remove(nonce).map_err(backend)?;
+        txn.commit().map_err(backend)
+    }
+}
+
+/// Nonces and pending transactions of the proxy wallet, shared by everything
+/// that sends from it and across reloads.
+pub struct NonceManager {
+    config: ArcSwap<TransactionConfig>,
+    store: Arc<dyn PendingTxStore>,
+    signer: ArcSwapOption<(BlockchainClient, Wallet)>,
+    /// Pending transactions by nonce. Held while sending, so nonces are
+    /// handed out one at a time.
+    pending: Mutex<BTreeMap<u64, PendingTx>>,
+    /// Versions of the latest mined transactions, oldest first.
+    finished: std::sync::Mutex<VecDeque<Vec<TxVersion>>>,
+}
+
+impl Default for NonceManager {
+    fn default() -> Self {
+        Self::new(&TransactionConfig::default(), Arc::new(MemoryPendingTxStore::default()))
+    }
+}
+
+impl NonceManager {
+    /// Resume tracking the transactions in `store`.
+    pub fn new(config: &TransactionConfig, store: Arc<dyn PendingTxStore>) -> Self {
+        let pending = match store.load() {
+            Ok(txs) => txs.into_iter().map(|tx| (tx.nonce, tx)).collect(),
+            Err(e) => {
+                tracing::error!("Failed to load pending transactions: {}", e);
+                BTreeMap::new()
+            }
+        };
+        Self {
+            config: ArcSwap::from_pointee(config.clone()),
+            store,
+            signer: ArcSwapOption::empty(),
+            pending: Mutex::new(pending),
+            finished: std::sync::Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Open the store at `config.store_path`, or keep pending transactions
+    /// in memory if the path is empty.
+    ///
+    /// Fails if the database cannot be opened: transactions sent while
+    /// tracking them in memory could not be replaced after a restart.
+    pub fn open(config: &TransactionConfig) -> StoreResult<Self> {
+        let store: Arc<dyn PendingTxStore> = if config.store_path.is_empty() {
+            Arc::new(MemoryPendingTxStore::default())
+        } else {
+            Arc::new(RedbPendingTxStore::open(&config.store_path)?)
+        };
+        Ok(Self::new(config, store))
+    }
+
+    /// Replace the policy on config reload.
+    pub fn set_config(&self, config: &TransactionConfig) {
+        self.config.store(Arc::new(config.clone()));
+    }
+
+    /// Send from `wallet` through `client` once connected.
+    pub fn attach(&self, client: BlockchainClient, wallet: Wallet) {
+        self.signer.store(Some(Arc::new((client, wallet))));
+    }
+
+    /// Transactions sent and not yet seen mined, by nonce.
+    pub async fn pending(&self) -> Vec<PendingTx> {
+        self.pending.lock().await.values().cloned().collect()
+    }
+
+    /// Versions of the transaction one of whose versions is `tx_hash`,
+    /// whether it is pending or was recently mined.
+    pub async fn versions(&self, tx_hash: TxHash) -> Option<Vec<TxVersion>> {
+        let has = |versions: &Vec<TxVersion>| versions.iter().any(|v| v.tx_hash == tx_hash);
+        let pending = self.pending.lock().await.values().map(PendingTx::versions).find(has);
+        pending.or_else(|| {
+            let finished = self.finished.lock().expect("finished transactions mutex poisoned");
+            finished.iter().find(|versions| has(versions)).cloned()
+        })
+    }
+
+    /// Sign `tx` with the next nonce, broadcast it and track it until mined.
+    pub async fn send(&self, tx: TransactionRequest) -> BlockchainResult<TxHash> {
+        let signer = self.signer()?;
+        let (client, wallet) = (&signer.0, &signer.1);
+        let from = wallet.address();
+        let mut pending = self.pending.lock().await;
+        let nonce = self.reconcile(&mut pending, client, from).await?;
+
+        let tx = tx.with_from(from).with_nonce(nonce);
+        let tx_hash = broadcast(client, wallet, &tx).await?;
+        let sent = PendingTx {
+            nonce,
+            from,
+            tx_hash,
+            sent_at: unix_now(),
+            cancelled: false,
+            replaced: Vec::new(),
+            request: tx,
+        };
+        self.persist(&sent);
+        pending.insert(nonce, sent);
+        Ok(tx_hash)
+    }
+
+    /// Replace the pending transaction with `nonce` by an empty transfer to
+    /// the wallet, with fees high enough to take its place.
+    pub async fn cancel(&self, nonce: u64) -> BlockchainResult<TxHash> {
+        let signer = self.signer()?;
+        let (client, wallet) = (&signer.0, &signer.1);
+        let from = wallet.address();
+        let mut pending = self.pending.lock().await;
+        self.reconcile(&mut pending, client, from).await?;
+        let tx = pending
+            .get_mut(&nonce)
+            .ok_or_else(|| BlockchainError::Nonce(format!("No pending transaction with nonce {}", nonce)))?;
+        if tx.cancelled {
+            return Ok(tx.tx_hash);
+        }
+
+        let mut cancel = TransactionRequest::default()
+            .with_from(from)
+            .with_to(from)
+            .with_value(U256::ZERO)
+            .with_nonce(nonce)
+            .with_chain_id(wallet.chain_id())
+            .with_gas_limit(TRANSFER_GAS);
+        cancel.gas_price = tx.request.gas_price;
+        cancel.max_fee_per_gas = tx.request.max_fee_per_gas;
+        cancel.max_priority_fee_per_gas = tx.request.max_priority_fee_per_gas;
+        if !bump_fees(&mut cancel, self.config.load().fee_bump_percent, max_fee(client)) {
+            return Err(BlockchainError::Nonce(format!(
+                "Cannot replace transaction {}: fees are at the maximum gas price",
+                nonce
+            )));
+        }
+
+        let tx_hash = broadcast(client, wallet, &cancel).await?;
+        tracing::info!(nonce, cancelled = %tx.tx_hash, tx_hash = %tx_hash, "Transaction cancelled");
+        tx.replace(tx_hash, cancel, true, unix_now());
+        self.persist(tx);
+        Ok(tx_hash)
+    }
+
+    /// Replace every transaction pending for longer than `stuck_after_secs`
+    /// with one paying `fee_bump_percent` more. Transactions already at the
+    /// maximum gas price are broadcast again unchanged.
+    pub async fn replace_stuck(&self) -> BlockchainResult<()> {
+        let signer = self.signer()?;
+        let (client, wallet) = (&signer.0, &signer.1);
+        let config = self.config.load();
+        let mut pending = self.pending.lock().await;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        self.reconcile(&mut pending, client, wallet.address()).await?;
+
+        let now = unix_now();
+        for tx in pending.values_mut().filter(|tx| now.saturating_sub(tx.sent_at) >= config.stuck_after_secs) {
+            let mut request = tx.request.clone();
+            if !bump_fees(&mut request, config.fee_bump_percent, max_fee(client)) {
+                tracing::warn!(nonce = tx.nonce, tx_hash = %tx.tx_hash, "Stuck transaction is at the maximum gas price, rebroadcasting");
+                if let Err(e) = broadcast(client, wallet, &tx.request).await {
+                    tracing::debug!(nonce = tx.nonce, "Rebroadcast failed: {}", e);
+                }
+                tx.sent_at = now;
+                self.persist(tx);
+                continue;
+            }
+            match broadcast(client, wallet, &request).await {
+                Ok(tx_hash) => {
+                    tracing::info!(nonce = tx.nonce, replaced = %tx.tx_hash, tx_hash = %tx_hash, "Replaced stuck transaction");
+                    let cancel = tx.cancelled;
+                    tx.replace(tx_hash, request, cancel, now);
+                    self.persist(tx);
+                }
+                Err(e) => tracing::warn!(nonce = tx.nonce, tx_hash = %tx.tx_hash, "Failed to replace stuck transaction: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace stuck transactions every `check_interval_secs` until shutdown.
+    /// Idle until a wallet is attached.
+    pub async fn run(self: Arc<Self>, mut shutdown: broadcast::Receiver<()>) {
+        loop {
+            let interval = Duration::from_secs(self.config.load().check_interval_secs);
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {
+                    if self.signer.load().is_none() {
+                        continue;
+                    }
+                    if let Err(e) = self.replace_stuck().await {
+                        tracing::warn!("Failed to check pending transactions: {}", e);
+                    }
+                }
+                _ = shutdown.recv() => break,
+            }
+        }
+    }
+
+    /// Forget mined transactions and return the next nonce to use.
+    ///
+    /// A transaction is mined once the account's confirmed nonce passes it,
+    /// whichever of its versions made it. The next nonce follows both the
+    /// node's pending count and our own pending transactions, which may
+    /// have dropped out of the node's mempool.
+    async fn reconcile(
+        &self,
+        pending: &mut BTreeMap<u64, PendingTx>,
+        client: &BlockchainClient,
+        from: Address,
+    ) -> BlockchainResult<u64> {
+        // Both counts from one node, or a lagging one could undercount
+        let client = client.pinned();
+        let mined = client.get_transaction_count(from).await?;
+        let chain_next = client.get_pending_transaction_count(from).await?;
+
+        let done: Vec<u64> = pending
+            .values()
+            .filter(|tx| tx.nonce < mined || tx.from != from)
+            .map(|tx| tx.nonce)
+            .collect();
+        for nonce in done {
+            if let Some(tx) = pending.remove(&nonce) {
+                if tx.from == from {
+                    tracing::debug!(nonce, tx_hash = %tx.tx_hash, "Pending transaction mined");
+                    let mut finished = self.finished.lock().expect("finished transactions mutex poisoned");
+                    if finished.len() == FINISHED_KEPT {
+                        finished.pop_front();
+                    }
+                    finished.push_back(tx.versions());
+                } else {
+                    tracing::warn!(nonce, from = %tx.from, "Dropping pending transaction of another wallet");
+                }
+            }
+            if let Err(e) = self.store.remove(nonce) {
+                tracing::error!(nonce, "Failed to remove pending transaction: {}", e);
+            }
+        }
+
+        let after_pending = pending.keys().next_back().map_or(0, |nonce| nonce + 1);
+        Ok(after_pending.max(mined).max(chain_next))
+    }
+
+    fn signer(&self) -> BlockchainResult<Arc<(BlockchainClient, Wallet)>> {
+        self.signer
+            .load_full()
+            .ok_or_else(|| BlockchainError::NotAvailable("no wallet attached for sending".to_string()))
+    }
+
+    fn persist(&self, tx: &PendingTx) {
+        // Still tracked in memory; only a restart would lose it
+        if let Err(e) = self.store.put(tx) {
+            tracing::error!(nonce = tx.nonce, "Failed to persist pending transaction: {}", e);
+        }
+    }
+}
+
+async fn broadcast(client: &BlockchainClient, wallet: &Wallet, tx: &TransactionRequest) -> BlockchainResult<TxHash> {
+    let raw = wallet.sign_transaction(tx.clone()).await?;
+    client.send_raw_transaction(&raw).await
+}
+
+/// Configured maximum gas price of `client`'s chain, in wei.
+fn max_fee(client: &BlockchainClient) -> u128 {
+    u128::from(client.config().max_gas_price_gwei) * WEI_PER_GWEI
+}
+
+/// Raise the fees of `tx` by `percent` for a replacement.
+///
+/// Returns `false`, leaving `tx` unchanged, if the raised fees would exceed
+/// `cap` wei per gas.
+fn bump_fees(tx: &mut TransactionRequest, percent: u64, cap: u128) -> bool {
+    let bump = |fee: u128| fee.saturating_mul(100 + u128::from(percent)).div_ceil(100);
+    match (tx.max_fee_per_gas, tx.gas_price) {
+        (Some(max_fee), _) => {
+            let max_fee = bump(max_fee);
+            if max_fee > cap {
+                return false;
+            }
+            tx.max_fee_per_gas = Some(max_fee);
+            tx.max_priority_fee_per_gas = Some(bump(tx.max_priority_fee_per_gas.unwrap_or_default()).min(max_fee));
+            true
+        }
+        (None, Some(gas_price)) => {
+            let gas_price = bump(gas_price);
+            if gas_price > cap {
+                return false;
+            }
+            tx.gas_price = Some(gas_price);
+            true
+        }
+        (None, None) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::consensus::{Transaction, TxEnvelope};
+    use alloy::eips::eip2718::Decodable2718;
+    use alloy::hex;
+    use alloy::primitives::keccak256;
+    use axum::{extract::State, routing::post, Json, Router};
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    // Anvil's first account
+    const TEST_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    /// Node reporting the wallet's nonces and accepting any transaction.
+    #[derive(Clone, Default)]
+    struct Node {
+        mined: Arc<AtomicU64>,
+        pending: Arc<AtomicU64>,
+        /// Broadcast transactions as (nonce, gas price, hash).
+        sent: Arc<std::sync::Mutex<Vec<(u64, u128, TxHash)>>>,
+    }
+
+    impl Node {
+        fn set(&self, mined: u64, pending: u64) {
+            self.mined.store(mined, Ordering::SeqCst);
+            self.pending.store(pending, Ordering::SeqCst);
+        }
+
+        fn sent(&self) -> Vec<(u64, u128, TxHash)> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    async fn rpc(State(node): State<Node>, Json(request): Json<Value>) -> Json<Value> {
+        let result = match request["method"].as_str().unwrap() {
+            "eth_chainId" => json!("0x7a69"),
+            "eth_blockNumber" => json!("0x1"),
+            "eth_getTransactionCount" => {
+                let count = match request["params"][1].as_str() {
+                    Some("pending") => node.pending.load(Ordering::SeqCst),
+                    _ => node.mined.load(Ordering::SeqCst),
+                };
+                json!(format!("{:#x}", count))
+            }
+            "eth_sendRawTransaction" => {
+                let raw = hex::decode(request["params"][0].as_str().unwrap()).unwrap();
+                let tx = TxEnvelope::decode_2718(&mut raw.as_slice()).unwrap();
+                let hash = keccak256(&raw);
+                node.sent.lock().unwrap().push((tx.nonce(), tx.gas_price().unwrap_or_default(), hash));
+                json!(hash)
+            }
+            method => panic!("unexpected call to {}", method),
+        };
+        Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+    }
+
+    async fn manager(node: &Node, stuck_after_secs: u64) -> NonceManager {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", post(rpc)).with_state(node.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = BlockchainClient::new(crate::blockchain::BlockchainConfig {
+            enabled: true,
+            rpc_url: format!("http://{}/", addr),
+            chain_id: 31337,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let config = TransactionConfig { store_path: String::new(), stuck_after_secs, fee_bump_percent: 10, ..TransactionConfig::default() };
+        let manager = NonceManager::open(&config).unwrap();
+        manager.attach(client, Wallet::from_private_key(TEST_KEY, 31337).unwrap());
+        manager
+    }
+
+    fn transfer() -> TransactionRequest {
+        TransactionRequest::default()
+            .with_to(Address::with_last_byte(9))
+            .with_value(U256::from(1))
+            .with_chain_id(31337)
+            .with_gas_limit(TRANSFER_GAS)
+            .with_gas_price(1_000_000_000)
+    }
+
+    fn pending(nonce: u64) -> PendingTx {
+        PendingTx {
+            nonce,
+            from: Address::with_last_byte(1),
+            tx_hash: TxHash::repeat_byte(nonce as u8),
+            sent_at: 100,
+            cancelled: false,
+            replaced: Vec::new(),
+            request: TransactionRequest::default().with_nonce(nonce).with_gas_price(1_000),
+        }
+    }
+
+    #[test]
+    fn test_bump_fees() {
+        let mut legacy = TransactionRequest::default().with_gas_price(1_000);
+        assert!(bump_fees(&mut legacy, 15, 10_000));
+        assert_eq!(legacy.gas_price, Some(1_150));
+
+        let mut eip1559 = TransactionRequest::default().with_max_fee_per_gas(2_000).with_max_priority_fee_per_gas(101);
+        assert!(bump_fees(&mut eip1559, 10, 10_000));
+        assert_eq!((eip1559.max_fee_per_gas, eip1559.max_priority_fee_per_gas), (Some(2_200), Some(112)));
+
+        // Replacements are not sent above the maximum
+        assert!(!bump_fees(&mut eip1559, 10, 2_300));
+        assert_eq!(eip1559.max_fee_per_gas, Some(2_200));
+    }
+
+    #[test]
+    fn test_replacement_versions() {
+        let mut tx = pending(3);
+        tx.replace(TxHash::repeat_byte(9), tx.request.clone(), false, 200);
+        tx.replace(TxHash::repeat_byte(10), tx.request.clone(), true, 300);
+
+        let versions = tx.versions();
+        assert_eq!(versions.len(), 3);
+        assert_eq!(versions[0], TxVersion { tx_hash: TxHash::repeat_byte(3), sent_at: 100, cancel: false });
+        assert_eq!(versions[2], TxVersion { tx_hash: TxHash::repeat_byte(10), sent_at: 300, cancel: true });
+        assert!(tx.cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_gaps_and_drops() {
+        let node = Node::default();
+        node.set(5, 5);
+        let manager = manager(&node, 3600).await;
+
+        manager.send(transfer()).await.unwrap();
+        // The node dropped the first transaction from its mempool; ours is
+        // still pending, so the next nonce follows it
+        manager.send(transfer()).await.unwrap();
+        assert_eq!(manager.pending().await.iter().map(|tx| tx.nonce).collect::<Vec<_>>(), vec![5, 6]);
+
+        // Another sender took nonces up to 9: skip past them
+        node.set(6, 10);
+        manager.send(transfer()).await.unwrap();
+        let nonces: Vec<u64> = node.sent().iter().map(|(nonce, _, _)| *nonce).collect();
+        assert_eq!(nonces, vec![5, 6, 10]);
+        // Nonce 5 was mined and is forgotten
+        assert_eq!(manager.pending().await.iter().map(|tx| tx.nonce).collect::<Vec<_>>(), vec![6, 10]);
+    }
+
+    #[tokio::test]
+    async fn test_mined_replacement_is_remembered() {
+        let node = Node::default();
+        let manager = manager(&node, 0).await;
+        let original = manager.send(transfer()).await.unwrap();
+
+        manager.replace_stuck().await.unwrap();
+        let sent = node.sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!((sent[1].0, sent[1].1), (0, 1_100_000_000));
+        let replacement = sent[1].2;
+        assert_eq!(manager.pending().await[0].tx_hash, replacement);
+
+        // The replacement is mined and reconciled before anyone polls again
+        node.set(1, 1);
+        manager.replace_stuck().await.unwrap();
+        assert!(manager.pending().await.is_empty());
+        assert_eq!(node.sent().len(), 2);
+        let versions = manager.versions(original).await.unwrap();
+        assert_eq!(versions.iter().map(|v| v.tx_hash).collect::<Vec<_>>(), vec![original, replacement]);
+    }
+
+    #[tokio::test]
+    async fn test_redb_pending_persist() {
+        let path = std::env::temp_dir().join(format!("transactions-{}.redb", uuid::Uuid::new_v4()));
+        {
+            let store = RedbPendingTxStore::open(&path).unwrap();
+            store.put(&pending(1)).unwrap();
+            store.put(&pending(2)).unwrap();
+            store.remove(1).unwrap();
+        }
+
+        let config = TransactionConfig { store_path: path.to_string_lossy().into_owned(), ..TransactionConfig::default() };
+        let manager = NonceManager::open(&config).unwrap();
+        // The database is held by the running instance
+        assert!(NonceManager::open(&config).is_err());
+        let txs = manager.pending().await;
+        assert_eq!(txs.len(), 1);
+        assert_eq!((txs[0].nonce, txs[0].tx_hash), (2, TxHash::repeat_byte(2)));
+        assert_eq!(manager.versions(TxHash::repeat_byte(2)).await.unwrap().len(), 1);
+
+        // Nothing is sent without a wallet
+        assert!(matches!(manager.cancel(2).await, Err(BlockchainError::NotAvailable(_))));
+        drop(manager);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,442 @@
+//! Health-scored pool of RPC providers.
+//!
+//! Each provider's latency, error rate and head block are tracked. Calls go
+//! to the best provider first and fail over down the ranking:
+//! - Providers failing `eject_after_failures` times in a row are ejected for
+//!   `eject_secs` and only tried once nothing else is left.
+//! - Providers more than `max_block_lag` blocks behind the highest head seen
+//!   rank after those in sync.
+//! - The rest are ranked by latency, inflated by their error rate.
+//!
+//! With `hedge_reads`, a read still unanswered after the best provider's
+//! `hedge_percentile` latency is also sent to the runner-up, and whichever
+//! answers first wins.
+
+use alloy::providers::Provider;
+use alloy::transports::TransportError;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+use crate::config::RpcPoolConfig;
+use crate::observability::metrics;
+
+/// Successful latencies kept per provider for the hedge percentile.
+const LATENCY_WINDOW: usize = 64;
+
+/// Weight of the newest sample in the latency and error rate averages.
+const EWMA_ALPHA: f64 = 0.2;
+
+pub type DynProvider = Arc<dyn Provider + Send + Sync>;
+
+/// What a call to a provider came to, for its score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Ok,
+    Error,
+    Timeout,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Error => "error",
+            Self::Timeout => "timeout",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    /// Average latency in milliseconds, `None` until the first answer.
+    latency_ms: Option<f64>,
+    /// Recent successful latencies in milliseconds, oldest first.
+    latencies: VecDeque<f64>,
+    /// Average share of failed calls.
+    error_rate: f64,
+    consecutive_failures: u32,
+    ejected_until: Option<Instant>,
+    /// Latest block number the provider reported.
+    head: Option<u64>,
+}
+
+struct PooledProvider {
+    /// Host of the provider URL, without any key in its path or query.
+    label: String,
+    provider: DynProvider,
+    health: Mutex<Health>,
+}
+
+impl PooledProvider {
+    fn record(&self, outcome: Outcome, elapsed: Duration, config: &RpcPoolConfig) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        metrics::record_rpc_request(&self.label, outcome.as_str(), elapsed);
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health.add_latency(ms, outcome == Outcome::Ok);
+
+        let failed = if outcome == Outcome::Ok { 0.0 } else { 1.0 };
+        health.error_rate += EWMA_ALPHA * (failed - health.error_rate);
+        if outcome == Outcome::Ok {
+            health.consecutive_failures = 0;
+            return;
+        }
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= config.eject_after_failures {
+            if health.ejected_until.is_none_or(|until| until <= Instant::now()) {
+                tracing::warn!(
+                    provider = %self.label,
+                    failures = health.consecutive_failures,
+                    eject_secs = config.eject_secs,
+                    "Ejecting RPC provider"
+                );
+            }
+            health.ejected_until = Some(Instant::now() + Duration::from_secs(config.eject_secs));
+            metrics::record_rpc_provider_ejected(&self.label, true);
+        }
+    }
+
+    /// A hedged call that lost: it took at least `elapsed`, which counts
+    /// towards its latency without being an error.
+    fn record_lost(&self, elapsed: Duration) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health.add_latency(elapsed.as_secs_f64() * 1000.0, false);
+    }
+
+    fn set_head(&self, head: u64) {
+        self.health.lock().unwrap_or_else(|e| e.into_inner()).head = Some(head);
+    }
+}
+
+impl Health {
+    fn add_latency(&mut self, ms: f64, window: bool) {
+        self.latency_ms = Some(match self.latency_ms {
+            Some(avg) => avg + EWMA_ALPHA * (ms - avg),
+            None => ms,
+        });
+        if window {
+            if self.latencies.len() == LATENCY_WINDOW {
+                self.latencies.pop_front();
+            }
+            self.latencies.push_back(ms);
+        }
+    }
+
+    /// Expected cost of a call: latency, inflated by the chance of having
+    /// to try elsewhere. Providers never heard from rank last.
+    fn score(&self) -> f64 {
+        match self.latency_ms {
+            Some(ms) => ms / (1.0 - self.error_rate).max(0.05),
+            None => f64::INFINITY,
+        }
+    }
+
+    fn ejected(&self, now: Instant) -> bool {
+        self.ejected_until.is_some_and(|until| until > now)
+    }
+
+    /// `percentile` (0-100) of the recent successful latencies.
+    fn latency_percentile(&self, percentile: f64) -> Option<f64> {
+        let mut sorted: Vec<f64> = self.latencies.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank = ((percentile / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.saturating_sub(1).min(sorted.len().saturating_sub(1))).copied()
+    }
+}
+
+/// RPC providers of one chain, ranked by health.
+pub struct ProviderPool {
+    providers: Vec<PooledProvider>,
+    config: RpcPoolConfig,
+    /// Timeout of each attempt.
+    attempt_timeout: Duration,
+}
+
+impl ProviderPool {
+    /// Pool of `providers`, labelled by URL, in configured order.
+    pub fn new(providers: Vec<(url::Url, DynProvider)>, config: RpcPoolConfig, attempt_timeout: Duration) -> Self {
+        let providers = providers
+            .into_iter()
+            .enumerate()
+            .map(|(i, (url, provider))| PooledProvider {
+                label: format!("{}:{}", i, url.host_str().unwrap_or("unknown")),
+                provider,
+                health: Mutex::new(Health::default()),
+            })
+            .collect();
+        Self { providers, config, attempt_timeout }
+    }
+
+    /// The first configured provider.
+    pub fn primary(&self) -> &DynProvider {
+        &self.providers[0].provider
+    }
+
+    /// Index of the provider ranked best now.
+    pub fn best(&self) -> Option<usize> {
+        self.ranked().first().copied()
+    }
+
+    /// Provider indexes, best first.
+    fn ranked(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let healths: Vec<_> = self
+            .providers
+            .iter()
+            .map(|p| {
+                let health = p.health.lock().unwrap_or_else(|e| e.into_inner());
+                (health.ejected(now), health.head, health.score())
+            })
+            .collect();
+        let best_head = healths.iter().filter_map(|(_, head, _)| *head).max();
+        let lagging = |head: Option<u64>| match (best_head, head) {
+            (Some(best), Some(head)) => best.saturating_sub(head) > self.config.max_block_lag,
+            _ => false,
+        };
+
+        let mut order: Vec<usize> = (0..self.providers.len()).collect();
+        // Stable, so equally scored providers keep the configured order
+        order.sort_by(|&a, &b| {
+            let (a_ejected, a_head, a_score) = healths[a];
+            let (b_ejected, b_head, b_score) = healths[b];
+            (a_ejected, lagging(a_head))
+                .cmp(&(b_ejected, lagging(b_head)))
+                .then(a_score.total_cmp(&b_score))
+        });
+        order
+    }
+
+    /// Call `call` on one provider, recording the outcome.
+    async fn attempt<T, F, Fut>(&self, idx: usize, call: &F) -> Option<T>
+    where
+        F: Fn(DynProvider) -> Fut,
+        Fut: Future<Output = Result<T, TransportError>>,
+    {
+        let pooled = &self.providers[idx];
+        let start = Instant::now();
+        match timeout(self.attempt_timeout, call(pooled.provider.clone())).await {
+            Ok(Ok(result)) => {
+                pooled.record(Outcome::Ok, start.elapsed(), &self.config);
+                Some(result)
+            }
+            Ok(Err(e)) => {
+                tracing::warn!(provider = %pooled.label, error = %e, "RPC error");
+                pooled.record(Outcome::Error, start.elapsed(), &self.config);
+                None
+            }
+            Err(_) => {
+                tracing::warn!(provider = %pooled.label, "RPC timeout");
+                pooled.record(Outcome::Timeout, start.elapsed(), &self.config);
+                None
+            }
+        }
+    }
+
+    /// Call `call` on the best provider, then down the ranking until one
+    /// answers. `None` if none did.
+    ///
+    /// Reads with `hedge` set are also sent to the runner-up if the best
+    /// provider is slow to answer (see [`hedge_reads`](RpcPoolConfig::hedge_reads)).
+    pub async fn execute<T, F, Fut>(&self, hedge: bool, call: F) -> Option<T>
+    where
+        F: Fn(DynProvider) -> Fut,
+        Fut: Future<Output = Result<T, TransportError>>,
+    {
+        let order = self.ranked();
+        let mut rest = order.iter().copied();
+        let first = rest.next()?;
+
+        if hedge && self.config.hedge_reads && order.len() > 1 {
+            let second = rest.next()?;
+            let began = Instant::now();
+            let primary = self.attempt(first, &call);
+            tokio::pin!(primary);
+            tokio::select! {
+                result = &mut primary => {
+                    if let Some(result) = result {
+                        return Some(result);
+                    }
+                    if let Some(result) = self.attempt(second, &call).await {
+                        return Some(result);
+                    }
+                }
+                _ = tokio::time::sleep(self.hedge_delay(first)) => {
+                    let start = Instant::now();
+                    let hedged = self.attempt(second, &call);
+                    tokio::pin!(hedged);
+                    tokio::select! {
+                        result = &mut primary => match result {
+                            Some(result) => {
+                                metrics::record_rpc_hedge("primary");
+                                self.providers[second].record_lost(start.elapsed());
+                                return Some(result);
+                            }
+                            None => if let Some(result) = hedged.await {
+                                metrics::record_rpc_hedge("hedge");
+                                return Some(result);
+                            },
+                        },
+                        result = &mut hedged => match result {
+                            Some(result) => {
+                                metrics::record_rpc_hedge("hedge");
+                                self.providers[first].record_lost(began.elapsed());
+                                return Some(result);
+                            }
+                            None => if let Some(result) = primary.await {
+                                metrics::record_rpc_hedge("primary");
+                                return Some(result);
+                            },
+                        },
+                    }
+                }
+            }
+        } else if let Some(result) = self.attempt(first, &call).await {
+            return Some(result);
+        }
+
+        for idx in rest {
+            if let Some(result) = self.attempt(idx, &call).await {
+                return Some(result);
+            }
+        }
+        None
+    }
+
+    /// Call `call` on provider `idx` only, without failing over, so that
+    /// related reads see the same chain. `None` if it did not answer.
+    pub async fn execute_on<T, F, Fut>(&self, idx: usize, call: F) -> Option<T>
+    where
+        F: Fn(DynProvider) -> Fut,
+        Fut: Future<Output = Result<T, TransportError>>,
+    {
+        self.attempt(idx, &call).await
+    }
+
+    /// How long to wait for `idx` before hedging: its recent percentile
+    /// latency, but no less than `hedge_min_delay_ms`.
+    fn hedge_delay(&self, idx: usize) -> Duration {
+        let health = self.providers[idx].health.lock().unwrap_or_else(|e| e.into_inner());
+        let ms = health
+            .latency_percentile(self.config.hedge_percentile)
+            .unwrap_or(0.0)
+            .max(self.config.hedge_min_delay_ms as f64);
+        Duration::from_secs_f64(ms / 1000.0)
+    }
+
+    /// Ask every provider for its head block, for latency and lag.
+    pub async fn probe(&self) {
+        let probes = self.providers.iter().enumerate().map(|(idx, pooled)| async move {
+            let head = self.attempt(idx, &|provider: DynProvider| async move { provider.get_block_number().await }).await;
+            if let Some(head) = head {
+                pooled.set_head(head);
+            }
+            head
+        });
+        let heads = futures_util::future::join_all(probes).await;
+
+        let best = heads.iter().flatten().copied().max().unwrap_or(0);
+        for (pooled, head) in self.providers.iter().zip(heads) {
+            if let Some(head) = head {
+                metrics::record_rpc_provider_lag(&pooled.label, best.saturating_sub(head));
+            }
+            let ejected = pooled.health.lock().unwrap_or_else(|e| e.into_inner()).ejected(Instant::now());
+            metrics::record_rpc_provider_ejected(&pooled.label, ejected);
+        }
+    }
+
+    /// Probe every `probe_interval_secs` for as long as the pool is in use.
+    pub fn spawn_probes(pool: &Arc<Self>) {
+        if pool.config.probe_interval_secs == 0 {
+            return;
+        }
+        let interval = Duration::from_secs(pool.config.probe_interval_secs);
+        let pool: Weak<Self> = Arc::downgrade(pool);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(pool) = pool.upgrade() else { break };
+                pool.probe().await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::ProviderBuilder;
+
+    fn pool(count: usize, config: RpcPoolConfig) -> ProviderPool {
+        let providers = (0..count)
+            .map(|i| {
+                let url: url::Url = format!("http://rpc{}.invalid:8545/secret-key", i).parse().unwrap();
+                (url.clone(), Arc::new(ProviderBuilder::new().connect_http(url)) as DynProvider)
+            })
+            .collect();
+        ProviderPool::new(providers, config, Duration::from_secs(1))
+    }
+
+    #[test]
+    fn test_ranking() {
+        let config = RpcPoolConfig { eject_after_failures: 2, ..Default::default() };
+        let pool = pool(3, config.clone());
+        assert_eq!(pool.providers[0].label, "0:rpc0.invalid");
+        // Unmeasured providers keep the configured order
+        assert_eq!(pool.ranked(), vec![0, 1, 2]);
+
+        pool.providers[0].record(Outcome::Ok, Duration::from_millis(900), &config);
+        pool.providers[1].record(Outcome::Ok, Duration::from_millis(50), &config);
+        pool.providers[2].record(Outcome::Ok, Duration::from_millis(100), &config);
+        assert_eq!(pool.ranked(), vec![1, 2, 0]);
+
+        // Lagging behind the best head
+        pool.providers[0].set_head(100);
+        pool.providers[1].set_head(90);
+        pool.providers[2].set_head(100);
+        assert_eq!(pool.ranked(), vec![2, 0, 1]);
+
+        // Ejected after consecutive failures, however fast
+        pool.providers[2].record(Outcome::Timeout, Duration::from_millis(10), &config);
+        assert_eq!(pool.ranked()[0], 2);
+        pool.providers[2].record(Outcome::Error, Duration::from_millis(10), &config);
+        assert_eq!(pool.ranked(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_hedge_delay() {
+        let config = RpcPoolConfig { hedge_percentile: 90.0, hedge_min_delay_ms: 20, ..Default::default() };
+        let pool = pool(2, config.clone());
+        assert_eq!(pool.hedge_delay(0), Duration::from_millis(20));
+
+        for ms in 1..=100 {
+            pool.providers[0].record(Outcome::Ok, Duration::from_millis(ms), &config);
+        }
+        // Only the last 64 samples (37..=100) count
+        assert_eq!(pool.hedge_delay(0), Duration::from_millis(94));
+    }
+
+    #[tokio::test]
+    async fn test_execute_fails_over() {
+        let pool = pool(2, RpcPoolConfig { hedge_reads: true, ..Default::default() });
+        let result = pool
+            .execute(true, |provider: DynProvider| async move { provider.get_block_number().await })
+            .await;
+        assert!(result.is_none());
+        assert!(pool.providers.iter().all(|p| p.health.lock().unwrap().consecutive_failures == 1));
+    }
+
+    #[tokio::test]
+    async fn test_execute_on_does_not_fail_over() {
+        let pool = pool(2, RpcPoolConfig::default());
+        assert_eq!(pool.best(), Some(0));
+        let result = pool
+            .execute_on(1, |provider: DynProvider| async move { provider.get_block_number().await })
+            .await;
+        assert!(result.is_none());
+        let failures: Vec<u32> = pool.providers.iter().map(|p| p.health.lock().unwrap().consecutive_failures).collect();
+        assert_eq!(failures, vec![0, 1]);
+    }
+}
@@ -0,0 +1,252 @@
+//! Remote signing over a Web3Signer-style HTTP API, so the proxy host never
+//! holds the private key.
+//!
+//! | Operation | Request |
+//! |-----------|---------|
+//! | health | `GET /upcheck` |
+//! | `sign` of typed data | JSON-RPC `eth_signTypedData(address, typedData)` posted to `/` |
+//! | `sign` of a keccak preimage | `POST /api/v1/eth1/sign/{address}` with `{"data": "0x<preimage>"}`; the service signs its keccak-256 hash and answers with the signature in hex |
+//! | `sign_message` | JSON-RPC `eth_sign(address, data)` posted to `/` |
+//! | `sign_transaction` | JSON-RPC `eth_signTransaction(tx)` posted to `/`, answered with the signed transaction |
+//!
+//! There is no endpoint signing a bare digest, so callers hand over what the
+//! digest is computed from (see [`Signable`]).
+//!
+//! Requests carry the bearer token from `PROXY_BLOCKCHAIN_SIGNER_TOKEN` and
+//! time out after `timeout_secs`. Every signature is recovered and checked
+//! against the configured address before it is used, and a signed
+//! transaction must match the request in every field.
+
+use alloy::consensus::transaction::SignerRecoverable;
+use alloy::consensus::{Transaction, TxEnvelope};
+use alloy::eips::eip2718::Decodable2718;
+use alloy::hex;
+use alloy::primitives::{Address, Bytes};
+use alloy::rpc::types::TransactionRequest;
+use alloy::signers::Signature;
+use futures_util::future::BoxFuture;
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+use crate::blockchain::types::{BlockchainError, BlockchainResult};
+use crate::blockchain::wallet::{Signable, WalletSigner};
+use crate::config::RemoteSignerConfig;
+
+/// Bearer token for the remote signer.
+pub const SIGNER_TOKEN_ENV_VAR: &str = "PROXY_BLOCKCHAIN_SIGNER_TOKEN";
+
+/// Client of a remote signing service holding the key of one address.
+pub struct RemoteSigner {
+    /// Base URL without a trailing slash.
+    url: String,
+    address: Address,
+    client: reqwest::Client,
+    token: Option<Zeroizing<String>>,
+    request_id: AtomicU64,
+}
+
+// Leaves out the token
+impl fmt::Debug for RemoteSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteSigner")
+            .field("url", &self.url)
+            .field("address", &self.address)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RemoteSigner {
+    /// Client of the service in `config`, authenticating with `token`.
+    pub fn new(config: &RemoteSignerConfig, token: Option<Zeroizing<String>>) -> BlockchainResult<Self> {
+        let address = config
+            .address
+            .parse()
+            .map_err(|e| BlockchainError::Wallet(format!("Invalid remote signer address: {}", e)))?;
+
+        let mut client = reqwest::Client::builder().timeout(Duration::from_secs(config.timeout_secs));
+        if !config.ca_cert_path.is_empty() {
+            let pem = read_pem(&config.ca_cert_path)?;
+            let cert = reqwest::Certificate::from_pem(&pem).map_err(|e| tls_error(&config.ca_cert_path, e))?;
+            client = client.add_root_certificate(cert);
+        }
+        if !config.client_cert_path.is_empty() {
+            let cert = read_pem(&config.client_cert_path)?;
+            let key = Zeroizing::new(read_pem(&config.client_key_path)?);
+            let identity =
+                reqwest::Identity::from_pkcs8_pem(&cert, &key).map_err(|e| tls_error(&config.client_cert_path, e))?;
+            client = client.identity(identity);
+        }
+        let client = client
+            .build()
+            .map_err(|e| BlockchainError::Wallet(format!("Failed to create remote signer client: {}", e)))?;
+
+        Ok(Self {
+            url: config.url.trim_end_matches('/').to_string(),
+            address,
+            client,
+            token,
+            request_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Send `request` with the bearer token, returning the body of a
+    /// successful response.
+    async fn send(&self, request: reqwest::RequestBuilder, what: &str) -> BlockchainResult<String> {
+        let request = match &self.token {
+            Some(token) => request.bearer_auth(token.as_str()),
+            None => request,
+        };
+        let response = request.send().await.map_err(|e| request_error(what, e))?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| request_error(what, e))?;
+        if !status.is_success() {
+            return Err(BlockchainError::RemoteSigner(format!("{} returned {}: {}", what, status, body.trim())));
+        }
+        Ok(body)
+    }
+
+    /// Call a JSON-RPC `method`, returning its string result.
+    async fn rpc(&self, method: &str, params: Value) -> BlockchainResult<String> {
+        let id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let request = self
+            .client
+            .post(format!("{}/", self.url))
+            .json(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        let body = self.send(request, method).await?;
+
+        let response: Value = serde_json::from_str(&body)
+            .map_err(|e| BlockchainError::RemoteSigner(format!("{} returned invalid JSON: {}", method, e)))?;
+        if let Some(error) = response.get("error") {
+            return Err(BlockchainError::RemoteSigner(format!("{} failed: {}", method, error)));
+        }
+        response
+            .get("result")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| BlockchainError::RemoteSigner(format!("{} returned no result", method)))
+    }
+
+    /// Refuse a signature that recovers to anyone but our address.
+    fn check_signer<E: fmt::Display>(&self, recovered: Result<Address, E>, what: &str) -> BlockchainResult<()> {
+        match recovered {
+            Ok(signer) if signer == self.address => Ok(()),
+            Ok(signer) => Err(BlockchainError::RemoteSigner(format!(
+                "{} signed by {} instead of {}",
+                what, signer, self.address
+            ))),
+            Err(e) => Err(BlockchainError::RemoteSigner(format!("{} returned an invalid signature: {}", what, e))),
+        }
+    }
+}
+
+impl WalletSigner for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn sign<'a>(&'a self, data: &'a Signable) -> BoxFuture<'a, BlockchainResult<Signature>> {
+        Box::pin(async move {
+            let hash = data.hash()?;
+            let (signature, what) = match data {
+                Signable::TypedData(typed_data) => {
+                    let result = self.rpc("eth_signTypedData", json!([self.address, typed_data])).await?;
+                    (parse_signature(&result, "eth_signTypedData")?, "eth_signTypedData")
+                }
+                Signable::Keccak(preimage) => {
+                    let request = self
+                        .client
+                        .post(format!("{}/api/v1/eth1/sign/{}", self.url, self.address))
+                        .json(&json!({ "data": preimage }));
+                    (parse_signature(&self.send(request, "sign").await?, "sign")?, "sign")
+                }
+            };
+            self.check_signer(signature.recover_address_from_prehash(&hash), what)?;
+            Ok(signature)
+        })
+    }
+
+    fn sign_message<'a>(&'a self, message: &'a [u8]) -> BoxFuture<'a, BlockchainResult<Signature>> {
+        Box::pin(async move {
+            let result = self.rpc("eth_sign", json!([self.address, hex::encode_prefixed(message)])).await?;
+            let signature = parse_signature(&result, "eth_sign")?;
+            self.check_signer(signature.recover_address_from_msg(message), "eth_sign")?;
+            Ok(signature)
+        })
+    }
+
+    fn sign_transaction(&self, mut tx: TransactionRequest) -> BoxFuture<'_, BlockchainResult<Bytes>> {
+        Box::pin(async move {
+            tx.from = Some(self.address);
+            let result = self.rpc("eth_signTransaction", json!([tx])).await?;
+            let raw = hex::decode(result.trim()).map_err(|e| {
+                BlockchainError::RemoteSigner(format!("eth_signTransaction returned invalid hex: {}", e))
+            })?;
+            let envelope = TxEnvelope::decode_2718(&mut raw.as_slice()).map_err(|e| {
+                BlockchainError::RemoteSigner(format!("eth_signTransaction returned an invalid transaction: {}", e))
+            })?;
+            self.check_signer(envelope.recover_signer(), "eth_signTransaction")?;
+
+            if !is_signed_request(&tx, &envelope) {
+                return Err(BlockchainError::RemoteSigner(
+                    "eth_signTransaction returned a different transaction".to_string(),
+                ));
+            }
+            Ok(raw.into())
+        })
+    }
+
+    fn check_health(&self) -> BoxFuture<'_, BlockchainResult<()>> {
+        Box::pin(async move {
+            self.send(self.client.get(format!("{}/upcheck", self.url)), "upcheck").await?;
+            Ok(())
+        })
+    }
+}
+
+/// Whether `envelope` is `tx` as requested. The signer may fill in nothing
+/// we did not ask for: the recipient, value, input, chain, gas limit and
+/// fees all have to match.
+fn is_signed_request(tx: &TransactionRequest, envelope: &TxEnvelope) -> bool {
+    let to = tx.to.and_then(|kind| kind.to().copied());
+    let input = tx.input.input().cloned().unwrap_or_default();
+    let access_list = tx.access_list.clone().unwrap_or_default();
+    tx.nonce == Some(envelope.nonce())
+        && envelope.to() == to
+        && envelope.value() == tx.value.unwrap_or_default()
+        && *envelope.input() == input
+        && envelope.chain_id() == tx.chain_id
+        && tx.gas == Some(envelope.gas_limit())
+        && envelope.gas_price() == tx.gas_price
+        && tx.max_fee_per_gas.is_none_or(|fee| envelope.max_fee_per_gas() == fee)
+        && envelope.max_priority_fee_per_gas() == tx.max_priority_fee_per_gas
+        && envelope.access_list().cloned().unwrap_or_default() == access_list
+        && envelope.blob_versioned_hashes().is_none()
+        && envelope.authorization_list().is_none()
+}
+
+/// A 65-byte signature in hex, bare or as a JSON string.
+fn parse_signature(text: &str, what: &str) -> BlockchainResult<Signature> {
+    hex::decode(text.trim().trim_matches('"'))
+        .ok()
+        .and_then(|bytes| Signature::from_raw(&bytes).ok())
+        .ok_or_else(|| BlockchainError::RemoteSigner(format!("{} returned an invalid signature", what)))
+}
+
+fn read_pem(path: &str) -> BlockchainResult<Vec<u8>> {
+    std::fs::read(path).map_err(|e| BlockchainError::Wallet(format!("Failed to read {}: {}", path, e)))
+}
+
+fn tls_error(path: &str, e: reqwest::Error) -> BlockchainError {
+    BlockchainError::Wallet(format!("Invalid remote signer TLS file {}: {}", path, e))
+}
+
+fn request_error(what: &str, e: reqwest::Error) -> BlockchainError {
+    if e.is_timeout() {
+        BlockchainError::RemoteSigner(format!("{} timed out", what))
+    } else {
+        BlockchainError::RemoteSigner(format!("{} failed: {}", what, e))
+    }
+}
@@ -0,0 +1,293 @@
+//! Push-based chain notifications over WebSocket JSON-RPC.
+//!
+//! # Responsibilities
+//! - Connect to a WebSocket RPC endpoint (with failover across URLs)
+//! - Issue `eth_subscribe` for `newHeads` and a `logs` filter
+//! - Surface notifications as [`ChainEvent`]s until the socket drops or
+//!   stays silent past the idle timeout
+//!
+//! Gap filling and confirmation depth are the caller's job: this module only
+//! reports what the node pushes.
+
+use alloy::primitives::{Address, B256};
+use alloy::rpc::types::Log;
+use futures_util::{sink::SinkExt, stream::StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::blockchain::types::{BlockchainError, BlockchainResult};
+
+/// Notification pushed by the node.
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    /// A new block was added to the canonical chain.
+    NewHead(u64),
+    /// A log matching the subscription filter was emitted or, if
+    /// `removed` is set, dropped by a reorg.
+    Log(Box<Log>),
+}
+
+/// Which subscription a notification belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Heads,
+    Logs,
+}
+
+/// Live `newHeads` + `logs` subscription on one WebSocket connection.
+pub struct HeadSubscription {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    subscriptions: HashMap<String, Kind>,
+    url: String,
+    /// How long the socket may stay silent before it is considered dead.
+    idle_timeout: Duration,
+}
+
+impl HeadSubscription {
+    /// Connect to the first reachable URL and subscribe to heads and to logs
+    /// emitted by any of `addresses` whose `topic0` is one of `topics`.
+    ///
+    /// A connection that pushes nothing for `idle_timeout` is dropped, so it
+    /// should span several blocks.
+    pub async fn connect(
+        urls: &[String],
+        addresses: &[Address],
+        topics: &[B256],
+        timeout_duration: Duration,
+        idle_timeout: Duration,
+    ) -> BlockchainResult<Self> {
+        for (i, url) in urls.iter().enumerate() {
+            match timeout(timeout_duration, Self::connect_one(url, addresses, topics, idle_timeout)).await {
+                Ok(Ok(sub)) => {
+                    tracing::info!(ws_url = %url, "WebSocket subscription established");
+                    return Ok(sub);
+                }
+                Ok(Err(e)) => tracing::warn!(provider_idx = i, error = %e, "WebSocket RPC error"),
+                Err(_) => tracing::warn!(provider_idx = i, "WebSocket RPC timeout"),
+            }
+        }
+        Err(BlockchainError::Rpc("All WebSocket providers failed".to_string()))
+    }
+
+    async fn connect_one(
+        url: &str,
+        addresses: &[Address],
+        topics: &[B256],
+        idle_timeout: Duration,
+    ) -> BlockchainResult<Self> {
+        let (stream, _) = connect_async(url)
+            .await
+            .map_err(|e| BlockchainError::Rpc(format!("WebSocket connect to '{}' failed: {}", url, e)))?;
+
+        let mut sub = Self {
+            stream,
+            subscriptions: HashMap::new(),
+            url: url.to_string(),
+            idle_timeout,
+        };
+
+        let heads_id = sub.subscribe(1, json!(["newHeads"])).await?;
+        sub.subscriptions.insert(heads_id, Kind::Heads);
+
+        let filter = json!({ "address": addresses, "topics": [topics] });
+        let logs_id = sub.subscribe(2, json!(["logs", filter])).await?;
+        sub.subscriptions.insert(logs_id, Kind::Logs);
+
+        Ok(sub)
+    }
+
+    /// Send an `eth_subscribe` call and wait for its subscription id.
+    async fn subscribe(&mut self, id: u64, params: Value) -> BlockchainResult<String> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "eth_subscribe",
+            "params": params,
+        });
+        self.stream
+            .send(Message::Text(request.to_string().into()))
+            .await
+            .map_err(|e| BlockchainError::Rpc(format!("WebSocket send failed: {}", e)))?;
+
+        while let Some(msg) = self.stream.next().await {
+            let msg = msg.map_err(|e| BlockchainError::Rpc(format!("WebSocket read failed: {}", e)))?;
+            let Message::Text(text) = msg else { continue };
+            let value: Value = serde_json::from_str(&text)
+                .map_err(|e| BlockchainError::Rpc(format!("Invalid JSON-RPC response: {}", e)))?;
+            if value.get("id").and_then(Value::as_u64) != Some(id) {
+                continue;
+            }
+            if let Some(err) = value.get("error") {
+                return Err(BlockchainError::Rpc(format!("eth_subscribe rejected: {}", err)));
+            }
+            return value
+                .get("result")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| BlockchainError::Rpc("eth_subscribe returned no id".to_string()));
+        }
+        Err(BlockchainError::Rpc("WebSocket closed during subscribe".to_string()))
+    }
+
+    /// Wait for the next notification.
+    ///
+    /// Returns `None` once the connection is closed, broken or idle for
+    /// longer than the idle timeout; the caller should fall back to polling
+    /// and reconnect.
+    pub async fn next(&mut self) -> Option<ChainEvent> {
+        loop {
+            let msg = match timeout(self.idle_timeout, self.stream.next()).await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(_) => {
+                    tracing::warn!(
+                        ws_url = %self.url,
+                        idle_secs = self.idle_timeout.as_secs_f64(),
+                        "WebSocket subscription idle, dropping it"
+                    );
+                    break;
+                }
+            };
+            let text = match msg {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) => break,
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::warn!(ws_url = %self.url, error = %e, "WebSocket subscription broken");
+                    break;
+                }
+            };
+            match serde_json::from_str::<Value>(&text) {
+                Ok(value) => {
+                    if let Some(event) = self.decode_notification(&value) {
+                        return Some(event);
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "Ignoring malformed WebSocket notification"),
+            }
+        }
+        None
+    }
+
+    fn decode_notification(&self, value: &Value) -> Option<ChainEvent> {
+        if value.get("method").and_then(Value::as_str) != Some("eth_subscription") {
+            return None;
+        }
+        let params = value.get("params")?;
+        let kind = self.subscriptions.get(params.get("subscription")?.as_str()?)?;
+        let result = params.get("result")?;
+        match kind {
+            Kind::Heads => {
+                let number = result.get("number")?.as_str()?;
+                u64::from_str_radix(number.trim_start_matches("0x"), 16)
+                    .ok()
+                    .map(ChainEvent::NewHead)
+            }
+            Kind::Logs => match serde_json::from_value::<Log>(result.clone()) {
+                Ok(log) => Some(ChainEvent::Log(Box::new(log))),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Ignoring undecodable log notification");
+                    None
+                }
+            },
+        }
+    }
+}
+
+impl std::fmt::Debug for HeadSubscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeadSubscription")
+            .field("url", &self.url)
+            .field("subscriptions", &self.subscriptions.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Minimal WebSocket JSON-RPC node: acknowledges both subscriptions,
+    /// pushes one head and one log, then closes.
+    async fn start_mock_node() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+            for sub_id in ["0xaa", "0xbb"] {
+                let Some(Ok(Message::Text(text))) = ws.next().await else { return };
+                let req: Value = serde_json::from_str(&text).unwrap();
+                let resp = json!({ "jsonrpc": "2.0", "id": req["id"], "result": sub_id });
+                ws.send(Message::Text(resp.to_string().into())).await.unwrap();
+            }
+            let head = json!({
+                "jsonrpc": "2.0",
+                "method": "eth_subscription",
+                "params": { "subscription": "0xaa", "result": { "number": "0x1b4" } }
+            });
+            ws.send(Message::Text(head.to_string().into())).await.unwrap();
+            let log = json!({
+                "jsonrpc": "2.0",
+                "method": "eth_subscription",
+                "params": { "subscription": "0xbb", "result": {
+                    "address": "0x0000000000000000000000000000000000000001",
+                    "topics": [format!("{}", B256::repeat_byte(9))],
+                    "data": "0x",
+                    "blockNumber": "0x1b3",
+                    "blockHash": format!("{}", B256::repeat_byte(4)),
+                    "transactionHash": format!("{}", B256::repeat_byte(3)),
+                    "transactionIndex": "0x0",
+                    "logIndex": "0x0",
+                    "removed": false
+                } }
+            });
+            ws.send(Message::Text(log.to_string().into())).await.unwrap();
+            let _ = ws.close(None).await;
+        });
+        format!("ws://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_subscription_events() {
+        let url = start_mock_node().await;
+        let mut sub = HeadSubscription::connect(
+            &[url],
+            &[Address::with_last_byte(1)],
+            &[B256::repeat_byte(9)],
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        )
+        .await
+        .expect("subscribe failed");
+
+        assert!(matches!(sub.next().await, Some(ChainEvent::NewHead(436))));
+        match sub.next().await {
+            Some(ChainEvent::Log(log)) => {
+                assert_eq!(log.block_number, Some(435));
+                assert!(!log.removed);
+            }
+            other => panic!("expected log, got {:?}", other),
+        }
+        assert!(sub.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_connect_failover_exhausted() {
+        let result = HeadSubscription::connect(
+            &["ws://127.0.0.1:1".to_string()],
+            &[Address::ZERO],
+            &[B256::ZERO],
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+        )
+        .await;
+        assert!(result.unwrap_err().to_string().contains("All WebSocket providers failed"));
+    }
+}
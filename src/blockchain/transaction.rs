@@ -1,35 +1,121 @@
 //! Transaction building, signing, and confirmation monitoring.
 //!
 //! # Responsibilities
-//! - Build transactions with proper gas estimation
-//! - Sign and broadcast transactions
-//! - Monitor confirmations
+//! - Build transactions with `eth_estimateGas` limits and EIP-1559 (or
+//!   legacy) fees
+//! - Sign and broadcast transactions through the [`NonceManager`]
+//! - Monitor confirmations, following replacements of stuck transactions
 //! - Handle retry logic for failed broadcasts
 
 use alloy::network::TransactionBuilder;
 use alloy::primitives::{Address, Bytes, TxHash, U256};
-use alloy::rpc::types::TransactionRequest;
+use alloy::rpc::types::{FeeHistory, TransactionRequest};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::{interval, timeout};
 
 use crate::blockchain::client::BlockchainClient;
-use crate::blockchain::types::{BlockchainError, BlockchainResult, ConfirmationStatus};
+use crate::blockchain::nonce::NonceManager;
+use crate::blockchain::types::{BlockchainConfig, BlockchainError, BlockchainResult, ConfirmationStatus};
 use crate::blockchain::wallet::Wallet;
 
+/// Blocks of fee history sampled for the priority fee.
+const FEE_HISTORY_BLOCKS: u64 = 10;
+
+/// Percentile of the priority fees paid in those blocks to offer.
+const PRIORITY_FEE_PERCENTILE: f64 = 50.0;
+
+pub(crate) const WEI_PER_GWEI: u128 = 1_000_000_000;
+
+/// Fees a transaction offers per unit of gas.
+///
+/// Both kinds are priced the same way: nothing is sent while the current
+/// price exceeds `max_gas_price_gwei`, then `gas_price_multiplier` is
+/// applied and the result capped at that maximum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasFees {
+    /// EIP-1559 fees. The max fee leaves room for the base fee to double.
+    Eip1559 { max_fee_per_gas: u128, max_priority_fee_per_gas: u128 },
+    /// Gas price for chains without EIP-1559.
+    Legacy { gas_price: u128 },
+}
+
+impl GasFees {
+    /// EIP-1559 fees for a next block `base_fee` and typical `priority_fee`.
+    pub fn eip1559(base_fee: u128, priority_fee: u128, config: &BlockchainConfig) -> BlockchainResult<Self> {
+        let cap = max_gas_price(base_fee.saturating_add(priority_fee), config)?;
+        let max_priority_fee_per_gas = scale(priority_fee, config.gas_price_multiplier).min(cap);
+        let max_fee = base_fee.saturating_mul(2).saturating_add(priority_fee);
+        Ok(Self::Eip1559 {
+            max_fee_per_gas: scale(max_fee, config.gas_price_multiplier).min(cap).max(max_priority_fee_per_gas),
+            max_priority_fee_per_gas,
+        })
+    }
+
+    /// Legacy fees for the current `gas_price`.
+    pub fn legacy(gas_price: u128, config: &BlockchainConfig) -> BlockchainResult<Self> {
+        let cap = max_gas_price(gas_price, config)?;
+        Ok(Self::Legacy { gas_price: scale(gas_price, config.gas_price_multiplier).min(cap) })
+    }
+}
+
+/// The configured maximum in wei, if `current` does not exceed it.
+fn max_gas_price(current: u128, config: &BlockchainConfig) -> BlockchainResult<u128> {
+    let cap = u128::from(config.max_gas_price_gwei) * WEI_PER_GWEI;
+    if current > cap {
+        return Err(BlockchainError::GasPriceTooHigh {
+            current_gwei: u64::try_from(current / WEI_PER_GWEI).unwrap_or(u64::MAX),
+            max_gwei: config.max_gas_price_gwei,
+        });
+    }
+    Ok(cap)
+}
+
+fn scale(value: u128, multiplier: f64) -> u128 {
+    (value as f64 * multiplier) as u128
+}
+
+/// Median of the per-block priority fee percentiles in `history`.
+fn median_reward(history: &FeeHistory) -> u128 {
+    let mut rewards: Vec<u128> = history
+        .reward
+        .iter()
+        .flatten()
+        .filter_map(|block| block.first().copied())
+        .collect();
+    rewards.sort_unstable();
+    rewards.get(rewards.len() / 2).copied().unwrap_or_default()
+}
+
 /// Transaction builder for common operations.
 pub struct TxBuilder {
     client: BlockchainClient,
     wallet: Wallet,
+    nonces: Arc<NonceManager>,
 }
 
 impl TxBuilder {
-    /// Create a new transaction builder.
+    /// Create a new transaction builder with its own in-memory nonce manager.
     pub fn new(client: BlockchainClient, wallet: Wallet) -> Self {
-        Self { client, wallet }
+        let nonces = Arc::new(NonceManager::default());
+        nonces.attach(client.clone(), wallet.clone());
+        Self { client, wallet, nonces }
+    }
+
+    /// Assign nonces through `nonces`, shared by every sender of the wallet.
+    pub fn with_nonces(mut self, nonces: Arc<NonceManager>) -> Self {
+        nonces.attach(self.client.clone(), self.wallet.clone());
+        self.nonces = nonces;
+        self
     }
 
     /// Build a transaction request with gas estimation.
     ///
+    /// The gas limit is `eth_estimateGas` times `gas_limit_multiplier`, so
+    /// calls that would revert fail here instead of on chain. Fees are
+    /// EIP-1559 where the chain reports a base fee, legacy otherwise (see
+    /// [`GasFees`]). The nonce is assigned on [`broadcast`](Self::broadcast).
+    ///
     /// # Arguments
     /// * `to` - Destination address
     /// * `value` - Amount of native token to send
@@ -40,47 +126,74 @@ impl TxBuilder {
         value: U256,
         data: Bytes,
     ) -> BlockchainResult<TransactionRequest> {
-        // Get current nonce from chain and sync wallet
-        let chain_nonce = self.client.get_transaction_count(self.wallet.address()).await?;
-        self.wallet.set_nonce(chain_nonce);
+        let config = self.client.config();
+        let fees = self.fees().await?;
+
+        let mut tx = TransactionRequest::default()
+            .with_from(self.wallet.address())
+            .with_to(to)
+            .with_value(value)
+            .with_input(data)
+            .with_chain_id(self.wallet.chain_id());
+        match fees {
+            GasFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } => {
+                tx.set_max_fee_per_gas(max_fee_per_gas);
+                tx.set_max_priority_fee_per_gas(max_priority_fee_per_gas);
+            }
+            GasFees::Legacy { gas_price } => tx.set_gas_price(gas_price),
+        }
 
-        // Get gas price
-        let gas_price = self.client.get_gas_price().await?;
-        let gas_price_gwei = gas_price / 1_000_000_000;
+        let estimate = self.client.estimate_gas(tx.clone()).await?;
+        tx.set_gas_limit((estimate as f64 * config.gas_limit_multiplier) as u64);
 
-        // Check against max gas price
+        Ok(tx)
+    }
+
+    /// Fees for the next block: EIP-1559 from recent fee history, or the
+    /// legacy gas price on chains without a base fee.
+    async fn fees(&self) -> BlockchainResult<GasFees> {
         let config = self.client.config();
-        if gas_price_gwei > config.max_gas_price_gwei as u128 {
-            return Err(BlockchainError::GasPriceTooHigh {
-                current_gwei: gas_price_gwei as u64,
-                max_gwei: config.max_gas_price_gwei,
-            });
+        match self.client.get_fee_history(FEE_HISTORY_BLOCKS, &[PRIORITY_FEE_PERCENTILE]).await {
+            Ok(history) => {
+                if let Some(base_fee) = history.next_block_base_fee().filter(|fee| *fee > 0) {
+                    return GasFees::eip1559(base_fee, median_reward(&history), config);
+                }
+            }
+            Err(e) => tracing::debug!("Fee history unavailable, using legacy gas price: {}", e),
         }
+        GasFees::legacy(self.client.get_gas_price().await?, config)
+    }
 
-        // Apply multiplier for safety margin
-        let adjusted_gas_price =
-            (gas_price as f64 * config.gas_price_multiplier) as u128;
-
-        let nonce = self.wallet.get_and_increment_nonce();
+    /// Build, sign and broadcast a transaction.
+    pub async fn send(&self, to: Address, value: U256, data: Bytes) -> BlockchainResult<TxHash> {
+        let tx = self.build(to, value, data).await?;
+        self.broadcast(tx).await
+    }
 
-        // Calculate gas limit before consuming data
-        // Base gas + data cost (16 gas per non-zero byte, simplified)
-        let gas_limit = 21000u64 + (data.len() as u64 * 16);
+    /// Sign and broadcast a transaction from [`build`](Self::build), e.g.
+    /// after checking its gas limit.
+    pub async fn broadcast(&self, tx: TransactionRequest) -> BlockchainResult<TxHash> {
+        let (to, value) = (tx.to.and_then(|kind| kind.to().copied()), tx.value.unwrap_or_default());
+        let tx_hash = self.nonces.send(tx).await?;
+        tracing::info!(tx_hash = %tx_hash, to = ?to, value = %value, "Transaction sent");
+        Ok(tx_hash)
+    }
 
-        let tx = TransactionRequest::default()
-            .with_to(to)
-            .with_value(value)
-            .with_input(data)
-            .with_nonce(nonce)
-            .with_gas_price(adjusted_gas_price)
-            .with_chain_id(self.wallet.chain_id())
-            .with_gas_limit(gas_limit);
+    /// The client used for RPC calls.
+    pub fn client(&self) -> &BlockchainClient {
+        &self.client
+    }
 
-        Ok(tx)
+    /// The nonce manager tracking transactions sent through this builder.
+    pub fn nonces(&self) -> &NonceManager {
+        &self.nonces
     }
 
     /// Wait for a transaction to be confirmed.
     ///
+    /// If the transaction is replaced while pending, whichever version is
+    /// mined counts. A mined cancellation is reported as failed.
+    ///
     /// # Arguments
     /// * `tx_hash` - Transaction hash to monitor
     /// * `timeout_secs` - Maximum time to wait for confirmation
@@ -95,18 +208,38 @@ impl TxBuilder {
 
         let result = timeout(timeout_duration, async {
             let mut ticker = interval(poll_interval);
+            let mut versions = vec![(tx_hash, false)];
 
             loop {
                 ticker.tick().await;
 
-                // Get the receipt
-                let receipt = match self.client.get_transaction_receipt(tx_hash).await? {
-                    Some(r) => r,
+                // Follow replacements, including one mined since the last poll
+                for version in self.nonces.versions(tx_hash).await.unwrap_or_default() {
+                    if !versions.iter().any(|(hash, _)| *hash == version.tx_hash) {
+                        versions.push((version.tx_hash, version.cancel));
+                    }
+                }
+
+                // Get the receipt of whichever version was mined
+                let mut mined = None;
+                for (hash, cancel) in &versions {
+                    if let Some(receipt) = self.client.get_transaction_receipt(*hash).await? {
+                        mined = Some((receipt, *cancel));
+                        break;
+                    }
+                }
+                let (receipt, cancelled) = match mined {
+                    Some(mined) => mined,
                     None => {
                         tracing::debug!(tx_hash = %tx_hash, "Transaction pending");
                         continue;
                     }
                 };
+                if cancelled {
+                    return Ok(ConfirmationStatus::Failed(
+                        "Transaction cancelled".to_string(),
+                    ));
+                }
 
                 // Check if transaction succeeded
                 if !receipt.status() {
@@ -163,4 +296,39 @@ mod tests {
         let status = ConfirmationStatus::Confirmed { block_number: 100 };
         assert!(matches!(status, ConfirmationStatus::Confirmed { .. }));
     }
+
+    fn gwei(n: u128) -> u128 {
+        n * WEI_PER_GWEI
+    }
+
+    #[test]
+    fn test_gas_fees() {
+        let config = BlockchainConfig { gas_price_multiplier: 1.5, max_gas_price_gwei: 100, ..Default::default() };
+
+        let fees = GasFees::eip1559(gwei(20), gwei(2), &config).unwrap();
+        assert_eq!(fees, GasFees::Eip1559 { max_fee_per_gas: gwei(63), max_priority_fee_per_gas: gwei(3) });
+        assert_eq!(GasFees::legacy(gwei(20), &config).unwrap(), GasFees::Legacy { gas_price: gwei(30) });
+
+        // The multiplied fees never exceed the maximum
+        let fees = GasFees::eip1559(gwei(45), gwei(5), &config).unwrap();
+        assert_eq!(fees, GasFees::Eip1559 { max_fee_per_gas: gwei(100), max_priority_fee_per_gas: gwei(7) + gwei(1) / 2 });
+        assert_eq!(GasFees::legacy(gwei(90), &config).unwrap(), GasFees::Legacy { gas_price: gwei(100) });
+
+        // Nor is anything sent while the current price does
+        assert!(matches!(
+            GasFees::eip1559(gwei(99), gwei(2), &config),
+            Err(BlockchainError::GasPriceTooHigh { current_gwei: 101, max_gwei: 100 })
+        ));
+        assert!(GasFees::legacy(gwei(101), &config).is_err());
+    }
+
+    #[test]
+    fn test_median_reward() {
+        let history = FeeHistory {
+            reward: Some(vec![vec![5], vec![1], vec![3], vec![]]),
+            ..Default::default()
+        };
+        assert_eq!(median_reward(&history), 3);
+        assert_eq!(median_reward(&FeeHistory::default()), 0);
+    }
 }
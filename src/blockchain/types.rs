@@ -44,6 +44,11 @@ pub enum BlockchainError {
     #[error("Wallet error: {0}")]
     Wallet(String),
 
+    /// Remote signer unreachable, refusing, or answering with a signature
+    /// from another key.
+    #[error("Remote signer error: {0}")]
+    RemoteSigner(String),
+
     /// Gas price exceeded maximum allowed.
     #[error("Gas price {current_gwei} gwei exceeds maximum {max_gwei} gwei")]
     GasPriceTooHigh { current_gwei: u64, max_gwei: u64 },
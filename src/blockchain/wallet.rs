@@ -1,28 +1,135 @@
 //! Wallet management and transaction signing.
 //!
+//! A [`Wallet`] signs through a [`WalletSigner`]: a key held in process, or
+//! a [`RemoteSigner`] so the proxy host never holds the key at all.
+//!
 //! # Security
-//! - Private keys are loaded ONLY from environment variables
+//! - Keys are loaded ONLY through environment variables: a raw private key,
+//!   an encrypted JSON V3 keystore, or a BIP-39 mnemonic
 //! - Keys are never logged or serialized
-//! - Uses secure memory handling where possible
+//! - Key material read along the way is zeroized once the signer is built
 
-use alloy::primitives::{Address, B256};
-use alloy::signers::local::PrivateKeySigner;
-use alloy::signers::Signer;
-use std::sync::atomic::{AtomicU64, Ordering};
+use alloy::dyn_abi::TypedData;
+use alloy::eips::eip2718::Encodable2718;
+use alloy::network::{EthereumWallet, TransactionBuilder};
+use alloy::primitives::{keccak256, Address, Bytes, B256};
+use alloy::rpc::types::TransactionRequest;
+use alloy::hex;
+use alloy::signers::local::{MnemonicBuilder, PrivateKeySigner};
+use alloy::signers::{Signature, Signer};
+use futures_util::future::BoxFuture;
+use std::fmt::Debug;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use zeroize::Zeroizing;
 
-use crate::blockchain::types::{BlockchainError, BlockchainResult};
+use crate::blockchain::remote_signer::{RemoteSigner, SIGNER_TOKEN_ENV_VAR};
+use crate::blockchain::types::{BlockchainConfig, BlockchainError, BlockchainResult};
+use crate::observability::metrics;
 
 /// Environment variable name for the private key.
 pub const PRIVATE_KEY_ENV_VAR: &str = "PROXY_BLOCKCHAIN_PRIVATE_KEY";
 
-/// Wallet for transaction signing with nonce management.
-#[derive(Debug)]
+/// Path of an encrypted JSON V3 keystore file.
+pub const KEYSTORE_ENV_VAR: &str = "PROXY_BLOCKCHAIN_KEYSTORE";
+
+/// Password of the keystore.
+pub const KEYSTORE_PASSWORD_ENV_VAR: &str = "PROXY_BLOCKCHAIN_KEYSTORE_PASSWORD";
+
+/// File holding the password of the keystore, e.g. a mounted secret.
+pub const KEYSTORE_PASSWORD_FILE_ENV_VAR: &str = "PROXY_BLOCKCHAIN_KEYSTORE_PASSWORD_FILE";
+
+/// BIP-39 mnemonic phrase.
+pub const MNEMONIC_ENV_VAR: &str = "PROXY_BLOCKCHAIN_MNEMONIC";
+
+/// Derivation path of the key within the mnemonic.
+pub const MNEMONIC_PATH_ENV_VAR: &str = "PROXY_BLOCKCHAIN_MNEMONIC_PATH";
+
+/// First account of the standard Ethereum derivation path.
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+
+/// Data signed over its hash, without the Ethereum message prefix.
+#[derive(Debug, Clone)]
+pub enum Signable {
+    /// EIP-712 typed data, signed over its signing hash.
+    TypedData(Box<TypedData>),
+    /// Bytes signed over their keccak-256 hash.
+    Keccak(Bytes),
+}
+
+impl Signable {
+    /// The digest the signature covers.
+    pub fn hash(&self) -> BlockchainResult<B256> {
+        match self {
+            Self::TypedData(typed_data) => typed_data
+                .eip712_signing_hash()
+                .map_err(|e| BlockchainError::Wallet(format!("Invalid typed data: {}", e))),
+            Self::Keccak(data) => Ok(keccak256(data)),
+        }
+    }
+}
+
+/// Something that signs for one address.
+pub trait WalletSigner: Send + Sync + Debug {
+    /// Address of the signing key.
+    fn address(&self) -> Address;
+
+    /// Sign the hash of `data`.
+    fn sign<'a>(&'a self, data: &'a Signable) -> BoxFuture<'a, BlockchainResult<Signature>>;
+
+    /// Sign `message` with the Ethereum message prefix (EIP-191).
+    fn sign_message<'a>(&'a self, message: &'a [u8]) -> BoxFuture<'a, BlockchainResult<Signature>>;
+
+    /// Sign a complete transaction, returning its EIP-2718 encoding.
+    fn sign_transaction(&self, tx: TransactionRequest) -> BoxFuture<'_, BlockchainResult<Bytes>>;
+
+    /// Check that the signer can currently sign.
+    fn check_health(&self) -> BoxFuture<'_, BlockchainResult<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl WalletSigner for PrivateKeySigner {
+    fn address(&self) -> Address {
+        Signer::address(self)
+    }
+
+    fn sign<'a>(&'a self, data: &'a Signable) -> BoxFuture<'a, BlockchainResult<Signature>> {
+        Box::pin(async move {
+            Signer::sign_hash(self, &data.hash()?)
+                .await
+                .map_err(|e| BlockchainError::Wallet(format!("Signing failed: {}", e)))
+        })
+    }
+
+    fn sign_message<'a>(&'a self, message: &'a [u8]) -> BoxFuture<'a, BlockchainResult<Signature>> {
+        Box::pin(async move {
+            Signer::sign_message(self, message)
+                .await
+                .map_err(|e| BlockchainError::Wallet(format!("Message signing failed: {}", e)))
+        })
+    }
+
+    fn sign_transaction(&self, tx: TransactionRequest) -> BoxFuture<'_, BlockchainResult<Bytes>> {
+        Box::pin(async move {
+            let wallet = EthereumWallet::from(self.clone());
+            let envelope = tx
+                .build(&wallet)
+                .await
+                .map_err(|e| BlockchainError::Wallet(format!("Transaction signing failed: {}", e)))?;
+            Ok(envelope.encoded_2718().into())
+        })
+    }
+}
+
+/// Wallet for transaction signing. Nonces are handed out by the
+/// [`NonceManager`](crate::blockchain::nonce::NonceManager).
+#[derive(Debug, Clone)]
 pub struct Wallet {
-    /// The underlying signer (private key).
-    signer: PrivateKeySigner,
-    /// Current nonce for sequential transactions.
-    nonce: Arc<AtomicU64>,
+    /// The underlying signer.
+    signer: Arc<dyn WalletSigner>,
     /// Chain ID for EIP-155 replay protection.
     chain_id: u64,
 }
@@ -40,37 +147,107 @@ impl Wallet {
         // Strip 0x prefix if present
         let key_hex = private_key_hex.strip_prefix("0x").unwrap_or(private_key_hex);
 
+        let key = Zeroizing::new(
+            hex::decode(key_hex)
+                .map_err(|e| BlockchainError::Wallet(format!("Invalid private key format: {}", e)))?,
+        );
+        let signer = PrivateKeySigner::from_slice(&key)
+            .map_err(|e| BlockchainError::Wallet(format!("Invalid private key format: {}", e)))?;
 
+        Ok(Self::new(Arc::new(signer), chain_id, "private key"))
+    }
 
-        let signer: PrivateKeySigner = key_hex
-            .parse()
-            .map_err(|e| BlockchainError::Wallet(format!("Invalid private key format: {}", e)))?;
+    /// Decrypt an encrypted JSON V3 keystore file.
+    pub fn from_keystore(path: impl AsRef<Path>, password: &[u8], chain_id: u64) -> BlockchainResult<Self> {
+        let path = path.as_ref();
+        let signer = PrivateKeySigner::decrypt_keystore(path, password).map_err(|e| {
+            BlockchainError::Wallet(format!("Failed to decrypt keystore {}: {}", path.display(), e))
+        })?;
+        Ok(Self::new(Arc::new(signer), chain_id, "keystore"))
+    }
+
+    /// Derive the key at `derivation_path` from a BIP-39 mnemonic phrase.
+    pub fn from_mnemonic(phrase: &str, derivation_path: &str, chain_id: u64) -> BlockchainResult<Self> {
+        // The builder zeroizes its copy of the phrase on drop
+        let signer = MnemonicBuilder::english()
+            .phrase(phrase)
+            .derivation_path(derivation_path)
+            .and_then(|builder| builder.build())
+            .map_err(|e| BlockchainError::Wallet(format!("Invalid mnemonic or derivation path: {}", e)))?;
+        Ok(Self::new(Arc::new(signer), chain_id, "mnemonic"))
+    }
+
+    /// Load wallet from environment variables.
+    ///
+    /// Exactly one source must be set: `PROXY_BLOCKCHAIN_PRIVATE_KEY`,
+    /// `PROXY_BLOCKCHAIN_KEYSTORE` with `PROXY_BLOCKCHAIN_KEYSTORE_PASSWORD`
+    /// or `PROXY_BLOCKCHAIN_KEYSTORE_PASSWORD_FILE`, or
+    /// `PROXY_BLOCKCHAIN_MNEMONIC` with an optional
+    /// `PROXY_BLOCKCHAIN_MNEMONIC_PATH`.
+    pub fn from_env(chain_id: u64) -> BlockchainResult<Self> {
+        let private_key = secret_var(PRIVATE_KEY_ENV_VAR);
+        let keystore = std::env::var(KEYSTORE_ENV_VAR).ok().filter(|path| !path.is_empty());
+        let mnemonic = secret_var(MNEMONIC_ENV_VAR);
+
+        match (private_key, keystore, mnemonic) {
+            (Some(private_key), None, None) => Self::from_private_key(&private_key, chain_id),
+            (None, Some(keystore), None) => Self::from_keystore(&keystore, keystore_password()?.as_bytes(), chain_id),
+            (None, None, Some(mnemonic)) => {
+                let path = std::env::var(MNEMONIC_PATH_ENV_VAR)
+                    .unwrap_or_else(|_| DEFAULT_DERIVATION_PATH.to_string());
+                Self::from_mnemonic(&mnemonic, &path, chain_id)
+            }
+            (None, None, None) => Err(BlockchainError::Wallet(format!(
+                "No wallet configured: set {}, {} or {}",
+                PRIVATE_KEY_ENV_VAR, KEYSTORE_ENV_VAR, MNEMONIC_ENV_VAR
+            ))),
+            _ => Err(BlockchainError::Wallet(format!(
+                "Only one of {}, {} and {} may be set",
+                PRIVATE_KEY_ENV_VAR, KEYSTORE_ENV_VAR, MNEMONIC_ENV_VAR
+            ))),
+        }
+    }
 
+    /// Sign through the remote signer in `config.remote_signer` if one is
+    /// configured, or else with a key from the environment.
+    ///
+    /// A key in the environment next to a remote signer is refused rather
+    /// than silently ignored.
+    pub fn load(config: &BlockchainConfig) -> BlockchainResult<Self> {
+        let remote = &config.remote_signer;
+        if remote.url.is_empty() {
+            return Self::from_env(config.chain_id);
+        }
+        let local = [PRIVATE_KEY_ENV_VAR, KEYSTORE_ENV_VAR, MNEMONIC_ENV_VAR]
+            .into_iter()
+            .find(|name| std::env::var(name).is_ok_and(|value| !value.is_empty()));
+        if let Some(name) = local {
+            return Err(BlockchainError::Wallet(format!(
+                "{} must not be set when a remote signer is configured",
+                name
+            )));
+        }
+        let signer = RemoteSigner::new(remote, secret_var(SIGNER_TOKEN_ENV_VAR))?;
+        Ok(Self::new(Arc::new(signer), config.chain_id, "remote signer"))
+    }
+
+    /// Sign through `signer`.
+    pub fn with_signer(signer: Arc<dyn WalletSigner>, chain_id: u64) -> Self {
+        Self::new(signer, chain_id, "custom signer")
+    }
+
+    fn new(signer: Arc<dyn WalletSigner>, chain_id: u64, source: &str) -> Self {
         tracing::info!(
             address = %signer.address(),
             chain_id = chain_id,
+            source = source,
             "Wallet initialized"
         );
 
-        Ok(Self {
+        Self {
             signer,
-            nonce: Arc::new(AtomicU64::new(0)),
             chain_id,
-        })
-    }
-
-    /// Load wallet from environment variable.
-    ///
-    /// Reads `PROXY_BLOCKCHAIN_PRIVATE_KEY` from environment.
-    pub fn from_env(chain_id: u64) -> BlockchainResult<Self> {
-        let private_key = std::env::var(PRIVATE_KEY_ENV_VAR).map_err(|_| {
-            BlockchainError::Wallet(format!(
-                "Environment variable {} not set",
-                PRIVATE_KEY_ENV_VAR
-            ))
-        })?;
-
-        Self::from_private_key(&private_key, chain_id)
+        }
     }
 
     /// Get the wallet's address.
@@ -83,54 +260,74 @@ impl Wallet {
         self.chain_id
     }
 
-    /// Get and increment the nonce atomically.
-    ///
-    /// This ensures sequential transactions don't collide.
-    pub fn get_and_increment_nonce(&self) -> u64 {
-        self.nonce.fetch_add(1, Ordering::SeqCst)
+    /// Sign typed data or a keccak-256 preimage (see [`Signable`]).
+    pub async fn sign(&self, data: &Signable) -> BlockchainResult<Signature> {
+        self.signer.sign(data).await
     }
 
-    /// Set the nonce to a specific value (e.g., after querying from chain).
-    pub fn set_nonce(&self, nonce: u64) {
-        self.nonce.store(nonce, Ordering::SeqCst);
+    /// Sign a transaction, returning its EIP-2718 encoding for broadcast.
+    pub async fn sign_transaction(&self, tx: TransactionRequest) -> BlockchainResult<Bytes> {
+        self.signer.sign_transaction(tx).await
     }
 
-    /// Get current nonce without incrementing.
-    pub fn current_nonce(&self) -> u64 {
-        self.nonce.load(Ordering::SeqCst)
+    /// Sign arbitrary message bytes (with Ethereum prefix).
+    pub async fn sign_message(&self, message: &[u8]) -> BlockchainResult<Signature> {
+        self.signer.sign_message(message).await
     }
 
-    /// Sign a message hash.
-    ///
-    /// # Arguments
-    /// * `hash` - The 32-byte hash to sign
-    ///
-    /// # Returns
-    /// The signature as bytes
-    pub async fn sign_hash(&self, hash: B256) -> BlockchainResult<alloy::signers::Signature> {
-        self.signer
-            .sign_hash(&hash)
-            .await
-            .map_err(|e| BlockchainError::Wallet(format!("Signing failed: {}", e)))
+    /// Check the signer every `interval` until shutdown, logging when it
+    /// becomes unavailable or recovers.
+    pub async fn monitor_signer(&self, interval: Duration, mut shutdown: broadcast::Receiver<()>) {
+        let mut ticker = tokio::time::interval(interval);
+        let mut healthy = true;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let result = self.signer.check_health().await;
+                    metrics::record_signer_health(result.is_ok());
+                    match result {
+                        Ok(()) if !healthy => {
+                            tracing::info!(address = %self.address(), "Signer available again");
+                            healthy = true;
+                        }
+                        Err(e) if healthy => {
+                            tracing::error!(address = %self.address(), "Signer unavailable: {}", e);
+                            healthy = false;
+                        }
+                        _ => {}
+                    }
+                }
+                _ = shutdown.recv() => break,
+            }
+        }
     }
+}
 
-    /// Sign arbitrary message bytes (with Ethereum prefix).
-    pub async fn sign_message(&self, message: &[u8]) -> BlockchainResult<alloy::signers::Signature> {
-        self.signer
-            .sign_message(message)
-            .await
-            .map_err(|e| BlockchainError::Wallet(format!("Message signing failed: {}", e)))
-    }
+/// A secret environment variable, zeroized when dropped. Unset and empty
+/// are the same.
+fn secret_var(name: &str) -> Option<Zeroizing<String>> {
+    std::env::var(name).ok().map(Zeroizing::new).filter(|value| !value.is_empty())
 }
 
-impl Clone for Wallet {
-    fn clone(&self) -> Self {
-        Self {
-            signer: self.signer.clone(),
-            nonce: self.nonce.clone(),
-            chain_id: self.chain_id,
-        }
+/// Keystore password from its environment variable, or else from the file
+/// it names, without the trailing newline.
+fn keystore_password() -> BlockchainResult<Zeroizing<String>> {
+    if let Some(password) = secret_var(KEYSTORE_PASSWORD_ENV_VAR) {
+        return Ok(password);
     }
+    let path = std::env::var(KEYSTORE_PASSWORD_FILE_ENV_VAR).map_err(|_| {
+        BlockchainError::Wallet(format!(
+            "{} requires {} or {}",
+            KEYSTORE_ENV_VAR, KEYSTORE_PASSWORD_ENV_VAR, KEYSTORE_PASSWORD_FILE_ENV_VAR
+        ))
+    })?;
+    let mut password = Zeroizing::new(
+        std::fs::read_to_string(&path)
+            .map_err(|e| BlockchainError::Wallet(format!("Failed to read keystore password file {}: {}", path, e)))?,
+    );
+    let len = password.trim_end_matches(['\r', '\n']).len();
+    password.truncate(len);
+    Ok(password)
 }
 
 #[cfg(test)]
@@ -160,23 +357,42 @@ mod tests {
     }
 
     #[test]
-    fn test_nonce_management() {
-        let wallet = Wallet::from_private_key(TEST_PRIVATE_KEY, 1).unwrap();
+    fn test_invalid_private_key() {
+        let result = Wallet::from_private_key("invalid_key", 1);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Invalid private key"));
+    }
+
+    #[test]
+    fn test_wallet_from_keystore() {
+        let dir = std::env::temp_dir();
+        let name = format!("keystore-{}.json", uuid::Uuid::new_v4());
+        let key = hex::decode(TEST_PRIVATE_KEY).unwrap();
+        PrivateKeySigner::encrypt_keystore(&dir, &mut rand::thread_rng(), &key, b"hunter2", Some(&name)).unwrap();
+        let path = dir.join(&name);
 
-        assert_eq!(wallet.current_nonce(), 0);
-        assert_eq!(wallet.get_and_increment_nonce(), 0);
-        assert_eq!(wallet.get_and_increment_nonce(), 1);
-        assert_eq!(wallet.current_nonce(), 2);
+        let wallet = Wallet::from_keystore(&path, b"hunter2", 1).unwrap();
+        assert_eq!(wallet.address(), Wallet::from_private_key(TEST_PRIVATE_KEY, 1).unwrap().address());
 
-        wallet.set_nonce(100);
-        assert_eq!(wallet.current_nonce(), 100);
+        let err = Wallet::from_keystore(&path, b"wrong", 1).unwrap_err();
+        assert!(err.to_string().contains("Failed to decrypt keystore"));
+        std::fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn test_invalid_private_key() {
-        let result = Wallet::from_private_key("invalid_key", 1);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("Invalid private key"));
+    fn test_wallet_from_mnemonic() {
+        // Anvil's mnemonic: its first account is the test key
+        let phrase = "test test test test test test test test test test test junk";
+        let wallet = Wallet::from_mnemonic(phrase, DEFAULT_DERIVATION_PATH, 1).unwrap();
+        assert_eq!(
+            wallet.address().to_string().to_lowercase(),
+            "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266"
+        );
+
+        let second = Wallet::from_mnemonic(phrase, "m/44'/60'/0'/0/1", 1).unwrap();
+        assert_ne!(second.address(), wallet.address());
+        assert!(Wallet::from_mnemonic("not a mnemonic", DEFAULT_DERIVATION_PATH, 1).is_err());
+        assert!(Wallet::from_mnemonic(phrase, "not/a/path", 1).is_err());
     }
 
     #[tokio::test]
@@ -187,4 +403,26 @@ mod tests {
         // Signature should be 65 bytes (r, s, v)
         assert_eq!(signature.as_bytes().len(), 65);
     }
+
+    #[tokio::test]
+    async fn test_sign_transaction() {
+        use alloy::consensus::transaction::SignerRecoverable;
+        use alloy::consensus::{Transaction, TxEnvelope};
+        use alloy::eips::eip2718::Decodable2718;
+        use alloy::primitives::U256;
+
+        let wallet = Wallet::from_private_key(TEST_PRIVATE_KEY, 31337).unwrap();
+        let tx = TransactionRequest::default()
+            .with_to(Address::with_last_byte(1))
+            .with_value(U256::from(1000))
+            .with_nonce(7)
+            .with_gas_price(1_000_000_000)
+            .with_gas_limit(21000)
+            .with_chain_id(31337);
+
+        let raw = wallet.sign_transaction(tx).await.unwrap();
+        let envelope = TxEnvelope::decode_2718(&mut raw.as_ref()).unwrap();
+        assert_eq!(envelope.recover_signer().unwrap(), wallet.address());
+        assert_eq!((envelope.nonce(), envelope.value()), (7, U256::from(1000)));
+    }
 }
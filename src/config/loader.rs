@@ -33,11 +33,64 @@ impl std::fmt::Display for ConfigError {
 impl std::error::Error for ConfigError {}
 
 /// Load and validate configuration from a TOML file.
+///
+/// Relative store paths are resolved against the directory of the file, so
+/// the stores do not move with the working directory.
 pub fn load_config(path: &Path) -> Result<ProxyConfig, ConfigError> {
     let content = fs::read_to_string(path).map_err(ConfigError::Io)?;
-    let config: ProxyConfig = toml::from_str(&content).map_err(ConfigError::Parse)?;
+    let mut config: ProxyConfig = toml::from_str(&content).map_err(ConfigError::Parse)?;
+
+    let dir = std::path::absolute(path).map_err(ConfigError::Io)?;
+    let dir = dir.parent().unwrap_or(Path::new("/"));
+    for store in [
+        &mut config.pricing.quote_store.path,
+        &mut config.payments.billing_path,
+        &mut config.payments.trial.store_path,
+        &mut config.payments.orgs.store_path,
+        &mut config.webhooks.outbox_path,
+        &mut config.blockchain.transactions.store_path,
+        &mut config.sla.ledger_path,
+    ] {
+        if !store.is_empty() && Path::new(store.as_str()).is_relative() {
+            *store = dir.join(store.as_str()).to_string_lossy().into_owned();
+        }
+    }
     
     validate_config(&config).map_err(ConfigError::Validation)?;
     
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_paths_are_relative_to_the_file() {
+        let dir = std::env::temp_dir().join(format!("config-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        let mut config = ProxyConfig::default();
+        config.pricing.quote_store.path = "quotes.redb".to_string();
+        config.payments.billing_path = "data/billing.redb".to_string();
+        config.payments.trial.store_path = "trials.redb".to_string();
+        config.payments.orgs.store_path = "/var/lib/seidar/orgs.redb".to_string();
+        config.webhooks.outbox_path = "webhooks.redb".to_string();
+        config.blockchain.transactions.store_path = String::new();
+        config.sla.ledger_path = "sla_ledger.jsonl".to_string();
+        let path = dir.join("config.toml");
+        fs::write(&path, toml::to_string_pretty(&config).unwrap()).unwrap();
+
+        let loaded = load_config(&path).unwrap();
+        let resolved = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        assert_eq!(loaded.pricing.quote_store.path, resolved("quotes.redb"));
+        assert_eq!(loaded.payments.billing_path, resolved("data/billing.redb"));
+        assert_eq!(loaded.payments.trial.store_path, resolved("trials.redb"));
+        assert_eq!(loaded.webhooks.outbox_path, resolved("webhooks.redb"));
+        assert_eq!(loaded.sla.ledger_path, resolved("sla_ledger.jsonl"));
+        // Absolute and empty paths are kept
+        assert_eq!(loaded.payments.orgs.store_path, "/var/lib/seidar/orgs.redb");
+        assert!(loaded.blockchain.transactions.store_path.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -27,6 +27,7 @@ pub mod validation;
 pub mod watcher;
 
 pub use schema::ProxyConfig;
+pub use schema::{PriceFeedConfig, PricingConfig, PromoCode, QuoteStoreConfig, ServicePrice, SignatureScheme, VolumeDiscount};
 pub use schema::ListenerConfig;
 pub use schema::RouteConfig;
 pub use schema::BackendConfig;
@@ -34,5 +35,13 @@ pub use schema::HealthCheckConfig;
 pub use schema::RetryConfig;
 pub use schema::ObservabilityConfig;
 pub use schema::PaymentConfig;
+pub use schema::ChainConfig;
 pub use schema::QosConfig;
+pub use schema::{SlaConfig, SlaPolicy};
+pub use schema::TierPolicy;
+pub use schema::TreasuryConfig;
+pub use schema::{RemoteSignerConfig, RpcPoolConfig, TransactionConfig};
+pub use schema::TrialConfig;
+pub use schema::OrgConfig;
+pub use schema::{WebhookConfig, WebhookEndpoint, WebhookEventKind};
 
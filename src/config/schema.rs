@@ -4,6 +4,7 @@
 //! All types derive Serde traits for deserialization from config files.
 
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Root configuration for the reverse proxy.
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
@@ -39,6 +40,10 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub payments: PaymentConfig,
 
+    /// Further chains on which payments are accepted, besides `blockchain`.
+    #[serde(default)]
+    pub chains: Vec<ChainConfig>,
+
     #[serde(default)]
     pub qos: QosConfig,
 
@@ -47,6 +52,15 @@ pub struct ProxyConfig {
 
     #[serde(default)]
     pub security: SecurityConfig,
+
+    #[serde(default)]
+    pub pricing: PricingConfig,
+
+    #[serde(default)]
+    pub sla: SlaConfig,
+
+    #[serde(default)]
+    pub webhooks: WebhookConfig,
 }
 
 /// Listener configuration.
@@ -298,6 +312,145 @@ impl Default for AdminConfig {
     }
 }
 
+/// Service level agreement credits.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SlaConfig {
+    /// Measure availability and credit subscribers.
+    pub enabled: bool,
+
+    /// Length of each measurement window in seconds. Credits are computed
+    /// at the end of every window.
+    pub window_secs: u64,
+
+    /// How often backend health is sampled, in seconds.
+    pub sample_interval_secs: u64,
+
+    /// Requests a subscriber must make in a window for their error rate to
+    /// count. Backend outages count regardless.
+    pub min_requests: u64,
+
+    /// Credits of at least this many seconds are refunded on chain instead
+    /// of extending the subscription. 0 never refunds.
+    pub refund_threshold_secs: u64,
+
+    /// How long a refund may take to confirm before it is left pending in
+    /// the ledger for an operator to check.
+    pub refund_confirmation_timeout_secs: u64,
+
+    /// Append-only ledger of issued credits.
+    pub ledger_path: String,
+
+    /// Policy per tier id. Subscribers of tiers without one are not credited.
+    #[serde(with = "tier_map")]
+    pub tiers: BTreeMap<u8, SlaPolicy>,
+}
+
+impl Default for SlaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_secs: 3600,
+            sample_interval_secs: 10,
+            min_requests: 10,
+            refund_threshold_secs: 0,
+            refund_confirmation_timeout_secs: 600,
+            ledger_path: "sla_ledger.jsonl".to_string(),
+            tiers: BTreeMap::new(),
+        }
+    }
+}
+
+/// Webhook notifications of subscription lifecycle events.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Send webhooks.
+    pub enabled: bool,
+
+    /// Database holding undelivered webhooks across restarts. Empty keeps
+    /// them in memory.
+    pub outbox_path: String,
+
+    /// Deliveries are retried until this many attempts failed.
+    pub max_attempts: u32,
+
+    /// First retry delay; doubled on every further attempt.
+    pub base_backoff_ms: u64,
+
+    /// Cap on the retry delay.
+    pub max_backoff_ms: u64,
+
+    /// Timeout of a single delivery request in seconds.
+    pub timeout_secs: u64,
+
+    /// Send `expiring` this many seconds before a subscription expires.
+    pub expiry_warning_secs: u64,
+
+    /// How often subscriptions are checked for approaching expiry.
+    pub scan_interval_secs: u64,
+
+    pub endpoints: Vec<WebhookEndpoint>,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            outbox_path: "webhooks.redb".to_string(),
+            max_attempts: 12,
+            base_backoff_ms: 1000,
+            max_backoff_ms: 3_600_000,
+            timeout_secs: 10,
+            expiry_warning_secs: 3 * 24 * 3600,
+            scan_interval_secs: 300,
+            endpoints: Vec::new(),
+        }
+    }
+}
+
+/// A receiver of webhooks.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WebhookEndpoint {
+    /// Unique name, used to track deliveries.
+    pub name: String,
+
+    pub url: String,
+
+    /// Key for the HMAC-SHA256 signature of each delivery.
+    pub secret: String,
+
+    /// Events to send. Empty sends all of them.
+    #[serde(default)]
+    pub events: Vec<WebhookEventKind>,
+}
+
+/// Subscription lifecycle events sent as webhooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    /// First payment, or payment after the previous subscription lapsed.
+    Created,
+    /// Payment extending an active subscription of the same tier.
+    Renewed,
+    /// The subscription expires within `expiry_warning_secs`.
+    Expiring,
+    Expired,
+    /// An active subscription was replaced by a purchase of another tier.
+    Cancelled,
+}
+
+/// Availability target and compensation for one tier.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SlaPolicy {
+    /// Guaranteed availability in basis points (9990 = 99.9%).
+    pub target_bps: u16,
+
+    /// Credit per unavailable second, in basis points (10000 credits each
+    /// lost second once).
+    pub credit_bps: u32,
+}
+
 /// Blockchain integration configuration.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
@@ -312,6 +465,11 @@ pub struct BlockchainConfig {
     #[serde(default)]
     pub failover_urls: Vec<String>,
 
+    /// Optional WebSocket JSON-RPC endpoints (tried in order) for push-based
+    /// payment detection. When empty the payment monitor only polls.
+    #[serde(default)]
+    pub ws_urls: Vec<String>,
+
     /// Chain ID (e.g., 1 for Ethereum mainnet, 31337 for local Anvil).
     pub chain_id: u64,
 
@@ -321,11 +479,146 @@ pub struct BlockchainConfig {
     /// Number of block confirmations required for finality.
     pub confirmation_blocks: u32,
 
-    /// Gas price multiplier (1.0 = estimated, 1.2 = 20% buffer).
+    /// Gas price multiplier (1.0 = estimated, 1.2 = 20% buffer). Applies to
+    /// the legacy gas price or to both EIP-1559 fees.
     pub gas_price_multiplier: f64,
 
-    /// Maximum gas price in gwei (protection against spikes).
+    /// Maximum gas price in gwei (protection against spikes). Transactions
+    /// are not sent while the current price exceeds it, and never offer
+    /// more per gas.
     pub max_gas_price_gwei: u64,
+
+    /// Gas limit multiplier applied to `eth_estimateGas` (1.2 = 20% buffer).
+    pub gas_limit_multiplier: f64,
+
+    /// Tracking and replacement of the proxy wallet's pending transactions.
+    pub transactions: TransactionConfig,
+
+    /// Remote signing service holding the proxy's key, instead of a key in
+    /// the environment.
+    pub remote_signer: RemoteSignerConfig,
+
+    /// Health scoring of `rpc_url` and `failover_urls`.
+    pub pool: RpcPoolConfig,
+}
+
+/// Ranking of RPC providers by latency, errors and head-block lag.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RpcPoolConfig {
+    /// How often every provider is asked for its head block, in seconds.
+    /// 0 disables probing, and with it lag tracking.
+    pub probe_interval_secs: u64,
+
+    /// Consecutive failures after which a provider is ejected.
+    pub eject_after_failures: u32,
+
+    /// How long an ejected provider is only used as a last resort.
+    pub eject_secs: u64,
+
+    /// Blocks a provider may be behind the highest head before it ranks
+    /// after those in sync.
+    pub max_block_lag: u64,
+
+    /// Send reads still unanswered after the best provider's
+    /// `hedge_percentile` latency to the runner-up as well.
+    pub hedge_reads: bool,
+
+    /// Percentile (0-100) of the best provider's recent latencies to wait
+    /// before hedging.
+    pub hedge_percentile: f64,
+
+    /// Shortest wait before hedging, in milliseconds.
+    pub hedge_min_delay_ms: u64,
+}
+
+impl Default for RpcPoolConfig {
+    fn default() -> Self {
+        Self {
+            probe_interval_secs: 15,
+            eject_after_failures: 3,
+            eject_secs: 30,
+            max_block_lag: 3,
+            hedge_reads: false,
+            hedge_percentile: 95.0,
+            hedge_min_delay_ms: 50,
+        }
+    }
+}
+
+/// Pending transactions of the proxy wallet.
+///
+/// Transactions not mined within `stuck_after_secs` are sent again under the
+/// same nonce with fees raised by `fee_bump_percent`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TransactionConfig {
+    /// Database of pending transactions. Empty keeps them in memory.
+    pub store_path: String,
+
+    /// How long a transaction may stay unmined before it is replaced.
+    pub stuck_after_secs: u64,
+
+    /// Fee increase of a replacement, in percent. Nodes reject
+    /// replacements below 10%.
+    pub fee_bump_percent: u64,
+
+    /// How often pending transactions are checked, in seconds.
+    pub check_interval_secs: u64,
+}
+
+impl Default for TransactionConfig {
+    fn default() -> Self {
+        Self {
+            store_path: "transactions.redb".to_string(),
+            stuck_after_secs: 300,
+            fee_bump_percent: 15,
+            check_interval_secs: 30,
+        }
+    }
+}
+
+/// Web3Signer-style signing service. The bearer token, if the service
+/// requires one, comes from `PROXY_BLOCKCHAIN_SIGNER_TOKEN`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RemoteSignerConfig {
+    /// Base URL of the service, e.g. `https://signer:9000`. Plain http is
+    /// only accepted on loopback. Empty loads the key from the environment
+    /// instead.
+    pub url: String,
+
+    /// Address of the key the service signs with.
+    pub address: String,
+
+    /// Timeout of each signing request, in seconds.
+    pub timeout_secs: u64,
+
+    /// How often the service's `/upcheck` is polled, in seconds.
+    pub health_check_interval_secs: u64,
+
+    /// PEM CA certificate trusted for the service, besides the system roots.
+    pub ca_cert_path: String,
+
+    /// PEM client certificate for mutual TLS, with `client_key_path`.
+    pub client_cert_path: String,
+
+    /// PEM PKCS#8 key of the client certificate.
+    pub client_key_path: String,
+}
+
+impl Default for RemoteSignerConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            address: String::new(),
+            timeout_secs: 5,
+            health_check_interval_secs: 30,
+            ca_cert_path: String::new(),
+            client_cert_path: String::new(),
+            client_key_path: String::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -337,34 +630,357 @@ pub struct PaymentConfig {
     /// Address of the PaymentProcessor contract.
     pub contract_address: String,
 
+    /// Address of the SubscriptionManager contract. When set, tier pricing is
+    /// loaded via `getTier` and kept current from `TierUpdated` events.
+    pub subscription_manager_address: String,
+
+    /// Tier ids to load from the SubscriptionManager at startup.
+    pub tier_ids: Vec<u8>,
+
     /// Polling interval in milliseconds.
     pub monitor_interval_ms: u64,
 
     /// Grace period for expired subscriptions in seconds.
     #[serde(default)]
     pub grace_period_secs: u64,
+
+    /// Add `X-Subscription-Tier`/`-Expires` (and a `Warning` during the
+    /// grace period) to proxied responses.
+    pub subscription_headers: bool,
+
+    /// Snapshot file holding the subscription cache and monitor checkpoint.
+    pub state_path: String,
+
+    /// Database recording every processed payment for billing history.
+    /// Empty keeps the history in memory.
+    pub billing_path: String,
+
+    /// First block to scan when backfilling (usually the contract deployment
+    /// block). With no snapshot and `start_block = 0` the monitor starts at
+    /// the current head.
+    pub start_block: u64,
+
+    /// Maximum number of blocks requested in a single `eth_getLogs` call.
+    pub max_block_range: u64,
+
+    /// Automatic withdrawals from the PaymentProcessor.
+    pub treasury: TreasuryConfig,
+
+    /// Restricted access for addresses without a subscription.
+    pub trial: TrialConfig,
+
+    /// Subscriptions shared with an owner's member addresses.
+    pub orgs: OrgConfig,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+/// Sweeping of PaymentProcessor revenue to a treasury address.
+///
+/// The proxy wallet must own the PaymentProcessor to call `withdraw`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TreasuryConfig {
+    /// Run the sweeper.
+    pub enabled: bool,
+
+    /// Address receiving the swept funds.
+    pub address: String,
+
+    /// Contract balance (wei) at which the whole balance is swept.
+    pub threshold: String,
+
+    /// How often the contract balance is checked, in seconds.
+    pub interval_secs: u64,
+
+    /// Most gas the `withdraw` transaction may use. Sweeps estimated to
+    /// need more are not sent.
+    pub gas_limit: u64,
+
+    /// How long to wait for a sweep to confirm before reporting it as
+    /// unconfirmed, in seconds.
+    pub confirmation_timeout_secs: u64,
+}
+
+impl Default for TreasuryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::new(),
+            threshold: "1000000000000000000".to_string(), // 1 ether
+            interval_secs: 3600,
+            gas_limit: 100_000,
+            confirmation_timeout_secs: 600,
+        }
+    }
+}
+
+/// Free trial for addresses that have no subscription.
+///
+/// Trial users prove they own their address by signing
+/// `"Seidar access for <address> at <timestamp>"` (EIP-191) and are served
+/// under the QoS policy of `tier_id`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TrialConfig {
+    /// Admit unknown addresses as trial users.
+    pub enabled: bool,
+
+    /// Tier whose `qos.tiers` policy (rate and connection limits) applies
+    /// to trial users.
+    pub tier_id: u8,
+
+    /// Requests per address over its lifetime. 0 disables the cap.
+    pub lifetime_requests: u64,
+
+    /// Requests per address per UTC day. 0 disables the cap.
+    pub daily_requests: u64,
+
+    /// Path prefixes trial users may request. Empty allows every route.
+    pub allowed_paths: Vec<String>,
+
+    /// Distinct trial addresses a single IP may use. 0 disables the cap.
+    pub max_addresses_per_ip: usize,
+
+    /// Trial requests per IP per UTC day, across addresses. 0 disables the
+    /// cap.
+    pub daily_requests_per_ip: u64,
+
+    /// How old a signed timestamp may be, in seconds.
+    pub signature_max_age_secs: u64,
+
+    /// Header a trusted load balancer in front of the proxy puts the client
+    /// IP in, e.g. `X-Forwarded-For`, whose last entry is used. Only set it
+    /// if every request passes that load balancer. Empty uses the peer
+    /// address.
+    pub client_ip_header: String,
+
+    /// Database of trial usage, so restarts do not reset it. Empty keeps
+    /// usage in memory.
+    pub store_path: String,
+}
+
+impl Default for TrialConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tier_id: 0,
+            lifetime_requests: 1000,
+            daily_requests: 100,
+            allowed_paths: Vec::new(),
+            max_addresses_per_ip: 3,
+            daily_requests_per_ip: 500,
+            signature_max_age_secs: 300,
+            client_ip_header: String::new(),
+            store_path: "trials.redb".to_string(),
+        }
+    }
+}
+
+/// Organizations: an owner's subscription also grants access to the
+/// owner's members.
+///
+/// Owners change their member list by signing
+/// `"Seidar org <add|remove> <member> for <owner> at <timestamp>"` (EIP-191).
+/// New members sign the same message, and members may sign their own removal.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
+pub struct OrgConfig {
+    /// Resolve members to their owner's subscription.
+    pub enabled: bool,
+
+    /// Members per organization, besides the owner.
+    pub max_members: usize,
+
+    /// How old a signed timestamp may be, in seconds.
+    pub signature_max_age_secs: u64,
+
+    /// Database of member lists, request counts and used signatures. Empty
+    /// keeps them in memory.
+    pub store_path: String,
+}
+
+impl Default for OrgConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_members: 25,
+            signature_max_age_secs: 300,
+            store_path: "orgs.redb".to_string(),
+        }
+    }
+}
+
+/// Quality-of-service configuration: one policy per subscription tier.
+///
+/// The pre-map flat fields (`tier_1_rps` ... `tier_3_max_conns`) are still
+/// accepted and patch tiers 1-3.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(from = "QosConfigRepr")]
 pub struct QosConfig {
-    pub tier_1_rps: u64,
-    pub tier_2_rps: u64,
-    pub tier_3_rps: u64,
-    pub tier_1_max_conns: usize,
-    pub tier_2_max_conns: usize,
-    pub tier_3_max_conns: usize,
+    /// Policy per tier id.
+    #[serde(with = "tier_map")]
+    pub tiers: BTreeMap<u8, TierPolicy>,
+
+    /// Tier whose policy applies to subscribers of a tier without one.
+    /// When unset such subscribers are rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_tier: Option<u8>,
+
+    /// Maximum number of requests proxied concurrently across all users.
+    /// When saturated, waiting requests are admitted by tier priority.
+    /// 0 disables the limit.
+    pub max_in_flight: usize,
+}
+
+/// Limits and scheduling settings for one subscription tier.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TierPolicy {
+    /// Sustained requests per second.
+    pub rps: u64,
+
+    /// Token bucket capacity. Defaults to twice `rps`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u64>,
+
+    /// Maximum concurrent long-lived connections (WebSocket/SSE).
+    pub max_conns: usize,
+
+    /// Maximum requests in flight per subscriber. Unlimited when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<usize>,
+
+    /// Maximum request body size in bytes. Defaults to `security.max_body_size`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_size: Option<usize>,
+
+    /// Scheduling priority under `max_in_flight` saturation (higher first).
+    #[serde(default)]
+    pub priority: u8,
+}
+
+impl TierPolicy {
+    /// Effective token bucket capacity.
+    pub fn burst(&self) -> u64 {
+        self.burst.unwrap_or(self.rps * 2)
+    }
+}
+
+impl QosConfig {
+    /// Policy for a tier, falling back to `fallback_tier` for unknown ids.
+    pub fn policy(&self, tier_id: u8) -> Option<&TierPolicy> {
+        self.tiers
+            .get(&tier_id)
+            .or_else(|| self.fallback_tier.and_then(|id| self.tiers.get(&id)))
+    }
 }
 
 impl Default for QosConfig {
     fn default() -> Self {
+        let tier = |rps: u64, max_conns: usize, max_concurrent_requests: usize, priority: u8| TierPolicy {
+            rps,
+            burst: None,
+            max_conns,
+            max_concurrent_requests: Some(max_concurrent_requests),
+            max_body_size: None,
+            priority,
+        };
         Self {
-            tier_1_rps: 10,
-            tier_2_rps: 100,
-            tier_3_rps: 1000,
-            tier_1_max_conns: 1,
-            tier_2_max_conns: 10,
-            tier_3_max_conns: 1000,
+            tiers: BTreeMap::from([
+                (1, tier(10, 1, 10, 0)),
+                (2, tier(100, 10, 100, 1)),
+                (3, tier(1000, 1000, 1000, 2)),
+            ]),
+            fallback_tier: None,
+            max_in_flight: 0,
+        }
+    }
+}
+
+/// (De)serialize tier-keyed maps with string keys, as TOML tables require.
+pub(crate) mod tier_map {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S, V>(map: &BTreeMap<u8, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        V: Serialize,
+    {
+        map.iter()
+            .map(|(id, v)| (id.to_string(), v))
+            .collect::<BTreeMap<_, _>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, V>(deserializer: D) -> Result<BTreeMap<u8, V>, D::Error>
+    where
+        D: Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        BTreeMap::<String, V>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(id, v)| {
+                id.parse::<u8>()
+                    .map(|id| (id, v))
+                    .map_err(|_| D::Error::custom(format!("invalid tier id '{}'", id)))
+            })
+            .collect()
+    }
+
+    pub fn deserialize_opt<'de, D, V>(deserializer: D) -> Result<Option<BTreeMap<u8, V>>, D::Error>
+    where
+        D: Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        deserialize(deserializer).map(Some)
+    }
+}
+
+/// Wire format of [`QosConfig`], accepting the legacy flat fields.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct QosConfigRepr {
+    #[serde(deserialize_with = "tier_map::deserialize_opt")]
+    tiers: Option<BTreeMap<u8, TierPolicy>>,
+    fallback_tier: Option<u8>,
+    max_in_flight: usize,
+    tier_1_rps: Option<u64>,
+    tier_2_rps: Option<u64>,
+    tier_3_rps: Option<u64>,
+    tier_1_max_conns: Option<usize>,
+    tier_2_max_conns: Option<usize>,
+    tier_3_max_conns: Option<usize>,
+}
+
+impl From<QosConfigRepr> for QosConfig {
+    fn from(repr: QosConfigRepr) -> Self {
+        let defaults = QosConfig::default();
+        let mut tiers = repr.tiers.unwrap_or_else(|| defaults.tiers.clone());
+
+        let legacy = [
+            (1, repr.tier_1_rps, repr.tier_1_max_conns),
+            (2, repr.tier_2_rps, repr.tier_2_max_conns),
+            (3, repr.tier_3_rps, repr.tier_3_max_conns),
+        ];
+        for (id, rps, max_conns) in legacy {
+            if rps.is_none() && max_conns.is_none() {
+                continue;
+            }
+            let policy = tiers
+                .entry(id)
+                .or_insert_with(|| defaults.tiers[&id].clone());
+            if let Some(rps) = rps {
+                policy.rps = rps;
+            }
+            if let Some(max_conns) = max_conns {
+                policy.max_conns = max_conns;
+            }
+        }
+
+        Self {
+            tiers,
+            fallback_tier: repr.fallback_tier,
+            max_in_flight: repr.max_in_flight,
         }
     }
 }
@@ -374,23 +990,129 @@ impl Default for PaymentConfig {
         Self {
             enabled: false,
             contract_address: String::new(),
+            subscription_manager_address: String::new(),
+            tier_ids: vec![1, 2, 3],
             monitor_interval_ms: 10000,
             grace_period_secs: 300, // 5 minutes default grace
+            subscription_headers: false,
+            state_path: "subscriptions.json".to_string(),
+            billing_path: "billing.redb".to_string(),
+            start_block: 0,
+            max_block_range: 2000,
+            treasury: TreasuryConfig::default(),
+            trial: TrialConfig::default(),
+            orgs: OrgConfig::default(),
+        }
+    }
+}
+
+/// An additional chain on which subscriptions can be paid.
+///
+/// Each chain has its own RPC client and payment monitor feeding the shared
+/// subscription cache. RPC timeout and gas limits come from `[blockchain]`;
+/// tier ids, polling interval and block range come from `[payments]`. Tier
+/// pricing is loaded from the primary chain only, so it applies on chains
+/// that set `same_native_currency`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChainConfig {
+    /// Name used in logs.
+    pub name: String,
+
+    /// Chain ID; must differ from `blockchain.chain_id` and other chains.
+    pub chain_id: u64,
+
+    /// JSON-RPC endpoint URL.
+    pub rpc_url: String,
+
+    /// Failover JSON-RPC endpoint URLs.
+    #[serde(default)]
+    pub failover_urls: Vec<String>,
+
+    /// Optional WebSocket JSON-RPC endpoints for push-based payment detection.
+    #[serde(default)]
+    pub ws_urls: Vec<String>,
+
+    /// Number of block confirmations required for finality.
+    #[serde(default = "default_confirmation_blocks")]
+    pub confirmation_blocks: u32,
+
+    /// Address of the PaymentProcessor contract on this chain.
+    pub contract_address: String,
+
+    /// First block to scan when backfilling this chain.
+    #[serde(default)]
+    pub start_block: u64,
+
+    /// Whether the chain's native currency is the primary chain's (e.g. a
+    /// rollup paying in ETH), so native prices apply here as they are.
+    /// Otherwise services priced in native currency are not quoted on this
+    /// chain, and payments without a quote are credited the duration its
+    /// PaymentProcessor accepted.
+    #[serde(default)]
+    pub same_native_currency: bool,
+}
+
+fn default_confirmation_blocks() -> u32 {
+    BlockchainConfig::default().confirmation_blocks
+}
+
+impl ChainConfig {
+    /// Client settings for this chain, taking the rest from `base`.
+    pub fn blockchain(&self, base: &BlockchainConfig) -> BlockchainConfig {
+        BlockchainConfig {
+            enabled: true,
+            rpc_url: self.rpc_url.clone(),
+            failover_urls: self.failover_urls.clone(),
+            ws_urls: self.ws_urls.clone(),
+            chain_id: self.chain_id,
+            confirmation_blocks: self.confirmation_blocks,
+            ..base.clone()
+        }
+    }
+
+    /// Monitor settings for this chain, taking the rest from `base`.
+    pub fn payments(&self, base: &PaymentConfig) -> PaymentConfig {
+        PaymentConfig {
+            contract_address: self.contract_address.clone(),
+            subscription_manager_address: String::new(),
+            start_block: self.start_block,
+            treasury: TreasuryConfig::default(),
+            ..base.clone()
         }
     }
 }
 
+impl ProxyConfig {
+    /// Chains whose native currency differs from the primary chain's.
+    pub fn unpriced_chains(&self) -> BTreeSet<u64> {
+        self.chains.iter().filter(|c| !c.same_native_currency).map(|c| c.chain_id).collect()
+    }
+
+    /// Client and monitor settings of every chain payments are accepted on,
+    /// the primary chain first.
+    pub fn payment_chains(&self) -> Vec<(BlockchainConfig, PaymentConfig)> {
+        std::iter::once((self.blockchain.clone(), self.payments.clone()))
+            .chain(self.chains.iter().map(|c| (c.blockchain(&self.blockchain), c.payments(&self.payments))))
+            .collect()
+    }
+}
+
 impl Default for BlockchainConfig {
     fn default() -> Self {
         Self {
             enabled: false,
             rpc_url: "http://localhost:8545".to_string(),
             failover_urls: Vec::new(),
+            ws_urls: Vec::new(),
             chain_id: 1,
             rpc_timeout_secs: 10,
             confirmation_blocks: 3,
             gas_price_multiplier: 1.2,
             max_gas_price_gwei: 500,
+            gas_limit_multiplier: 1.2,
+            transactions: TransactionConfig::default(),
+            remote_signer: RemoteSignerConfig::default(),
+            pool: RpcPoolConfig::default(),
         }
     }
 }
@@ -416,3 +1138,325 @@ impl Default for SecurityConfig {
         }
     }
 }
+
+/// Price book for the quote engine.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PricingConfig {
+    /// Quotable services keyed by service type identifier
+    /// (the `service_type` of a quote request).
+    pub services: BTreeMap<String, ServicePrice>,
+
+    /// Price feed converting `amount_usd` prices. Required if any service
+    /// is priced in USD.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_feed: Option<PriceFeedConfig>,
+
+    /// How quotes are signed. `legacy` keeps the pre-EIP-712 hash for
+    /// clients that have not migrated yet.
+    pub signature_scheme: SignatureScheme,
+
+    /// Also accept quotes carrying the legacy hash when verifying, while
+    /// clients migrate to EIP-712. Off by default; implied when
+    /// `signature_scheme` is `legacy`.
+    pub accept_legacy_signatures: bool,
+
+    /// Where issued quotes are kept. Read at startup only.
+    pub quote_store: QuoteStoreConfig,
+
+    /// Promotional codes keyed by code (matched case-insensitively).
+    /// More can be added at runtime through the admin API.
+    pub promo_codes: BTreeMap<String, PromoCode>,
+}
+
+/// A discount clients can claim by passing its code in a quote request.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PromoCode {
+    /// Percentage off in basis points (100 = 1%). Mutually exclusive with
+    /// `discount_amount`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discount_bps: Option<u16>,
+
+    /// Fixed amount off, in the smallest unit of the quoted currency.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discount_amount: Option<String>,
+
+    /// Service types the code applies to. Empty means every service.
+    pub services: Vec<String>,
+
+    /// Unix time the code becomes valid. 0 means immediately.
+    pub valid_from: u64,
+
+    /// Unix time the code stops being valid. 0 means never.
+    pub valid_until: u64,
+
+    /// Redemptions allowed in total, counting unpaid quotes. 0 means
+    /// unlimited.
+    pub max_redemptions: u64,
+
+    /// Redemptions allowed per address, counting unpaid quotes. 0 means
+    /// unlimited.
+    pub max_per_address: u64,
+}
+
+/// Storage for issued quotes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QuoteStoreConfig {
+    /// Database file for issued quotes, relative to the config file. Empty
+    /// keeps quotes in memory only.
+    pub path: String,
+
+    /// Most quotes kept at once, including expired quotes awaiting eviction.
+    pub max_quotes: usize,
+
+    /// Most unpaid, unexpired quotes a single user may hold.
+    pub max_outstanding_per_user: usize,
+}
+
+impl Default for QuoteStoreConfig {
+    fn default() -> Self {
+        Self {
+            path: "quotes.redb".to_string(),
+            max_quotes: 100_000,
+            max_outstanding_per_user: 20,
+        }
+    }
+}
+
+/// Quote signature format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureScheme {
+    /// EIP-712 typed data bound to the chain id and PaymentProcessor.
+    #[default]
+    Eip712,
+    /// Keccak of concatenated quote fields (deprecated).
+    Legacy,
+}
+
+/// Chainlink-style aggregator quoting the payment currency in USD.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PriceFeedConfig {
+    /// Aggregator contract exposing `latestRoundData` and `decimals`.
+    pub aggregator_address: String,
+
+    /// Reject rounds older than this many seconds.
+    pub max_staleness_secs: u64,
+
+    /// Reject rounds moving more than this from the last accepted price,
+    /// in basis points, while that price is still fresh.
+    pub max_deviation_bps: u32,
+
+    /// Decimals of the payment currency (18 for ETH).
+    pub token_decimals: u8,
+
+    /// Reuse an accepted price for this many seconds before re-reading.
+    pub refresh_secs: u64,
+}
+
+impl Default for PriceFeedConfig {
+    fn default() -> Self {
+        Self {
+            aggregator_address: String::new(),
+            max_staleness_secs: 3600,
+            max_deviation_bps: 1000, // 10%
+            token_decimals: 18,
+            refresh_secs: 60,
+        }
+    }
+}
+
+/// Price and quoting rules for one service type.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServicePrice {
+    /// Subscription tier sold by this service, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tier_id: Option<u8>,
+
+    /// Price as a decimal integer in the smallest unit of the currency.
+    /// Subscription services without an amount use the on-chain tier price.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<String>,
+
+    /// Price in USD as a decimal (e.g. "4.99"), converted to `currency` at
+    /// quote time through `pricing.price_feed`. Mutually exclusive with
+    /// `amount`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_usd: Option<String>,
+
+    /// Currency symbol reported in quotes (e.g. "ETH", "USDC").
+    pub currency: String,
+
+    /// ERC-20 token the amount is denominated in. Native currency when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_address: Option<String>,
+
+    /// Minimum requested duration in seconds.
+    pub min_duration_secs: u64,
+
+    /// Maximum requested duration in seconds. 0 means the service does not
+    /// take a duration.
+    pub max_duration_secs: u64,
+
+    /// How long an issued quote stays valid, in seconds.
+    pub quote_validity_secs: u64,
+
+    /// Discounts for longer subscription commitments. The entry with the
+    /// largest `min_duration_secs` not above the requested duration applies.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volume_discounts: Vec<VolumeDiscount>,
+}
+
+/// Price reduction for subscriptions of at least a given duration.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VolumeDiscount {
+    /// Minimum subscribed duration in seconds.
+    pub min_duration_secs: u64,
+    /// Discount in basis points (100 = 1%).
+    pub discount_bps: u16,
+}
+
+impl Default for ServicePrice {
+    fn default() -> Self {
+        Self {
+            tier_id: None,
+            amount: None,
+            amount_usd: None,
+            currency: "ETH".to_string(),
+            token_address: None,
+            min_duration_secs: 0,
+            max_duration_secs: 0,
+            quote_validity_secs: 3600,
+            volume_discounts: Vec::new(),
+        }
+    }
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        let subscription = |tier_id: u8| ServicePrice {
+            tier_id: Some(tier_id),
+            min_duration_secs: 3600,           // 1 hour
+            max_duration_secs: 365 * 24 * 3600, // 1 year
+            ..ServicePrice::default()
+        };
+        Self {
+            services: BTreeMap::from([
+                ("subscription_tier1".to_string(), subscription(1)),
+                ("subscription_tier2".to_string(), subscription(2)),
+                ("proof_generation".to_string(), ServicePrice {
+                    amount: Some("1000000000000000".to_string()), // 0.001 ETH
+                    ..ServicePrice::default()
+                }),
+            ]),
+            price_feed: None,
+            signature_scheme: SignatureScheme::default(),
+            accept_legacy_signatures: false,
+            quote_store: QuoteStoreConfig::default(),
+            promo_codes: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qos_tier_map_roundtrip() {
+        let config = ProxyConfig::default();
+        let text = toml::to_string_pretty(&config).unwrap();
+        let parsed: ProxyConfig = toml::from_str(&text).unwrap();
+        assert_eq!(parsed.qos, config.qos);
+    }
+
+    #[test]
+    fn test_qos_tier_map() {
+        let qos: QosConfig = toml::from_str(r#"
+            fallback_tier = 1
+            max_in_flight = 64
+
+            [tiers.1]
+            rps = 5
+            max_conns = 1
+
+            [tiers.7]
+            rps = 500
+            burst = 600
+            max_conns = 50
+            max_concurrent_requests = 20
+            max_body_size = 10485760
+            priority = 9
+        "#).unwrap();
+
+        assert_eq!(qos.tiers.len(), 2);
+        assert_eq!(qos.tiers[&1].burst(), 10);
+        assert_eq!(qos.tiers[&7].burst(), 600);
+        assert_eq!(qos.tiers[&7].priority, 9);
+        // Unknown tiers use the fallback
+        assert_eq!(qos.policy(3).unwrap().rps, 5);
+        assert_eq!(qos.max_in_flight, 64);
+    }
+
+    #[test]
+    fn test_price_book() {
+        let pricing: PricingConfig = toml::from_str(r#"
+            [services.subscription_tier1]
+            tier_id = 1
+
+            [[services.subscription_tier1.volume_discounts]]
+            min_duration_secs = 7776000
+            discount_bps = 500
+
+            [services.monthly]
+            tier_id = 2
+            amount_usd = "5"
+
+            [price_feed]
+            aggregator_address = "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419"
+            max_staleness_secs = 7200
+
+            [services.api_credits]
+            amount = "2500000"
+            currency = "USDC"
+            token_address = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+            quote_validity_secs = 600
+        "#).unwrap();
+
+        let credits = &pricing.services["api_credits"];
+        assert_eq!(credits.amount.as_deref(), Some("2500000"));
+        assert_eq!(credits.currency, "USDC");
+        assert_eq!(credits.quote_validity_secs, 600);
+        assert_eq!(credits.max_duration_secs, 0);
+        // A configured price book replaces the defaults
+        assert_eq!(pricing.services.len(), 3);
+        assert_eq!(pricing.services["monthly"].amount_usd.as_deref(), Some("5"));
+        let feed = pricing.price_feed.as_ref().unwrap();
+        assert_eq!((feed.max_staleness_secs, feed.token_decimals), (7200, 18));
+        let discounts = &pricing.services["subscription_tier1"].volume_discounts;
+        assert_eq!(discounts, &vec![VolumeDiscount { min_duration_secs: 7776000, discount_bps: 500 }]);
+
+        let defaults = PricingConfig::default();
+        assert_eq!(defaults.services["subscription_tier2"].tier_id, Some(2));
+        assert_eq!(defaults.services["proof_generation"].quote_validity_secs, 3600);
+    }
+
+    #[test]
+    fn test_qos_legacy_fields() {
+        let qos: QosConfig = toml::from_str(r#"
+            tier_1_rps = 3
+            tier_3_max_conns = 7
+        "#).unwrap();
+
+        assert_eq!(qos.tiers[&1].rps, 3);
+        assert_eq!(qos.tiers[&1].max_conns, 1);
+        assert_eq!(qos.tiers[&2], QosConfig::default().tiers[&2]);
+        assert_eq!(qos.tiers[&3].rps, 1000);
+        assert_eq!(qos.tiers[&3].max_conns, 7);
+        assert!(qos.policy(4).is_none());
+    }
+}
@@ -1,6 +1,6 @@
 //! Configuration validation logic.
 
-use crate::config::schema::ProxyConfig;
+use crate::config::schema::{PricingConfig, PromoCode, ProxyConfig};
 use std::collections::HashSet;
 
 /// Error type for configuration validation failures.
@@ -42,7 +42,295 @@ pub fn validate_config(config: &ProxyConfig) -> Result<(), Vec<ValidationError>>
         errors.push(ValidationError("retries.budget_ratio must be between 0.0 and 1.0".to_string()));
     }
 
-    // 4. Validate timeouts (basic check)
+    if config.blockchain.enabled {
+        // EIP-712 quote and invoice signatures are bound to the contract
+        if config.payments.contract_address.parse::<alloy::primitives::Address>().is_err() {
+            errors.push(ValidationError("payments.contract_address must be a valid address".to_string()));
+        }
+        if config.blockchain.gas_price_multiplier <= 0.0 {
+            errors.push(ValidationError("blockchain.gas_price_multiplier must be > 0".to_string()));
+        }
+        if config.blockchain.gas_limit_multiplier < 1.0 {
+            errors.push(ValidationError("blockchain.gas_limit_multiplier must be at least 1.0".to_string()));
+        }
+        let transactions = &config.blockchain.transactions;
+        if transactions.fee_bump_percent < 10 {
+            errors.push(ValidationError("blockchain.transactions.fee_bump_percent must be at least 10".to_string()));
+        }
+        if transactions.stuck_after_secs == 0 || transactions.check_interval_secs == 0 {
+            errors.push(ValidationError(
+                "blockchain.transactions.stuck_after_secs and check_interval_secs must be > 0".to_string(),
+            ));
+        }
+        let pool = &config.blockchain.pool;
+        if pool.eject_after_failures == 0 {
+            errors.push(ValidationError("blockchain.pool.eject_after_failures must be > 0".to_string()));
+        }
+        if !(pool.hedge_percentile > 0.0 && pool.hedge_percentile <= 100.0) {
+            errors.push(ValidationError("blockchain.pool.hedge_percentile must be in (0, 100]".to_string()));
+        }
+    }
+
+    let signer = &config.blockchain.remote_signer;
+    if !signer.url.is_empty() {
+        // The bearer token must not cross the network in the clear
+        match url::Url::parse(&signer.url) {
+            Ok(url) if url.scheme() == "https" => {}
+            Ok(url) if url.scheme() == "http" && is_loopback(&url) => {}
+            Ok(url) if url.scheme() == "http" => errors.push(ValidationError(
+                "blockchain.remote_signer.url must use https unless the signer runs on this host".to_string(),
+            )),
+            _ => errors.push(ValidationError("blockchain.remote_signer.url must be an http(s) URL".to_string())),
+        }
+        if signer.address.parse::<alloy::primitives::Address>().is_err() {
+            errors.push(ValidationError("blockchain.remote_signer.address must be a valid address".to_string()));
+        }
+        if signer.timeout_secs == 0 || signer.health_check_interval_secs == 0 {
+            errors.push(ValidationError(
+                "blockchain.remote_signer.timeout_secs and health_check_interval_secs must be > 0".to_string(),
+            ));
+        }
+        if signer.client_cert_path.is_empty() != signer.client_key_path.is_empty() {
+            errors.push(ValidationError(
+                "blockchain.remote_signer.client_cert_path and client_key_path must be set together".to_string(),
+            ));
+        }
+    }
+
+    // 4. Validate payment monitor settings
+    if config.payments.enabled {
+        if config.payments.max_block_range == 0 {
+            errors.push(ValidationError("payments.max_block_range must be > 0".to_string()));
+        }
+        if config.payments.state_path.is_empty() {
+            errors.push(ValidationError("payments.state_path must not be empty".to_string()));
+        }
+        for url in &config.blockchain.ws_urls {
+            if !(url.starts_with("ws://") || url.starts_with("wss://")) {
+                errors.push(ValidationError(format!(
+                    "blockchain.ws_urls entry '{}' must use ws:// or wss://",
+                    url
+                )));
+            }
+        }
+        let mut chain_ids = HashSet::from([config.blockchain.chain_id]);
+        for chain in &config.chains {
+            if !chain_ids.insert(chain.chain_id) {
+                errors.push(ValidationError(format!("chains.{}: chain_id {} is used twice", chain.name, chain.chain_id)));
+            }
+            if chain.rpc_url.is_empty() {
+                errors.push(ValidationError(format!("chains.{}: rpc_url must not be empty", chain.name)));
+            }
+            if chain.contract_address.parse::<alloy::primitives::Address>().is_err() {
+                errors.push(ValidationError(format!("chains.{}: contract_address must be a valid address", chain.name)));
+            }
+            if chain.ws_urls.iter().any(|url| !(url.starts_with("ws://") || url.starts_with("wss://"))) {
+                errors.push(ValidationError(format!("chains.{}: ws_urls must use ws:// or wss://", chain.name)));
+            }
+        }
+        let treasury = &config.payments.treasury;
+        if treasury.enabled {
+            if treasury.address.parse::<alloy::primitives::Address>().is_err() {
+                errors.push(ValidationError("payments.treasury.address must be a valid address".to_string()));
+            }
+            if alloy::primitives::U256::from_str_radix(&treasury.threshold, 10).is_err() {
+                errors.push(ValidationError("payments.treasury.threshold must be a base-10 wei amount".to_string()));
+            }
+            if treasury.interval_secs == 0 || treasury.gas_limit < 21_000 {
+                errors.push(ValidationError(
+                    "payments.treasury: interval_secs must be > 0 and gas_limit at least 21000".to_string(),
+                ));
+            }
+        }
+    }
+
+    // 5. Validate QoS tiers and references to them
+    for (id, policy) in &config.qos.tiers {
+        if policy.rps == 0 || policy.burst() == 0 {
+            errors.push(ValidationError(format!("qos.tiers.{}: rps and burst must be > 0", id)));
+        }
+        if policy.max_concurrent_requests == Some(0) || policy.max_body_size == Some(0) {
+            errors.push(ValidationError(format!(
+                "qos.tiers.{}: max_concurrent_requests and max_body_size must be > 0 when set",
+                id
+            )));
+        }
+    }
+    if let Some(id) = config.qos.fallback_tier {
+        if !config.qos.tiers.contains_key(&id) {
+            errors.push(ValidationError(format!("qos.fallback_tier references undefined tier {}", id)));
+        }
+    }
+    for id in &config.payments.tier_ids {
+        if config.qos.policy(*id).is_none() {
+            errors.push(ValidationError(format!("payments.tier_ids references undefined QoS tier {}", id)));
+        }
+    }
+    let trial = &config.payments.trial;
+    if config.payments.enabled && trial.enabled {
+        if config.qos.policy(trial.tier_id).is_none() {
+            errors.push(ValidationError(format!("payments.trial.tier_id references undefined QoS tier {}", trial.tier_id)));
+        }
+        if trial.signature_max_age_secs == 0 {
+            errors.push(ValidationError("payments.trial.signature_max_age_secs must be > 0".to_string()));
+        }
+        if trial.allowed_paths.iter().any(|p| !p.starts_with('/')) {
+            errors.push(ValidationError("payments.trial.allowed_paths entries must start with '/'".to_string()));
+        }
+    }
+    let orgs = &config.payments.orgs;
+    if config.payments.enabled && orgs.enabled && (orgs.max_members == 0 || orgs.signature_max_age_secs == 0) {
+        errors.push(ValidationError(
+            "payments.orgs: max_members and signature_max_age_secs must be > 0".to_string(),
+        ));
+    }
+
+    // 6. Validate the price book
+    for (name, service) in &config.pricing.services {
+        match (&service.amount, &service.amount_usd) {
+            (Some(_), Some(_)) => {
+                errors.push(ValidationError(format!(
+                    "pricing.services.{}: set either amount or amount_usd, not both",
+                    name
+                )));
+            }
+            (Some(amount), None) if alloy::primitives::U256::from_str_radix(amount, 10).is_err() => {
+                errors.push(ValidationError(format!(
+                    "pricing.services.{}: amount '{}' is not a decimal integer",
+                    name, amount
+                )));
+            }
+            (None, Some(usd)) => {
+                if crate::quoting::oracle::parse_usd(usd).is_none() {
+                    errors.push(ValidationError(format!(
+                        "pricing.services.{}: amount_usd '{}' is not a USD amount",
+                        name, usd
+                    )));
+                }
+                if config.pricing.price_feed.is_none() {
+                    errors.push(ValidationError(format!(
+                        "pricing.services.{}: amount_usd requires pricing.price_feed",
+                        name
+                    )));
+                }
+            }
+            (None, None) if service.tier_id.is_none() => {
+                errors.push(ValidationError(format!(
+                    "pricing.services.{}: amount is required for non-subscription services",
+                    name
+                )));
+            }
+            _ => {}
+        }
+        if let Some(token) = &service.token_address {
+            if token.parse::<alloy::primitives::Address>().is_err() {
+                errors.push(ValidationError(format!(
+                    "pricing.services.{}: invalid token_address '{}'",
+                    name, token
+                )));
+            }
+        }
+        if service.max_duration_secs != 0 && service.min_duration_secs > service.max_duration_secs {
+            errors.push(ValidationError(format!(
+                "pricing.services.{}: min_duration_secs exceeds max_duration_secs",
+                name
+            )));
+        }
+        if !service.volume_discounts.is_empty() && service.tier_id.is_none() {
+            errors.push(ValidationError(format!(
+                "pricing.services.{}: volume_discounts only apply to subscription services",
+                name
+            )));
+        }
+        if service.volume_discounts.iter().any(|d| d.discount_bps > 10_000) {
+            errors.push(ValidationError(format!(
+                "pricing.services.{}: discount_bps must be <= 10000",
+                name
+            )));
+        }
+        if service.quote_validity_secs == 0 {
+            errors.push(ValidationError(format!(
+                "pricing.services.{}: quote_validity_secs must be > 0",
+                name
+            )));
+        }
+    }
+
+    if let Some(feed) = &config.pricing.price_feed {
+        if feed.aggregator_address.parse::<alloy::primitives::Address>().is_err() {
+            errors.push(ValidationError(format!(
+                "pricing.price_feed: invalid aggregator_address '{}'",
+                feed.aggregator_address
+            )));
+        }
+        if feed.max_staleness_secs == 0 {
+            errors.push(ValidationError("pricing.price_feed.max_staleness_secs must be > 0".to_string()));
+        }
+    }
+    let mut sold_tiers = std::collections::HashSet::new();
+    for service in config.pricing.services.values() {
+        if let Some(id) = service.tier_id {
+            if !sold_tiers.insert(id) {
+                errors.push(ValidationError(format!(
+                    "pricing.services: tier {} is sold by more than one service",
+                    id
+                )));
+            }
+        }
+    }
+    for (code, promo) in &config.pricing.promo_codes {
+        errors.extend(validate_promo_code(code, promo, &config.pricing));
+    }
+    if config.pricing.quote_store.max_quotes == 0 || config.pricing.quote_store.max_outstanding_per_user == 0 {
+        errors.push(ValidationError(
+            "pricing.quote_store: max_quotes and max_outstanding_per_user must be > 0".to_string(),
+        ));
+    }
+
+    // 7. Validate SLA credits
+    if config.sla.enabled {
+        if config.sla.window_secs == 0 || config.sla.sample_interval_secs == 0 {
+            errors.push(ValidationError("sla: window_secs and sample_interval_secs must be > 0".to_string()));
+        }
+        if config.sla.ledger_path.is_empty() {
+            errors.push(ValidationError("sla.ledger_path must not be empty".to_string()));
+        }
+        for (id, policy) in &config.sla.tiers {
+            if policy.target_bps == 0 || policy.target_bps > 10_000 {
+                errors.push(ValidationError(format!("sla.tiers.{}: target_bps must be between 1 and 10000", id)));
+            }
+            if config.qos.policy(*id).is_none() {
+                errors.push(ValidationError(format!("sla.tiers references undefined QoS tier {}", id)));
+            }
+        }
+    }
+
+    // 8. Validate webhooks
+    if config.webhooks.enabled {
+        let webhooks = &config.webhooks;
+        if webhooks.max_attempts == 0 || webhooks.scan_interval_secs == 0 || webhooks.timeout_secs == 0 {
+            errors.push(ValidationError(
+                "webhooks: max_attempts, scan_interval_secs and timeout_secs must be > 0".to_string(),
+            ));
+        }
+        let mut names = HashSet::new();
+        for endpoint in &webhooks.endpoints {
+            if endpoint.name.is_empty() || !names.insert(endpoint.name.as_str()) {
+                errors.push(ValidationError(format!(
+                    "webhooks.endpoints: names must be unique and non-empty ('{}')",
+                    endpoint.name
+                )));
+            }
+            if !(endpoint.url.starts_with("http://") || endpoint.url.starts_with("https://")) {
+                errors.push(ValidationError(format!("webhooks.endpoints.{}: url must use http:// or https://", endpoint.name)));
+            }
+            if endpoint.secret.is_empty() {
+                errors.push(ValidationError(format!("webhooks.endpoints.{}: secret must not be empty", endpoint.name)));
+            }
+        }
+    }
+
+    // 9. Validate timeouts (basic check)
     if config.timeouts.connect_secs == 0 && config.timeouts.request_secs == 0 {
         // Technically they could be 0 but likely a mistake
         tracing::warn!("Timeouts are set to 0, matching requests might time out immediately");
@@ -55,6 +343,49 @@ pub fn validate_config(config: &ProxyConfig) -> Result<(), Vec<ValidationError>>
     }
 }
 
+/// Validate a promo code against the price book.
+///
+/// Used for configured codes and codes created through the admin API.
+pub fn validate_promo_code(code: &str, promo: &PromoCode, pricing: &PricingConfig) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    let invalid = |msg: String| ValidationError(format!("pricing.promo_codes.{}: {}", code, msg));
+
+    if code.trim().is_empty() {
+        errors.push(invalid("code must not be empty".to_string()));
+    }
+    match (&promo.discount_bps, &promo.discount_amount) {
+        (Some(_), Some(_)) | (None, None) => {
+            errors.push(invalid("set exactly one of discount_bps or discount_amount".to_string()));
+        }
+        (Some(bps), None) if *bps == 0 || *bps > 10_000 => {
+            errors.push(invalid("discount_bps must be between 1 and 10000".to_string()));
+        }
+        (None, Some(amount)) if alloy::primitives::U256::from_str_radix(amount, 10).is_err() => {
+            errors.push(invalid(format!("discount_amount '{}' is not a decimal integer", amount)));
+        }
+        _ => {}
+    }
+    for service in &promo.services {
+        if !pricing.services.contains_key(service) {
+            errors.push(invalid(format!("unknown service '{}'", service)));
+        }
+    }
+    if promo.valid_until != 0 && promo.valid_until <= promo.valid_from {
+        errors.push(invalid("valid_until must be after valid_from".to_string()));
+    }
+    errors
+}
+
+/// Whether `url` points at this host.
+fn is_loopback(url: &url::Url) -> bool {
+    match url.host() {
+        Some(url::Host::Domain(domain)) => domain == "localhost",
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -96,4 +427,242 @@ mod tests {
         assert_eq!(errs.len(), 1);
         assert!(errs[0].0.contains("unknown backend group 'missing'"));
     }
+
+    #[test]
+    fn test_undefined_tier_references() {
+        let mut config = ProxyConfig::default();
+        config.qos.fallback_tier = Some(9);
+        config.payments.tier_ids = vec![1, 4];
+
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[0].0.contains("fallback_tier references undefined tier 9"));
+        assert!(errs[1].0.contains("undefined QoS tier 4"));
+
+        // A valid fallback covers tiers without their own policy
+        config.qos.fallback_tier = Some(1);
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_price_book_validation() {
+        use crate::config::ServicePrice;
+
+        let mut config = ProxyConfig::default();
+        config.pricing.services.insert("bad".to_string(), ServicePrice {
+            amount: Some("0.5".to_string()),
+            min_duration_secs: 100,
+            max_duration_secs: 10,
+            ..ServicePrice::default()
+        });
+        config.pricing.services.insert("free".to_string(), ServicePrice::default());
+        config.pricing.services.insert("usd".to_string(), ServicePrice {
+            amount_usd: Some("4.99".to_string()),
+            ..ServicePrice::default()
+        });
+
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 4);
+        assert!(errs[0].0.contains("not a decimal integer"));
+        assert!(errs[1].0.contains("min_duration_secs exceeds"));
+        assert!(errs[2].0.contains("pricing.services.free: amount is required"));
+        assert!(errs[3].0.contains("amount_usd requires pricing.price_feed"));
+    }
+
+    #[test]
+    fn test_promo_code_validation() {
+        use crate::config::PromoCode;
+
+        let mut config = ProxyConfig::default();
+        config.pricing.promo_codes.insert("SPRING".to_string(), PromoCode {
+            discount_bps: Some(2500),
+            services: vec!["subscription_tier1".to_string()],
+            ..PromoCode::default()
+        });
+        assert!(validate_config(&config).is_ok());
+
+        config.pricing.promo_codes.insert("BAD".to_string(), PromoCode {
+            discount_bps: Some(100),
+            discount_amount: Some("5".to_string()),
+            services: vec!["nope".to_string()],
+            valid_from: 10,
+            valid_until: 5,
+            ..PromoCode::default()
+        });
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 3);
+        assert!(errs[0].0.contains("exactly one"));
+        assert!(errs[1].0.contains("unknown service 'nope'"));
+        assert!(errs[2].0.contains("valid_until"));
+    }
+
+    #[test]
+    fn test_sla_validation() {
+        use crate::config::SlaPolicy;
+
+        let mut config = ProxyConfig::default();
+        config.sla.enabled = true;
+        config.sla.tiers.insert(1, SlaPolicy { target_bps: 9990, credit_bps: 10_000 });
+        assert!(validate_config(&config).is_ok());
+
+        config.sla.tiers.insert(9, SlaPolicy { target_bps: 10_001, credit_bps: 10_000 });
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[0].0.contains("target_bps"));
+        assert!(errs[1].0.contains("undefined QoS tier 9"));
+    }
+
+    #[test]
+    fn test_treasury_validation() {
+        let mut config = ProxyConfig::default();
+        config.payments.enabled = true;
+        config.payments.treasury.enabled = true;
+        config.payments.treasury.address = "0x000000000000000000000000000000000000dEaD".to_string();
+        assert!(validate_config(&config).is_ok());
+
+        config.payments.treasury.address = "treasury".to_string();
+        config.payments.treasury.threshold = "1e18".to_string();
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[0].0.contains("treasury.address"));
+        assert!(errs[1].0.contains("treasury.threshold"));
+    }
+
+    #[test]
+    fn test_webhook_validation() {
+        let mut config = ProxyConfig::default();
+        config.webhooks.enabled = true;
+        let endpoint = crate::config::WebhookEndpoint {
+            name: "crm".to_string(),
+            url: "https://crm.example.com/hooks".to_string(),
+            secret: "whsec".to_string(),
+            events: vec![],
+        };
+        config.webhooks.endpoints = vec![endpoint.clone()];
+        assert!(validate_config(&config).is_ok());
+
+        config.webhooks.endpoints.push(crate::config::WebhookEndpoint {
+            url: "ftp://crm.example.com".to_string(),
+            secret: String::new(),
+            ..endpoint
+        });
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 3);
+        assert!(errs[0].0.contains("unique"));
+        assert!(errs[1].0.contains("url"));
+        assert!(errs[2].0.contains("secret"));
+    }
+
+    #[test]
+    fn test_chain_validation() {
+        let mut config = ProxyConfig::default();
+        config.payments.enabled = true;
+        let chain = ChainConfig {
+            name: "litvm".to_string(),
+            chain_id: 2810,
+            rpc_url: "http://localhost:8546".to_string(),
+            failover_urls: vec![],
+            ws_urls: vec![],
+            confirmation_blocks: 1,
+            contract_address: "0x000000000000000000000000000000000000dEaD".to_string(),
+            start_block: 0,
+            same_native_currency: false,
+        };
+        config.chains = vec![chain.clone()];
+        assert!(validate_config(&config).is_ok());
+
+        config.chains.push(ChainConfig {
+            name: "dup".to_string(),
+            contract_address: "processor".to_string(),
+            ..chain
+        });
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[0].0.contains("used twice"));
+        assert!(errs[1].0.contains("contract_address"));
+    }
+
+    #[test]
+    fn test_gas_validation() {
+        let mut config = ProxyConfig::default();
+        config.blockchain.enabled = true;
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].0.contains("payments.contract_address"));
+        config.payments.contract_address = "0x000000000000000000000000000000000000dEaD".to_string();
+        assert!(validate_config(&config).is_ok());
+
+        config.blockchain.gas_limit_multiplier = 0.9;
+        config.blockchain.transactions.fee_bump_percent = 5;
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[0].0.contains("gas_limit_multiplier"));
+        assert!(errs[1].0.contains("fee_bump_percent"));
+    }
+
+    #[test]
+    fn test_pool_validation() {
+        let mut config = ProxyConfig::default();
+        config.blockchain.enabled = true;
+        config.payments.contract_address = "0x000000000000000000000000000000000000dEaD".to_string();
+        config.blockchain.pool.eject_after_failures = 0;
+        config.blockchain.pool.hedge_percentile = 150.0;
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[0].0.contains("eject_after_failures"));
+        assert!(errs[1].0.contains("hedge_percentile"));
+    }
+
+    #[test]
+    fn test_remote_signer_validation() {
+        let mut config = ProxyConfig::default();
+        config.blockchain.remote_signer.url = "https://signer:9000".to_string();
+        config.blockchain.remote_signer.address = "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266".to_string();
+        assert!(validate_config(&config).is_ok());
+
+        config.blockchain.remote_signer.url = "signer:9000".to_string();
+        config.blockchain.remote_signer.client_cert_path = "client.pem".to_string();
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[0].0.contains("http(s) URL"));
+        assert!(errs[1].0.contains("client_key_path"));
+        // Plain http only to a signer on this host
+        config.blockchain.remote_signer.client_cert_path = String::new();
+        config.blockchain.remote_signer.url = "http://127.0.0.1:9000".to_string();
+        assert!(validate_config(&config).is_ok());
+        config.blockchain.remote_signer.url = "http://localhost:9000".to_string();
+        assert!(validate_config(&config).is_ok());
+        config.blockchain.remote_signer.url = "http://signer:9000".to_string();
+        let errs = validate_config(&config).unwrap_err();
+        assert!(errs[0].0.contains("must use https"));
+    }
+
+    #[test]
+    fn test_trial_validation() {
+        let mut config = ProxyConfig::default();
+        config.payments.enabled = true;
+        config.payments.trial.enabled = true;
+        config.payments.trial.tier_id = 1;
+        assert!(validate_config(&config).is_ok());
+
+        config.payments.trial.tier_id = 9;
+        config.payments.trial.allowed_paths = vec!["api/".to_string()];
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[0].0.contains("trial.tier_id"));
+        assert!(errs[1].0.contains("allowed_paths"));
+    }
+
+    #[test]
+    fn test_org_validation() {
+        let mut config = ProxyConfig::default();
+        config.payments.enabled = true;
+        config.payments.orgs.enabled = true;
+        assert!(validate_config(&config).is_ok());
+
+        config.payments.orgs.max_members = 0;
+        let errs = validate_config(&config).unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].0.contains("payments.orgs"));
+    }
 }
@@ -0,0 +1,162 @@
+//! Subscriber account endpoints, for the address in `X-User-Address`.
+
+use alloy::primitives::Address;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use crate::http::server::InnerStateWrapper;
+use crate::payments::cache::SubscriptionStatus;
+use crate::payments::billing::BillingHistory;
+use crate::payments::invoice::InvoiceSigner;
+use crate::security::access_control::subscription_for;
+
+/// The caller's address from `X-User-Address`.
+pub(crate) fn user_address(headers: &HeaderMap) -> Result<Address, (StatusCode, &'static str)> {
+    match headers.get("X-User-Address").and_then(|v| v.to_str().ok()) {
+        Some(value) => value
+            .parse::<Address>()
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid X-User-Address format")),
+        None => Err((StatusCode::UNAUTHORIZED, "Missing X-User-Address header")),
+    }
+}
+
+/// The caller's subscription and what it entitles them to.
+#[derive(Debug, Serialize)]
+pub struct AccountInfo {
+    pub address: Address,
+    /// Owner of the caller's organization, if it shares its subscription.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org: Option<Address>,
+    pub tier_id: u8,
+    /// Subscription expiry (seconds since epoch).
+    pub expiry: u64,
+    pub status: SubscriptionStatus,
+    /// When access ends: `expiry` plus the grace period.
+    pub grace_ends_at: u64,
+    /// Seconds of grace left; zero unless `status` is `grace`.
+    pub grace_remaining_secs: u64,
+    /// `None` if the tier is not served.
+    pub limits: Option<AccountLimits>,
+}
+
+/// Effective QoS limits of the caller's tier.
+#[derive(Debug, Serialize)]
+pub struct AccountLimits {
+    pub rps: u64,
+    pub burst: u64,
+    pub max_conns: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<usize>,
+    pub max_body_size: usize,
+    pub priority: u8,
+}
+
+/// The caller's tier, expiry, grace status and limits.
+pub async fn get_account(
+    State(state): State<InnerStateWrapper>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match user_address(&headers) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let inner = &state.inner;
+    let grace = inner.config.payments.grace_period_secs;
+    let org = inner.orgs.org_of(user);
+    let Some((subscriber, sub)) = subscription_for(&inner.subscription_cache, user, org, grace) else {
+        return (StatusCode::NOT_FOUND, "No subscription found").into_response();
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let status = sub.status(grace, now);
+    let grace_ends_at = sub.expiry.saturating_add(grace);
+    let limits = inner.tier_catalog.policy(sub.tier_id).map(|policy| AccountLimits {
+        rps: policy.rps,
+        burst: policy.burst(),
+        max_conns: policy.max_conns,
+        max_concurrent_requests: policy.max_concurrent_requests,
+        max_body_size: policy.max_body_size.unwrap_or(inner.config.security.max_body_size),
+        priority: policy.priority,
+    });
+    Json(AccountInfo {
+        address: user,
+        org: (subscriber != user).then_some(subscriber),
+        tier_id: sub.tier_id,
+        expiry: sub.expiry,
+        status,
+        grace_ends_at,
+        grace_remaining_secs: match status {
+            SubscriptionStatus::Grace => grace_ends_at - now,
+            _ => 0,
+        },
+        limits,
+    })
+    .into_response()
+}
+
+/// The caller's payments, newest first.
+pub async fn list_payments(
+    State(state): State<InnerStateWrapper>,
+    headers: HeaderMap,
+) -> Response {
+    match user_address(&headers) {
+        Ok(user) => payment_history(&state.inner.billing, user),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// A signed invoice for one of the caller's payments.
+pub async fn get_invoice(
+    State(state): State<InnerStateWrapper>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    match user_address(&headers) {
+        Ok(user) => invoice(&state.inner.billing, state.inner.invoice_signer.as_ref(), user, &id).await,
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Payments of `user`, shared with the admin API.
+pub(crate) fn payment_history(billing: &BillingHistory, user: Address) -> Response {
+    match billing.history(user) {
+        Ok(records) => Json(records).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to read billing history for {}: {}", user, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read billing history").into_response()
+        }
+    }
+}
+
+/// Invoice for payment `id` of `user`, shared with the admin API.
+pub(crate) async fn invoice(
+    billing: &BillingHistory,
+    signer: Option<&InvoiceSigner>,
+    user: Address,
+    id: &str,
+) -> Response {
+    let Some(signer) = signer else {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Invoicing disabled").into_response();
+    };
+    let record = match billing.get(user, id) {
+        Ok(Some(record)) => record,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Payment not found").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to read payment {}: {}", id, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read payment").into_response();
+        }
+    };
+    match signer.sign(record).await {
+        Ok(invoice) => Json(invoice).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to sign invoice for {}: {}", id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to sign invoice").into_response()
+        }
+    }
+}
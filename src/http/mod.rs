@@ -16,6 +16,8 @@ pub mod response;
 pub mod server;
 pub mod websocket;
 pub mod quote;
+pub mod account;
+pub mod org;
 
 pub use request::{RequestId, RequestIdExt, RequestIdLayer, X_REQUEST_ID};
 pub use server::HttpServer;
@@ -0,0 +1,190 @@
+//! Organization endpoints. The owner in `X-User-Address` manages members
+//! with requests signed over [`change_message`]. Adding a member also takes
+//! the member's signature over the same message, and a member may remove
+//! themselves by signing as `X-User-Address`. Each signed message is
+//! accepted once.
+
+use alloy::primitives::Address;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use crate::http::account::user_address;
+use crate::http::server::InnerStateWrapper;
+use crate::security::orgs::{change_message, MemberChange, OrgError, Organizations};
+use crate::security::trial::{signed_by, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+
+/// Header carrying the member's signature when they are added.
+pub const MEMBER_SIGNATURE_HEADER: &str = "X-Member-Signature";
+
+#[derive(Debug, Deserialize)]
+pub struct AddMember {
+    pub member: Address,
+}
+
+/// The caller's organization: owner, members and their request counts.
+pub async fn get_org(
+    State(state): State<InnerStateWrapper>,
+    headers: HeaderMap,
+) -> Response {
+    match user_address(&headers) {
+        Ok(user) => match state.inner.orgs.org_of(user) {
+            Some(owner) => org_usage(&state.inner.orgs, owner),
+            None => (StatusCode::NOT_FOUND, "No organization found").into_response(),
+        },
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Add a member to the caller's organization.
+pub async fn add_member(
+    State(state): State<InnerStateWrapper>,
+    headers: HeaderMap,
+    Json(body): Json<AddMember>,
+) -> Response {
+    signed_change(&state.inner.orgs, &headers, MemberChange::Add, body.member)
+}
+
+/// Remove a member from the caller's organization, or leave the
+/// organization if the caller is `member`.
+pub async fn remove_member(
+    State(state): State<InnerStateWrapper>,
+    Path(member): Path<Address>,
+    headers: HeaderMap,
+) -> Response {
+    signed_change(&state.inner.orgs, &headers, MemberChange::Remove, member)
+}
+
+/// Apply `change` if the caller signed it within the allowed clock skew and
+/// has not used the signed message before. The caller is the owner, or the
+/// member leaving; additions also need the member's signature.
+fn signed_change(orgs: &Organizations, headers: &HeaderMap, change: MemberChange, member: Address) -> Response {
+    let caller = match user_address(headers) {
+        Ok(caller) => caller,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let owner = match change {
+        MemberChange::Remove if caller == member => match orgs.org_of(member) {
+            Some(owner) if owner != member => owner,
+            _ => return (StatusCode::NOT_FOUND, format!("{} is not a member of an organization", member)).into_response(),
+        },
+        _ => caller,
+    };
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(signature), Some(timestamp)) = (header(SIGNATURE_HEADER), header(TIMESTAMP_HEADER)) else {
+        return (StatusCode::UNAUTHORIZED, "Member changes must be signed").into_response();
+    };
+    let member_signature = match change {
+        MemberChange::Add => match header(MEMBER_SIGNATURE_HEADER) {
+            Some(signature) => Some(signature),
+            None => return (StatusCode::UNAUTHORIZED, "New members must sign X-Member-Signature").into_response(),
+        },
+        MemberChange::Remove => None,
+    };
+    let Ok(timestamp) = timestamp.parse::<u64>() else {
+        return (StatusCode::BAD_REQUEST, "Invalid X-User-Timestamp").into_response();
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let message = change_message(change, owner, member, timestamp);
+    if now.abs_diff(timestamp) > orgs.config().signature_max_age_secs
+        || !signed_by(caller, &message, signature)
+        || member_signature.is_some_and(|signature| !signed_by(member, &message, signature))
+    {
+        return (StatusCode::UNAUTHORIZED, "Invalid or expired signature").into_response();
+    }
+    match orgs.claim_message(&message, timestamp, now) {
+        Ok(true) => {}
+        Ok(false) => return (StatusCode::UNAUTHORIZED, "Signature already used").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to record organization signature of {}: {}", caller, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update organization").into_response();
+        }
+    }
+    change_member(orgs, change, owner, member)
+}
+
+/// Organization of `owner` with its usage, shared with the admin API.
+pub(crate) fn org_usage(orgs: &Organizations, owner: Address) -> Response {
+    match orgs.usage(owner) {
+        Some(usage) => Json(usage).into_response(),
+        None => (StatusCode::NOT_FOUND, "No organization found").into_response(),
+    }
+}
+
+/// Apply `change` to `owner`'s member list, shared with the admin API.
+pub(crate) fn change_member(orgs: &Organizations, change: MemberChange, owner: Address, member: Address) -> Response {
+    let result = match change {
+        MemberChange::Add => orgs.add_member(owner, member),
+        MemberChange::Remove => orgs.remove_member(owner, member),
+    };
+    let e = match result {
+        Ok(()) => return StatusCode::NO_CONTENT.into_response(),
+        Err(e) => e,
+    };
+    let status = match &e {
+        OrgError::Disabled => StatusCode::SERVICE_UNAVAILABLE,
+        OrgError::SelfMember => StatusCode::BAD_REQUEST,
+        OrgError::AlreadyMember(_) | OrgError::IsOwner(_) | OrgError::TooManyMembers(_) => StatusCode::CONFLICT,
+        OrgError::NotMember(_) => StatusCode::NOT_FOUND,
+        OrgError::Store(store) => {
+            tracing::error!("Failed to update organization of {}: {}", owner, store);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update organization").into_response();
+        }
+    };
+    (status, e.to_string()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OrgConfig;
+    use alloy::signers::{local::PrivateKeySigner, SignerSync};
+
+    fn signed_headers(caller: &PrivateKeySigner, member: Option<&PrivateKeySigner>, message: &str, timestamp: u64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-User-Address", caller.address().to_string().parse().unwrap());
+        headers.insert(TIMESTAMP_HEADER, timestamp.to_string().parse().unwrap());
+        let signature = caller.sign_message_sync(message.as_bytes()).unwrap().to_string();
+        headers.insert(SIGNATURE_HEADER, signature.parse().unwrap());
+        if let Some(member) = member {
+            let signature = member.sign_message_sync(message.as_bytes()).unwrap().to_string();
+            headers.insert(MEMBER_SIGNATURE_HEADER, signature.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_signed_changes() {
+        let orgs = Organizations::open(&OrgConfig { enabled: true, store_path: String::new(), ..OrgConfig::default() }).unwrap();
+        let (owner, member) = (PrivateKeySigner::random(), PrivateKeySigner::random());
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        let add = change_message(MemberChange::Add, owner.address(), member.address(), now);
+
+        // The member has to consent
+        let res = signed_change(&orgs, &signed_headers(&owner, None, &add, now), MemberChange::Add, member.address());
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let stranger = PrivateKeySigner::random();
+        let headers = signed_headers(&owner, Some(&stranger), &add, now);
+        assert_eq!(signed_change(&orgs, &headers, MemberChange::Add, member.address()).status(), StatusCode::UNAUTHORIZED);
+
+        let headers = signed_headers(&owner, Some(&member), &add, now);
+        assert_eq!(signed_change(&orgs, &headers, MemberChange::Add, member.address()).status(), StatusCode::NO_CONTENT);
+        assert_eq!(orgs.org_of(member.address()), Some(owner.address()));
+
+        // A member leaves, and the same request cannot be replayed after rejoining
+        let remove = change_message(MemberChange::Remove, owner.address(), member.address(), now);
+        let leave = signed_headers(&member, None, &remove, now);
+        assert_eq!(signed_change(&orgs, &leave, MemberChange::Remove, member.address()).status(), StatusCode::NO_CONTENT);
+        assert_eq!(orgs.org_of(member.address()), None);
+        assert_eq!(signed_change(&orgs, &headers, MemberChange::Add, member.address()).status(), StatusCode::UNAUTHORIZED);
+        orgs.add_member(owner.address(), member.address()).unwrap();
+        assert_eq!(signed_change(&orgs, &leave, MemberChange::Remove, member.address()).status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(orgs.org_of(member.address()), Some(owner.address()));
+    }
+}
@@ -1,7 +1,8 @@
-use axum::{extract::{State, Json, Path}, http::StatusCode, response::IntoResponse};
+use axum::{extract::{State, Json, Path}, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use uuid::Uuid;
+use crate::http::account::user_address;
 use crate::http::server::InnerStateWrapper;
-use crate::quoting::QuoteRequest;
+use crate::quoting::{QuoteError, QuoteRequest, SignedQuote, StoreError};
 
 pub async fn create_quote(
     State(state): State<InnerStateWrapper>,
@@ -13,30 +14,33 @@ pub async fn create_quote(
     };
 
     // Strict Validation (Phase 24)
-    if state.inner.config.security.strict_validation {
-        if request.user_address.is_zero() {
-            return (StatusCode::BAD_REQUEST, "Zero address not allowed").into_response();
-        }
-        
-        if let Some(ds) = request.duration_seconds {
-            if ds < 3600 { // Min 1 hour
-                return (StatusCode::BAD_REQUEST, "Duration too short (min 1h)").into_response();
-            }
-            if ds > 365 * 24 * 3600 { // Max 1 year
-                return (StatusCode::BAD_REQUEST, "Duration too long (max 1y)").into_response();
-            }
-        }
+    if state.inner.config.security.strict_validation && request.user_address.is_zero() {
+        return (StatusCode::BAD_REQUEST, "Zero address not allowed").into_response();
     }
 
+    // Service and duration bounds are checked against the price book
+
     match engine.generate_quote(request).await {
         Ok(quote) => (StatusCode::CREATED, Json(quote)).into_response(),
-        Err(e) => {
+        Err(QuoteError::PriceFeed(e)) => {
+            tracing::warn!("Cannot price quote: {}", e);
+            (StatusCode::SERVICE_UNAVAILABLE, "Price feed unavailable").into_response()
+        }
+        Err(e @ QuoteError::CatalogUnavailable) => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response(),
+        Err(e @ QuoteError::TooManyQuotes(_)) => (StatusCode::TOO_MANY_REQUESTS, e.to_string()).into_response(),
+        Err(QuoteError::Store(StoreError::Full)) => {
+            tracing::warn!("Quote store full, refusing quote");
+            (StatusCode::SERVICE_UNAVAILABLE, "Quote store full").into_response()
+        }
+        Err(e @ (QuoteError::Blockchain(_) | QuoteError::Store(_))) => {
             tracing::error!("Failed to generate quote: {}", e);
             (StatusCode::INTERNAL_SERVER_ERROR, "Failed to generate quote").into_response()
         }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     }
 }
 
+/// Get a quote and its status (`issued`, `paid` or `expired`) by ID.
 pub async fn get_quote(
     State(state): State<InnerStateWrapper>,
     Path(id): Path<Uuid>,
@@ -47,7 +51,48 @@ pub async fn get_quote(
     };
 
     match engine.get_quote(id) {
-        Some(quote) => (StatusCode::OK, Json(quote)).into_response(),
-        None => (StatusCode::NOT_FOUND, "Quote not found").into_response(),
+        Ok(Some(quote)) => (StatusCode::OK, Json(quote)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Quote not found").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to read quote {}: {}", id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read quote").into_response()
+        }
     }
 }
+
+/// List the caller's unpaid, unexpired quotes, identified by `X-User-Address`.
+pub async fn list_quotes(
+    State(state): State<InnerStateWrapper>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let engine = match &state.inner.quote_engine {
+        Some(e) => e,
+        None => return (StatusCode::SERVICE_UNAVAILABLE, "Quoting service disabled").into_response(),
+    };
+
+    let user = match user_address(&headers) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match engine.outstanding_quotes(user) {
+        Ok(quotes) => (StatusCode::OK, Json(quotes)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list quotes for {}: {}", user, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list quotes").into_response()
+        }
+    }
+}
+
+/// Check that a signed quote was issued by this proxy and is still valid.
+pub async fn verify_quote(
+    State(state): State<InnerStateWrapper>,
+    Json(signed): Json<SignedQuote>,
+) -> impl IntoResponse {
+    let engine = match &state.inner.quote_engine {
+        Some(e) => e,
+        None => return (StatusCode::SERVICE_UNAVAILABLE, "Quoting service disabled").into_response(),
+    };
+
+    (StatusCode::OK, Json(engine.verify_quote(&signed))).into_response()
+}
@@ -84,7 +84,7 @@ where
             .and_then(|v| v.to_str().ok())
             .and_then(|s| Uuid::parse_str(s).ok())
             .map(RequestId)
-            .unwrap_or_else(RequestId::new);
+            .unwrap_or_default();
 
         // Add request ID to request headers (for downstream)
         if let Ok(value) = HeaderValue::from_str(&request_id.to_string()) {
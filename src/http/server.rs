@@ -5,7 +5,7 @@ use axum::{
     extract::{ConnectInfo, State},
     http::{Method, Request, StatusCode, header},
     response::{IntoResponse, Response},
-    routing::any,
+    routing::{any, delete, get, post},
     Router,
     middleware,
     extract::{DefaultBodyLimit, Request as AxumRequest},
@@ -33,9 +33,20 @@ use crate::blockchain::wallet::Wallet;
 use crate::blockchain::client::BlockchainClient;
 use crate::payments::monitor::PaymentMonitor;
 use crate::payments::cache::SubscriptionCache;
-use crate::config::ProxyConfig;
+use crate::payments::catalog::TierCatalog;
+use crate::payments::billing::BillingHistory;
+use crate::webhooks::Webhooks;
+use crate::security::orgs::{Organizations, org_usage_middleware};
+use crate::blockchain::nonce::NonceManager;
+use crate::security::trial::TrialGate;
+use crate::payments::invoice::InvoiceSigner;
+use crate::payments::ledger::SlaLedger;
+use crate::payments::sla::{SlaService, SlaTracker, sla_middleware};
+use crate::payments::treasury::{SweepLog, TreasurySweeper};
+use crate::blockchain::transaction::TxBuilder;
+use crate::config::{PaymentConfig, ProxyConfig};
 use crate::http::request::RequestIdLayer;
-use crate::quoting::QuoteEngine;
+use crate::quoting::{PriceOracle, PromoBook, QuoteEngine, QuoteRegistry};
 use crate::routing::Router as ProxyRouter;
 use crate::load_balancer::pool::BackendManager;
 use crate::health::active::HealthMonitor;
@@ -43,8 +54,8 @@ use crate::resilience::retries::{RetryBudget, is_retryable};
 use crate::resilience::backoff::calculate_backoff;
 use crate::observability::metrics;
 use crate::security::rate_limit::{RateLimiterState, rate_limit_middleware};
-use crate::security::access_control::{AccessControlState, access_control_middleware};
-use crate::security::qos::ConnectionTracker;
+use crate::security::access_control::{AccessControlState, access_control_middleware, subscription_headers_middleware};
+use crate::security::qos::{ConnectionTracker, PriorityScheduler, QosState, qos_middleware};
 use crate::net::tls::load_tls_config;
 use crate::admin::setup_admin_router;
 
@@ -56,12 +67,80 @@ pub struct InnerState {
     pub retry_budget: Arc<RetryBudget>,
     pub rate_limiter: Option<Arc<RateLimiterState>>,
     pub quote_engine: Option<QuoteEngine>,
+    pub invoice_signer: Option<InvoiceSigner>,
     pub subscription_cache: Arc<SubscriptionCache>,
+    pub tier_catalog: Arc<TierCatalog>,
+    pub price_oracle: Arc<PriceOracle>,
+    pub quote_registry: Arc<QuoteRegistry>,
+    pub promo_book: Arc<PromoBook>,
+    pub sla_tracker: Arc<SlaTracker>,
+    pub sla_ledger: Arc<SlaLedger>,
+    pub sweep_log: Arc<SweepLog>,
+    pub billing: BillingHistory,
+    pub webhooks: Arc<Webhooks>,
+    pub trial_gate: Arc<TrialGate>,
+    pub orgs: Arc<Organizations>,
+    pub nonces: Arc<NonceManager>,
+    pub wallet: Option<Wallet>,
     pub conn_tracker: Arc<ConnectionTracker>,
     pub axum_router: Router<InnerStateWrapper>,
     pub request_count: Arc<std::sync::atomic::AtomicUsize>,
 }
 
+impl InnerState {
+    /// The long-lived objects to carry over into the next state.
+    fn shared(&self) -> SharedServices {
+        SharedServices {
+            subscription_cache: self.subscription_cache.clone(),
+            tier_catalog: self.tier_catalog.clone(),
+            price_oracle: self.price_oracle.clone(),
+            quote_registry: self.quote_registry.clone(),
+            promo_book: self.promo_book.clone(),
+            sla_tracker: self.sla_tracker.clone(),
+            sla_ledger: self.sla_ledger.clone(),
+            sweep_log: self.sweep_log.clone(),
+            billing: self.billing.clone(),
+            webhooks: self.webhooks.clone(),
+            trial_gate: self.trial_gate.clone(),
+            orgs: self.orgs.clone(),
+            nonces: self.nonces.clone(),
+            wallet: self.wallet.clone(),
+        }
+    }
+}
+
+/// Objects that outlive config reloads, created once by [`HttpServer::new`].
+#[derive(Clone)]
+struct SharedServices {
+    /// The payment monitor keeps writing to the cache.
+    subscription_cache: Arc<SubscriptionCache>,
+    /// Follows the chain independently of reloads.
+    tier_catalog: Arc<TierCatalog>,
+    /// Keeps the last good USD rate.
+    price_oracle: Arc<PriceOracle>,
+    /// Issued quotes, which the payment monitor redeems.
+    quote_registry: Arc<QuoteRegistry>,
+    /// Promo codes created through the admin API.
+    promo_book: Arc<PromoBook>,
+    /// Availability measured for the SLA credit service.
+    sla_tracker: Arc<SlaTracker>,
+    sla_ledger: Arc<SlaLedger>,
+    /// Balance checks and sweeps of the treasury sweeper.
+    sweep_log: Arc<SweepLog>,
+    /// Processed payments, written by the payment monitor.
+    billing: BillingHistory,
+    /// Outbox of undelivered webhooks.
+    webhooks: Arc<Webhooks>,
+    /// Free trial usage, which must not reset on reload.
+    trial_gate: Arc<TrialGate>,
+    /// Organization member lists and their usage.
+    orgs: Arc<Organizations>,
+    /// Pending transactions of the proxy wallet, replaced while stuck.
+    nonces: Arc<NonceManager>,
+    /// The proxy's signing key, read once: a keystore is slow to decrypt.
+    wallet: Option<Wallet>,
+}
+
 /// A wrapper to allow and inject State into the inner router
 #[derive(Clone)]
 pub struct InnerStateWrapper {
@@ -102,7 +181,99 @@ impl HttpServer {
         let client = Client::builder(TokioExecutor::new())
             .build(HttpConnector::new());
 
-        let inner = Self::build_inner(&config, client.clone());
+        let subscription_cache = match SubscriptionCache::load_from_file(&config.payments.state_path, config.blockchain.chain_id) {
+            Ok(cache) => Arc::new(cache),
+            Err(e) => {
+                tracing::warn!("Failed to load subscription cache: {}. Starting empty.", e);
+                Arc::new(SubscriptionCache::new(Some(config.payments.state_path.clone())))
+            }
+        };
+
+        let quote_registry = if config.blockchain.enabled || config.payments.enabled {
+            let store = &config.pricing.quote_store;
+            let registry = QuoteRegistry::open(store)
+                .unwrap_or_else(|e| panic!("failed to open quote store {}: {}", store.path, e));
+            Arc::new(registry)
+        } else {
+            Arc::new(QuoteRegistry::default())
+        };
+        // Runtime promo codes are kept with the quotes
+        let promo_book = PromoBook::open(quote_registry.store())
+            .unwrap_or_else(|e| panic!("failed to load promo codes: {}", e));
+        let promo_book = Arc::new(promo_book);
+        let billing = if config.payments.enabled {
+            let path = &config.payments.billing_path;
+            BillingHistory::open(path).unwrap_or_else(|e| panic!("failed to open billing store {}: {}", path, e))
+        } else {
+            BillingHistory::default()
+        };
+        let webhooks = if config.webhooks.enabled {
+            let webhooks = Webhooks::open(&config.webhooks)
+                .unwrap_or_else(|e| panic!("failed to open webhook outbox {}: {}", config.webhooks.outbox_path, e));
+            Arc::new(webhooks)
+        } else {
+            Arc::new(Webhooks::default())
+        };
+        let trial_gate = if config.payments.enabled && config.payments.trial.enabled {
+            let trial = &config.payments.trial;
+            let gate = TrialGate::open(trial)
+                .unwrap_or_else(|e| panic!("failed to open trial store {}: {}", trial.store_path, e));
+            Arc::new(gate)
+        } else {
+            Arc::new(TrialGate::default())
+        };
+        let orgs = if config.payments.enabled && config.payments.orgs.enabled {
+            let orgs = &config.payments.orgs;
+            let organizations = Organizations::open(orgs)
+                .unwrap_or_else(|e| panic!("failed to open organization store {}: {}", orgs.store_path, e));
+            Arc::new(organizations)
+        } else {
+            Arc::new(Organizations::default())
+        };
+        let nonces = if config.blockchain.enabled || config.payments.enabled {
+            let transactions = &config.blockchain.transactions;
+            let nonces = NonceManager::open(transactions)
+                .unwrap_or_else(|e| panic!("failed to open transaction store {}: {}", transactions.store_path, e));
+            Arc::new(nonces)
+        } else {
+            Arc::new(NonceManager::default())
+        };
+        let wallet = if config.blockchain.enabled || config.payments.enabled {
+            match Wallet::load(&config.blockchain) {
+                Ok(wallet) => Some(wallet),
+                Err(e) => {
+                    tracing::warn!("No wallet loaded, signing is unavailable: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let sla_ledger = if config.sla.enabled {
+            let path = &config.sla.ledger_path;
+            let ledger = SlaLedger::open(path).unwrap_or_else(|e| panic!("failed to open SLA ledger {}: {}", path, e));
+            Arc::new(ledger)
+        } else {
+            Arc::new(SlaLedger::in_memory())
+        };
+
+        let shared = SharedServices {
+            subscription_cache,
+            tier_catalog: Arc::new(TierCatalog::new(&config.qos)),
+            price_oracle: Arc::new(PriceOracle::new()),
+            quote_registry,
+            promo_book,
+            sla_tracker: Arc::new(SlaTracker::new()),
+            sla_ledger,
+            sweep_log: Arc::new(SweepLog::new()),
+            billing,
+            webhooks,
+            trial_gate,
+            orgs,
+            nonces,
+            wallet,
+        };
+        let inner = Self::build_inner(&config, shared);
         let inner_state = Arc::new(ArcSwap::from_pointee(inner));
 
         Self { 
@@ -113,13 +284,39 @@ impl HttpServer {
     }
 
     /// Build the internal state from a configuration.
-    fn build_inner(config: &ProxyConfig, _client: Client<HttpConnector, Body>) -> InnerState {
+    fn build_inner(config: &ProxyConfig, shared: SharedServices) -> InnerState {
+        let SharedServices {
+            subscription_cache,
+            tier_catalog,
+            price_oracle,
+            quote_registry,
+            promo_book,
+            sla_tracker,
+            sla_ledger,
+            sweep_log,
+            billing,
+            webhooks,
+            trial_gate,
+            orgs,
+            nonces,
+            wallet,
+        } = shared;
+        tier_catalog.set_qos(&config.qos);
+        tier_catalog.set_price_book(&config.pricing);
+        tier_catalog.set_unpriced_chains(config.unpriced_chains());
+        price_oracle.configure(config.pricing.price_feed.as_ref());
+        promo_book.set_configured(&config.pricing.promo_codes);
+        webhooks.set_config(&config.webhooks);
+        trial_gate.set_config(&config.payments.trial);
+        orgs.set_config(&config.payments.orgs);
+        nonces.set_config(&config.blockchain.transactions);
+
         let proxy_router = Arc::new(ProxyRouter::from_config(config.routes.clone()));
         let backend_manager = Arc::new(BackendManager::new(config.backends.clone()));
         let retry_budget = Arc::new(RetryBudget::new(config.retries.budget_ratio, 100));
         let rate_limiter = if config.rate_limit.enabled {
             Some(Arc::new(RateLimiterState::new(
-                config.qos.clone(),
+                tier_catalog.clone(),
                 config.rate_limit.requests_per_second,
                 config.rate_limit.burst_size,
             )))
@@ -127,39 +324,107 @@ impl HttpServer {
             None
         };
 
-        let conn_tracker = Arc::new(ConnectionTracker::new(config.qos.clone()));
+        let conn_tracker = Arc::new(ConnectionTracker::new(tier_catalog.clone()));
 
         // Initialize QuoteEngine if blockchain enabled
+        // EIP-712 quotes and invoices are bound to the PaymentProcessor,
+        // which validation requires when blockchain is enabled
+        let payment_processor = config.payments.contract_address.parse().unwrap_or(Address::ZERO);
+        // Further chains were validated to have a contract address
+        let extra_chains: Vec<(u64, Address)> = config
+            .chains
+            .iter()
+            .filter_map(|chain| Some((chain.chain_id, chain.contract_address.parse().ok()?)))
+            .collect();
         let quote_engine = if config.blockchain.enabled {
-            match Wallet::from_env(config.blockchain.chain_id) {
-                Ok(wallet) => {
+            match &wallet {
+                Some(wallet) => {
                     tracing::info!("Quote engine initialized with wallet");
-                    Some(QuoteEngine::new(wallet))
+                    let engine = QuoteEngine::new(
+                        wallet.clone(),
+                        tier_catalog.clone(),
+                        subscription_cache.clone(),
+                        config.pricing.clone(),
+                        price_oracle.clone(),
+                        payment_processor,
+                        quote_registry.clone(),
+                        promo_book.clone(),
+                    );
+                    Some(extra_chains.iter().fold(engine, |engine, &(chain_id, processor)| {
+                        engine.with_chain(chain_id, processor)
+                    }))
                 }
-                Err(e) => {
-                    tracing::error!("Failed to init wallet for quote engine: {}", e);
+                None => {
+                    tracing::error!("No wallet loaded, quote engine disabled");
                     None
                 }
             }
         } else {
             None
         };
-        // Initialize Subscription Cache
-        let subscription_cache = match SubscriptionCache::load_from_file("subscriptions.json") {
-            Ok(cache) => Arc::new(cache),
-            Err(e) => {
-                tracing::warn!("Failed to load subscription cache: {}. Starting empty.", e);
-                Arc::new(SubscriptionCache::new(Some("subscriptions.json".to_string())))
+        // Invoices are signed by the same wallet, under the quote domain
+        let invoice_signer = if config.blockchain.enabled && config.payments.enabled {
+            match &wallet {
+                Some(wallet) => Some(extra_chains.iter().fold(
+                    InvoiceSigner::new(wallet.clone(), payment_processor),
+                    |signer, &(chain_id, processor)| signer.with_chain(chain_id, processor),
+                )),
+                None => {
+                    tracing::error!("No wallet loaded, invoicing disabled");
+                    None
+                }
             }
+        } else {
+            None
         };
-
         let request_count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
 
+        let mut proxy_routes: Router<InnerStateWrapper> = Router::new()
+            .route("/{*path}", any(proxy_handler))
+            .route("/", any(proxy_handler));
+        // SLA outcomes (Runs inside Access Control, proxied requests only)
+        if config.sla.enabled {
+            proxy_routes = proxy_routes.route_layer(middleware::from_fn_with_state(
+                sla_tracker.clone(),
+                sla_middleware,
+            ));
+        }
+        // Organization usage (Runs inside Rate Limit and QoS, proxied requests only)
+        if config.payments.enabled && config.payments.orgs.enabled {
+            proxy_routes = proxy_routes.route_layer(middleware::from_fn_with_state(
+                orgs.clone(),
+                org_usage_middleware,
+            ));
+        }
+        // Subscription status headers (Runs inside Access Control, proxied requests only)
+        if config.payments.subscription_headers {
+            proxy_routes = proxy_routes.route_layer(middleware::from_fn_with_state(
+                config.payments.grace_period_secs,
+                subscription_headers_middleware,
+            ));
+        }
+
         let mut axum_router: Router<InnerStateWrapper> = Router::new()
             .route("/api/v1/quote", any(crate::http::quote::create_quote))
+            .route("/api/v1/quote/verify", post(crate::http::quote::verify_quote))
             .route("/api/v1/quote/{id}", any(crate::http::quote::get_quote))
-            .route("/{*path}", any(proxy_handler))
-            .route("/", any(proxy_handler));
+            .route("/api/v1/quotes", get(crate::http::quote::list_quotes))
+            .route("/api/v1/org", get(crate::http::org::get_org))
+            .route("/api/v1/org/members", post(crate::http::org::add_member))
+            .route("/api/v1/org/members/{member}", delete(crate::http::org::remove_member))
+            .merge(proxy_routes);
+
+        // Per-tier QoS (Runs after Rate Limit)
+        let qos_state = QosState {
+            catalog: tier_catalog.clone(),
+            scheduler: Arc::new(PriorityScheduler::new(config.qos.max_in_flight)),
+            in_flight: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
+            default_max_body_size: config.security.max_body_size,
+        };
+        axum_router = axum_router.layer(middleware::from_fn_with_state(
+            qos_state,
+            qos_middleware,
+        ));
 
         if let Some(ref rl_state) = rate_limiter {
             axum_router = axum_router.layer(middleware::from_fn_with_state(
@@ -173,12 +438,21 @@ impl HttpServer {
             cache: subscription_cache.clone(),
             enabled: config.payments.enabled,
             grace_period_secs: config.payments.grace_period_secs,
+            trial: trial_gate.clone(),
+            orgs: orgs.clone(),
         };
         axum_router = axum_router.layer(middleware::from_fn_with_state(
             ac_state,
             access_control_middleware,
         ));
 
+        // Account endpoints (Outside Access Control, so expired and unknown
+        // addresses can check their status)
+        axum_router = axum_router
+            .route("/api/v1/account", get(crate::http::account::get_account))
+            .route("/api/v1/account/payments", get(crate::http::account::list_payments))
+            .route("/api/v1/account/payments/{id}/invoice", get(crate::http::account::get_invoice));
+
         // Security Hardening (Phase 24)
         if config.security.enable_headers {
             axum_router = axum_router
@@ -220,7 +494,21 @@ impl HttpServer {
             retry_budget,
             rate_limiter,
             quote_engine,
+            invoice_signer,
             subscription_cache,
+            tier_catalog,
+            price_oracle,
+            quote_registry,
+            promo_book,
+            sla_tracker,
+            sla_ledger,
+            sweep_log,
+            billing,
+            webhooks,
+            trial_gate,
+            orgs,
+            nonces,
+            wallet,
             conn_tracker,
             axum_router,
             request_count,
@@ -245,14 +533,14 @@ impl HttpServer {
         
         // Spawn Reloader Task
         let reloader_inner = inner_state.clone();
-        let reloader_client = client.clone();
         let mut reloader_shutdown = shutdown.resubscribe();
         tokio::spawn(async move {
             loop {
                 tokio::select! {
                     Some(new_config) = config_updates.recv() => {
                         tracing::info!("Applying new configuration...");
-                        let new_inner = Self::build_inner(&new_config, reloader_client.clone());
+                        let current = reloader_inner.load();
+                        let new_inner = Self::build_inner(&new_config, current.shared());
                         reloader_inner.store(Arc::new(new_inner));
                         tracing::info!("Configuration reload complete");
                     }
@@ -298,24 +586,125 @@ impl HttpServer {
                 monitor.run(monitor_shutdown).await;
             });
         }
-        // Start Payment Monitor
-        if self.config.payments.enabled {
+        // Connect to the chain for quoting (price feed, tier pricing), the payment monitor and SLA refunds
+        let mut sla_refunds = None;
+        if self.config.blockchain.enabled || self.config.payments.enabled {
             match BlockchainClient::new(self.config.blockchain.clone()).await {
                 Ok(client) => {
-                    match PaymentMonitor::new(client, self.config.payments.clone(), inner_state.load().subscription_cache.clone()) {
-                        Ok(monitor) => {
-                            tracing::info!("Spawning payment monitor task");
-                            tokio::spawn(async move {
-                                monitor.run().await;
-                            });
+                    let current = inner_state.load();
+                    let catalog = current.tier_catalog.clone();
+                    current.price_oracle.attach(client.clone());
+
+                    // Tiers are quoted once their pricing is loaded; keep trying until it is
+                    let payments = &self.config.payments;
+                    if let Ok(manager) = payments.subscription_manager_address.parse() {
+                        let (client, tier_ids) = (client.clone(), payments.tier_ids.clone());
+                        let interval = Duration::from_millis(payments.monitor_interval_ms);
+                        tokio::spawn(async move {
+                            catalog.sync_until_ready(&client, manager, &tier_ids, interval).await;
+                        });
+                    }
+
+                    if self.config.sla.enabled && self.config.sla.refund_threshold_secs > 0 {
+                        match &current.wallet {
+                            Some(wallet) => {
+                                sla_refunds = Some(TxBuilder::new(client.clone(), wallet.clone()).with_nonces(current.nonces.clone()))
+                            }
+                            None => tracing::error!("No wallet loaded for SLA refunds, crediting time instead"),
+                        }
+                    }
+
+                    if self.config.payments.enabled && self.config.payments.treasury.enabled {
+                        let sweeper = current.wallet.clone()
+                            .ok_or_else(|| "no wallet loaded".to_string())
+                            .and_then(|wallet| TreasurySweeper::new(
+                                self.config.payments.treasury.clone(),
+                                &self.config.payments.contract_address,
+                                TxBuilder::new(client.clone(), wallet).with_nonces(current.nonces.clone()),
+                                current.sweep_log.clone(),
+                            ));
+                        match sweeper {
+                            Ok(sweeper) => {
+                                let sweeper_shutdown = shutdown.resubscribe();
+                                tokio::spawn(async move {
+                                    sweeper.run(sweeper_shutdown).await;
+                                });
+                            }
+                            Err(e) => tracing::error!("Failed to create treasury sweeper: {}", e),
                         }
-                        Err(e) => tracing::error!("Failed to create payment monitor: {}", e),
                     }
+
+                    // A remote signer may go away while the proxy keeps running
+                    if let (Some(wallet), false) = (current.wallet.clone(), self.config.blockchain.remote_signer.url.is_empty()) {
+                        let interval = Duration::from_secs(self.config.blockchain.remote_signer.health_check_interval_secs);
+                        let signer_shutdown = shutdown.resubscribe();
+                        tokio::spawn(async move {
+                            wallet.monitor_signer(interval, signer_shutdown).await;
+                        });
+                    }
+
+                    // Replace stuck transactions of whichever service sends from the wallet
+                    let nonces = current.nonces.clone();
+                    let nonces_shutdown = shutdown.resubscribe();
+                    tokio::spawn(async move {
+                        nonces.run(nonces_shutdown).await;
+                    });
+
+                    // Start Payment Monitor
+                    if self.config.payments.enabled {
+                        spawn_payment_monitor(client, self.config.payments.clone(), &current);
+                    }
+                }
+                Err(e) => tracing::error!("Failed to create blockchain client: {}", e),
+            }
+        }
+
+        // Further chains each get their own client and payment monitor
+        if self.config.payments.enabled {
+            let current = inner_state.load();
+            for (blockchain, payments) in self.config.payment_chains().into_iter().skip(1) {
+                let chain_id = blockchain.chain_id;
+                match BlockchainClient::new(blockchain).await {
+                    Ok(client) => spawn_payment_monitor(client, payments, &current),
+                    Err(e) => tracing::error!(chain_id, "Failed to create blockchain client: {}", e),
                 }
-                Err(e) => tracing::error!("Failed to create blockchain client for payment monitor: {}", e),
             }
         }
 
+        if self.config.sla.enabled {
+            let current = inner_state.load();
+            let mut service = SlaService::new(
+                self.config.sla.clone(),
+                current.sla_tracker.clone(),
+                current.sla_ledger.clone(),
+                current.subscription_cache.clone(),
+                current.tier_catalog.clone(),
+            );
+            if let Some(tx_builder) = sla_refunds {
+                service = service.with_refunds(tx_builder);
+            }
+            // Sample whichever backends the latest config defines
+            let health_state = inner_state.clone();
+            let healthy = move || health_state.load().backends.all_backends().iter().any(|b| b.is_healthy());
+            let sla_shutdown = shutdown.resubscribe();
+            tokio::spawn(async move {
+                service.run(healthy, sla_shutdown).await;
+            });
+        }
+
+        if self.config.payments.enabled && self.config.payments.orgs.enabled {
+            let usage_shutdown = shutdown.resubscribe();
+            tokio::spawn(inner_state.load().orgs.clone().run_usage_flush(usage_shutdown));
+        }
+
+        if self.config.webhooks.enabled {
+            let current = inner_state.load();
+            let delivery_shutdown = shutdown.resubscribe();
+            tokio::spawn(current.webhooks.clone().run_delivery(delivery_shutdown));
+            let scanner_shutdown = shutdown.resubscribe();
+            tokio::spawn(current.webhooks.clone().run_expiry_scanner(current.subscription_cache.clone(), scanner_shutdown));
+        }
+
         let app_state = AppState {
             client: client.clone(),
             inner: inner_state.clone(),
@@ -378,6 +767,26 @@ impl HttpServer {
     }
 }
 
+/// Spawn the payment monitor of the chain `client` is connected to.
+fn spawn_payment_monitor(client: BlockchainClient, payments: PaymentConfig, state: &InnerState) {
+    let chain_id = client.config().chain_id;
+    match PaymentMonitor::new(
+        client,
+        payments,
+        state.subscription_cache.clone(),
+        state.tier_catalog.clone(),
+        state.quote_registry.clone(),
+        state.billing.clone(),
+        state.webhooks.clone(),
+    ) {
+        Ok(monitor) => {
+            tracing::info!(chain_id, "Spawning payment monitor task");
+            tokio::spawn(monitor.run());
+        }
+        Err(e) => tracing::error!(chain_id, "Failed to create payment monitor: {}", e),
+    }
+}
+
 /// Proxy handler using InnerStateWrapper
 async fn proxy_handler(
     State(wrapper): State<InnerStateWrapper>,
@@ -445,10 +854,7 @@ async fn proxy_handler(
 
     let body_bytes = if retry_config.enabled && method.is_idempotent() {
         if let Some(b) = body_opt.take() {
-            match axum::body::to_bytes(b, 1024 * 1024).await {
-                Ok(bytes) => Some(bytes),
-                Err(_) => None,
-            }
+            axum::body::to_bytes(b, 1024 * 1024).await.ok()
         } else {
             None
         }
@@ -67,7 +67,7 @@ async fn proxy_ws(client_ws: WebSocket, backend_url: Url) {
         "https" => "wss",
         s => s,
     };
-    if let Err(_) = ws_backend_url.set_scheme(scheme) {
+    if ws_backend_url.set_scheme(scheme).is_err() {
         error!("Failed to set WS scheme: {}", scheme);
         return;
     }
@@ -81,9 +81,9 @@ async fn proxy_ws(client_ws: WebSocket, backend_url: Url) {
                 while let Some(Ok(msg)) = c_stream.next().await {
                     let b_msg = match msg {
                         Message::Text(t) => TgMessage::Text(t.to_string().into()),
-                        Message::Binary(b) => TgMessage::Binary(b.into()),
-                        Message::Ping(p) => TgMessage::Ping(p.into()),
-                        Message::Pong(p) => TgMessage::Pong(p.into()),
+                        Message::Binary(b) => TgMessage::Binary(b),
+                        Message::Ping(p) => TgMessage::Ping(p),
+                        Message::Pong(p) => TgMessage::Pong(p),
                         Message::Close(c) => {
                             let frame = c.map(tg_close_frame_converter);
                             TgMessage::Close(frame)
@@ -100,9 +100,9 @@ async fn proxy_ws(client_ws: WebSocket, backend_url: Url) {
                 while let Some(Ok(msg)) = b_stream.next().await {
                     let c_msg = match msg {
                         TgMessage::Text(t) => Message::Text(t.to_string().into()),
-                        TgMessage::Binary(b) => Message::Binary(b.into()),
-                        TgMessage::Ping(p) => Message::Ping(p.into()),
-                        TgMessage::Pong(p) => Message::Pong(p.into()),
+                        TgMessage::Binary(b) => Message::Binary(b),
+                        TgMessage::Ping(p) => Message::Ping(p),
+                        TgMessage::Pong(p) => Message::Pong(p),
                         TgMessage::Close(c) => {
                             let frame = c.map(ax_close_frame_converter);
                             Message::Close(frame)
@@ -14,6 +14,8 @@ pub mod security;
 pub mod blockchain;
 pub mod quoting;
 pub mod admin;
+pub mod webhooks;
+pub mod store;
 
 pub use config::schema::ProxyConfig;
 pub use http::HttpServer;
@@ -11,7 +11,7 @@ pub struct LeastConnections;
 
 impl LeastConnections {
     pub fn new() -> Self {
-        Self::default()
+        Self
     }
 }
 
@@ -15,11 +15,14 @@ use crate::load_balancer::{
     round_robin::RoundRobin,
 };
 
+/// A backend group: its members and the algorithm used to pick between them.
+type BackendGroup = (Vec<Arc<Backend>>, Box<dyn LoadBalancer>);
+
 /// Manages backend pools and load balancing.
 #[derive(Debug)]
 pub struct BackendManager {
     /// Map of backend_group name -> (Backends, LoadBalancerAlgo).
-    groups: HashMap<String, (Vec<Arc<Backend>>, Box<dyn LoadBalancer>)>,
+    groups: HashMap<String, BackendGroup>,
 }
 
 impl BackendManager {
@@ -52,3 +52,59 @@ pub fn record_subscription_event(event_type: &str) {
 pub fn record_cache_size(size: usize) {
     gauge!("proxy_subscription_cache_size").set(size as f64);
 }
+
+/// Helper to track the last block processed by the payment monitor.
+pub fn record_payment_monitor_block(block: u64) {
+    gauge!("proxy_payment_monitor_last_block").set(block as f64);
+}
+
+/// Helper to track whether the payment monitor receives pushed events (1) or polls (0).
+pub fn record_payment_monitor_push(active: bool) {
+    gauge!("proxy_payment_monitor_push_active").set(if active { 1.0 } else { 0.0 });
+}
+
+/// Helper to track the PaymentProcessor balance seen by the treasury sweeper, in wei.
+pub fn record_treasury_balance(wei: f64) {
+    gauge!("proxy_treasury_contract_balance_wei").set(wei);
+}
+
+/// Helper to track RPC calls and their latency per provider.
+pub fn record_rpc_request(provider: &str, outcome: &str, duration: std::time::Duration) {
+    counter!("proxy_rpc_requests_total", "provider" => provider.to_string(), "outcome" => outcome.to_string()).increment(1);
+    histogram!("proxy_rpc_request_duration_seconds", "provider" => provider.to_string()).record(duration.as_secs_f64());
+}
+
+/// Helper to track whether an RPC provider is ejected from the pool.
+pub fn record_rpc_provider_ejected(provider: &str, ejected: bool) {
+    gauge!("proxy_rpc_provider_ejected", "provider" => provider.to_string()).set(if ejected { 1.0 } else { 0.0 });
+}
+
+/// Helper to track how many blocks an RPC provider is behind the best head.
+pub fn record_rpc_provider_lag(provider: &str, blocks: u64) {
+    gauge!("proxy_rpc_provider_block_lag", "provider" => provider.to_string()).set(blocks as f64);
+}
+
+/// Helper to track hedged RPC reads by which request answered first.
+pub fn record_rpc_hedge(winner: &str) {
+    counter!("proxy_rpc_hedged_reads_total", "winner" => winner.to_string()).increment(1);
+}
+
+/// Helper to track whether the wallet's signer answers health checks.
+pub fn record_signer_health(healthy: bool) {
+    gauge!("proxy_signer_healthy").set(if healthy { 1.0 } else { 0.0 });
+}
+
+/// Helper to track treasury sweeps by outcome.
+pub fn record_treasury_sweep(outcome: &str) {
+    counter!("proxy_treasury_sweeps_total", "outcome" => outcome.to_string()).increment(1);
+}
+
+/// Helper to track webhook delivery attempts by outcome.
+pub fn record_webhook_delivery(outcome: &str) {
+    counter!("proxy_webhook_deliveries_total", "outcome" => outcome.to_string()).increment(1);
+}
+
+/// Helper to track free trial requests by outcome.
+pub fn record_trial_request(outcome: &str) {
+    counter!("proxy_trial_requests_total", "outcome" => outcome.to_string()).increment(1);
+}
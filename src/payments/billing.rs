@@ -0,0 +1,276 @@
+//! Billing history.
+//!
+//! Every payment the monitor applies is recorded with the subscription time
+//! it bought, so subscribers can see what they paid and when without a
+//! block explorer. Records are keyed by transaction hash and log index, so a
+//! backfill that replays old blocks does not duplicate them.
+
+use alloy::primitives::Address;
+use dashmap::DashMap;
+use redb::{Database, MultimapTableDefinition, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::payments::types::PaymentEvent;
+use crate::store::{backend, StoreResult};
+
+/// A processed payment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaymentRecord {
+    /// `<tx hash>-<log index>`.
+    pub id: String,
+    /// Chain the payment was made on; zero for records written before
+    /// multi-chain support, which are all from the primary chain.
+    #[serde(default)]
+    pub chain_id: u64,
+    pub tx_hash: String,
+    pub log_index: u64,
+    pub block_number: u64,
+    pub user: Address,
+    /// Amount paid in wei.
+    pub amount: String,
+    pub tier_id: u8,
+    /// Subscription time credited for the payment.
+    pub credited_secs: u64,
+    /// Subscription expiry after the payment was applied.
+    pub expiry: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_id: Option<Uuid>,
+    /// When the proxy applied the payment.
+    pub processed_at: u64,
+}
+
+impl PaymentRecord {
+    pub fn new(event: &PaymentEvent, credited_secs: u64, expiry: u64, processed_at: u64) -> Self {
+        Self {
+            id: record_id(&event.tx_hash, event.log_index),
+            chain_id: event.chain_id,
+            tx_hash: event.tx_hash.clone(),
+            log_index: event.log_index,
+            block_number: event.block_number,
+            user: event.user,
+            amount: event.amount.to_string(),
+            tier_id: event.tier_id,
+            credited_secs,
+            expiry,
+            quote_id: event.quote_id,
+            processed_at,
+        }
+    }
+}
+
+/// Id of the record for the payment logged at `log_index` of `tx_hash`.
+pub fn record_id(tx_hash: &str, log_index: u64) -> String {
+    format!("{}-{}", tx_hash.to_ascii_lowercase(), log_index)
+}
+
+/// Persistent storage for payment records.
+pub trait BillingStore: Send + Sync {
+    /// Store a record unless one with the same id exists. Returns whether it
+    /// was stored.
+    fn insert(&self, record: &PaymentRecord) -> StoreResult<bool>;
+
+    /// Get a record by id.
+    fn get(&self, id: &str) -> StoreResult<Option<PaymentRecord>>;
+
+    /// All records of `user`, in no particular order.
+    fn by_user(&self, user: Address) -> StoreResult<Vec<PaymentRecord>>;
+}
+
+/// In-memory store. Records are lost on restart.
+#[derive(Default)]
+pub struct MemoryBillingStore {
+    records: DashMap<String, PaymentRecord>,
+}
+
+impl BillingStore for MemoryBillingStore {
+    fn insert(&self, record: &PaymentRecord) -> StoreResult<bool> {
+        match self.records.entry(record.id.clone()) {
+            dashmap::Entry::Occupied(_) => Ok(false),
+            dashmap::Entry::Vacant(slot) => {
+                slot.insert(record.clone());
+                Ok(true)
+            }
+        }
+    }
+
+    fn get(&self, id: &str) -> StoreResult<Option<PaymentRecord>> {
+        Ok(self.records.get(id).map(|r| r.clone()))
+    }
+
+    fn by_user(&self, user: Address) -> StoreResult<Vec<PaymentRecord>> {
+        Ok(self.records.iter().filter(|r| r.user == user).map(|r| r.clone()).collect())
+    }
+}
+
+/// Record JSON keyed by record id.
+const PAYMENTS: TableDefinition<&str, &[u8]> = TableDefinition::new("payments");
+/// Record ids by user address.
+const BY_USER: MultimapTableDefinition<[u8; 20], &str> = MultimapTableDefinition::new("payments_by_user");
+
+/// Embedded on-disk store backed by redb.
+pub struct RedbBillingStore {
+    db: Database,
+}
+
+impl RedbBillingStore {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        let db = Database::create(path).map_err(backend)?;
+        let txn = db.begin_write().map_err(backend)?;
+        txn.open_table(PAYMENTS).map_err(backend)?;
+        txn.open_multimap_table(BY_USER).map_err(backend)?;
+        txn.commit().map_err(backend)?;
+        Ok(Self { db })
+    }
+}
+
+impl BillingStore for RedbBillingStore {
+    fn insert(&self, record: &PaymentRecord) -> StoreResult<bool> {
+        let json = serde_json::to_vec(record)?;
+        let txn = self.db.begin_write().map_err(backend)?;
+        {
+            let mut payments = txn.open_table(PAYMENTS).map_err(backend)?;
+            if payments.get(record.id.as_str()).map_err(backend)?.is_some() {
+                return Ok(false);
+            }
+            payments.insert(record.id.as_str(), json.as_slice()).map_err(backend)?;
+            txn.open_multimap_table(BY_USER)
+                .map_err(backend)?
+                .insert(record.user.into_array(), record.id.as_str())
+                .map_err(backend)?;
+        }
+        txn.commit().map_err(backend)?;
+        Ok(true)
+    }
+
+    fn get(&self, id: &str) -> StoreResult<Option<PaymentRecord>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let payments = txn.open_table(PAYMENTS).map_err(backend)?;
+        let stored = payments.get(id).map_err(backend)?;
+        Ok(stored.map(|v| serde_json::from_slice(v.value())).transpose()?)
+    }
+
+    fn by_user(&self, user: Address) -> StoreResult<Vec<PaymentRecord>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let payments = txn.open_table(PAYMENTS).map_err(backend)?;
+        let index = txn.open_multimap_table(BY_USER).map_err(backend)?;
+
+        let mut found = Vec::new();
+        for id in index.get(user.into_array()).map_err(backend)? {
+            let id = id.map_err(backend)?;
+            if let Some(stored) = payments.get(id.value()).map_err(backend)? {
+                found.push(serde_json::from_slice(stored.value())?);
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// Payment records of all subscribers, shared across reloads.
+#[derive(Clone)]
+pub struct BillingHistory {
+    store: Arc<dyn BillingStore>,
+}
+
+impl Default for BillingHistory {
+    fn default() -> Self {
+        Self::new(Arc::new(MemoryBillingStore::default()))
+    }
+}
+
+impl BillingHistory {
+    pub fn new(store: Arc<dyn BillingStore>) -> Self {
+        Self { store }
+    }
+
+    /// Open the database at `path`, or keep records in memory if `path` is
+    /// empty.
+    ///
+    /// Fails if the database cannot be opened, rather than losing the
+    /// payments recorded until the next restart.
+    pub fn open(path: &str) -> StoreResult<Self> {
+        if path.is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(Self::new(Arc::new(RedbBillingStore::open(path)?)))
+    }
+
+    /// Record a processed payment. Replayed payments are ignored.
+    pub fn record(&self, record: &PaymentRecord) -> StoreResult<bool> {
+        self.store.insert(record)
+    }
+
+    /// A user's payments, newest first.
+    pub fn history(&self, user: Address) -> StoreResult<Vec<PaymentRecord>> {
+        let mut records = self.store.by_user(user)?;
+        records.sort_by_key(|r| std::cmp::Reverse((r.block_number, r.log_index)));
+        Ok(records)
+    }
+
+    /// A payment of `user` by record id.
+    pub fn get(&self, user: Address, id: &str) -> StoreResult<Option<PaymentRecord>> {
+        Ok(self.store.get(&id.to_ascii_lowercase())?.filter(|r| r.user == user))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::U256;
+    use crate::store::conformance::check_backends;
+
+    fn event(user: u8, tx: u8, block: u64) -> PaymentEvent {
+        PaymentEvent {
+            chain_id: 1,
+            tx_hash: format!("0x{:064x}", tx),
+            log_index: 0,
+            block_number: block,
+            block_timestamp: 0,
+            user: Address::with_last_byte(user),
+            amount: U256::from(1000),
+            tier_id: 1,
+            duration_secs: None,
+            quote_id: None,
+        }
+    }
+
+    fn exercise(history: &BillingHistory) {
+        let alice = Address::with_last_byte(1);
+        let first = PaymentRecord::new(&event(1, 1, 10), 3600, 5000, 100);
+        assert!(history.record(&first).unwrap());
+        assert!(history.record(&PaymentRecord::new(&event(1, 2, 20), 3600, 8600, 200)).unwrap());
+        assert!(history.record(&PaymentRecord::new(&event(2, 3, 30), 3600, 3600, 300)).unwrap());
+        // Replays are not recorded twice
+        assert!(!history.record(&first).unwrap());
+
+        let records = history.history(alice).unwrap();
+        assert_eq!(records.iter().map(|r| r.block_number).collect::<Vec<_>>(), vec![20, 10]);
+        assert_eq!(history.get(alice, &first.id.to_uppercase().replace("0X", "0x")).unwrap(), Some(first.clone()));
+        // Other users' payments are not visible
+        assert!(history.get(Address::with_last_byte(2), &first.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_backends() {
+        check_backends(
+            "billing",
+            Box::new(BillingHistory::default()),
+            |path| Box::new(BillingHistory::new(Arc::new(RedbBillingStore::open(path).unwrap()))),
+            exercise,
+            |reopened| assert_eq!(reopened.history(Address::with_last_byte(1)).unwrap().len(), 2),
+        );
+    }
+
+    #[test]
+    fn test_open_fails_on_held_store() {
+        let path = std::env::temp_dir().join(format!("billing-{}.redb", Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let held = BillingHistory::open(path).unwrap();
+        assert!(BillingHistory::open(path).is_err());
+        drop(held);
+        std::fs::remove_file(path).unwrap();
+    }
+}
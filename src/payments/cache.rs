@@ -1,17 +1,46 @@
 //! Subscription caching and persistence.
+//!
+//! The cache is persisted together with the payment monitor checkpoints (the
+//! last fully processed block of each chain) in a single snapshot file. Snapshots are
+//! written to a temporary file, synced and renamed over the previous one, so
+//! a crash leaves either the old or the new state on disk, never a mix.
+//!
+//! The snapshot also lists every payment log applied to the subscriptions,
+//! so it can be written at any time: a monitor replaying blocks past its
+//! checkpoint skips the payments the snapshot already reflects. Each entry
+//! keeps its block and the subscription it replaced, so the payments of
+//! blocks a reorg replaced can be undone. Entries are pruned once their
+//! block is [`REORG_WINDOW_BLOCKS`] behind the checkpoint.
 
-use alloy::primitives::Address;
+use alloy::primitives::{keccak256, Address, B256};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufWriter, Write};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 use crate::observability::metrics;
 
+/// How far behind its chain's checkpoint an applied payment can still be
+/// rolled back. Older payments are considered final and pruned.
+pub const REORG_WINDOW_BLOCKS: u64 = 256;
+
+/// Snapshot format version written by this build.
+const SNAPSHOT_VERSION: u32 = 4;
+
+/// Version listing applied payments by id only.
+const UNJOURNALED_VERSION: u32 = 3;
+
+/// Version without the applied payments.
+const UNTRACKED_VERSION: u32 = 2;
+
+/// Version that held a single checkpoint instead of one per chain.
+const SINGLE_CHAIN_VERSION: u32 = 1;
+
 /// Information about a user's subscription.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SubscriptionInfo {
     /// The tier ID subscribed to.
     pub tier_id: u8,
@@ -37,18 +66,150 @@ impl SubscriptionInfo {
             .as_secs();
         self.expiry + grace_secs > now
     }
+
+    /// Standing of the subscription at `now`.
+    pub fn status(&self, grace_secs: u64, now: u64) -> SubscriptionStatus {
+        if self.expiry > now {
+            SubscriptionStatus::Active
+        } else if self.expiry.saturating_add(grace_secs) > now {
+            SubscriptionStatus::Grace
+        } else {
+            SubscriptionStatus::Expired
+        }
+    }
+}
+
+/// Whether a subscription still grants access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    Active,
+    /// Expired, but still served until the grace period ends.
+    Grace,
+    Expired,
+}
+
+/// The last block whose events are fully reflected in the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Last processed block number.
+    pub last_block: u64,
+    /// Hash of `last_block`, used to detect reorgs and chain resets on restart.
+    pub block_hash: Option<B256>,
+}
+
+/// Outcome of loading the persisted snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckpointState {
+    /// No snapshot on disk (first start).
+    Missing,
+    /// Snapshot loaded and verified.
+    Valid(Checkpoint),
+    /// Snapshot unreadable, tampered with or written by an incompatible
+    /// version. The cache is empty and must be rebuilt by a backfill.
+    Corrupted(String),
+}
+
+/// A payment log applied to the subscriptions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct AppliedPayment {
+    /// Id of the log, see [`record_id`](crate::payments::billing::record_id).
+    id: String,
+    /// Block the log was mined in.
+    block: u64,
+    /// Subscriber and the subscription the payment replaced; `None` for
+    /// payments taken over from a version 3 snapshot, which cannot be undone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    undo: Option<(Address, Option<SubscriptionInfo>)>,
+}
+
+/// On-disk representation of the cache and its checkpoints.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    /// Checkpoint of a version 1 snapshot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checkpoint: Option<Checkpoint>,
+    /// Checkpoint per chain id.
+    #[serde(default)]
+    checkpoints: BTreeMap<u64, Checkpoint>,
+    subscriptions: BTreeMap<Address, SubscriptionInfo>,
+    /// Ids of the payment logs reflected in `subscriptions` of a version 3
+    /// snapshot, per chain id.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    applied: BTreeMap<u64, BTreeSet<String>>,
+    /// Payment logs reflected in `subscriptions` in the order they were
+    /// applied, per chain id.
+    #[serde(default)]
+    journal: BTreeMap<u64, Vec<AppliedPayment>>,
+    /// keccak256 over the canonical JSON of the fields above.
+    checksum: B256,
+}
+
+impl Snapshot {
+    fn compute_checksum(
+        version: u32,
+        checkpoints: &BTreeMap<u64, Checkpoint>,
+        subscriptions: &BTreeMap<Address, SubscriptionInfo>,
+        journal: &BTreeMap<u64, Vec<AppliedPayment>>,
+    ) -> B256 {
+        // BTreeMap keeps the encoding deterministic.
+        let body = serde_json::to_vec(&(version, checkpoints, subscriptions, journal)).unwrap_or_default();
+        keccak256(body)
+    }
+
+    fn compute_unjournaled_checksum(
+        checkpoints: &BTreeMap<u64, Checkpoint>,
+        subscriptions: &BTreeMap<Address, SubscriptionInfo>,
+        applied: &BTreeMap<u64, BTreeSet<String>>,
+    ) -> B256 {
+        let body =
+            serde_json::to_vec(&(UNJOURNALED_VERSION, checkpoints, subscriptions, applied)).unwrap_or_default();
+        keccak256(body)
+    }
+
+    fn compute_untracked_checksum(
+        checkpoints: &BTreeMap<u64, Checkpoint>,
+        subscriptions: &BTreeMap<Address, SubscriptionInfo>,
+    ) -> B256 {
+        let body = serde_json::to_vec(&(UNTRACKED_VERSION, checkpoints, subscriptions)).unwrap_or_default();
+        keccak256(body)
+    }
+
+    fn compute_single_chain_checksum(
+        checkpoint: &Checkpoint,
+        subscriptions: &BTreeMap<Address, SubscriptionInfo>,
+    ) -> B256 {
+        let body = serde_json::to_vec(&(SINGLE_CHAIN_VERSION, checkpoint, subscriptions)).unwrap_or_default();
+        keccak256(body)
+    }
+}
+
+/// Checkpoints of all chains feeding the cache.
+#[derive(Default)]
+struct Checkpoints {
+    by_chain: BTreeMap<u64, Checkpoint>,
+    /// Payment logs applied so far, in order, per chain id.
+    journal: BTreeMap<u64, Vec<AppliedPayment>>,
+}
+
+/// Result of the initial load, handed out once per chain.
+struct LoadedState {
+    /// State of chains without a checkpoint in the snapshot.
+    default: CheckpointState,
+    checkpoints: BTreeMap<u64, Checkpoint>,
+    taken: BTreeSet<u64>,
 }
 
 /// A thread-safe cache for subscription data.
 #[derive(Clone, Default)]
 pub struct SubscriptionCache {
     /// The internal map of address -> subscription info.
-    /// Wrapped in Arc internally by DashMap? No, DashMap is concurrent but not Arc'd itself usually needs Arc wrapper for cloning locally?
-    /// DashMap uses internal locking. But we want to share the whole map.
-    /// Usually `Arc<DashMap<...>>` is the way.
-    /// Wait, `DashMap` is `Send + Sync`.
-    /// I will implement `SubscriptionCache` as a wrapper around `Arc<DashMap>`.
     inner: Arc<DashMap<Address, SubscriptionInfo>>,
+    /// Last block reflected in `inner` per chain, persisted alongside it.
+    checkpoints: Arc<Mutex<Checkpoints>>,
+    /// Result of the initial load, consumed by the payment monitors.
+    loaded_state: Arc<Mutex<Option<LoadedState>>>,
     persistence_path: Option<String>,
 }
 
@@ -57,53 +218,236 @@ impl SubscriptionCache {
     pub fn new(persistence_path: Option<String>) -> Self {
         Self {
             inner: Arc::new(DashMap::new()),
+            checkpoints: Arc::new(Mutex::new(Checkpoints::default())),
+            loaded_state: Arc::new(Mutex::new(None)),
             persistence_path,
         }
     }
 
     /// Load from file if exists.
-    pub fn load_from_file(path: &str) -> std::io::Result<Self> {
+    ///
+    /// A snapshot that fails to parse or verify does not abort startup: the
+    /// file is moved aside to `<path>.corrupt` and the cache starts empty with
+    /// [`CheckpointState::Corrupted`], so the monitors backfill from chain.
+    /// The checkpoint of a single-chain (version 1) snapshot is attributed
+    /// to `legacy_chain_id`.
+    pub fn load_from_file(path: &str, legacy_chain_id: u64) -> std::io::Result<Self> {
         let cache = Self::new(Some(path.to_string()));
-        if Path::new(path).exists() {
-            let file = File::open(path)?;
-            let reader = BufReader::new(file);
-            let map: std::collections::HashMap<Address, SubscriptionInfo> = serde_json::from_reader(reader)?;
-            
-            for (k, v) in map {
-                cache.inner.insert(k, v);
+        let (default, checkpoints) = if Path::new(path).exists() {
+            let content = std::fs::read(path)?;
+            match Self::decode_snapshot(&content, legacy_chain_id) {
+                Ok(snapshot) => {
+                    for (k, v) in snapshot.subscriptions {
+                        cache.inner.insert(k, v);
+                    }
+                    let mut guard = cache.checkpoints.lock().expect("checkpoint mutex poisoned");
+                    guard.by_chain = snapshot.checkpoints.clone();
+                    guard.journal = snapshot.journal;
+                    drop(guard);
+                    metrics::record_cache_size(cache.inner.len());
+                    tracing::info!(
+                        chains = snapshot.checkpoints.len(),
+                        "Loaded {} subscriptions from cache file",
+                        cache.inner.len()
+                    );
+                    (CheckpointState::Missing, snapshot.checkpoints)
+                }
+                Err(reason) => {
+                    let quarantine = format!("{}.corrupt", path);
+                    tracing::error!(
+                        path = %path,
+                        quarantine = %quarantine,
+                        reason = %reason,
+                        "Subscription snapshot is corrupted, a backfill is required"
+                    );
+                    std::fs::rename(path, &quarantine)?;
+                    (CheckpointState::Corrupted(reason), BTreeMap::new())
+                }
             }
-            metrics::record_cache_size(cache.inner.len());
-            tracing::info!("Loaded {} subscriptions from cache file", cache.inner.len());
-        }
+        } else {
+            (CheckpointState::Missing, BTreeMap::new())
+        };
+        *cache.loaded_state.lock().expect("checkpoint mutex poisoned") =
+            Some(LoadedState { default, checkpoints, taken: BTreeSet::new() });
         Ok(cache)
     }
 
+    fn decode_snapshot(content: &[u8], legacy_chain_id: u64) -> Result<Snapshot, String> {
+        let mut snapshot: Snapshot = serde_json::from_slice(content)
+            .map_err(|e| format!("unreadable snapshot: {}", e))?;
+        let expected = match (snapshot.version, snapshot.checkpoint) {
+            (SNAPSHOT_VERSION, None) => Snapshot::compute_checksum(
+                snapshot.version,
+                &snapshot.checkpoints,
+                &snapshot.subscriptions,
+                &snapshot.journal,
+            ),
+            (UNJOURNALED_VERSION, None) => {
+                Snapshot::compute_unjournaled_checksum(&snapshot.checkpoints, &snapshot.subscriptions, &snapshot.applied)
+            }
+            (UNTRACKED_VERSION, None) => {
+                Snapshot::compute_untracked_checksum(&snapshot.checkpoints, &snapshot.subscriptions)
+            }
+            (SINGLE_CHAIN_VERSION, Some(checkpoint)) => {
+                snapshot.checkpoints = BTreeMap::from([(legacy_chain_id, checkpoint)]);
+                Snapshot::compute_single_chain_checksum(&checkpoint, &snapshot.subscriptions)
+            }
+            (version, _) => return Err(format!("unsupported snapshot version {}", version)),
+        };
+        if expected != snapshot.checksum {
+            return Err("checksum mismatch".to_string());
+        }
+        // Payments listed by id were applied past the checkpoint
+        for (chain_id, ids) in std::mem::take(&mut snapshot.applied) {
+            let block = snapshot.checkpoints.get(&chain_id).map_or(0, |c| c.last_block + 1);
+            let journal = snapshot.journal.entry(chain_id).or_default();
+            journal.extend(ids.into_iter().map(|id| AppliedPayment { id, block, undo: None }));
+        }
+        Ok(snapshot)
+    }
+
     /// Save to file.
+    ///
+    /// Writes the subscriptions, the payments applied to them and the
+    /// current checkpoints atomically. Safe at any time, including while a
+    /// monitor is between checkpoints.
     pub fn save_to_file(&self) -> std::io::Result<()> {
+        let guard = self.checkpoints.lock().expect("checkpoint mutex poisoned");
+        self.write_snapshot(&guard)
+    }
+
+    /// Write the snapshot while the checkpoint lock is held, so the map and
+    /// block numbers are captured together.
+    fn write_snapshot(&self, checkpoints: &Checkpoints) -> std::io::Result<()> {
         if let Some(path) = &self.persistence_path {
-            let file = File::create(path)?;
-            let writer = BufWriter::new(file);
-            
-            // DashMap doesn't serialize directly efficiently without collecting?
-            // Collect to HashMap first.
-            let map: std::collections::HashMap<_, _> = self.inner.iter()
+            let journal = checkpoints.journal.clone();
+            let checkpoints = checkpoints.by_chain.clone();
+            let subscriptions: BTreeMap<_, _> = self.inner.iter()
                 .map(|r| (*r.key(), r.value().clone()))
                 .collect();
-                
-            serde_json::to_writer(writer, &map)?;
-            tracing::info!("Saved {} subscriptions to cache file", map.len());
+
+            let checksum = Snapshot::compute_checksum(SNAPSHOT_VERSION, &checkpoints, &subscriptions, &journal);
+            let snapshot = Snapshot {
+                version: SNAPSHOT_VERSION,
+                checkpoint: None,
+                checkpoints,
+                subscriptions,
+                applied: BTreeMap::new(),
+                journal,
+                checksum,
+            };
+
+            let tmp_path = format!("{}.tmp", path);
+            {
+                let file = File::create(&tmp_path)?;
+                let mut writer = BufWriter::new(file);
+                serde_json::to_writer(&mut writer, &snapshot)?;
+                writer.flush()?;
+                writer.get_ref().sync_all()?;
+            }
+            std::fs::rename(&tmp_path, path)?;
+
+            tracing::debug!(
+                chains = snapshot.checkpoints.len(),
+                "Saved {} subscriptions to cache file",
+                snapshot.subscriptions.len()
+            );
         }
         Ok(())
     }
 
+    /// Record that all events of `chain_id` up to and including
+    /// `checkpoint.last_block` are applied, prune the payments that fell out
+    /// of the reorg window, and persist the snapshot.
+    pub fn commit_checkpoint(&self, chain_id: u64, checkpoint: Checkpoint) -> std::io::Result<()> {
+        let mut guard = self.checkpoints.lock().expect("checkpoint mutex poisoned");
+        guard.by_chain.insert(chain_id, checkpoint);
+        if let Some(journal) = guard.journal.get_mut(&chain_id) {
+            journal.retain(|p| p.block + REORG_WINDOW_BLOCKS > checkpoint.last_block);
+        }
+        self.write_snapshot(&guard)
+    }
+
+    /// Get the current checkpoint of `chain_id`, if any.
+    pub fn checkpoint(&self, chain_id: u64) -> Option<Checkpoint> {
+        self.checkpoints.lock().expect("checkpoint mutex poisoned").by_chain.get(&chain_id).copied()
+    }
+
+    /// Whether the payment log `id` of `chain_id` is already applied.
+    pub fn is_payment_applied(&self, chain_id: u64, id: &str) -> bool {
+        let guard = self.checkpoints.lock().expect("checkpoint mutex poisoned");
+        guard.journal.get(&chain_id).is_some_and(|journal| journal.iter().any(|p| p.id == id))
+    }
+
+    /// Set a user's subscription as the result of the payment log `id` of
+    /// `chain_id`, mined in `block`, unless that payment is already applied.
+    /// Returns whether it was applied now.
+    ///
+    /// The subscription and the record of the payment change together, so
+    /// any snapshot either reflects a payment and lists it, or neither.
+    pub fn apply_payment(&self, chain_id: u64, id: &str, block: u64, user: Address, tier_id: u8, expiry: u64) -> bool {
+        let mut guard = self.checkpoints.lock().expect("checkpoint mutex poisoned");
+        let journal = guard.journal.entry(chain_id).or_default();
+        if journal.iter().any(|p| p.id == id) {
+            return false;
+        }
+        let undo = Some((user, self.get_subscription(&user)));
+        journal.push(AppliedPayment { id: id.to_string(), block, undo });
+        self.update_subscription(user, tier_id, expiry);
+        true
+    }
+
+    /// Take the result of the initial load for `chain_id`.
+    ///
+    /// Returns `None` if it was already taken for the chain or the cache was
+    /// not loaded from disk.
+    pub fn take_loaded_state(&self, chain_id: u64) -> Option<CheckpointState> {
+        let mut guard = self.loaded_state.lock().expect("checkpoint mutex poisoned");
+        let loaded = guard.as_mut()?;
+        if !loaded.taken.insert(chain_id) {
+            return None;
+        }
+        Some(match loaded.checkpoints.get(&chain_id) {
+            Some(checkpoint) => CheckpointState::Valid(*checkpoint),
+            None => loaded.default.clone(),
+        })
+    }
+
+    /// Undo the payments of `chain_id` mined in or after `from_block`,
+    /// newest first, and drop the chain's checkpoint ahead of a rescan from
+    /// there. Returns how many payments were undone.
+    ///
+    /// Each subscriber gets back the subscription the payment replaced, so
+    /// later changes to it, e.g. payments on other chains or SLA credits,
+    /// are undone as well. The rescan applies the payments still on chain
+    /// again.
+    pub fn roll_back(&self, chain_id: u64, from_block: u64) -> usize {
+        let mut guard = self.checkpoints.lock().expect("checkpoint mutex poisoned");
+        guard.by_chain.remove(&chain_id);
+        let journal = guard.journal.entry(chain_id).or_default();
+        let (undone, kept): (Vec<_>, Vec<_>) = std::mem::take(journal).into_iter().partition(|p| p.block >= from_block);
+        *journal = kept;
+        for payment in undone.iter().rev() {
+            match &payment.undo {
+                Some((user, Some(previous))) => {
+                    self.inner.insert(*user, previous.clone());
+                }
+                Some((user, None)) => {
+                    self.inner.remove(user);
+                }
+                None => {}
+            }
+        }
+        metrics::record_cache_size(self.inner.len());
+        undone.len()
+    }
+
     /// Update subscription for a user.
     pub fn update_subscription(&self, user: Address, tier_id: u8, expiry: u64) {
         self.inner.insert(user, SubscriptionInfo { tier_id, expiry });
         metrics::record_subscription_event("update");
         metrics::record_cache_size(self.inner.len());
-        // Auto-save on update? Or rely on periodic save?
-        // For simplicity, save on update if critical, but might be slow.
-        // Let's rely on shutdown save for now, or calling code to trigger save.
+        // Persisted by the payment monitor together with its checkpoint.
     }
 
     /// Get subscription info if active.
@@ -111,6 +455,30 @@ impl SubscriptionCache {
         self.inner.get(user).map(|r| r.value().clone())
     }
 
+    /// Push a user's expiry back by `secs`, returning the new subscription.
+    ///
+    /// Returns `None` if the user has no subscription.
+    pub fn extend_subscription(&self, user: &Address, secs: u64) -> Option<SubscriptionInfo> {
+        let mut entry = self.inner.get_mut(user)?;
+        entry.expiry = entry.expiry.saturating_add(secs);
+        metrics::record_subscription_event("extend");
+        Some(entry.value().clone())
+    }
+
+    /// All subscriptions, including expired ones.
+    pub fn subscriptions(&self) -> Vec<(Address, SubscriptionInfo)> {
+        self.inner.iter().map(|r| (*r.key(), r.value().clone())).collect()
+    }
+
+    /// All currently active subscriptions.
+    pub fn active_subscriptions(&self) -> Vec<(Address, SubscriptionInfo)> {
+        self.inner
+            .iter()
+            .filter(|r| r.value().is_active())
+            .map(|r| (*r.key(), r.value().clone()))
+            .collect()
+    }
+
     /// Count active subscriptions.
     pub fn count(&self) -> usize {
         self.inner.len()
@@ -155,6 +523,16 @@ mod tests {
         assert!(!sub.is_active());
     }
 
+    #[test]
+    fn test_subscription_status() {
+        let sub = SubscriptionInfo { tier_id: 1, expiry: 1000 };
+        assert_eq!(sub.status(300, 999), SubscriptionStatus::Active);
+        assert_eq!(sub.status(300, 1000), SubscriptionStatus::Grace);
+        assert_eq!(sub.status(300, 1299), SubscriptionStatus::Grace);
+        assert_eq!(sub.status(300, 1300), SubscriptionStatus::Expired);
+        assert_eq!(sub.status(0, 1000), SubscriptionStatus::Expired);
+    }
+
     #[test]
     fn test_grace_period() {
         let now = SystemTime::now()
@@ -175,21 +553,220 @@ mod tests {
         assert!(!sub.is_active_with_grace(5));
     }
 
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{}_{}.json", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
     #[test]
     fn test_persistence() {
-        let path = "test_subs_persistence.json";
+        let path = temp_path("test_subs_persistence");
         
-        let cache = SubscriptionCache::new(Some(path.to_string()));
+        let cache = SubscriptionCache::new(Some(path.clone()));
         let user = Address::ZERO;
         cache.update_subscription(user, 2, 1234567890);
-        cache.save_to_file().unwrap();
+        let checkpoint = Checkpoint { last_block: 42, block_hash: Some(B256::repeat_byte(7)) };
+        cache.commit_checkpoint(1, checkpoint).unwrap();
         
         // Load new instance
-        let loaded = SubscriptionCache::load_from_file(path).unwrap();
+        let loaded = SubscriptionCache::load_from_file(&path, 1).unwrap();
         let sub = loaded.get_subscription(&user).unwrap();
         assert_eq!(sub.tier_id, 2);
+        assert_eq!(loaded.checkpoint(1), Some(checkpoint));
+        assert_eq!(loaded.take_loaded_state(1), Some(CheckpointState::Valid(checkpoint)));
+        assert!(loaded.take_loaded_state(1).is_none());
+        // A chain added since the snapshot was written starts without a checkpoint
+        assert_eq!(loaded.take_loaded_state(10), Some(CheckpointState::Missing));
         
         // Cleanup
-        std::fs::remove_file(path).unwrap_or_default();
+        std::fs::remove_file(&path).unwrap_or_default();
+    }
+
+    #[test]
+    fn test_applied_payments_persist() {
+        let path = temp_path("test_subs_applied");
+        let cache = SubscriptionCache::new(Some(path.clone()));
+        let user = Address::ZERO;
+        assert!(cache.apply_payment(1, "0xaa-0", 5, user, 1, 1000));
+        assert!(!cache.apply_payment(1, "0xaa-0", 5, user, 1, 2000));
+        assert_eq!(cache.get_subscription(&user).unwrap().expiry, 1000);
+        // Ids are tracked per chain
+        assert!(!cache.is_payment_applied(2, "0xaa-0"));
+
+        // A snapshot written between checkpoints lists what it reflects
+        cache.save_to_file().unwrap();
+        let loaded = SubscriptionCache::load_from_file(&path, 1).unwrap();
+        assert!(loaded.is_payment_applied(1, "0xaa-0"));
+        assert_eq!(loaded.take_loaded_state(1), Some(CheckpointState::Missing));
+
+        std::fs::remove_file(&path).unwrap_or_default();
+    }
+
+    #[test]
+    fn test_untracked_snapshot_is_migrated() {
+        let path = temp_path("test_subs_untracked");
+        let checkpoints = BTreeMap::from([(1, Checkpoint { last_block: 42, block_hash: None })]);
+        let subscriptions = BTreeMap::from([(Address::ZERO, SubscriptionInfo { tier_id: 1, expiry: 5 })]);
+        let snapshot = serde_json::json!({
+            "version": 2,
+            "checkpoints": checkpoints,
+            "subscriptions": subscriptions,
+            "checksum": Snapshot::compute_untracked_checksum(&checkpoints, &subscriptions),
+        });
+        std::fs::write(&path, snapshot.to_string()).unwrap();
+
+        let loaded = SubscriptionCache::load_from_file(&path, 1).unwrap();
+        assert_eq!(loaded.count(), 1);
+        assert_eq!(loaded.take_loaded_state(1), Some(CheckpointState::Valid(checkpoints[&1])));
+
+        std::fs::remove_file(&path).unwrap_or_default();
+    }
+
+    #[test]
+    fn test_missing_snapshot() {
+        let path = temp_path("test_subs_missing");
+        std::fs::remove_file(&path).unwrap_or_default();
+
+        let loaded = SubscriptionCache::load_from_file(&path, 1).unwrap();
+        assert_eq!(loaded.count(), 0);
+        assert_eq!(loaded.take_loaded_state(1), Some(CheckpointState::Missing));
+    }
+
+    #[test]
+    fn test_tampered_snapshot_is_quarantined() {
+        let path = temp_path("test_subs_tampered");
+        let cache = SubscriptionCache::new(Some(path.clone()));
+        cache.update_subscription(Address::ZERO, 1, 1000);
+        cache.commit_checkpoint(1, Checkpoint { last_block: 10, block_hash: None }).unwrap();
+
+        // Bump the expiry without updating the checksum
+        let content = std::fs::read_to_string(&path).unwrap().replace("1000", "9999999999");
+        std::fs::write(&path, content).unwrap();
+
+        let loaded = SubscriptionCache::load_from_file(&path, 1).unwrap();
+        assert_eq!(loaded.count(), 0);
+        assert!(loaded.checkpoint(1).is_none());
+        assert!(matches!(loaded.take_loaded_state(1), Some(CheckpointState::Corrupted(_))));
+        assert!(matches!(loaded.take_loaded_state(10), Some(CheckpointState::Corrupted(_))));
+        assert!(!Path::new(&path).exists());
+
+        let quarantine = format!("{}.corrupt", path);
+        assert!(Path::new(&quarantine).exists());
+        std::fs::remove_file(&quarantine).unwrap_or_default();
+    }
+
+    #[test]
+    fn test_legacy_snapshot_requires_backfill() {
+        // Files written before checkpoints were persisted are a bare map.
+        let path = temp_path("test_subs_legacy");
+        std::fs::write(&path, r#"{"0x0000000000000000000000000000000000000000":{"tier_id":1,"expiry":5}}"#).unwrap();
+
+        let loaded = SubscriptionCache::load_from_file(&path, 1).unwrap();
+        assert_eq!(loaded.count(), 0);
+        assert!(matches!(loaded.take_loaded_state(1), Some(CheckpointState::Corrupted(_))));
+
+        std::fs::remove_file(format!("{}.corrupt", path)).unwrap_or_default();
+    }
+
+    #[test]
+    fn test_single_chain_snapshot_is_migrated() {
+        let path = temp_path("test_subs_single_chain");
+        let checkpoint = Checkpoint { last_block: 42, block_hash: None };
+        let subscriptions = BTreeMap::from([(Address::ZERO, SubscriptionInfo { tier_id: 1, expiry: 5 })]);
+        let snapshot = serde_json::json!({
+            "version": 1,
+            "checkpoint": checkpoint,
+            "subscriptions": subscriptions,
+            "checksum": Snapshot::compute_single_chain_checksum(&checkpoint, &subscriptions),
+        });
+        std::fs::write(&path, snapshot.to_string()).unwrap();
+
+        let loaded = SubscriptionCache::load_from_file(&path, 7).unwrap();
+        assert_eq!(loaded.count(), 1);
+        assert_eq!(loaded.take_loaded_state(7), Some(CheckpointState::Valid(checkpoint)));
+        assert_eq!(loaded.take_loaded_state(1), Some(CheckpointState::Missing));
+
+        // Saved again in the per-chain format
+        loaded.save_to_file().unwrap();
+        let reloaded = SubscriptionCache::load_from_file(&path, 1).unwrap();
+        assert_eq!(reloaded.checkpoint(7), Some(checkpoint));
+
+        std::fs::remove_file(&path).unwrap_or_default();
+    }
+
+    #[test]
+    fn test_roll_back_restores_replaced_subscriptions() {
+        let cache = SubscriptionCache::new(None);
+        let checkpoint = Checkpoint { last_block: 10, block_hash: None };
+        cache.commit_checkpoint(1, checkpoint).unwrap();
+        cache.commit_checkpoint(2, checkpoint).unwrap();
+        let renewed = Address::repeat_byte(1);
+        let created = Address::repeat_byte(2);
+        cache.update_subscription(renewed, 1, 500);
+        assert!(cache.apply_payment(1, "0xab-0", 8, renewed, 1, 1000));
+        assert!(cache.apply_payment(1, "0xac-0", 11, renewed, 2, 2000));
+        assert!(cache.apply_payment(1, "0xad-0", 12, created, 1, 3000));
+        assert!(cache.apply_payment(2, "0xae-0", 12, created, 1, 4000));
+
+        assert_eq!(cache.roll_back(1, 11), 2);
+        assert!(cache.checkpoint(1).is_none());
+        assert_eq!(cache.checkpoint(2), Some(checkpoint));
+        // Undone newest first, so each subscriber is back before the block
+        assert_eq!(cache.get_subscription(&renewed), Some(SubscriptionInfo { tier_id: 1, expiry: 1000 }));
+        assert!(cache.get_subscription(&created).is_none());
+        assert!(cache.is_payment_applied(1, "0xab-0"));
+        assert!(!cache.is_payment_applied(1, "0xac-0"));
+        assert!(cache.is_payment_applied(2, "0xae-0"));
+
+        // The rescan applies the payments still on chain again
+        assert!(cache.apply_payment(1, "0xac-0", 11, renewed, 2, 2000));
+        assert_eq!(cache.roll_back(1, 0), 2);
+        assert_eq!(cache.get_subscription(&renewed), Some(SubscriptionInfo { tier_id: 1, expiry: 500 }));
+    }
+
+    #[test]
+    fn test_commit_prunes_final_payments() {
+        let cache = SubscriptionCache::new(None);
+        assert!(cache.apply_payment(1, "0xaa-0", 100, Address::ZERO, 1, 1000));
+        assert!(cache.apply_payment(1, "0xab-0", 101, Address::ZERO, 1, 2000));
+        assert!(cache.apply_payment(2, "0xac-0", 100, Address::with_last_byte(2), 1, 3000));
+
+        let last_block = 100 + REORG_WINDOW_BLOCKS;
+        cache.commit_checkpoint(1, Checkpoint { last_block, block_hash: None }).unwrap();
+        assert!(!cache.is_payment_applied(1, "0xaa-0"));
+        assert!(cache.is_payment_applied(1, "0xab-0"));
+        // Other chains keep their own window
+        assert!(cache.is_payment_applied(2, "0xac-0"));
+
+        // A pruned payment is final and its subscription stays
+        assert_eq!(cache.roll_back(1, 0), 1);
+        assert_eq!(cache.get_subscription(&Address::ZERO).unwrap().expiry, 1000);
+    }
+
+    #[test]
+    fn test_unjournaled_snapshot_is_migrated() {
+        let path = temp_path("test_subs_unjournaled");
+        let checkpoints = BTreeMap::from([(1, Checkpoint { last_block: 42, block_hash: None })]);
+        let subscriptions = BTreeMap::from([(Address::ZERO, SubscriptionInfo { tier_id: 1, expiry: 5 })]);
+        let applied = BTreeMap::from([(1, BTreeSet::from(["0xaa-0".to_string()]))]);
+        let snapshot = serde_json::json!({
+            "version": 3,
+            "checkpoints": checkpoints,
+            "subscriptions": subscriptions,
+            "applied": applied,
+            "checksum": Snapshot::compute_unjournaled_checksum(&checkpoints, &subscriptions, &applied),
+        });
+        std::fs::write(&path, snapshot.to_string()).unwrap();
+
+        let loaded = SubscriptionCache::load_from_file(&path, 1).unwrap();
+        assert!(loaded.is_payment_applied(1, "0xaa-0"));
+        assert_eq!(loaded.take_loaded_state(1), Some(CheckpointState::Valid(checkpoints[&1])));
+        // Payments without their replaced subscription are kept on roll back
+        assert_eq!(loaded.roll_back(1, 0), 1);
+        assert_eq!(loaded.count(), 1);
+
+        std::fs::remove_file(&path).unwrap_or_default();
     }
 }
@@ -0,0 +1,516 @@
+//! Subscription tier catalog.
+//!
+//! Single source of truth for what a tier costs and what it grants. Pricing
+//! (price, duration, active flag) is loaded from `SubscriptionManager.getTier`
+//! and kept current by the payment monitor following `TierUpdated` events.
+//! Prices are in the primary chain's native currency and do not apply on
+//! chains paying in another one.
+//! Policies (rate, burst, connections, priority, ...) come from the local QoS
+//! configuration and price book terms (amount override, volume discounts)
+//! from the pricing configuration; both are replaced on every config reload.
+
+use alloy::network::TransactionBuilder;
+use alloy::primitives::{Address, U256};
+use alloy::rpc::types::TransactionRequest;
+use alloy::sol;
+use alloy::sol_types::SolCall;
+use arc_swap::ArcSwap;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::blockchain::client::BlockchainClient;
+use crate::blockchain::types::{BlockchainError, BlockchainResult};
+use crate::config::{PricingConfig, QosConfig, TierPolicy, VolumeDiscount};
+
+/// Period credited for payments to tiers the catalog does not know.
+const DEFAULT_PERIOD_SECS: u64 = 30 * 24 * 3600;
+
+sol! {
+    /// Tier definition as stored by `SubscriptionManager`.
+    #[derive(Debug)]
+    struct Tier {
+        uint256 price;
+        uint256 duration;
+        bool isActive;
+    }
+
+    /// `SubscriptionManager.getTier`.
+    function getTier(uint8 tierId) external view returns (Tier memory);
+
+    /// Emitted by `SubscriptionManager.setTier`.
+    #[derive(Debug)]
+    event TierUpdated(uint8 indexed tierId, uint256 price, uint256 duration);
+}
+
+/// On-chain pricing of a tier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TierPricing {
+    /// Price per period in wei.
+    pub price: U256,
+    /// Length of one paid period in seconds.
+    pub duration_secs: u64,
+    /// Whether the tier can currently be purchased.
+    pub active: bool,
+}
+
+impl TierPricing {
+    /// Price of `duration_secs`, pro-rata per second of the base period and
+    /// reduced by the best applicable volume discount. Rounds up.
+    pub fn price_for(&self, duration_secs: u64, discounts: &[VolumeDiscount]) -> U256 {
+        let base = if self.duration_secs == 0 {
+            self.price
+        } else {
+            (self.price * U256::from(duration_secs)).div_ceil(U256::from(self.duration_secs))
+        };
+        let discount_bps = discounts
+            .iter()
+            .filter(|d| d.min_duration_secs <= duration_secs)
+            .max_by_key(|d| d.min_duration_secs)
+            .map_or(0, |d| d.discount_bps.min(10_000));
+        (base * U256::from(10_000 - discount_bps)).div_ceil(U256::from(10_000))
+    }
+}
+
+/// Price book terms for a tier.
+#[derive(Debug, Clone, Default)]
+struct TierTerms {
+    /// Per-period price overriding the on-chain one.
+    amount: Option<U256>,
+    discounts: Vec<VolumeDiscount>,
+}
+
+/// Merged view of a tier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TierInfo {
+    pub tier_id: u8,
+    /// `None` if the tier is not defined on chain.
+    pub pricing: Option<TierPricing>,
+    /// `None` if no local QoS policy is configured for the tier.
+    pub policy: Option<TierPolicy>,
+}
+
+/// Catalog of subscription tiers shared by quoting, rate limiting,
+/// connection tracking and the admin API.
+pub struct TierCatalog {
+    pricing: DashMap<u8, TierPricing>,
+    qos: ArcSwap<QosConfig>,
+    terms: ArcSwap<BTreeMap<u8, TierTerms>>,
+    /// Whether pricing was loaded from chain.
+    synced: AtomicBool,
+    /// Chains whose native currency the prices are not in.
+    unpriced_chains: ArcSwap<BTreeSet<u64>>,
+}
+
+impl TierCatalog {
+    /// Create a catalog with local policies from `qos`.
+    ///
+    /// Pricing stays empty until [`TierCatalog::sync`] loads the deployed
+    /// values; until then no tier can be quoted.
+    pub fn new(qos: &QosConfig) -> Self {
+        Self {
+            pricing: DashMap::new(),
+            qos: ArcSwap::from_pointee(qos.clone()),
+            terms: ArcSwap::from_pointee(BTreeMap::new()),
+            synced: AtomicBool::new(false),
+            unpriced_chains: ArcSwap::from_pointee(BTreeSet::new()),
+        }
+    }
+
+    /// Catalog priced like a fresh `SubscriptionManager` deployment
+    /// (0.01 and 0.05 ETH per month for tiers 1 and 2).
+    #[cfg(test)]
+    pub(crate) fn with_default_pricing(qos: &QosConfig) -> Self {
+        let catalog = Self::new(qos);
+        let month = 30 * 24 * 3600;
+        catalog.set_pricing(1, TierPricing {
+            price: U256::from(10_000_000_000_000_000u64),
+            duration_secs: month,
+            active: true,
+        });
+        catalog.set_pricing(2, TierPricing {
+            price: U256::from(50_000_000_000_000_000u64),
+            duration_secs: month,
+            active: true,
+        });
+        catalog.synced.store(true, Ordering::Relaxed);
+        catalog
+    }
+
+    /// Whether pricing was loaded from chain, so tiers can be quoted.
+    pub fn is_synced(&self) -> bool {
+        self.synced.load(Ordering::Relaxed)
+    }
+
+    /// Replace local policies after a config reload.
+    pub fn set_qos(&self, qos: &QosConfig) {
+        self.qos.store(Arc::new(qos.clone()));
+    }
+
+    /// Replace price book terms after a config reload.
+    pub fn set_price_book(&self, pricing: &PricingConfig) {
+        let terms = pricing
+            .services
+            .values()
+            .filter_map(|service| {
+                let tier_id = service.tier_id?;
+                Some((tier_id, TierTerms {
+                    amount: service
+                        .amount
+                        .as_deref()
+                        .and_then(|a| U256::from_str_radix(a, 10).ok()),
+                    discounts: service.volume_discounts.clone(),
+                }))
+            })
+            .collect();
+        self.terms.store(Arc::new(terms));
+    }
+
+    /// Replace the chains the prices do not apply on after a config reload.
+    pub fn set_unpriced_chains(&self, chains: BTreeSet<u64>) {
+        self.unpriced_chains.store(Arc::new(chains));
+    }
+
+    /// Whether native prices apply to payments on `chain_id`.
+    pub fn prices_chain(&self, chain_id: u64) -> bool {
+        !self.unpriced_chains.load().contains(&chain_id)
+    }
+
+    /// Set the pricing of a tier.
+    pub fn set_pricing(&self, tier_id: u8, pricing: TierPricing) {
+        self.pricing.insert(tier_id, pricing);
+    }
+
+    /// Least `PaymentProcessor.purchaseSubscriptionFor` accepts for
+    /// `duration_secs` of a tier without a quote: the on-chain price,
+    /// pro-rata. Rounds down like the contract.
+    ///
+    /// `None` if the tier has no on-chain pricing.
+    pub fn contract_floor(&self, tier_id: u8, duration_secs: u64) -> Option<U256> {
+        let pricing = self.pricing.get(&tier_id).map(|r| r.value().clone())?;
+        if pricing.duration_secs == 0 {
+            return None;
+        }
+        Some(pricing.price * U256::from(duration_secs) / U256::from(pricing.duration_secs))
+    }
+
+    /// Get the merged view of a tier, if it is known to either source.
+    pub fn get(&self, tier_id: u8) -> Option<TierInfo> {
+        let pricing = self.pricing.get(&tier_id).map(|r| r.value().clone());
+        let policy = self.qos.load().tiers.get(&tier_id).cloned();
+        if pricing.is_none() && policy.is_none() {
+            return None;
+        }
+        Some(TierInfo { tier_id, pricing, policy })
+    }
+
+    /// Pricing of a tier that can currently be purchased.
+    pub fn active_pricing(&self, tier_id: u8) -> Option<TierPricing> {
+        self.pricing
+            .get(&tier_id)
+            .map(|r| r.value().clone())
+            .filter(|p| p.active)
+    }
+
+    /// Price of subscribing to an offered tier for `duration_secs`.
+    ///
+    /// `converted` is the per-period price of a USD-priced tier at the rate
+    /// being quoted; it applies unless the price book sets an amount.
+    pub fn price_for_duration(&self, tier_id: u8, duration_secs: u64, converted: Option<U256>) -> Option<U256> {
+        self.active_pricing(tier_id)?;
+        let (pricing, discounts) = self.effective_pricing(tier_id, converted)?;
+        Some(pricing.price_for(duration_secs, &discounts))
+    }
+
+    /// Value of `duration_secs` of a tier, whether or not it is still
+    /// offered. Used to credit unused time when switching tiers.
+    pub fn value_of(&self, tier_id: u8, duration_secs: u64, converted: Option<U256>) -> Option<U256> {
+        let (pricing, discounts) = self.effective_pricing(tier_id, converted)?;
+        Some(pricing.price_for(duration_secs, &discounts))
+    }
+
+    /// Time of an offered tier that `amount` pays for at the undiscounted rate.
+    pub fn duration_for(&self, tier_id: u8, amount: U256, converted: Option<U256>) -> Option<u64> {
+        self.active_pricing(tier_id)?;
+        let (pricing, _) = self.effective_pricing(tier_id, converted)?;
+        if pricing.price.is_zero() {
+            return None;
+        }
+        let covered = amount * U256::from(pricing.duration_secs) / pricing.price;
+        Some(u64::try_from(covered).unwrap_or(u64::MAX))
+    }
+
+    /// Subscription time bought by a confirmed payment of `amount` that
+    /// did not redeem a quote.
+    ///
+    /// Payments without a duration buy one base period. A requested
+    /// duration is honored if the amount covers it at the undiscounted
+    /// on-chain price (see [`contract_floor`](Self::contract_floor));
+    /// otherwise only the time the amount pays for at that rate is credited.
+    pub fn credited_duration(&self, tier_id: u8, amount: U256, requested: Option<u64>) -> u64 {
+        let Some(pricing) = self.pricing.get(&tier_id).map(|r| r.value().clone()) else {
+            // Unknown locally; the chain accepted the payment
+            return self.accepted_duration(tier_id, requested);
+        };
+        let Some(requested) = requested else {
+            return pricing.duration_secs;
+        };
+        let floor = self.contract_floor(tier_id, requested).unwrap_or_default();
+        if amount >= floor || pricing.price.is_zero() {
+            return requested;
+        }
+        let covered = amount * U256::from(pricing.duration_secs) / pricing.price;
+        tracing::warn!(
+            tier = tier_id,
+            %amount,
+            requested,
+            "Payment does not cover quoted duration, crediting pro-rata"
+        );
+        u64::try_from(covered).unwrap_or(u64::MAX).min(requested)
+    }
+
+    /// Subscription time bought by a payment the catalog cannot price, e.g.
+    /// on a chain in [`set_unpriced_chains`](Self::set_unpriced_chains):
+    /// the requested duration or one base period, as the contract there
+    /// checked the amount.
+    pub fn accepted_duration(&self, tier_id: u8, requested: Option<u64>) -> u64 {
+        requested.unwrap_or_else(|| self.pricing.get(&tier_id).map_or(DEFAULT_PERIOD_SECS, |p| p.duration_secs))
+    }
+
+    /// On-chain pricing with the price book amount (or the `converted`
+    /// USD price) overriding the price.
+    fn effective_pricing(&self, tier_id: u8, converted: Option<U256>) -> Option<(TierPricing, Vec<VolumeDiscount>)> {
+        let mut pricing = self.pricing.get(&tier_id).map(|r| r.value().clone())?;
+        let terms = self.terms.load().get(&tier_id).cloned().unwrap_or_default();
+        if let Some(amount) = terms.amount.or(converted) {
+            pricing.price = amount;
+        }
+        Some((pricing, terms.discounts))
+    }
+
+    /// Effective QoS policy of a tier, honoring `qos.fallback_tier`.
+    ///
+    /// `None` means subscribers of this tier must not be served.
+    pub fn policy(&self, tier_id: u8) -> Option<TierPolicy> {
+        self.qos.load().policy(tier_id).cloned()
+    }
+
+    /// All known tiers, ordered by id.
+    pub fn all(&self) -> Vec<TierInfo> {
+        let mut ids: Vec<u8> = self.pricing.iter().map(|r| *r.key()).collect();
+        ids.extend(self.qos.load().tiers.keys().copied());
+        ids.sort_unstable();
+        ids.dedup();
+        ids.into_iter().filter_map(|id| self.get(id)).collect()
+    }
+
+    /// Load the given tiers from the `SubscriptionManager` contract.
+    pub async fn sync(&self, client: &BlockchainClient, manager: Address, tier_ids: &[u8]) -> BlockchainResult<()> {
+        for &tier_id in tier_ids {
+            self.refresh(client, manager, tier_id).await?;
+        }
+        self.synced.store(true, Ordering::Relaxed);
+        tracing::info!(tiers = tier_ids.len(), "Tier catalog synced from chain");
+        Ok(())
+    }
+
+    /// [`sync`](Self::sync), retried every `interval` until it succeeds.
+    pub async fn sync_until_ready(
+        &self,
+        client: &BlockchainClient,
+        manager: Address,
+        tier_ids: &[u8],
+        interval: Duration,
+    ) {
+        while let Err(e) = self.sync(client, manager, tier_ids).await {
+            tracing::error!("Failed to sync tier catalog, tiers cannot be quoted yet: {}", e);
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Reload a single tier from the `SubscriptionManager` contract.
+    pub async fn refresh(
+        &self,
+        client: &BlockchainClient,
+        manager: Address,
+        tier_id: u8,
+    ) -> BlockchainResult<()> {
+        let tx = TransactionRequest::default()
+            .with_to(manager)
+            .with_input(getTierCall { tierId: tier_id }.abi_encode());
+        let output = client.call(tx).await?;
+        let tier = getTierCall::abi_decode_returns(&output)
+            .map_err(|e| BlockchainError::Rpc(format!("Invalid getTier response: {}", e)))?;
+
+        let duration_secs = u64::try_from(tier.duration).unwrap_or(u64::MAX);
+        // Unset mapping entries read back as all zeroes
+        if tier.price.is_zero() && duration_secs == 0 && !tier.isActive {
+            self.pricing.remove(&tier_id);
+            return Ok(());
+        }
+        self.set_pricing(tier_id, TierPricing {
+            price: tier.price,
+            duration_secs,
+            active: tier.isActive,
+        });
+        Ok(())
+    }
+
+    /// Apply a `TierUpdated` event when the contract cannot be re-read.
+    ///
+    /// The event does not carry the active flag, so the known one is kept
+    /// (new tiers are assumed active).
+    pub fn apply_update(&self, event: &TierUpdated) {
+        let active = self
+            .pricing
+            .get(&event.tierId)
+            .map(|r| r.value().active)
+            .unwrap_or(true);
+        self.set_pricing(event.tierId, TierPricing {
+            price: event.price,
+            duration_secs: u64::try_from(event.duration).unwrap_or(u64::MAX),
+            active,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starts_without_pricing() {
+        let catalog = TierCatalog::new(&QosConfig::default());
+        assert!(!catalog.is_synced());
+        assert!(catalog.active_pricing(1).is_none());
+        assert!(catalog.get(1).unwrap().pricing.is_none());
+    }
+
+    #[test]
+    fn test_merges_chain_pricing_and_local_limits() {
+        let catalog = TierCatalog::with_default_pricing(&QosConfig::default());
+
+        let tier_1 = catalog.get(1).unwrap();
+        assert_eq!(tier_1.pricing.unwrap().duration_secs, 30 * 24 * 3600);
+        let policy = tier_1.policy.unwrap();
+        assert_eq!((policy.rps, policy.burst(), policy.max_conns), (10, 20, 1));
+
+        // Tier 3 only has a local policy until it is defined on chain
+        let tier_3 = catalog.get(3).unwrap();
+        assert!(tier_3.pricing.is_none());
+        assert!(catalog.active_pricing(3).is_none());
+
+        assert!(catalog.get(9).is_none());
+        assert_eq!(catalog.all().len(), 3);
+    }
+
+    #[test]
+    fn test_tier_updated_keeps_active_flag() {
+        let catalog = TierCatalog::new(&QosConfig::default());
+        catalog.set_pricing(2, TierPricing { price: U256::from(1), duration_secs: 10, active: false });
+
+        catalog.apply_update(&TierUpdated {
+            tierId: 2,
+            price: U256::from(7),
+            duration: U256::from(3600),
+        });
+        let pricing = catalog.get(2).unwrap().pricing.unwrap();
+        assert_eq!(pricing.price, U256::from(7));
+        assert_eq!(pricing.duration_secs, 3600);
+        assert!(!pricing.active);
+
+        // Unknown tiers are assumed active
+        catalog.apply_update(&TierUpdated { tierId: 4, price: U256::from(1), duration: U256::from(60) });
+        assert!(catalog.active_pricing(4).is_some());
+    }
+
+    #[test]
+    fn test_qos_reload() {
+        let catalog = TierCatalog::with_default_pricing(&QosConfig::default());
+        let mut qos = QosConfig::default();
+        qos.tiers.get_mut(&2).unwrap().rps = 7;
+        catalog.set_qos(&qos);
+        assert_eq!(catalog.policy(2).unwrap().rps, 7);
+        // Pricing survives the reload
+        assert!(catalog.active_pricing(2).is_some());
+
+        // Unknown tiers have no policy unless a fallback is configured
+        assert!(catalog.policy(5).is_none());
+        qos.fallback_tier = Some(1);
+        catalog.set_qos(&qos);
+        assert_eq!(catalog.policy(5).unwrap().rps, 10);
+    }
+
+    #[test]
+    fn test_duration_pricing() {
+        let pricing = TierPricing { price: U256::from(3000), duration_secs: 30, active: true };
+        let discounts = vec![
+            VolumeDiscount { min_duration_secs: 60, discount_bps: 1000 },
+            VolumeDiscount { min_duration_secs: 90, discount_bps: 2000 },
+        ];
+
+        // Pro-rata per second of the base period
+        assert_eq!(pricing.price_for(30, &[]), U256::from(3000));
+        assert_eq!(pricing.price_for(15, &discounts), U256::from(1500));
+        assert_eq!(pricing.price_for(1, &[]), U256::from(100));
+        // Best applicable discount wins
+        assert_eq!(pricing.price_for(60, &discounts), U256::from(5400));
+        assert_eq!(pricing.price_for(120, &discounts), U256::from(9600));
+    }
+
+    #[test]
+    fn test_credited_duration() {
+        let catalog = TierCatalog::new(&QosConfig::default());
+        catalog.set_pricing(1, TierPricing { price: U256::from(1000), duration_secs: 100, active: true });
+        let mut book = PricingConfig::default();
+        book.services.get_mut("subscription_tier1").unwrap().volume_discounts =
+            vec![VolumeDiscount { min_duration_secs: 1000, discount_bps: 5000 }];
+        catalog.set_price_book(&book);
+
+        assert_eq!(catalog.price_for_duration(1, 1000, None), Some(U256::from(5000)));
+        assert_eq!(catalog.price_for_duration(1, 1000, Some(U256::from(100))), Some(U256::from(500)));
+        // Legacy payments buy one base period
+        assert_eq!(catalog.credited_duration(1, U256::from(1000), None), 100);
+        // Requested durations are honored when the payment covers the undiscounted price
+        assert_eq!(catalog.credited_duration(1, U256::from(10_000), Some(1000)), 1000);
+        // Discounts need a quote: anything less is credited pro-rata
+        assert_eq!(catalog.credited_duration(1, U256::from(5000), Some(1000)), 500);
+        assert_eq!(catalog.credited_duration(1, U256::from(2000), Some(1000)), 200);
+        // Unknown tiers fall back to the requested or default period
+        assert_eq!(catalog.credited_duration(9, U256::from(1), Some(50)), 50);
+        assert_eq!(catalog.credited_duration(9, U256::from(1), None), DEFAULT_PERIOD_SECS);
+        // Payments the catalog cannot price get what their contract accepted
+        assert_eq!(catalog.accepted_duration(1, Some(1000)), 1000);
+        assert_eq!(catalog.accepted_duration(1, None), 100);
+
+        catalog.set_unpriced_chains([10].into());
+        assert!(catalog.prices_chain(1));
+        assert!(!catalog.prices_chain(10));
+
+        // Price book amounts override the on-chain price
+        book.services.get_mut("subscription_tier1").unwrap().amount = Some("10".to_string());
+        catalog.set_price_book(&book);
+        assert_eq!(catalog.price_for_duration(1, 100, None), Some(U256::from(10)));
+    }
+
+    #[test]
+    fn test_contract_floor() {
+        let catalog = TierCatalog::new(&QosConfig::default());
+        catalog.set_pricing(1, TierPricing { price: U256::from(1000), duration_secs: 100, active: true });
+        assert_eq!(catalog.contract_floor(1, 200), Some(U256::from(2000)));
+        assert_eq!(catalog.contract_floor(9, 200), None);
+        // Rounds down like the contract
+        catalog.set_pricing(1, TierPricing { price: U256::from(999), duration_secs: 100, active: true });
+        assert_eq!(catalog.contract_floor(1, 1), Some(U256::from(9)));
+
+        // Un-quoted payments are credited as the contract credited them,
+        // whatever the price book says
+        let mut book = PricingConfig::default();
+        book.services.get_mut("subscription_tier1").unwrap().amount = Some("5000".to_string());
+        catalog.set_price_book(&book);
+        assert_eq!(catalog.credited_duration(1, U256::from(1998), Some(200)), 200);
+    }
+}
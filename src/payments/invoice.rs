@@ -0,0 +1,198 @@
+//! Signed invoices.
+//!
+//! An invoice is a [`PaymentRecord`] signed by the proxy wallet as EIP-712
+//! typed data under the same domain as quotes for the chain the payment was
+//! made on, so anyone can check that the proxy acknowledged the payment and
+//! the subscription time it bought.
+
+use alloy::dyn_abi::TypedData;
+use alloy::primitives::{Address, FixedBytes, B256, U256};
+use alloy::signers::Signature;
+use alloy::sol_types::{Eip712Domain, SolStruct};
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::types::{BlockchainError, BlockchainResult};
+use crate::blockchain::wallet::{Signable, Wallet};
+use crate::payments::billing::PaymentRecord;
+use crate::quoting::signing::ChainDomains;
+
+mod typed {
+    alloy::sol! {
+        /// EIP-712 representation of an invoice. `quoteId` is zero for
+        /// payments without a quote.
+        #[derive(serde::Serialize)]
+        struct Invoice {
+            bytes32 txHash;
+            uint64 logIndex;
+            uint64 blockNumber;
+            address user;
+            uint256 amount;
+            uint8 tierId;
+            uint64 credited;
+            uint64 expiry;
+            bytes16 quoteId;
+            uint64 issuedAt;
+        }
+    }
+}
+
+/// A payment as acknowledged by the proxy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invoice {
+    /// Address of the signing proxy wallet.
+    pub issuer: Address,
+    pub payment_processor: Address,
+    /// Issue date; the time the payment was applied.
+    pub issued_at: u64,
+    /// The payment, including the chain it was made on.
+    #[serde(flatten)]
+    pub payment: PaymentRecord,
+}
+
+/// An invoice with the issuer's signature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedInvoice {
+    pub invoice: Invoice,
+    pub signature: Signature,
+    /// The EIP-712 hash that was signed.
+    pub hash: B256,
+}
+
+/// Signs invoices with the proxy wallet.
+pub struct InvoiceSigner {
+    wallet: Wallet,
+    chains: ChainDomains,
+}
+
+impl InvoiceSigner {
+    /// Sign invoices for payments to `payment_processor` on the wallet's chain.
+    pub fn new(wallet: Wallet, payment_processor: Address) -> Self {
+        let chains = ChainDomains::new(wallet.chain_id(), payment_processor);
+        Self { wallet, chains }
+    }
+
+    /// Also sign invoices for payments to `payment_processor` on `chain_id`.
+    pub fn with_chain(mut self, chain_id: u64, payment_processor: Address) -> Self {
+        self.chains = self.chains.with_chain(chain_id, payment_processor);
+        self
+    }
+
+    /// Render and sign the invoice for `payment`.
+    pub async fn sign(&self, mut payment: PaymentRecord) -> BlockchainResult<SignedInvoice> {
+        if payment.chain_id == 0 {
+            payment.chain_id = self.chains.default_chain();
+        }
+        let (Some(payment_processor), Some(domain)) =
+            (self.chains.processor(payment.chain_id), self.chains.domain(payment.chain_id))
+        else {
+            return Err(BlockchainError::NotAvailable(format!(
+                "payments on chain {} are not accepted",
+                payment.chain_id
+            )));
+        };
+        let invoice = Invoice {
+            issuer: self.wallet.address(),
+            payment_processor,
+            issued_at: payment.processed_at,
+            payment,
+        };
+        let hash = invoice_hash(&invoice, &domain);
+        let typed_data = TypedData::from_struct(&typed_invoice(&invoice), Some(domain));
+        let signature = self.wallet.sign(&Signable::TypedData(Box::new(typed_data))).await?;
+        Ok(SignedInvoice { invoice, signature, hash })
+    }
+}
+
+/// EIP-712 hash of `invoice` under `domain`.
+pub fn invoice_hash(invoice: &Invoice, domain: &Eip712Domain) -> B256 {
+    typed_invoice(invoice).eip712_signing_hash(domain)
+}
+
+fn typed_invoice(invoice: &Invoice) -> typed::Invoice {
+    let payment = &invoice.payment;
+    // Records are built from decoded logs, so both always parse
+    let tx_hash = payment.tx_hash.parse::<B256>().unwrap_or_default();
+    let amount = U256::from_str_radix(&payment.amount, 10).unwrap_or_default();
+    typed::Invoice {
+        txHash: tx_hash,
+        logIndex: payment.log_index,
+        blockNumber: payment.block_number,
+        user: payment.user,
+        amount,
+        tierId: payment.tier_id,
+        credited: payment.credited_secs,
+        expiry: payment.expiry,
+        quoteId: FixedBytes(payment.quote_id.map(|id| *id.as_bytes()).unwrap_or_default()),
+        issuedAt: invoice.issued_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payments::types::PaymentEvent;
+    use crate::quoting::signing::quote_domain;
+
+    const TEST_PRIVATE_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    #[tokio::test]
+    async fn test_signed_invoice() {
+        let wallet = Wallet::from_private_key(TEST_PRIVATE_KEY, 31337).unwrap();
+        let issuer = wallet.address();
+        let signer = InvoiceSigner::new(wallet, Address::with_last_byte(0xaa));
+        let event = PaymentEvent {
+            chain_id: 31337,
+            tx_hash: B256::repeat_byte(1).to_string(),
+            log_index: 2,
+            block_number: 10,
+            block_timestamp: 0,
+            user: Address::with_last_byte(1),
+            amount: U256::from(1000),
+            tier_id: 1,
+            duration_secs: None,
+            quote_id: None,
+        };
+        let signed = signer.sign(PaymentRecord::new(&event, 3600, 7200, 3600)).await.unwrap();
+
+        let domain = quote_domain(31337, Address::with_last_byte(0xaa));
+        assert_eq!(invoice_hash(&signed.invoice, &domain), signed.hash);
+        assert_eq!(signed.signature.recover_address_from_prehash(&signed.hash).unwrap(), issuer);
+
+        // Any change to the invoice invalidates the signature
+        let mut tampered = signed.invoice.clone();
+        tampered.payment.credited_secs = 7200;
+        assert_ne!(invoice_hash(&tampered, &domain), signed.hash);
+
+        let json = serde_json::to_value(&signed).unwrap();
+        assert_eq!(json["invoice"]["issuer"], serde_json::json!(issuer));
+        assert_eq!(json["invoice"]["amount"], "1000");
+        assert_eq!(json["invoice"]["chain_id"], 31337);
+    }
+
+    #[tokio::test]
+    async fn test_invoice_chain() {
+        let wallet = Wallet::from_private_key(TEST_PRIVATE_KEY, 31337).unwrap();
+        let signer = InvoiceSigner::new(wallet, Address::with_last_byte(0xaa)).with_chain(10, Address::with_last_byte(0xbb));
+        let event = |chain_id| PaymentEvent {
+            chain_id,
+            tx_hash: B256::repeat_byte(1).to_string(),
+            log_index: 0,
+            block_number: 10,
+            block_timestamp: 0,
+            user: Address::with_last_byte(1),
+            amount: U256::from(1000),
+            tier_id: 1,
+            duration_secs: None,
+            quote_id: None,
+        };
+
+        let signed = signer.sign(PaymentRecord::new(&event(10), 3600, 7200, 3600)).await.unwrap();
+        assert_eq!(signed.invoice.payment_processor, Address::with_last_byte(0xbb));
+        assert_eq!(invoice_hash(&signed.invoice, &quote_domain(10, Address::with_last_byte(0xbb))), signed.hash);
+
+        // Records from before multi-chain support are from the primary chain
+        let signed = signer.sign(PaymentRecord::new(&event(0), 3600, 7200, 3600)).await.unwrap();
+        assert_eq!(signed.invoice.payment.chain_id, 31337);
+        assert!(signer.sign(PaymentRecord::new(&event(5), 3600, 7200, 3600)).await.is_err());
+    }
+}
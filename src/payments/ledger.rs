@@ -0,0 +1,183 @@
+//! Ledger of SLA credits.
+//!
+//! Every credit is appended as one JSON line and synced to disk, so the
+//! file is a complete audit trail across restarts. A credit whose
+//! settlement changes later (a pending refund confirming or falling back to
+//! an extension) is appended again, and the later line supersedes the
+//! earlier one. The most recent entries are also kept in memory for the
+//! admin API.
+
+use alloy::primitives::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Entries kept in memory; older ones remain in the file.
+const MAX_RECENT: usize = 10_000;
+
+/// How a credit was paid out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "settlement", rename_all = "snake_case")]
+pub enum Settlement {
+    /// The subscription expiry was pushed back.
+    Extended { new_expiry: u64 },
+    /// The pro-rated price of the credited time was sent back on chain and
+    /// is awaiting confirmation.
+    RefundPending { amount: String, tx_hash: String },
+    /// The refund was confirmed on chain.
+    Refunded { amount: String, tx_hash: String },
+}
+
+/// One credit issued to a subscriber for one measurement window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlaCredit {
+    pub id: Uuid,
+    pub issued_at: u64,
+    pub user: Address,
+    pub tier_id: u8,
+    pub window_start: u64,
+    pub window_end: u64,
+    /// Proxied requests and 5xx responses seen for the user in the window.
+    pub requests: u64,
+    pub failures: u64,
+    /// Measured availability in basis points.
+    pub availability_bps: u16,
+    pub credit_secs: u64,
+    #[serde(flatten)]
+    pub settlement: Settlement,
+}
+
+/// Append-only credit ledger.
+pub struct SlaLedger {
+    file: Mutex<Option<File>>,
+    recent: Mutex<VecDeque<SlaCredit>>,
+}
+
+impl SlaLedger {
+    /// A ledger that is not written to disk.
+    pub fn in_memory() -> Self {
+        Self {
+            file: Mutex::new(None),
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Open or create the ledger at `path`, loading its latest entries.
+    ///
+    /// Unreadable lines are skipped with a warning rather than failing startup.
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let mut recent = VecDeque::new();
+        if let Ok(file) = File::open(path) {
+            for (n, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<SlaCredit>(&line) {
+                    Ok(credit) => remember(&mut recent, credit),
+                    Err(e) => tracing::warn!(path = %path, line = n + 1, error = %e, "Skipping unreadable SLA ledger entry"),
+                }
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(Some(file)),
+            recent: Mutex::new(recent),
+        })
+    }
+
+    /// Record a credit. The entry is on disk when this returns `Ok`.
+    pub fn append(&self, credit: SlaCredit) -> std::io::Result<()> {
+        if let Some(file) = self.file.lock().expect("ledger mutex poisoned").as_mut() {
+            let mut line = serde_json::to_vec(&credit)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+            file.sync_data()?;
+        }
+        remember(&mut self.recent.lock().expect("ledger mutex poisoned"), credit);
+        Ok(())
+    }
+
+    /// Most recent credits first, optionally for one user.
+    pub fn recent(&self, user: Option<Address>, limit: usize) -> Vec<SlaCredit> {
+        self.recent
+            .lock()
+            .expect("ledger mutex poisoned")
+            .iter()
+            .rev()
+            .filter(|c| user.is_none_or(|u| c.user == u))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Keep `credit` as the latest entry, replacing an earlier one for the same
+/// credit.
+fn remember(recent: &mut VecDeque<SlaCredit>, credit: SlaCredit) {
+    recent.retain(|c| c.id != credit.id);
+    if recent.len() == MAX_RECENT {
+        recent.pop_front();
+    }
+    recent.push_back(credit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credit(user: Address, credit_secs: u64) -> SlaCredit {
+        SlaCredit {
+            id: Uuid::new_v4(),
+            issued_at: 100,
+            user,
+            tier_id: 1,
+            window_start: 0,
+            window_end: 100,
+            requests: 20,
+            failures: 5,
+            availability_bps: 7500,
+            credit_secs,
+            settlement: Settlement::Extended { new_expiry: 1000 + credit_secs },
+        }
+    }
+
+    #[test]
+    fn test_ledger_reopen() {
+        let path = std::env::temp_dir()
+            .join(format!("test_sla_ledger_{}.jsonl", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let _ = std::fs::remove_file(&path);
+        let (a, b) = (Address::with_last_byte(1), Address::with_last_byte(2));
+
+        let ledger = SlaLedger::open(&path).unwrap();
+        ledger.append(credit(a, 10)).unwrap();
+        ledger.append(credit(b, 20)).unwrap();
+        ledger.append(credit(a, 30)).unwrap();
+        drop(ledger);
+
+        let ledger = SlaLedger::open(&path).unwrap();
+        let all = ledger.recent(None, 10);
+        assert_eq!(all.iter().map(|c| c.credit_secs).collect::<Vec<_>>(), vec![30, 20, 10]);
+        assert_eq!(ledger.recent(Some(a), 1)[0].credit_secs, 30);
+        assert_eq!(ledger.recent(Some(b), 10).len(), 1);
+
+        // A settled refund supersedes its pending entry, also after reopening
+        let mut refund = credit(b, 40);
+        refund.settlement = Settlement::RefundPending { amount: "1".into(), tx_hash: "0xab".into() };
+        ledger.append(refund.clone()).unwrap();
+        refund.settlement = Settlement::Refunded { amount: "1".into(), tx_hash: "0xab".into() };
+        ledger.append(refund).unwrap();
+        drop(ledger);
+        let ledger = SlaLedger::open(&path).unwrap();
+        let entries = ledger.recent(Some(b), 10);
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(entries[0].settlement, Settlement::Refunded { .. }));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
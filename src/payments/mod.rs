@@ -1,8 +1,18 @@
 //! Payment monitoring module.
 
+pub mod billing;
 pub mod cache;
+pub mod catalog;
+pub mod invoice;
+pub mod ledger;
 pub mod monitor;
 pub mod processor;
+pub mod sla;
+pub mod treasury;
 pub mod types;
 
+pub use billing::{BillingHistory, PaymentRecord};
+pub use catalog::TierCatalog;
+pub use ledger::SlaLedger;
+pub use sla::{SlaService, SlaTracker};
 pub use types::PaymentEvent;
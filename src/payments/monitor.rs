@@ -1,23 +1,47 @@
 //! Payment monitoring service.
+//!
+//! Events are detected by polling `eth_getLogs` every `monitor_interval_ms`
+//! or, when `blockchain.ws_urls` is set, pushed over an `eth_subscribe`
+//! WebSocket. Push mode buffers logs until they reach the confirmation depth
+//! and falls back to polling whenever the socket drops.
+//!
+//! Each chain payments are accepted on has its own monitor, all feeding the
+//! same [`SubscriptionCache`] and checkpointing their chain separately. A
+//! checkpoint that no longer matches its chain only rescans that chain.
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
 use alloy::sol;
-use alloy::primitives::Address;
-use alloy::rpc::types::eth::Filter;
+use alloy::primitives::{Address, B256};
+use alloy::rpc::types::eth::{Filter, Log};
 use alloy::sol_types::SolEvent;
+use uuid::Uuid;
 
 use crate::blockchain::client::BlockchainClient;
+use crate::blockchain::subscription::{ChainEvent, HeadSubscription};
 use crate::config::PaymentConfig;
-use crate::payments::cache::SubscriptionCache;
+use crate::observability::metrics;
+use crate::payments::cache::{Checkpoint, CheckpointState, SubscriptionCache, REORG_WINDOW_BLOCKS};
+use crate::payments::catalog::{TierCatalog, TierUpdated};
+use crate::payments::billing::BillingHistory;
 use crate::payments::processor::process_payment;
 use crate::payments::types::PaymentEvent;
+use crate::quoting::QuoteRegistry;
+use crate::webhooks::Webhooks;
+
+/// Polling intervals a push subscription may stay silent before it is dropped.
+const WS_IDLE_INTERVALS: u64 = 6;
 
 sol! {
     /// Emitted when a payment is received.
     #[derive(Debug)]
     event PaymentReceived(address indexed user, uint256 amount, uint8 tierId);
+
+    /// Emitted when a subscription is bought for a quoted duration.
+    #[derive(Debug)]
+    event SubscriptionPurchased(address indexed user, uint256 amount, uint8 tierId, uint256 duration, bytes16 quoteId);
     
     /// Emitted when a subscription is created.
     #[derive(Debug)]
@@ -28,9 +52,25 @@ sol! {
 pub struct PaymentMonitor {
     client: BlockchainClient,
     config: PaymentConfig,
+    /// Chain the monitor follows, from the client configuration.
+    chain_id: u64,
     contract_address: Address,
-    last_block: u64,
+    /// SubscriptionManager whose `TierUpdated` events feed the catalog.
+    manager_address: Option<Address>,
+    /// Last block whose events are applied; `None` before the first one.
+    last_block: Option<u64>,
     cache: Arc<SubscriptionCache>,
+    catalog: Arc<TierCatalog>,
+    /// Issued quotes redeemed by confirmed payments.
+    quotes: Arc<QuoteRegistry>,
+    /// Where applied payments are recorded.
+    billing: BillingHistory,
+    /// Receives subscription lifecycle events.
+    webhooks: Arc<Webhooks>,
+    /// Pushed logs awaiting confirmation, keyed by (block, log index).
+    pending: BTreeMap<(u64, u64), Log>,
+    /// First block whose logs are guaranteed to arrive over the subscription.
+    push_start: u64,
 }
 
 impl PaymentMonitor {
@@ -38,17 +78,35 @@ impl PaymentMonitor {
     pub fn new(
         client: BlockchainClient, 
         config: PaymentConfig,
-        cache: Arc<SubscriptionCache>
+        cache: Arc<SubscriptionCache>,
+        catalog: Arc<TierCatalog>,
+        quotes: Arc<QuoteRegistry>,
+        billing: BillingHistory,
+        webhooks: Arc<Webhooks>,
     ) -> Result<Self, String> {
         let contract_address: Address = config.contract_address.parse()
             .map_err(|e| format!("Invalid contract address: {}", e))?;
+        let manager_address = if config.subscription_manager_address.is_empty() {
+            None
+        } else {
+            Some(config.subscription_manager_address.parse()
+                .map_err(|e| format!("Invalid subscription manager address: {}", e))?)
+        };
 
         Ok(Self {
+            chain_id: client.config().chain_id,
             client,
             config,
             contract_address,
-            last_block: 0,
+            manager_address,
+            last_block: None,
             cache,
+            catalog,
+            quotes,
+            billing,
+            webhooks,
+            pending: BTreeMap::new(),
+            push_start: 0,
         })
     }
 
@@ -59,17 +117,47 @@ impl PaymentMonitor {
             return;
         }
 
-        tracing::info!("Starting payment monitor for contract {}", self.contract_address);
+        tracing::info!(
+            chain_id = self.chain_id,
+            "Starting payment monitor for contract {}",
+            self.contract_address
+        );
 
-        // Initialize last_block to current block if 0
-        if self.last_block == 0 {
-            if let Ok(block) = self.client.get_block_number().await {
-                self.last_block = block;
-                tracing::info!("Initialized payment monitor at block {}", block);
+        // Payments are credited at catalog prices, so wait until they are loaded
+        if self.manager_address.is_some() {
+            while !self.catalog.is_synced() {
+                sleep(Duration::from_millis(self.config.monitor_interval_ms)).await;
             }
         }
 
+        // Resolve the resume point before scanning; retry until the chain is reachable
+        let state = self.cache.take_loaded_state(self.chain_id).unwrap_or_else(|| match self.cache.checkpoint(self.chain_id) {
+            Some(cp) => CheckpointState::Valid(cp),
+            None => CheckpointState::Missing,
+        });
+        while let Err(e) = self.resume(state.clone()).await {
+            tracing::error!("Failed to resolve payment monitor checkpoint: {}", e);
+            sleep(Duration::from_millis(self.config.monitor_interval_ms)).await;
+        }
+
+        let ws_urls = self.client.config().ws_urls.clone();
+        let (addresses, topics) = self.watched();
+        let ws_timeout = Duration::from_secs(self.client.config().rpc_timeout_secs);
+        let idle_timeout = self.ws_idle_timeout();
+
         loop {
+            if !ws_urls.is_empty() {
+                match HeadSubscription::connect(&ws_urls, &addresses, &topics, ws_timeout, idle_timeout).await {
+                    Ok(sub) => {
+                        metrics::record_payment_monitor_push(true);
+                        self.run_push(sub).await;
+                        metrics::record_payment_monitor_push(false);
+                        tracing::warn!("Payment subscription lost, falling back to polling");
+                    }
+                    Err(e) => tracing::warn!("Payment subscription unavailable, polling instead: {}", e),
+                }
+            }
+
             if let Err(e) = self.poll_events().await {
                 tracing::error!("Error polling payment events: {}", e);
             }
@@ -78,45 +166,551 @@ impl PaymentMonitor {
         }
     }
 
-    async fn poll_events(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let current_block = self.client.get_block_number().await?;
-        
-        // Wait for confirmations
-        let target_block = current_block.saturating_sub(self.client.confirmation_blocks() as u64);
+    /// How long a push subscription may go without a notification. Heads
+    /// arrive every block, so a socket silent for several polling intervals
+    /// is dead even if it was never closed.
+    fn ws_idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.monitor_interval_ms.saturating_mul(WS_IDLE_INTERVALS))
+    }
+
+    /// Contracts and event signatures the monitor follows.
+    fn watched(&self) -> (Vec<Address>, Vec<B256>) {
+        let mut addresses = vec![self.contract_address];
+        let mut topics = vec![
+            PaymentReceived::SIGNATURE_HASH,
+            SubscriptionPurchased::SIGNATURE_HASH,
+        ];
+        if let Some(manager) = self.manager_address {
+            addresses.push(manager);
+            topics.push(TierUpdated::SIGNATURE_HASH);
+        }
+        (addresses, topics)
+    }
+
+    /// Apply a confirmed log to the cache or the tier catalog.
+    async fn handle_log(&self, client: &BlockchainClient, log: &Log) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if log.address() == self.contract_address {
+            if let Some(mut event) = decode_payment(log, self.chain_id) {
+                // Quote expiry is judged by when the payment was mined
+                if event.block_timestamp == 0 {
+                    event.block_timestamp = client
+                        .get_block_timestamp(event.block_number)
+                        .await?
+                        .ok_or_else(|| format!("block {} not found", event.block_number))?;
+                }
+                process_payment(event, &self.cache, &self.catalog, &self.quotes, &self.billing, &self.webhooks).await;
+            }
+        } else if Some(log.address()) == self.manager_address {
+            if let Ok(decoded) = log.log_decode::<TierUpdated>() {
+                let event = decoded.inner.data;
+                tracing::info!(tier = event.tierId, price = %event.price, "Tier updated on chain");
+                // Re-read the tier so the active flag is current as well
+                if let Err(e) = self.catalog.refresh(client, log.address(), event.tierId).await {
+                    tracing::warn!(tier = event.tierId, error = %e, "Failed to re-read tier, applying event values");
+                    self.catalog.apply_update(&event);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Consume pushed events until the subscription drops.
+    async fn run_push(&mut self, mut sub: HeadSubscription) {
+        self.pending.clear();
+
+        // Fill the gap since the checkpoint. Blocks up to the head seen here
+        // may predate the subscription, so they are only trusted via getLogs.
+        match self.poll_events().await {
+            Ok(head) => self.push_start = head + 1,
+            Err(e) => {
+                tracing::error!("Error filling payment gap before push mode: {}", e);
+                return;
+            }
+        }
+
+        while let Some(event) = sub.next().await {
+            match event {
+                ChainEvent::Log(log) => self.buffer_log(*log),
+                ChainEvent::NewHead(head) => {
+                    if let Err(e) = self.apply_confirmed(head).await {
+                        tracing::error!("Error applying pushed payment events: {}", e);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Track a pushed log, or forget it if a reorg removed it.
+    fn buffer_log(&mut self, log: Log) {
+        let (Some(block), Some(index)) = (log.block_number, log.log_index) else {
+            return;
+        };
+        if log.removed {
+            if self.is_applied(block) {
+                tracing::warn!(block = block, "Reorg removed a payment log that was already applied");
+            }
+            self.pending.remove(&(block, index));
+        } else if !self.is_applied(block) {
+            self.pending.insert((block, index), log);
+        }
+    }
 
-        if target_block <= self.last_block {
+    /// Apply buffered logs that reached the confirmation depth at `head`.
+    async fn apply_confirmed(&mut self, head: u64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let target_block = head.saturating_sub(self.client.confirmation_blocks() as u64);
+        if self.is_applied(target_block) {
             return Ok(());
         }
+        let client = self.client.pinned();
+
+        // Blocks from before the subscription started still come from getLogs
+        if let Some(scanned_to) = self.push_start.checked_sub(1) {
+            self.scan_to(&client, target_block.min(scanned_to)).await?;
+        }
+
+        let rest = self.pending.split_off(&(target_block + 1, 0));
+        let confirmed = std::mem::replace(&mut self.pending, rest);
+        for ((block, _), log) in confirmed {
+            if !self.is_applied(block) {
+                self.handle_log(&client, &log).await?;
+            }
+        }
+
+        self.commit(&client, target_block).await?;
+        self.last_block = Some(target_block);
+        metrics::record_payment_monitor_block(target_block);
+        Ok(())
+    }
 
-        let filter = Filter::new()
-            .address(self.contract_address)
-            .from_block(self.last_block + 1)
-            .to_block(target_block)
-            .event(PaymentReceived::SIGNATURE); // For now filtering specific event
-
-        let logs = self.client.provider().get_logs(&filter).await?;
-
-        for log in logs {
-            // Try decoding PaymentReceived
-            if let Ok(decoded) = log.log_decode::<PaymentReceived>() {
-                let event = decoded.inner;
-                let user = event.user;
-                let amount = event.amount;
-                let tier_id = event.tierId;
-                
-                let payment_event = PaymentEvent {
-                    tx_hash: log.transaction_hash.map(|h| h.to_string()).unwrap_or_default(),
-                    block_number: log.block_number.unwrap_or_default(),
-                    user,
-                    amount,
-                    tier_id,
+    /// Decide where to resume scanning from the persisted checkpoint.
+    async fn resume(&mut self, state: CheckpointState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match state {
+            CheckpointState::Valid(checkpoint) => {
+                let client = self.client.pinned();
+                let head = client.get_block_number().await?;
+                let chain_hash = match checkpoint.block_hash {
+                    Some(_) if checkpoint.last_block <= head => {
+                        client.get_block_hash(checkpoint.last_block).await?
+                    }
+                    _ => None,
                 };
+                match verify_checkpoint(&checkpoint, head, chain_hash) {
+                    Ok(()) => {
+                        self.last_block = Some(checkpoint.last_block);
+                        tracing::info!(
+                            chain_id = self.chain_id,
+                            "Resuming payment monitor after block {}",
+                            checkpoint.last_block
+                        );
+                    }
+                    Err(reason) => {
+                        // Which blocks were replaced is unknown, so rescan the whole reorg window.
+                        // Payments before it are final and stay credited.
+                        let from_block = (checkpoint.last_block + 1)
+                            .saturating_sub(REORG_WINDOW_BLOCKS)
+                            .min(head + 1)
+                            .max(self.config.start_block);
+                        let undone = self.cache.roll_back(self.chain_id, from_block);
+                        tracing::warn!(
+                            chain_id = self.chain_id,
+                            undone,
+                            "Rolled back payments from block {}",
+                            from_block
+                        );
+                        self.start_backfill(&reason, from_block);
+                    }
+                }
+            }
+            // The cache holds nothing from this chain, so other chains are kept
+            CheckpointState::Corrupted(reason) => self.start_backfill(&reason, self.config.start_block),
+            CheckpointState::Missing if self.config.start_block > 0 => {
+                self.start_backfill("no checkpoint on disk", self.config.start_block);
+            }
+            CheckpointState::Missing => {
+                let client = self.client.pinned();
+                let block = client.get_block_number().await?;
+                self.last_block = Some(block);
+                self.commit(&client, block).await?;
+                tracing::info!(chain_id = self.chain_id, "Initialized payment monitor at block {}", block);
+            }
+        }
+        metrics::record_payment_monitor_block(self.last_block.unwrap_or_default());
+        Ok(())
+    }
+
+    /// Rescan from `from_block`.
+    fn start_backfill(&mut self, reason: &str, from_block: u64) {
+        tracing::warn!(
+            chain_id = self.chain_id,
+            reason = %reason,
+            from_block,
+            "Payment monitor checkpoint unusable, backfilling from chain"
+        );
+        metrics::record_subscription_event("backfill");
+        self.last_block = from_block.checked_sub(1);
+    }
+
+    /// Whether the events of `block` are already applied.
+    fn is_applied(&self, block: u64) -> bool {
+        self.last_block.is_some_and(|last| block <= last)
+    }
+
+    /// Persist the cache together with `block` as the last processed block.
+    async fn commit(&self, client: &BlockchainClient, block: u64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let block_hash = client.get_block_hash(block).await?;
+        let checkpoint = Checkpoint { last_block: block, block_hash };
+        self.cache.commit_checkpoint(self.chain_id, checkpoint)?;
+        Ok(())
+    }
+
+    /// Scan confirmed blocks since the checkpoint. Returns the chain head.
+    ///
+    /// The round reads from one provider, so no log or block hash is asked
+    /// of a node that has not reached the head yet.
+    async fn poll_events(&mut self) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let client = self.client.pinned();
+        let current_block = client.get_block_number().await?;
+        
+        // Wait for confirmations
+        let target_block = current_block.saturating_sub(self.client.confirmation_blocks() as u64);
+
+        self.scan_to(&client, target_block).await?;
+        Ok(current_block)
+    }
 
-                process_payment(payment_event, &self.cache).await;
+    /// Fetch and apply logs up to `target_block` in bounded ranges,
+    /// checkpointing after each one.
+    async fn scan_to(
+        &mut self,
+        client: &BlockchainClient,
+        target_block: u64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        while !self.is_applied(target_block) {
+            let from_block = self.last_block.map_or(0, |last| last + 1);
+            let to_block = target_block.min(from_block + self.config.max_block_range.max(1) - 1);
+
+            let (addresses, topics) = self.watched();
+            let filter = Filter::new()
+                .address(addresses)
+                .from_block(from_block)
+                .to_block(to_block)
+                .event_signature(topics);
+
+            let logs = client.get_logs(&filter).await?;
+
+            for log in logs {
+                self.handle_log(client, &log).await?;
             }
+
+            // Cache and block number are saved together, so a restart
+            // neither skips nor re-applies the events of this range.
+            self.commit(client, to_block).await?;
+            self.last_block = Some(to_block);
+            metrics::record_payment_monitor_block(to_block);
         }
 
-        self.last_block = target_block;
         Ok(())
     }
 }
+
+/// Decode a `PaymentReceived` or `SubscriptionPurchased` log of `chain_id`
+/// into a payment event.
+fn decode_payment(log: &Log, chain_id: u64) -> Option<PaymentEvent> {
+    let (user, amount, tier_id, duration_secs, quote_id) = match log.topic0() {
+        Some(&PaymentReceived::SIGNATURE_HASH) => {
+            let event = log.log_decode::<PaymentReceived>().ok()?.inner.data;
+            (event.user, event.amount, event.tierId, None, None)
+        }
+        Some(&SubscriptionPurchased::SIGNATURE_HASH) => {
+            let event = log.log_decode::<SubscriptionPurchased>().ok()?.inner.data;
+            let duration = u64::try_from(event.duration).unwrap_or(u64::MAX);
+            // A zero id means the purchase was not made against a quote
+            let quote_id = Some(Uuid::from_bytes(event.quoteId.0)).filter(|id| !id.is_nil());
+            (event.user, event.amount, event.tierId, Some(duration), quote_id)
+        }
+        _ => return None,
+    };
+
+    Some(PaymentEvent {
+        chain_id,
+        tx_hash: log.transaction_hash.map(|h| h.to_string()).unwrap_or_default(),
+        log_index: log.log_index.unwrap_or_default(),
+        block_number: log.block_number.unwrap_or_default(),
+        block_timestamp: log.block_timestamp.unwrap_or_default(),
+        user,
+        amount,
+        tier_id,
+        duration_secs,
+        quote_id,
+    })
+}
+
+/// Check a persisted checkpoint against the chain.
+///
+/// `chain_hash` is the hash the chain currently reports for
+/// `checkpoint.last_block`, if it was queried.
+fn verify_checkpoint(checkpoint: &Checkpoint, head: u64, chain_hash: Option<B256>) -> Result<(), String> {
+    if checkpoint.last_block > head {
+        return Err(format!(
+            "checkpoint block {} is ahead of chain head {}",
+            checkpoint.last_block, head
+        ));
+    }
+    if let Some(expected) = checkpoint.block_hash {
+        if chain_hash != Some(expected) {
+            return Err(format!(
+                "block {} hash changed (reorg or chain reset)",
+                checkpoint.last_block
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::rpc::types::{Block, Transaction};
+    use axum::{extract::State, routing::post, Json, Router};
+    use futures_util::{sink::SinkExt, stream::StreamExt};
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use tokio_tungstenite::tungstenite::Message;
+
+    #[test]
+    fn test_verify_checkpoint() {
+        let hash = B256::repeat_byte(1);
+        let checkpoint = Checkpoint { last_block: 100, block_hash: Some(hash) };
+
+        assert!(verify_checkpoint(&checkpoint, 120, Some(hash)).is_ok());
+        // Chain reset: head is behind the checkpoint
+        assert!(verify_checkpoint(&checkpoint, 50, None).is_err());
+        // Reorg: the block was replaced
+        assert!(verify_checkpoint(&checkpoint, 120, Some(B256::repeat_byte(2))).is_err());
+        assert!(verify_checkpoint(&checkpoint, 120, None).is_err());
+
+        // Checkpoints without a hash only get the height check
+        let unhashed = Checkpoint { last_block: 100, block_hash: None };
+        assert!(verify_checkpoint(&unhashed, 100, None).is_ok());
+    }
+
+    async fn test_monitor() -> PaymentMonitor {
+        monitor_on(crate::config::schema::BlockchainConfig::default()).await
+    }
+
+    async fn monitor_on(blockchain: crate::config::schema::BlockchainConfig) -> PaymentMonitor {
+        let client = BlockchainClient::new(blockchain).await.unwrap();
+        let config = PaymentConfig {
+            contract_address: Address::ZERO.to_string(),
+            ..PaymentConfig::default()
+        };
+        let catalog = Arc::new(TierCatalog::new(&crate::config::QosConfig::default()));
+        PaymentMonitor::new(
+            client,
+            config,
+            Arc::new(SubscriptionCache::new(None)),
+            catalog,
+            Arc::new(QuoteRegistry::default()),
+            BillingHistory::default(),
+            Arc::new(Webhooks::default()),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_backfill_from_genesis_scans_block_zero() {
+        let mut monitor = test_monitor().await;
+        monitor.last_block = Some(10);
+        monitor.start_backfill("test", 0);
+        assert_eq!(monitor.last_block, None);
+        assert!(!monitor.is_applied(0));
+
+        monitor.start_backfill("test", 5);
+        assert!(monitor.is_applied(4));
+        assert!(!monitor.is_applied(5));
+    }
+
+    /// Mock JSON-RPC node whose blocks all hash to `0x0202…`.
+    #[derive(Clone, Default)]
+    struct Node {
+        head: Arc<AtomicU64>,
+        /// Block ranges asked for with `eth_getLogs`.
+        scans: Arc<Mutex<Vec<(u64, u64)>>>,
+    }
+
+    fn quantity(value: &Value) -> u64 {
+        u64::from_str_radix(value.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+    }
+
+    async fn rpc(State(node): State<Node>, Json(request): Json<Value>) -> Json<Value> {
+        let result = match request["method"].as_str().unwrap() {
+            "eth_chainId" => json!("0x7a69"),
+            "eth_blockNumber" => json!(format!("{:#x}", node.head.load(Ordering::SeqCst))),
+            "eth_getBlockByNumber" => {
+                let mut block = Block::<Transaction>::default();
+                block.header.hash = B256::repeat_byte(2);
+                block.header.inner.number = quantity(&request["params"][0]);
+                json!(block)
+            }
+            "eth_getLogs" => {
+                let filter = &request["params"][0];
+                node.scans.lock().unwrap().push((quantity(&filter["fromBlock"]), quantity(&filter["toBlock"])));
+                json!([])
+            }
+            method => panic!("unexpected call to {}", method),
+        };
+        Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+    }
+
+    /// Serve `node` over HTTP and, on every WebSocket connection, acknowledge
+    /// both subscriptions, push the head after the node's, then go silent.
+    async fn start_node(node: &Node) -> crate::config::schema::BlockchainConfig {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http = listener.local_addr().unwrap();
+        let app = Router::new().route("/", post(rpc)).with_state(node.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ws = listener.local_addr().unwrap();
+        let head = node.head.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let head = head.clone();
+                tokio::spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+                    for sub_id in ["0xaa", "0xbb"] {
+                        let Some(Ok(Message::Text(text))) = ws.next().await else { return };
+                        let req: Value = serde_json::from_str(&text).unwrap();
+                        let resp = json!({ "jsonrpc": "2.0", "id": req["id"], "result": sub_id });
+                        ws.send(Message::Text(resp.to_string().into())).await.unwrap();
+                    }
+                    let number = format!("{:#x}", head.load(Ordering::SeqCst) + 1);
+                    let notification = json!({
+                        "jsonrpc": "2.0",
+                        "method": "eth_subscription",
+                        "params": { "subscription": "0xaa", "result": { "number": number } }
+                    });
+                    ws.send(Message::Text(notification.to_string().into())).await.unwrap();
+                    // Keep the socket open without pushing anything
+                    while ws.next().await.is_some() {}
+                });
+            }
+        });
+
+        crate::config::schema::BlockchainConfig {
+            enabled: true,
+            rpc_url: format!("http://{}/", http),
+            ws_urls: vec![format!("ws://{}", ws)],
+            chain_id: 31337,
+            confirmation_blocks: 0,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replaced_checkpoint_rolls_back_payments() {
+        let node = Node::default();
+        node.head.store(512, Ordering::SeqCst);
+        let mut monitor = monitor_on(start_node(&node).await).await;
+
+        let (settled, replaced) = (Address::with_last_byte(7), Address::with_last_byte(8));
+        monitor.cache.apply_payment(31337, "0xaa-0", 100, settled, 1, 1000);
+        monitor.cache.apply_payment(31337, "0xab-0", 200, replaced, 1, 1000);
+        monitor.cache.apply_payment(1, "0xac-0", 200, Address::with_last_byte(9), 2, 2000);
+        let checkpoint = Checkpoint { last_block: 400, block_hash: Some(B256::repeat_byte(1)) };
+        monitor.cache.commit_checkpoint(31337, checkpoint).unwrap();
+
+        monitor.resume(CheckpointState::Valid(checkpoint)).await.unwrap();
+        // Block 400 was replaced, so the reorg window is rescanned without its payments
+        assert_eq!(monitor.last_block, Some(400 - REORG_WINDOW_BLOCKS));
+        assert!(monitor.cache.get_subscription(&settled).is_some());
+        assert!(!monitor.cache.is_payment_applied(31337, "0xab-0"));
+        assert!(monitor.cache.get_subscription(&replaced).is_none());
+        assert!(monitor.cache.is_payment_applied(1, "0xac-0"));
+    }
+
+    #[tokio::test]
+    async fn test_push_reconnect_fills_gap() {
+        let node = Node::default();
+        node.head.store(10, Ordering::SeqCst);
+        let blockchain = start_node(&node).await;
+        let ws_urls = blockchain.ws_urls.clone();
+        let mut monitor = monitor_on(blockchain).await;
+        monitor.config.monitor_interval_ms = 50;
+        monitor.last_block = Some(5);
+        let (addresses, topics) = monitor.watched();
+        let idle_timeout = monitor.ws_idle_timeout();
+        let connect = || HeadSubscription::connect(&ws_urls, &addresses, &topics, Duration::from_secs(5), idle_timeout);
+
+        // The silent socket is dropped after the idle timeout
+        let sub = connect().await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), monitor.run_push(sub)).await.unwrap();
+        assert_eq!(*node.scans.lock().unwrap(), vec![(6, 10)]);
+        // The pushed head is applied without another scan
+        assert_eq!(monitor.cache.checkpoint(31337).unwrap().last_block, 11);
+
+        // Blocks mined while disconnected are scanned after the reconnect
+        node.head.store(20, Ordering::SeqCst);
+        let sub = connect().await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), monitor.run_push(sub)).await.unwrap();
+        assert_eq!(*node.scans.lock().unwrap(), vec![(6, 10), (12, 20)]);
+        assert_eq!(monitor.cache.checkpoint(31337).unwrap().last_block, 21);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_log_tracks_reorgs() {
+        let mut monitor = test_monitor().await;
+        monitor.last_block = Some(10);
+
+        let log_at = |block: u64, removed: bool| Log {
+            block_number: Some(block),
+            log_index: Some(0),
+            removed,
+            ..Log::default()
+        };
+
+        // Already-applied blocks are ignored
+        monitor.buffer_log(log_at(10, false));
+        assert!(monitor.pending.is_empty());
+
+        monitor.buffer_log(log_at(12, false));
+        assert_eq!(monitor.pending.len(), 1);
+
+        // The reorg drops the pending log before it is confirmed
+        monitor.buffer_log(log_at(12, true));
+        assert!(monitor.pending.is_empty());
+    }
+
+    #[test]
+    fn test_decode_quoted_payment() {
+        let user = Address::with_last_byte(7);
+        let to_log = |data| Log {
+            inner: alloy::primitives::Log { address: Address::ZERO, data },
+            block_number: Some(5),
+            ..Log::default()
+        };
+
+        let quote_id = Uuid::new_v4();
+        let mut quoted = SubscriptionPurchased {
+            user,
+            amount: alloy::primitives::U256::from(100),
+            tierId: 2,
+            duration: alloy::primitives::U256::from(86400),
+            quoteId: alloy::primitives::FixedBytes(*quote_id.as_bytes()),
+        };
+        let event = decode_payment(&to_log(quoted.encode_log_data()), 1).unwrap();
+        assert_eq!((event.user, event.tier_id, event.duration_secs), (user, 2, Some(86400)));
+        assert_eq!(event.quote_id, Some(quote_id));
+        assert_eq!(event.chain_id, 1);
+
+        quoted.quoteId = alloy::primitives::FixedBytes::ZERO;
+        let event = decode_payment(&to_log(quoted.encode_log_data()), 1).unwrap();
+        assert_eq!(event.quote_id, None);
+
+        let plain = PaymentReceived { user, amount: alloy::primitives::U256::from(100), tierId: 1 };
+        let event = decode_payment(&to_log(plain.encode_log_data()), 1).unwrap();
+        assert_eq!((event.tier_id, event.duration_secs, event.quote_id), (1, None, None));
+    }
+}
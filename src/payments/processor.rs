@@ -1,25 +1,155 @@
+use crate::payments::billing::{record_id, BillingHistory, PaymentRecord};
 use crate::payments::cache::SubscriptionCache;
+use crate::payments::catalog::TierCatalog;
 use crate::payments::types::PaymentEvent;
-use tracing::info;
+use crate::quoting::QuoteRegistry;
+use crate::config::WebhookEventKind;
+use crate::webhooks::{SubscriptionEvent, Webhooks};
+use tracing::{info, warn};
 
 /// Process a detected payment event.
-pub async fn process_payment(event: PaymentEvent, cache: &SubscriptionCache) {
+///
+/// A payment that redeems an issued quote is credited with the quoted
+/// duration, plus the bonus time of a tier change quote. Otherwise the
+/// subscription is credited with the requested duration if the payment
+/// covers it at current prices (see [`TierCatalog::credited_duration`]).
+/// The payment is then added to the user's billing history and announced to
+/// webhook endpoints.
+///
+/// Time is credited from when the payment was mined, like the contract
+/// does, so payments found by a backfill expire when they did on chain.
+///
+/// Payments the cache already reflects, e.g. when blocks past the last
+/// checkpoint are scanned again after a restart, are skipped.
+pub async fn process_payment(
+    event: PaymentEvent,
+    cache: &SubscriptionCache,
+    catalog: &TierCatalog,
+    quotes: &QuoteRegistry,
+    billing: &BillingHistory,
+    webhooks: &Webhooks,
+) {
+    let id = record_id(&event.tx_hash, event.log_index);
+    if cache.is_payment_applied(event.chain_id, &id) {
+        info!("Payment {} is already applied", id);
+        return;
+    }
     info!(
         "Processing payment: User {:?} paid {} for Tier {}",
         event.user, event.amount, event.tier_id
     );
 
-    // Calculate expiry (e.g. 30 days from now)
-    // In real system, might depend on amount or plan
+    let redeemed = quotes.redeem(&event).unwrap_or_else(|e| {
+        warn!("Failed to redeem quote for {}: {}", event.tx_hash, e);
+        None
+    });
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
-    let expiry = now + 30 * 24 * 3600; // 30 days default
+    let mined_at = match event.block_timestamp {
+        0 => now,
+        mined_at => mined_at,
+    };
 
-    cache.update_subscription(event.user, event.tier_id, expiry);
-    info!("Updated subscription for user {:?}", event.user);
-    
-    // Attempt save? (optional, maybe too frequent)
-    // cache.save_to_file().ok();
+    // Renewals of the same tier extend the current period, as on chain
+    let previous = cache.get_subscription(&event.user).filter(|sub| sub.expiry > mined_at);
+    let start = match &previous {
+        Some(sub) if sub.tier_id == event.tier_id => sub.expiry,
+        _ => mined_at,
+    };
+    let quoted = redeemed.and_then(|q| Some((q.signed.quote.duration_seconds?, q.signed.quote.tier_change)));
+    let credited = match quoted {
+        Some((duration, None)) => duration,
+        // A tier change credits the replaced subscription only if it was
+        // not switched again since the quote
+        Some((duration, Some(change)))
+            if previous.as_ref().is_some_and(|sub| sub.tier_id == change.from_tier && sub.expiry >= change.from_expiry) =>
+        {
+            duration.saturating_add(change.bonus_seconds)
+        }
+        Some((duration, Some(_))) => {
+            warn!("Subscription of {:?} changed since its tier change quote, crediting the payment only", event.user);
+            catalog.credited_duration(event.tier_id, event.amount, Some(duration))
+        }
+        None if catalog.prices_chain(event.chain_id) => {
+            catalog.credited_duration(event.tier_id, event.amount, event.duration_secs)
+        }
+        // Paid in another currency, which that chain's contract checked
+        None => catalog.accepted_duration(event.tier_id, event.duration_secs),
+    };
+    let expiry = start.saturating_add(credited);
+
+    if !cache.apply_payment(event.chain_id, &id, event.block_number, event.user, event.tier_id, expiry) {
+        return;
+    }
+    info!("Updated subscription for user {:?} (+{}s)", event.user, credited);
+
+    if let Err(e) = billing.record(&PaymentRecord::new(&event, credited, expiry, now)) {
+        warn!("Failed to record payment {} in billing history: {}", event.tx_hash, e);
+    }
+
+    let kind = match previous {
+        Some(sub) if sub.tier_id == event.tier_id => WebhookEventKind::Renewed,
+        Some(sub) => {
+            // Switching tiers replaces the active subscription
+            webhooks.emit(
+                SubscriptionEvent::new(WebhookEventKind::Cancelled, event.user, sub.tier_id, sub.expiry, now)
+                    .caused_by(event.chain_id, &id),
+            );
+            WebhookEventKind::Created
+        }
+        None => WebhookEventKind::Created,
+    };
+    webhooks.emit(
+        SubscriptionEvent::new(kind, event.user, event.tier_id, expiry, now)
+            .with_tx_hash(&event.tx_hash)
+            .caused_by(event.chain_id, &id),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QosConfig;
+    use alloy::primitives::{Address, U256};
+
+    #[tokio::test]
+    async fn test_backfilled_payment_expires_on_chain_time() {
+        let cache = SubscriptionCache::new(None);
+        let catalog = TierCatalog::with_default_pricing(&QosConfig::default());
+        let (quotes, billing, webhooks) = (QuoteRegistry::default(), BillingHistory::default(), Webhooks::default());
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let month = 30 * 24 * 3600;
+        let year_ago = now - 365 * 24 * 3600;
+        let user = Address::with_last_byte(1);
+        let payment = |tx: &str, mined_at: u64| PaymentEvent {
+            chain_id: 1,
+            tx_hash: tx.to_string(),
+            log_index: 0,
+            block_number: 1,
+            block_timestamp: mined_at,
+            user,
+            amount: U256::from(10_000_000_000_000_000u64),
+            tier_id: 1,
+            duration_secs: None,
+            quote_id: None,
+        };
+
+        // A month bought a year ago, renewed ten days later, is long over
+        process_payment(payment("0x01", year_ago), &cache, &catalog, &quotes, &billing, &webhooks).await;
+        assert_eq!(cache.get_subscription(&user).unwrap().expiry, year_ago + month);
+        process_payment(payment("0x02", year_ago + 10 * 24 * 3600), &cache, &catalog, &quotes, &billing, &webhooks).await;
+        let sub = cache.get_subscription(&user).unwrap();
+        assert_eq!(sub.expiry, year_ago + 2 * month);
+        assert!(!sub.is_active());
+
+        // A renewal mined after that expiry starts from when it was mined
+        let mined_at = now - 3600;
+        process_payment(payment("0x03", mined_at), &cache, &catalog, &quotes, &billing, &webhooks).await;
+        assert_eq!(cache.get_subscription(&user).unwrap().expiry, mined_at + month);
+    }
 }
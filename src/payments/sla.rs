@@ -0,0 +1,436 @@
+//! SLA credits for downtime.
+//!
+//! Availability is measured per subscriber over fixed windows from two
+//! signals: the share of health samples in which no backend was healthy,
+//! and the share of the subscriber's proxied requests that ended in a 5xx.
+//! The worse of the two is compared against the tier's target. Subscribers
+//! below target are credited a share of the unavailable time, either by
+//! extending their subscription or, above `sla.refund_threshold_secs`, by
+//! refunding its pro-rated price on chain. Every credit goes to the
+//! [`SlaLedger`].
+//!
+//! A refund is recorded as pending once broadcast and only marked refunded
+//! when it confirms. If it fails on chain, the credit falls back to an
+//! extension under the same ledger id.
+
+use alloy::primitives::{Address, Bytes, U256};
+use axum::{
+    body::Body,
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::blockchain::transaction::TxBuilder;
+use crate::blockchain::types::ConfirmationStatus;
+use crate::config::{SlaConfig, SlaPolicy};
+use crate::payments::cache::SubscriptionCache;
+use crate::payments::catalog::TierCatalog;
+use crate::payments::ledger::{Settlement, SlaCredit, SlaLedger};
+use crate::security::access_control::UserContext;
+use crate::store::unix_now;
+
+/// Proxied requests of one subscriber in the current window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub requests: u64,
+    pub failures: u64,
+}
+
+/// Measurements of one finished window.
+#[derive(Debug, Default)]
+pub struct WindowStats {
+    pub usage: HashMap<Address, Usage>,
+    pub samples: u64,
+    pub outage_samples: u64,
+}
+
+impl WindowStats {
+    /// Share of samples with no healthy backend, in basis points.
+    pub fn outage_bps(&self) -> u64 {
+        if self.samples == 0 {
+            return 0;
+        }
+        self.outage_samples * 10_000 / self.samples
+    }
+}
+
+/// Collects availability signals, shared across reloads.
+#[derive(Default)]
+pub struct SlaTracker {
+    usage: DashMap<Address, Usage>,
+    samples: AtomicU64,
+    outage_samples: AtomicU64,
+}
+
+impl SlaTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the outcome of a proxied request.
+    pub fn record(&self, user: Address, failed: bool) {
+        let mut usage = self.usage.entry(user).or_default();
+        usage.requests += 1;
+        if failed {
+            usage.failures += 1;
+        }
+    }
+
+    /// Record a backend health sample.
+    pub fn sample(&self, healthy: bool) {
+        self.samples.fetch_add(1, Ordering::Relaxed);
+        if !healthy {
+            self.outage_samples.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Take the measurements so far and start a new window.
+    pub fn take_window(&self) -> WindowStats {
+        let mut usage = HashMap::new();
+        self.usage.retain(|user, u| {
+            usage.insert(*user, *u);
+            false
+        });
+        WindowStats {
+            usage,
+            samples: self.samples.swap(0, Ordering::Relaxed),
+            outage_samples: self.outage_samples.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Record proxied 5xx outcomes of subscribed users. Requests of
+/// organization members count towards the owner's subscription.
+///
+/// Must run inside access control, which attaches the [`UserContext`].
+pub async fn sla_middleware(
+    State(tracker): State<Arc<SlaTracker>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let user = req.extensions().get::<UserContext>().map(|ctx| ctx.subscriber);
+    let response = next.run(req).await;
+    if let Some(user) = user {
+        tracker.record(user, response.status().is_server_error());
+    }
+    response
+}
+
+/// Outcome of checking one subscriber's window against their policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Assessment {
+    pub availability_bps: u16,
+    pub credit_secs: u64,
+}
+
+/// Credit owed for a window, or `None` if the target was met.
+///
+/// The error rate only counts once the subscriber made `min_requests`, so a
+/// single failed request does not breach the SLA.
+pub fn assess(
+    policy: &SlaPolicy,
+    window_secs: u64,
+    min_requests: u64,
+    usage: Usage,
+    outage_bps: u64,
+) -> Option<Assessment> {
+    let error_bps = if usage.requests >= min_requests.max(1) {
+        usage.failures * 10_000 / usage.requests
+    } else {
+        0
+    };
+    let unavailable_bps = error_bps.max(outage_bps).min(10_000);
+    let availability_bps = (10_000 - unavailable_bps) as u16;
+    if availability_bps >= policy.target_bps {
+        return None;
+    }
+    let credit = window_secs as u128 * unavailable_bps as u128 * policy.credit_bps as u128 / 100_000_000;
+    let credit_secs = u64::try_from(credit).unwrap_or(u64::MAX);
+    (credit_secs > 0).then_some(Assessment { availability_bps, credit_secs })
+}
+
+/// Settles SLA credits at the end of every window.
+pub struct SlaService {
+    config: SlaConfig,
+    tracker: Arc<SlaTracker>,
+    ledger: Arc<SlaLedger>,
+    cache: Arc<SubscriptionCache>,
+    catalog: Arc<TierCatalog>,
+    refunds: Option<Arc<TxBuilder>>,
+}
+
+impl SlaService {
+    pub fn new(
+        config: SlaConfig,
+        tracker: Arc<SlaTracker>,
+        ledger: Arc<SlaLedger>,
+        cache: Arc<SubscriptionCache>,
+        catalog: Arc<TierCatalog>,
+    ) -> Self {
+        Self { config, tracker, ledger, cache, catalog, refunds: None }
+    }
+
+    /// Refund credits above the threshold from the proxy wallet.
+    pub fn with_refunds(mut self, tx_builder: TxBuilder) -> Self {
+        self.refunds = Some(Arc::new(tx_builder));
+        self
+    }
+
+    /// Sample backend health and settle windows until shutdown.
+    pub async fn run(self, healthy: impl Fn() -> bool + Send + 'static, mut shutdown: broadcast::Receiver<()>) {
+        tracing::info!(
+            window_secs = self.config.window_secs,
+            tiers = self.config.tiers.len(),
+            "SLA credit service starting"
+        );
+        // Discard anything recorded before the first full window
+        self.tracker.take_window();
+        let mut window_start = unix_now();
+        let mut ticker = tokio::time::interval(Duration::from_secs(self.config.sample_interval_secs));
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    self.tracker.sample(healthy());
+                    let now = unix_now();
+                    if now >= window_start + self.config.window_secs {
+                        let stats = self.tracker.take_window();
+                        self.settle(&stats, window_start, now).await;
+                        window_start = now;
+                    }
+                }
+                _ = shutdown.recv() => {
+                    tracing::info!("SLA credit service received shutdown signal, exiting loop");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Credit every active subscriber whose tier target was missed.
+    pub async fn settle(&self, stats: &WindowStats, window_start: u64, window_end: u64) -> Vec<SlaCredit> {
+        let window_secs = window_end.saturating_sub(window_start);
+        let outage_bps = stats.outage_bps();
+        let mut credits = Vec::new();
+
+        for (user, sub) in self.cache.active_subscriptions() {
+            let Some(policy) = self.config.tiers.get(&sub.tier_id) else {
+                continue;
+            };
+            let usage = stats.usage.get(&user).copied().unwrap_or_default();
+            let Some(assessment) = assess(policy, window_secs, self.config.min_requests, usage, outage_bps) else {
+                continue;
+            };
+
+            let settlement = match self.refund(user, sub.tier_id, assessment.credit_secs).await {
+                Some(settlement) => settlement,
+                None => match self.cache.extend_subscription(&user, assessment.credit_secs) {
+                    Some(extended) => Settlement::Extended { new_expiry: extended.expiry },
+                    None => continue,
+                },
+            };
+            let credit = SlaCredit {
+                id: Uuid::new_v4(),
+                issued_at: unix_now(),
+                user,
+                tier_id: sub.tier_id,
+                window_start,
+                window_end,
+                requests: usage.requests,
+                failures: usage.failures,
+                availability_bps: assessment.availability_bps,
+                credit_secs: assessment.credit_secs,
+                settlement,
+            };
+            tracing::info!(
+                user = %user,
+                tier = sub.tier_id,
+                availability_bps = assessment.availability_bps,
+                credit_secs = assessment.credit_secs,
+                "Issued SLA credit"
+            );
+            if let Err(e) = self.ledger.append(credit.clone()) {
+                tracing::error!(user = %user, error = %e, "Failed to write SLA ledger entry");
+            }
+            if let (Settlement::RefundPending { tx_hash, .. }, Some(tx_builder)) = (&credit.settlement, &self.refunds) {
+                if let Ok(tx_hash) = tx_hash.parse() {
+                    tokio::spawn(confirm_refund(
+                        tx_builder.clone(),
+                        tx_hash,
+                        self.config.refund_confirmation_timeout_secs,
+                        credit.clone(),
+                        self.ledger.clone(),
+                        self.cache.clone(),
+                    ));
+                }
+            }
+            credits.push(credit);
+        }
+
+        // Extensions are otherwise only persisted with the next payment checkpoint
+        if credits.iter().any(|c| matches!(c.settlement, Settlement::Extended { .. })) {
+            if let Err(e) = self.cache.save_to_file() {
+                tracing::error!("Failed to persist subscription cache after SLA credits: {}", e);
+            }
+        }
+        credits
+    }
+
+    /// Refund the on-chain price of `credit_secs` if it is over the threshold.
+    ///
+    /// Returns `None` when the credit should be applied as an extension
+    /// instead, including when the refund could not be sent.
+    async fn refund(&self, user: Address, tier_id: u8, credit_secs: u64) -> Option<Settlement> {
+        let threshold = self.config.refund_threshold_secs;
+        if threshold == 0 || credit_secs < threshold {
+            return None;
+        }
+        let tx_builder = self.refunds.as_ref()?;
+        // The contract charges the on-chain price in native currency
+        let pricing = self.catalog.get(tier_id)?.pricing?;
+        if pricing.duration_secs == 0 {
+            return None;
+        }
+        let amount = pricing.price * U256::from(credit_secs) / U256::from(pricing.duration_secs);
+        if amount.is_zero() {
+            return None;
+        }
+        match tx_builder.send(user, amount, Bytes::new()).await {
+            Ok(tx_hash) => Some(Settlement::RefundPending {
+                amount: amount.to_string(),
+                tx_hash: tx_hash.to_string(),
+            }),
+            Err(e) => {
+                tracing::error!(user = %user, %amount, error = %e, "SLA refund failed, extending subscription instead");
+                None
+            }
+        }
+    }
+}
+
+/// Wait for a refund to confirm and settle its credit for good.
+///
+/// A refund that fails on chain is applied as an extension instead. One
+/// that does not confirm in time stays pending, since it may still be mined.
+async fn confirm_refund(
+    tx_builder: Arc<TxBuilder>,
+    tx_hash: alloy::primitives::TxHash,
+    timeout_secs: u64,
+    mut credit: SlaCredit,
+    ledger: Arc<SlaLedger>,
+    cache: Arc<SubscriptionCache>,
+) {
+    let Settlement::RefundPending { amount, .. } = &credit.settlement else {
+        return;
+    };
+    let amount = amount.clone();
+    credit.settlement = match tx_builder.wait_for_confirmation(tx_hash, timeout_secs).await {
+        Ok(ConfirmationStatus::Confirmed { .. }) => Settlement::Refunded { amount, tx_hash: tx_hash.to_string() },
+        Ok(status) => {
+            tracing::error!(user = %credit.user, %tx_hash, ?status, "SLA refund failed, extending subscription instead");
+            let Some(extended) = cache.extend_subscription(&credit.user, credit.credit_secs) else {
+                tracing::error!(user = %credit.user, "No subscription left to extend for failed SLA refund");
+                return;
+            };
+            if let Err(e) = cache.save_to_file() {
+                tracing::error!("Failed to persist subscription cache after SLA credits: {}", e);
+            }
+            Settlement::Extended { new_expiry: extended.expiry }
+        }
+        Err(e) => {
+            tracing::error!(user = %credit.user, %tx_hash, error = %e, "SLA refund unconfirmed, leaving it pending");
+            return;
+        }
+    };
+    if let Err(e) = ledger.append(credit.clone()) {
+        tracing::error!(user = %credit.user, error = %e, "Failed to write SLA ledger entry");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QosConfig;
+
+    const POLICY: SlaPolicy = SlaPolicy { target_bps: 9900, credit_bps: 20_000 };
+
+    #[test]
+    fn test_assess() {
+        let healthy = Usage { requests: 100, failures: 0 };
+        assert_eq!(assess(&POLICY, 3600, 10, healthy, 0), None);
+
+        // 5% errors: 180s unavailable, credited twice over
+        let errors = Usage { requests: 100, failures: 5 };
+        assert_eq!(
+            assess(&POLICY, 3600, 10, errors, 0),
+            Some(Assessment { availability_bps: 9500, credit_secs: 360 })
+        );
+
+        // Too few requests for the error rate to count, but outages always do
+        let sparse = Usage { requests: 2, failures: 2 };
+        assert_eq!(assess(&POLICY, 3600, 10, sparse, 0), None);
+        assert_eq!(
+            assess(&POLICY, 3600, 10, sparse, 1000),
+            Some(Assessment { availability_bps: 9000, credit_secs: 720 })
+        );
+    }
+
+    #[test]
+    fn test_tracker_windows() {
+        let tracker = SlaTracker::new();
+        let user = Address::with_last_byte(1);
+        tracker.record(user, false);
+        tracker.record(user, true);
+        tracker.sample(true);
+        tracker.sample(false);
+
+        let stats = tracker.take_window();
+        assert_eq!(stats.usage[&user], Usage { requests: 2, failures: 1 });
+        assert_eq!(stats.outage_bps(), 5000);
+
+        let next = tracker.take_window();
+        assert!(next.usage.is_empty());
+        assert_eq!(next.outage_bps(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_settle_extends_and_records() {
+        let cache = Arc::new(SubscriptionCache::new(None));
+        let (covered, uncovered) = (Address::with_last_byte(1), Address::with_last_byte(2));
+        cache.update_subscription(covered, 1, 9_999_999_000);
+        cache.update_subscription(uncovered, 2, 9_999_999_000);
+
+        let config = SlaConfig {
+            enabled: true,
+            tiers: [(1, POLICY)].into(),
+            ..SlaConfig::default()
+        };
+        let ledger = Arc::new(SlaLedger::in_memory());
+        let service = SlaService::new(
+            config,
+            Arc::new(SlaTracker::new()),
+            ledger.clone(),
+            cache.clone(),
+            Arc::new(TierCatalog::new(&QosConfig::default())),
+        );
+
+        let stats = WindowStats { samples: 10, outage_samples: 1, ..WindowStats::default() };
+        let credits = service.settle(&stats, 0, 3600).await;
+
+        assert_eq!(credits.len(), 1);
+        assert_eq!(credits[0].credit_secs, 720);
+        assert_eq!(credits[0].settlement, Settlement::Extended { new_expiry: 9_999_999_720 });
+        assert_eq!(cache.get_subscription(&covered).unwrap().expiry, 9_999_999_720);
+        assert_eq!(cache.get_subscription(&uncovered).unwrap().expiry, 9_999_999_000);
+        assert_eq!(ledger.recent(Some(covered), 10), credits);
+    }
+}
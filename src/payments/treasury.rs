@@ -0,0 +1,345 @@
+//! Treasury sweeper.
+//!
+//! Periodically checks the PaymentProcessor balance and, once it reaches
+//! `payments.treasury.threshold`, withdraws all of it to the treasury
+//! address. Each sweep is tracked until `wait_for_confirmation` resolves.
+//!
+//! Sweeps take the next nonce from the wallet's [`NonceManager`], so an
+//! unconfirmed sweep is never replaced by a new one. Instead no sweep is
+//! sent while an earlier one is still pending there; the nonce manager
+//! bumps its fees until it is mined or cancels it.
+//!
+//! [`NonceManager`]: crate::blockchain::nonce::NonceManager
+
+use alloy::network::TransactionBuilder;
+use alloy::primitives::{Address, TxHash, U256};
+use alloy::rpc::types::TransactionRequest;
+use alloy::sol;
+use alloy::sol_types::SolCall;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::blockchain::transaction::TxBuilder;
+use crate::blockchain::types::{BlockchainError, ConfirmationStatus};
+use crate::config::TreasuryConfig;
+use crate::observability::metrics;
+use crate::store::unix_now;
+
+/// Sweeps kept for the admin API.
+const MAX_SWEEPS: usize = 100;
+
+sol! {
+    function withdraw(address to, uint256 amount);
+    function owner() view returns (address);
+}
+
+/// Progress of a sweep transaction.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SweepStatus {
+    /// Broadcast, waiting for confirmations.
+    Pending,
+    Confirmed { block_number: u64 },
+    /// Reverted or rejected.
+    Failed { reason: String },
+    /// Not confirmed within `confirmation_timeout_secs`. No further sweep
+    /// is sent while it is still pending.
+    Unconfirmed,
+}
+
+/// One withdrawal to the treasury.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sweep {
+    pub id: Uuid,
+    pub sent_at: u64,
+    pub amount: String,
+    pub to: Address,
+    pub tx_hash: TxHash,
+    #[serde(flatten)]
+    pub status: SweepStatus,
+}
+
+/// Last observed contract balance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BalanceCheck {
+    pub balance: String,
+    pub checked_at: u64,
+}
+
+/// Sweeper state visible through the admin API, shared across reloads.
+#[derive(Default)]
+pub struct SweepLog {
+    last_balance: Mutex<Option<BalanceCheck>>,
+    sweeps: Mutex<VecDeque<Sweep>>,
+}
+
+impl SweepLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn last_balance(&self) -> Option<BalanceCheck> {
+        self.last_balance.lock().expect("sweep log mutex poisoned").clone()
+    }
+
+    /// Most recent sweeps first.
+    pub fn sweeps(&self) -> Vec<Sweep> {
+        self.sweeps.lock().expect("sweep log mutex poisoned").iter().rev().cloned().collect()
+    }
+
+    fn record_balance(&self, balance: U256) {
+        *self.last_balance.lock().expect("sweep log mutex poisoned") = Some(BalanceCheck {
+            balance: balance.to_string(),
+            checked_at: unix_now(),
+        });
+    }
+
+    fn push(&self, sweep: Sweep) {
+        let mut sweeps = self.sweeps.lock().expect("sweep log mutex poisoned");
+        if sweeps.len() == MAX_SWEEPS {
+            sweeps.pop_front();
+        }
+        sweeps.push_back(sweep);
+    }
+
+    fn set_status(&self, id: Uuid, status: SweepStatus) {
+        let mut sweeps = self.sweeps.lock().expect("sweep log mutex poisoned");
+        if let Some(sweep) = sweeps.iter_mut().find(|s| s.id == id) {
+            sweep.status = status;
+        }
+    }
+}
+
+/// Background job withdrawing PaymentProcessor revenue.
+pub struct TreasurySweeper {
+    config: TreasuryConfig,
+    contract: Address,
+    treasury: Address,
+    threshold: U256,
+    tx_builder: TxBuilder,
+    log: Arc<SweepLog>,
+}
+
+impl TreasurySweeper {
+    pub fn new(
+        config: TreasuryConfig,
+        contract_address: &str,
+        tx_builder: TxBuilder,
+        log: Arc<SweepLog>,
+    ) -> Result<Self, String> {
+        let contract = contract_address.parse()
+            .map_err(|e| format!("Invalid contract address: {}", e))?;
+        let treasury = config.address.parse()
+            .map_err(|e| format!("Invalid treasury address: {}", e))?;
+        let threshold = U256::from_str_radix(&config.threshold, 10)
+            .map_err(|e| format!("Invalid treasury threshold: {}", e))?;
+        Ok(Self { config, contract, treasury, threshold, tx_builder, log })
+    }
+
+    /// Check the balance every `interval_secs` until shutdown.
+    pub async fn run(self, mut shutdown: broadcast::Receiver<()>) {
+        tracing::info!(
+            contract = %self.contract,
+            treasury = %self.treasury,
+            threshold = %self.threshold,
+            "Treasury sweeper starting"
+        );
+        self.check_owner().await;
+
+        let mut ticker = tokio::time::interval(Duration::from_secs(self.config.interval_secs));
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    self.tick().await;
+                }
+                _ = shutdown.recv() => {
+                    tracing::info!("Treasury sweeper received shutdown signal, exiting loop");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Warn early if sweeps are bound to revert.
+    async fn check_owner(&self) {
+        let call = alloy::rpc::types::TransactionRequest::default()
+            .with_to(self.contract)
+            .with_input(ownerCall {}.abi_encode());
+        let Ok(output) = self.tx_builder.client().call(call).await else {
+            return;
+        };
+        match ownerCall::abi_decode_returns(&output) {
+            Ok(owner) if owner != self.tx_builder.address() => tracing::error!(
+                owner = %owner,
+                wallet = %self.tx_builder.address(),
+                "Proxy wallet does not own the PaymentProcessor, treasury sweeps will revert"
+            ),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Could not decode PaymentProcessor owner: {}", e),
+        }
+    }
+
+    async fn tick(&self) {
+        let balance = match self.tx_builder.client().get_balance(self.contract).await {
+            Ok(balance) => balance,
+            Err(e) => {
+                tracing::warn!("Failed to read PaymentProcessor balance: {}", e);
+                return;
+            }
+        };
+        self.log.record_balance(balance);
+        metrics::record_treasury_balance(f64::from(balance));
+
+        if balance.is_zero() || balance < self.threshold {
+            tracing::debug!(%balance, threshold = %self.threshold, "Below sweep threshold");
+            return;
+        }
+        let pending = self.tx_builder.nonces().pending().await;
+        if let Some(tx) = pending.iter().find(|tx| is_sweep(&tx.request, self.contract)) {
+            tracing::info!(tx_hash = %tx.tx_hash, nonce = tx.nonce, "Earlier treasury sweep still pending, skipping");
+            metrics::record_treasury_sweep("postponed");
+            return;
+        }
+        self.sweep(balance).await;
+    }
+
+    /// Withdraw `amount` and follow the transaction to confirmation.
+    async fn sweep(&self, amount: U256) {
+        let data = withdrawCall { to: self.treasury, amount }.abi_encode();
+        let tx = match self.tx_builder.build(self.contract, U256::ZERO, data.into()).await {
+            Ok(tx) => tx,
+            Err(e @ BlockchainError::GasPriceTooHigh { .. }) => {
+                tracing::warn!("Postponing treasury sweep: {}", e);
+                metrics::record_treasury_sweep("postponed");
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Failed to build treasury sweep: {}", e);
+                metrics::record_treasury_sweep("error");
+                return;
+            }
+        };
+        if let Some(gas) = tx.gas.filter(|gas| *gas > self.config.gas_limit) {
+            tracing::error!("Treasury sweep needs {} gas, more than the configured {}", gas, self.config.gas_limit);
+            metrics::record_treasury_sweep("error");
+            return;
+        }
+
+        let tx_hash = match self.tx_builder.broadcast(tx).await {
+            Ok(tx_hash) => tx_hash,
+            Err(e) => {
+                tracing::error!("Failed to send treasury sweep: {}", e);
+                metrics::record_treasury_sweep("error");
+                return;
+            }
+        };
+        let id = Uuid::new_v4();
+        self.log.push(Sweep {
+            id,
+            sent_at: unix_now(),
+            amount: amount.to_string(),
+            to: self.treasury,
+            tx_hash,
+            status: SweepStatus::Pending,
+        });
+        metrics::record_treasury_sweep("sent");
+        tracing::info!(tx_hash = %tx_hash, %amount, treasury = %self.treasury, "Treasury sweep sent");
+
+        let status = match self
+            .tx_builder
+            .wait_for_confirmation(tx_hash, self.config.confirmation_timeout_secs)
+            .await
+        {
+            Ok(ConfirmationStatus::Confirmed { block_number }) => {
+                tracing::info!(tx_hash = %tx_hash, block_number, "Treasury sweep confirmed");
+                metrics::record_treasury_sweep("confirmed");
+                SweepStatus::Confirmed { block_number }
+            }
+            Ok(ConfirmationStatus::Failed(reason)) => {
+                tracing::error!(tx_hash = %tx_hash, reason = %reason, "Treasury sweep failed");
+                metrics::record_treasury_sweep("failed");
+                SweepStatus::Failed { reason }
+            }
+            Ok(other) => {
+                tracing::warn!(tx_hash = %tx_hash, status = ?other, "Treasury sweep not confirmed");
+                metrics::record_treasury_sweep("unconfirmed");
+                SweepStatus::Unconfirmed
+            }
+            Err(e) => {
+                tracing::warn!(tx_hash = %tx_hash, "Treasury sweep not confirmed: {}", e);
+                metrics::record_treasury_sweep("unconfirmed");
+                SweepStatus::Unconfirmed
+            }
+        };
+        self.log.set_status(id, status);
+    }
+}
+
+/// Whether `tx` withdraws from the PaymentProcessor at `contract`.
+fn is_sweep(tx: &TransactionRequest, contract: Address) -> bool {
+    tx.to.and_then(|kind| kind.to().copied()) == Some(contract)
+        && tx.input.input().is_some_and(|input| input.starts_with(&withdrawCall::SELECTOR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(n: u8) -> Sweep {
+        Sweep {
+            id: Uuid::new_v4(),
+            sent_at: n as u64,
+            amount: "1".to_string(),
+            to: Address::ZERO,
+            tx_hash: TxHash::repeat_byte(n),
+            status: SweepStatus::Pending,
+        }
+    }
+
+    #[test]
+    fn test_sweep_log() {
+        let log = SweepLog::new();
+        for n in 0..(MAX_SWEEPS + 5) {
+            log.push(sweep(n as u8));
+        }
+        let sweeps = log.sweeps();
+        assert_eq!(sweeps.len(), MAX_SWEEPS);
+        assert_eq!(sweeps[0].sent_at, MAX_SWEEPS as u64 + 4);
+
+        log.set_status(sweeps[0].id, SweepStatus::Confirmed { block_number: 7 });
+        assert_eq!(log.sweeps()[0].status, SweepStatus::Confirmed { block_number: 7 });
+
+        let json = serde_json::to_value(&log.sweeps()[0]).unwrap();
+        assert_eq!(json["status"], "confirmed");
+        assert_eq!(json["block_number"], 7);
+    }
+
+    #[test]
+    fn test_withdraw_encoding() {
+        let to = Address::with_last_byte(9);
+        let data = withdrawCall { to, amount: U256::from(5) }.abi_encode();
+        // selector + two words
+        assert_eq!(data.len(), 4 + 64);
+        assert_eq!(&data[..4], &withdrawCall::SELECTOR);
+        let decoded = withdrawCall::abi_decode(&data).unwrap();
+        assert_eq!((decoded.to, decoded.amount), (to, U256::from(5)));
+    }
+
+    #[test]
+    fn test_is_sweep() {
+        let contract = Address::with_last_byte(1);
+        let data = withdrawCall { to: Address::with_last_byte(9), amount: U256::from(5) }.abi_encode();
+        let sweep = TransactionRequest::default().with_to(contract).with_input(data.clone());
+        assert!(is_sweep(&sweep, contract));
+
+        // Another contract, or a cancellation replacing the sweep
+        assert!(!is_sweep(&sweep, Address::with_last_byte(2)));
+        let cancel = TransactionRequest::default().with_to(Address::with_last_byte(3));
+        assert!(!is_sweep(&cancel, contract));
+    }
+}
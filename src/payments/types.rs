@@ -2,20 +2,36 @@
 
 use alloy::primitives::{Address, U256};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 /// Represents a detected payment event on the blockchain.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaymentEvent {
+    /// Chain the payment was made on.
+    #[serde(default)]
+    pub chain_id: u64,
     /// The transaction hash.
     pub tx_hash: String,
+    /// Index of the event log within its block.
+    #[serde(default)]
+    pub log_index: u64,
     /// block number where event occurred.
     pub block_number: u64,
+    /// Timestamp of that block in seconds; zero if unknown.
+    #[serde(default)]
+    pub block_timestamp: u64,
     /// User who made the payment.
     pub user: Address,
     /// Amount paid.
     pub amount: U256,
     /// Tier ID purchased.
     pub tier_id: u8,
+    /// Quoted duration in seconds; `None` for plain single-period purchases.
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+    /// Quote the payment settles, if the purchase referenced one.
+    #[serde(default)]
+    pub quote_id: Option<Uuid>,
 }
 
 #[cfg(test)]
@@ -25,15 +41,21 @@ mod tests {
     #[test]
     fn test_payment_event_serde() {
         let event = PaymentEvent {
+            chain_id: 1,
             tx_hash: "0x123".to_string(),
+            log_index: 0,
             block_number: 100,
+            block_timestamp: 1_700_000_000,
             user: Address::ZERO,
             amount: U256::from(1000),
             tier_id: 1,
+            duration_secs: Some(3600),
+            quote_id: None,
         };
         let json = serde_json::to_string(&event).unwrap();
         let decoded: PaymentEvent = serde_json::from_str(&json).unwrap();
         assert_eq!(decoded.amount, U256::from(1000));
         assert_eq!(decoded.user, Address::ZERO);
+        assert_eq!(decoded.duration_secs, Some(3600));
     }
 }
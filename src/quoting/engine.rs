@@ -1,112 +1,425 @@
 //! Core logic for calculating prices and generating signed quotes.
 
-use alloy::primitives::{keccak256, U256};
+use alloy::primitives::{Address, U256};
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
-use crate::blockchain::types::BlockchainResult;
 use crate::blockchain::wallet::Wallet;
-use crate::quoting::types::{Quote, QuoteRequest, ServiceType, SignedQuote};
+use crate::config::{PricingConfig, PromoCode, ServicePrice, SignatureScheme};
+use crate::payments::cache::SubscriptionCache;
+use crate::payments::catalog::TierCatalog;
+use crate::quoting::lifecycle::{QuoteRegistry, TrackedQuote};
+use crate::quoting::oracle::{parse_usd, FeedRate, PriceOracle};
+use crate::quoting::promo::{self, PromoBook};
+use crate::quoting::signing::{quote_hash, quote_signable, ChainDomains};
+use crate::quoting::types::{
+    Quote, QuoteError, QuoteRequest, QuoteResult, QuoteVerification, SignedQuote, TierChange,
+};
 
-use dashmap::DashMap;
 use std::sync::Arc;
 
 /// Engine for generating and signing quotes.
 #[derive(Clone)]
 pub struct QuoteEngine {
     wallet: Wallet,
-    quotes: Arc<DashMap<Uuid, SignedQuote>>,
+    catalog: Arc<TierCatalog>,
+    subscriptions: Arc<SubscriptionCache>,
+    pricing: Arc<PricingConfig>,
+    oracle: Arc<PriceOracle>,
+    chains: ChainDomains,
+    quotes: Arc<QuoteRegistry>,
+    promos: Arc<PromoBook>,
+}
+
+/// Outcome of pricing a request.
+struct Price {
+    amount: U256,
+    duration_seconds: Option<u64>,
+    /// Set for USD-priced services.
+    conversion: Option<(String, FeedRate)>,
+    /// Per-period price of a USD-priced tier at the quoted rate.
+    converted: Option<U256>,
 }
 
 impl QuoteEngine {
-    /// Create a new quote engine.
-    pub fn new(wallet: Wallet) -> Self {
+    /// Create a new quote engine selling the services in `pricing`.
+    ///
+    /// USD prices are converted with `oracle`. Quotes are payable to
+    /// `payment_processor` on the wallet's chain unless they name another
+    /// chain added with [`with_chain`](Self::with_chain), and their EIP-712
+    /// signatures are bound to that chain and contract. Issued quotes are
+    /// tracked in `quotes`; promo codes are looked up in `promos`. Tier
+    /// changes are credited from the subscriptions in `subscriptions`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        wallet: Wallet,
+        catalog: Arc<TierCatalog>,
+        subscriptions: Arc<SubscriptionCache>,
+        pricing: PricingConfig,
+        oracle: Arc<PriceOracle>,
+        payment_processor: Address,
+        quotes: Arc<QuoteRegistry>,
+        promos: Arc<PromoBook>,
+    ) -> Self {
         Self {
+            chains: ChainDomains::new(wallet.chain_id(), payment_processor),
             wallet,
-            quotes: Arc::new(DashMap::new()),
+            catalog,
+            subscriptions,
+            pricing: Arc::new(pricing),
+            oracle,
+            quotes,
+            promos,
         }
     }
 
+    /// Also quote payments to `payment_processor` on `chain_id`.
+    pub fn with_chain(mut self, chain_id: u64, payment_processor: Address) -> Self {
+        self.chains = self.chains.with_chain(chain_id, payment_processor);
+        self
+    }
+
     /// Generate a signed quote for a request.
-    pub async fn generate_quote(&self, request: QuoteRequest) -> BlockchainResult<SignedQuote> {
-        let (amount, currency) = self.calculate_price(&request);
-        let expiry = self.calculate_expiry(&request);
+    pub async fn generate_quote(&self, request: QuoteRequest) -> QuoteResult<SignedQuote> {
+        let service = self.service(&request)?;
+        let chain_id = self.chain(&request, service)?;
+        let mut price = self.calculate_price(&request).await?;
+        let tier_change = match request.change_tier {
+            true => Some(self.tier_change(&request, service, &mut price).await?),
+            false => None,
+        };
+        let (promo, promo_code, discount) = match &request.promo_code {
+            Some(code) => {
+                let (code, promo, discount) = self.apply_promo(code, &request, price.amount)?;
+                price.amount -= discount;
+                (Some(promo), Some(code), Some(discount.to_string()))
+            }
+            None => (None, None, None),
+        };
+        if let (Some(tier_id), Some(duration), None, SignatureScheme::Legacy) =
+            (service.tier_id, price.duration_seconds, &service.token_address, self.pricing.signature_scheme)
+        {
+            self.check_contract_floor(&request, tier_id, duration, price.amount)?;
+        }
+        let (usd_amount, exchange_rate) = price.conversion.unzip();
+        let expiry = self.calculate_expiry(service);
         let nonce = fastrand::u64(..);
         let id = Uuid::new_v4();
 
         let quote = Quote {
             id,
             service_type: request.service_type,
-            amount: amount.to_string(),
-            currency,
+            amount: price.amount.to_string(),
+            currency: service.currency.clone(),
+            token_address: service.token_address.as_deref().and_then(|a| a.parse::<Address>().ok()),
+            duration_seconds: price.duration_seconds,
+            tier_id: service.tier_id,
+            usd_amount,
+            exchange_rate,
+            promo_code,
+            discount,
             expiry,
             nonce,
             user_address: request.user_address,
+            chain_id: Some(chain_id),
+            tier_change,
         };
 
         let signed = self.sign_quote(quote).await?;
         
-        // Store quote
-        self.quotes.insert(id, signed.clone());
+        // Track quote until it is paid or expires; this also holds the
+        // promo code's redemption limits
+        self.quotes.insert(signed.clone(), service.tier_id, promo.as_ref())?;
 
         Ok(signed)
     }
 
-    /// Get a quote by ID.
-    pub fn get_quote(&self, id: Uuid) -> Option<SignedQuote> {
-        self.quotes.get(&id).map(|r| r.value().clone())
+    /// Get a quote and its lifecycle status by ID.
+    pub fn get_quote(&self, id: Uuid) -> QuoteResult<Option<TrackedQuote>> {
+        self.quotes.get(id)
+    }
+
+    /// Unpaid, unexpired quotes issued to `user`.
+    pub fn outstanding_quotes(&self, user: Address) -> QuoteResult<Vec<TrackedQuote>> {
+        self.quotes.outstanding(user)
     }
 
-    /// Calculate price based on service type.
+    /// Check that a quote was signed by this proxy, is untampered and has
+    /// not expired.
+    pub fn verify_quote(&self, signed: &SignedQuote) -> QuoteVerification {
+        let expected_signer = self.wallet.address();
+        let chain_id = signed.quote.chain_id.unwrap_or(self.chains.default_chain());
+        let domain = self.chains.domain(chain_id);
+        let hash = domain.and_then(|domain| quote_hash(&signed.quote, signed.scheme, &domain));
+        let signer = hash.and_then(|h| signed.signature.recover_address_from_prehash(&h).ok());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let expired = signed.quote.expiry <= now;
+        let legacy_accepted =
+            self.pricing.accept_legacy_signatures || self.pricing.signature_scheme == SignatureScheme::Legacy;
+
+        let reason = if self.chains.processor(chain_id).is_none() {
+            Some(format!("not payable on chain {}", chain_id))
+        } else if signed.scheme == SignatureScheme::Legacy && !legacy_accepted {
+            Some("legacy signatures are not accepted".to_string())
+        } else if hash.is_none() {
+            Some("malformed amount".to_string())
+        } else if hash != Some(signed.hash) {
+            Some("quote fields do not match the signed hash".to_string())
+        } else if signer != Some(expected_signer) {
+            Some("not signed by this proxy".to_string())
+        } else if expired {
+            Some("quote expired".to_string())
+        } else {
+            None
+        };
+
+        QuoteVerification {
+            valid: reason.is_none(),
+            signer,
+            expected_signer,
+            hash,
+            expired,
+            reason,
+        }
+    }
+
+    /// Look up the price book entry for a request and check its duration.
+    fn service(&self, request: &QuoteRequest) -> QuoteResult<&ServicePrice> {
+        let service = self
+            .pricing
+            .services
+            .get(request.service_type.as_str())
+            .ok_or_else(|| QuoteError::UnknownService(request.service_type.to_string()))?;
+
+        if let Some(duration) = request.duration_seconds {
+            if service.max_duration_secs == 0 {
+                return Err(QuoteError::InvalidDuration(format!(
+                    "{} does not take a duration",
+                    request.service_type
+                )));
+            }
+            if duration < service.min_duration_secs || duration > service.max_duration_secs {
+                return Err(QuoteError::InvalidDuration(format!(
+                    "duration must be between {}s and {}s",
+                    service.min_duration_secs, service.max_duration_secs
+                )));
+            }
+        }
+        Ok(service)
+    }
+
+    /// Chain the request will be payable on.
     ///
-    /// In a real system, this would look up dynamic pricing or query an oracle.
-    fn calculate_price(&self, request: &QuoteRequest) -> (U256, String) {
-        match request.service_type {
-            ServiceType::SubscriptionTier1 => (
-                U256::from(10_000_000_000_000_000u64), // 0.01 ETH
-                "ETH".to_string(),
-            ),
-            ServiceType::SubscriptionTier2 => (
-                U256::from(50_000_000_000_000_000u64), // 0.05 ETH
-                "ETH".to_string(),
-            ),
-            ServiceType::ProofGeneration => (
-                U256::from(1_000_000_000_000_000u64), // 0.001 ETH
-                "ETH".to_string(),
-            ),
+    /// Token addresses in the price book refer to the primary chain, so
+    /// token-priced services are only quoted there. Native prices are only
+    /// quoted on chains paying in the primary chain's native currency.
+    fn chain(&self, request: &QuoteRequest, service: &ServicePrice) -> QuoteResult<u64> {
+        let default_chain = self.chains.default_chain();
+        let chain_id = request.chain_id.unwrap_or(default_chain);
+        if self.chains.processor(chain_id).is_none() {
+            return Err(QuoteError::UnsupportedChain(chain_id));
         }
+        if service.token_address.is_some() && chain_id != default_chain {
+            return Err(QuoteError::NotOffered(format!(
+                "{} is only payable on chain {}",
+                request.service_type, default_chain
+            )));
+        }
+        if service.token_address.is_none() && !self.catalog.prices_chain(chain_id) {
+            return Err(QuoteError::NotOffered(format!(
+                "{} is not priced on chain {}",
+                request.service_type, chain_id
+            )));
+        }
+        Ok(chain_id)
     }
 
-    /// Calculate quote expiration time.
-    fn calculate_expiry(&self, _request: &QuoteRequest) -> u64 {
-        // Quotes valid for 1 hour by default
+    /// Calculate price and subscribed duration based on service type.
+    ///
+    /// USD prices are first converted at the current feed rate. Subscription
+    /// services are priced pro-rata per second of the tier's base period
+    /// (one period when no duration is requested), less volume discounts.
+    /// Other services cost their flat amount.
+    async fn calculate_price(&self, request: &QuoteRequest) -> QuoteResult<Price> {
+        let service = self.service(request)?;
+
+        let conversion = match &service.amount_usd {
+            Some(usd) => {
+                let (amount, rate) = self.convert_usd(usd, request.service_type.as_str()).await?;
+                Some((amount, (usd.clone(), rate)))
+            }
+            None => None,
+        };
+        let (converted, conversion) = conversion.unzip();
+
+        if let Some(tier_id) = service.tier_id {
+            if !self.catalog.is_synced() {
+                return Err(QuoteError::CatalogUnavailable);
+            }
+            let not_offered = || QuoteError::NotOffered(format!("Tier {} is not offered", tier_id));
+            let pricing = self.catalog.active_pricing(tier_id).ok_or_else(not_offered)?;
+            let duration = request.duration_seconds.unwrap_or(pricing.duration_secs);
+            let amount = self
+                .catalog
+                .price_for_duration(tier_id, duration, converted)
+                .ok_or_else(not_offered)?;
+            return Ok(Price { amount, duration_seconds: Some(duration), conversion, converted });
+        }
+
+        let amount = match converted {
+            Some(amount) => amount,
+            None => {
+                let amount = service.amount.as_deref().unwrap_or_default();
+                U256::from_str_radix(amount, 10).map_err(|_| {
+                    QuoteError::NotOffered(format!("{} has no valid price", request.service_type))
+                })?
+            }
+        };
+        Ok(Price { amount, duration_seconds: None, conversion, converted: None })
+    }
+
+    /// Convert a USD price to the payment token at the current feed rate.
+    async fn convert_usd(&self, usd: &str, what: &str) -> QuoteResult<(U256, FeedRate)> {
+        let usd_value = parse_usd(usd).ok_or_else(|| QuoteError::NotOffered(format!("{} has no valid price", what)))?;
+        let rate = self.oracle.latest_rate().await?;
+        let decimals = self.oracle.token_decimals().unwrap_or(18);
+        Ok((rate.to_token_amount(usd_value, decimals), rate))
+    }
+
+    /// Credit the user's active subscription against switching to the
+    /// requested tier.
+    ///
+    /// The unused time is valued at the current tier's price. A credit
+    /// below `price` is deducted from it; otherwise the full price is
+    /// quoted and the credit is converted to extra time on the new tier.
+    /// The `PaymentProcessor` honors both only for EIP-712 signed quotes.
+    async fn tier_change(&self, request: &QuoteRequest, service: &ServicePrice, price: &mut Price) -> QuoteResult<TierChange> {
+        let invalid = |reason: String| QuoteError::InvalidTierChange(reason);
+        let to_tier = service
+            .tier_id
+            .ok_or_else(|| invalid(format!("{} is not a subscription", request.service_type)))?;
+        if self.pricing.signature_scheme == SignatureScheme::Legacy {
+            return Err(invalid("tier changes need EIP-712 signed quotes".to_string()));
+        }
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
-        now + 3600
+        let current = self
+            .subscriptions
+            .get_subscription(&request.user_address)
+            .filter(|sub| sub.expiry > now)
+            .ok_or_else(|| invalid("no active subscription".to_string()))?;
+        if current.tier_id == to_tier {
+            return Err(invalid(format!("already subscribed to tier {}, renew instead", to_tier)));
+        }
+        // Credits are only comparable between tiers paid in the same currency
+        let current_service = self
+            .pricing
+            .services
+            .values()
+            .find(|s| {
+                s.tier_id == Some(current.tier_id) && s.currency == service.currency && s.token_address == service.token_address
+            })
+            .ok_or_else(|| invalid(format!("tier {} is not paid in {}", current.tier_id, service.currency)))?;
+        // A USD-priced current tier is valued at the rate being quoted
+        let current_converted = match &current_service.amount_usd {
+            Some(usd) => Some(self.convert_usd(usd, &format!("tier {}", current.tier_id)).await?.0),
+            None => None,
+        };
+
+        let credit = self
+            .catalog
+            .value_of(current.tier_id, current.expiry - now, current_converted)
+            .ok_or_else(|| invalid(format!("tier {} has no price", current.tier_id)))?;
+        let bonus_seconds = if credit < price.amount {
+            price.amount -= credit;
+            0
+        } else {
+            self.catalog
+                .duration_for(to_tier, credit, price.converted)
+                .ok_or_else(|| QuoteError::NotOffered(format!("Tier {} is not offered", to_tier)))?
+        };
+        Ok(TierChange {
+            from_tier: current.tier_id,
+            from_expiry: current.expiry,
+            credit: credit.to_string(),
+            bonus_seconds,
+        })
+    }
+
+    /// Check a promo code against the request. Redemption limits are checked
+    /// when the quote is tracked.
+    ///
+    /// Returns the canonical code, its terms and the discount it gives on
+    /// `amount`.
+    fn apply_promo(&self, code: &str, request: &QuoteRequest, amount: U256) -> QuoteResult<(String, PromoCode, U256)> {
+        let invalid = |reason: &str| QuoteError::InvalidPromo(format!("{}: {}", code, reason));
+        let (_, promo) = self.promos.get(code).ok_or_else(|| invalid("unknown code"))?;
+        let code = promo::normalize(code);
+
+        if !promo.services.is_empty() && !promo.services.iter().any(|s| s == request.service_type.as_str()) {
+            return Err(invalid("not valid for this service"));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if !promo::is_active(&promo, now) {
+            return Err(invalid("not currently valid"));
+        }
+
+        let discount = promo::discount(&promo, amount);
+        Ok((code, promo, discount))
+    }
+
+    /// Refuse legacy-signed subscription quotes below the on-chain price.
+    ///
+    /// The `PaymentProcessor` cannot check legacy signatures, so such quotes
+    /// are paid without one, at no less than the on-chain price.
+    fn check_contract_floor(&self, request: &QuoteRequest, tier_id: u8, duration: u64, amount: U256) -> QuoteResult<()> {
+        let floor = self
+            .catalog
+            .contract_floor(tier_id, duration)
+            .ok_or_else(|| QuoteError::NotOffered(format!("Tier {} is not offered", tier_id)))?;
+        if amount < floor {
+            return Err(QuoteError::NotOffered(format!(
+                "{} would cost {}, below the payment contract minimum of {}",
+                request.service_type, amount, floor
+            )));
+        }
+        Ok(())
     }
 
-    /// Sign the quote using the wallet.
-    async fn sign_quote(&self, quote: Quote) -> BlockchainResult<SignedQuote> {
-        // EIP-712 style hashing would be better, but for now simple hash of fields
-        // Serialize relevant fields for hashing
-        // This is a simplified hashing scheme for demonstration
-        let mut data = Vec::new();
-        data.extend_from_slice(quote.id.as_bytes());
-        data.extend_from_slice(&U256::from_str_radix(&quote.amount, 10).unwrap_or_default().to_be_bytes::<32>());
-        data.extend_from_slice(&quote.nonce.to_be_bytes());
-        data.extend_from_slice(quote.user_address.as_slice());
+    /// Calculate quote expiration time.
+    fn calculate_expiry(&self, service: &ServicePrice) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        now + service.quote_validity_secs
+    }
 
-        let hash = keccak256(&data);
+    /// Sign the quote using the wallet, in the configured scheme.
+    async fn sign_quote(&self, quote: Quote) -> QuoteResult<SignedQuote> {
+        let scheme = self.pricing.signature_scheme;
+        let chain_id = quote.chain_id.unwrap_or(self.chains.default_chain());
+        let domain = self.chains.domain(chain_id).ok_or(QuoteError::UnsupportedChain(chain_id))?;
+        let invalid = || QuoteError::NotOffered(format!("invalid amount '{}'", quote.amount));
+        let hash = quote_hash(&quote, scheme, &domain).ok_or_else(invalid)?;
+        let signable = quote_signable(&quote, scheme, &domain).ok_or_else(invalid)?;
 
-        // Sign the hash
-        let signature = self.wallet.sign_hash(hash).await?;
+        // Sign the typed quote, or the preimage of the legacy hash
+        let signature = self.wallet.sign(&signable).await?;
 
         Ok(SignedQuote {
             quote,
             signature,
             hash,
+            scheme,
         })
     }
 }
@@ -114,7 +427,28 @@ impl QuoteEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alloy::primitives::Address;
+    use crate::config::QosConfig;
+    use crate::payments::catalog::TierPricing;
+    use crate::quoting::types::ServiceType;
+
+    fn test_engine() -> QuoteEngine {
+        test_engine_with(PricingConfig::default())
+    }
+
+    fn test_engine_with(pricing: PricingConfig) -> QuoteEngine {
+        let catalog = Arc::new(TierCatalog::with_default_pricing(&QosConfig::default()));
+        catalog.set_price_book(&pricing);
+        QuoteEngine::new(
+            test_wallet(),
+            catalog,
+            Arc::new(SubscriptionCache::new(None)),
+            pricing,
+            Arc::new(PriceOracle::new()),
+            Address::ZERO,
+            Arc::new(QuoteRegistry::default()),
+            Arc::new(PromoBook::new()),
+        )
+    }
 
     fn test_wallet() -> Wallet {
         // Use Anvil's well-known test account #0 for deterministic testing
@@ -123,41 +457,428 @@ mod tests {
         Wallet::from_private_key(TEST_KEY, 31337).expect("Failed to create test wallet")
     }
 
+    fn request(service: &str, duration_seconds: Option<u64>) -> QuoteRequest {
+        QuoteRequest {
+            service_type: ServiceType::new(service),
+            user_address: Address::ZERO,
+            duration_seconds,
+            promo_code: None,
+            chain_id: None,
+            change_tier: false,
+        }
+    }
+
     #[tokio::test]
     async fn test_quote_generation() {
-        let wallet = test_wallet();
-        let engine = QuoteEngine::new(wallet);
+        let engine = test_engine();
 
-        let request = QuoteRequest {
-            service_type: ServiceType::SubscriptionTier1,
-            user_address: Address::ZERO,
-            duration_seconds: None,
-        };
+        let signed_quote = engine
+            .generate_quote(request("subscription_tier1", None))
+            .await
+            .expect("Failed to generate quote");
 
-        let signed_quote = engine.generate_quote(request.clone()).await.expect("Failed to generate quote");
-
-        assert_eq!(signed_quote.quote.service_type, ServiceType::SubscriptionTier1);
+        assert_eq!(signed_quote.quote.service_type, ServiceType::new("subscription_tier1"));
         assert_eq!(signed_quote.quote.currency, "ETH");
         assert!(signed_quote.quote.expiry > 0);
         
         // Verify storage
-        let retrieved = engine.get_quote(signed_quote.quote.id).expect("Quote not found");
-        assert_eq!(retrieved.quote.id, signed_quote.quote.id);
+        let retrieved = engine.get_quote(signed_quote.quote.id).unwrap().expect("Quote not found");
+        assert_eq!(retrieved.signed.quote.id, signed_quote.quote.id);
+        assert_eq!(retrieved.status, crate::quoting::QuoteStatus::Issued);
+        assert_eq!(retrieved.tier_id, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_refuses_tiers_until_catalog_synced() {
+        let engine = QuoteEngine::new(
+            test_wallet(),
+            Arc::new(TierCatalog::new(&QosConfig::default())),
+            Arc::new(SubscriptionCache::new(None)),
+            PricingConfig::default(),
+            Arc::new(PriceOracle::new()),
+            Address::ZERO,
+            Arc::new(QuoteRegistry::default()),
+            Arc::new(PromoBook::new()),
+        );
+        let result = engine.generate_quote(request("subscription_tier1", None)).await;
+        assert!(matches!(result, Err(QuoteError::CatalogUnavailable)));
+        // Flat-priced services do not depend on the catalog
+        assert!(engine.generate_quote(request("proof_generation", None)).await.is_ok());
     }
 
     #[tokio::test]
     async fn test_price_calculation() {
-        let wallet = test_wallet();
-        let engine = QuoteEngine::new(wallet);
+        let engine = test_engine();
+        let request = request("subscription_tier2", None);
         
-        let request = QuoteRequest {
-            service_type: ServiceType::SubscriptionTier2,
-            user_address: Address::ZERO,
-            duration_seconds: None,
+        let price = engine.calculate_price(&request).await.unwrap();
+        assert_eq!(price.amount, U256::from(50_000_000_000_000_000u64));
+        assert_eq!(price.duration_seconds, Some(30 * 24 * 3600));
+
+        // Prices follow the catalog
+        engine.catalog.set_pricing(2, TierPricing {
+            price: U256::from(42),
+            duration_secs: 3600,
+            active: true,
+        });
+        assert_eq!(engine.calculate_price(&request).await.unwrap().amount, U256::from(42));
+
+        // Deactivated tiers cannot be quoted
+        engine.catalog.set_pricing(2, TierPricing {
+            price: U256::from(42),
+            duration_secs: 3600,
+            active: false,
+        });
+        assert!(matches!(engine.calculate_price(&request).await, Err(QuoteError::NotOffered(_))));
+    }
+
+    #[tokio::test]
+    async fn test_duration_pricing() {
+        let engine = test_engine();
+        engine.catalog.set_pricing(1, TierPricing {
+            price: U256::from(1_000_000),
+            duration_secs: 30 * 24 * 3600,
+            active: true,
+        });
+
+        // One year costs twelve-and-a-sixth base periods
+        let year = 365 * 24 * 3600;
+        let signed = engine.generate_quote(request("subscription_tier1", Some(year))).await.unwrap();
+        assert_eq!(signed.quote.amount, "12166667");
+        assert_eq!(signed.quote.duration_seconds, Some(year));
+
+        // The duration is part of the signed payload
+        let short = engine.generate_quote(request("subscription_tier1", Some(3600))).await.unwrap();
+        let mut tampered = short.quote.clone();
+        tampered.duration_seconds = Some(year);
+        let resigned = engine.sign_quote(tampered).await.unwrap();
+        assert_ne!(resigned.hash, short.hash);
+    }
+
+    #[tokio::test]
+    async fn test_promo_code() {
+        let engine = test_engine();
+        engine.catalog.set_pricing(1, TierPricing {
+            price: U256::from(1_000_000),
+            duration_secs: 30 * 24 * 3600,
+            active: true,
+        });
+        engine.promos.put("spring", crate::config::PromoCode {
+            discount_bps: Some(2500),
+            services: vec!["subscription_tier1".to_string()],
+            max_per_address: 1,
+            ..Default::default()
+        }).unwrap();
+        let with_code = |service: &str| QuoteRequest {
+            promo_code: Some("Spring".to_string()),
+            ..request(service, None)
         };
-        
-        let (price, currency) = engine.calculate_price(&request);
-        assert_eq!(price, U256::from(50_000_000_000_000_000u64));
-        assert_eq!(currency, "ETH");
+
+        let signed = engine.generate_quote(with_code("subscription_tier1")).await.unwrap();
+        assert_eq!(signed.quote.amount, "750000");
+        assert_eq!(signed.quote.discount.as_deref(), Some("250000"));
+        assert_eq!(signed.quote.promo_code.as_deref(), Some("SPRING"));
+        assert!(engine.verify_quote(&signed).valid);
+
+        // The unpaid quote already uses up the per-address allowance
+        let again = engine.generate_quote(with_code("subscription_tier1")).await;
+        assert!(matches!(again, Err(QuoteError::InvalidPromo(_))));
+        let other_service = engine.generate_quote(with_code("proof_generation")).await;
+        assert!(matches!(other_service, Err(QuoteError::InvalidPromo(_))));
+        let unknown = QuoteRequest {
+            promo_code: Some("NOPE".to_string()),
+            ..request("subscription_tier1", None)
+        };
+        assert!(matches!(engine.generate_quote(unknown).await, Err(QuoteError::InvalidPromo(_))));
+
+        // Outstanding quotes also count against the total
+        engine.promos.put("once", crate::config::PromoCode {
+            discount_bps: Some(1000),
+            max_redemptions: 1,
+            ..Default::default()
+        }).unwrap();
+        let once = |user: u8| QuoteRequest {
+            promo_code: Some("once".to_string()),
+            user_address: Address::with_last_byte(user),
+            ..request("subscription_tier1", None)
+        };
+        engine.generate_quote(once(1)).await.unwrap();
+        assert!(matches!(engine.generate_quote(once(2)).await, Err(QuoteError::InvalidPromo(_))));
+    }
+
+    #[tokio::test]
+    async fn test_tier_change() {
+        let engine = test_engine();
+        let month = 30 * 24 * 3600;
+        for (tier_id, price) in [(1, 1_000_000u64), (2, 2_000_000)] {
+            engine.catalog.set_pricing(tier_id, TierPricing { price: U256::from(price), duration_secs: month, active: true });
+        }
+        let change = |service: &str| QuoteRequest { change_tier: true, ..request(service, None) };
+        assert!(matches!(engine.generate_quote(change("subscription_tier2")).await, Err(QuoteError::InvalidTierChange(_))));
+
+        // Upgrading with half a month of tier 1 left pays the difference
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        engine.subscriptions.update_subscription(Address::ZERO, 1, now + month / 2);
+        let upgrade = engine.generate_quote(change("subscription_tier2")).await.unwrap();
+        assert_eq!(upgrade.quote.amount, "1500000");
+        assert_eq!(upgrade.quote.duration_seconds, Some(month));
+        let tier_change = upgrade.quote.tier_change.clone().unwrap();
+        assert_eq!((tier_change.from_tier, tier_change.from_expiry), (1, now + month / 2));
+        assert_eq!((tier_change.credit.as_str(), tier_change.bonus_seconds), ("500000", 0));
+        assert!(engine.verify_quote(&upgrade).valid);
+
+        // The credit is signed
+        let mut inflated = upgrade.clone();
+        inflated.quote.tier_change.as_mut().unwrap().bonus_seconds = month;
+        assert!(!engine.verify_quote(&inflated).valid);
+
+        // Downgrading with more credit than the new price adds the rest as time
+        engine.subscriptions.update_subscription(Address::ZERO, 2, now + month / 2);
+        let downgrade = engine.generate_quote(change("subscription_tier1")).await.unwrap();
+        assert_eq!(downgrade.quote.amount, "1000000");
+        assert_eq!(downgrade.quote.tier_change.unwrap().bonus_seconds, month);
+
+        assert!(matches!(engine.generate_quote(change("subscription_tier2")).await, Err(QuoteError::InvalidTierChange(_))));
+        assert!(matches!(engine.generate_quote(change("proof_generation")).await, Err(QuoteError::InvalidTierChange(_))));
+    }
+
+    #[tokio::test]
+    async fn test_tier_change_is_not_held_to_a_discount_limit() {
+        let engine = test_engine();
+        let month = 30 * 24 * 3600;
+        for (tier_id, price) in [(1, 1_000_000u64), (2, 2_000_000)] {
+            engine.catalog.set_pricing(tier_id, TierPricing { price: U256::from(price), duration_secs: month, active: true });
+        }
+        let upgrade = QuoteRequest { change_tier: true, ..request("subscription_tier2", None) };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        // The contract checks the signed amount, however large the credit
+        engine.subscriptions.update_subscription(Address::ZERO, 1, now + month * 3 / 4);
+        let signed = engine.generate_quote(upgrade.clone()).await.unwrap();
+        assert_eq!(signed.quote.amount, "1250000");
+        assert_eq!(signed.quote.tier_id, Some(2));
+
+        // Together with a promo code
+        engine.promos.put("fifth", crate::config::PromoCode {
+            discount_bps: Some(2000),
+            ..Default::default()
+        }).unwrap();
+        let with_code = QuoteRequest { promo_code: Some("FIFTH".to_string()), ..upgrade.clone() };
+        assert_eq!(engine.generate_quote(with_code).await.unwrap().quote.amount, "1000000");
+
+        // Legacy signatures cannot carry a credit the contract would honor
+        let legacy = QuoteEngine {
+            pricing: Arc::new(PricingConfig { signature_scheme: SignatureScheme::Legacy, ..PricingConfig::default() }),
+            ..engine.clone()
+        };
+        assert!(matches!(legacy.generate_quote(upgrade).await, Err(QuoteError::InvalidTierChange(_))));
+    }
+
+    #[tokio::test]
+    async fn test_usd_pricing() {
+        let mut pricing = PricingConfig {
+            price_feed: Some(crate::config::PriceFeedConfig {
+                aggregator_address: Address::with_last_byte(1).to_string(),
+                ..Default::default()
+            }),
+            ..PricingConfig::default()
+        };
+        pricing.services.insert("report".to_string(), ServicePrice {
+            amount_usd: Some("5".to_string()),
+            ..ServicePrice::default()
+        });
+        let tier = pricing.services.get_mut("subscription_tier1").unwrap();
+        tier.amount_usd = Some("10".to_string());
+        let engine = test_engine_with(pricing.clone());
+        engine.oracle.configure(pricing.price_feed.as_ref());
+
+        // No rate yet
+        assert!(matches!(
+            engine.generate_quote(request("report", None)).await,
+            Err(QuoteError::PriceFeed(_))
+        ));
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let rate = FeedRate {
+            answer: U256::from(2000u64 * 100_000_000),
+            decimals: 8,
+            round_id: 7,
+            updated_at: now,
+        };
+        engine.oracle.seed(rate);
+
+        // $5 at $2000/ETH, with the rate in the signed quote
+        let signed = engine.generate_quote(request("report", None)).await.unwrap();
+        assert_eq!(signed.quote.amount, "2500000000000000");
+        assert_eq!(signed.quote.usd_amount.as_deref(), Some("5"));
+        assert_eq!(signed.quote.exchange_rate, Some(rate));
+
+        // USD tiers are converted per period, then pro-rated
+        let signed = engine
+            .generate_quote(request("subscription_tier1", Some(15 * 24 * 3600)))
+            .await
+            .unwrap();
+        assert_eq!(signed.quote.amount, "2500000000000000");
+        // Conversions are not kept: the catalog still holds the on-chain price
+        assert_eq!(engine.catalog.price_for_duration(1, 30 * 24 * 3600, None), Some(U256::from(10_000_000_000_000_000u64)));
+    }
+
+    #[tokio::test]
+    async fn test_contract_floor() {
+        let engine = test_engine();
+        engine.catalog.set_pricing(1, TierPricing {
+            price: U256::from(1_000_000),
+            duration_secs: 30 * 24 * 3600,
+            active: true,
+        });
+        engine.promos.put("half", crate::config::PromoCode {
+            discount_bps: Some(5000),
+            ..Default::default()
+        }).unwrap();
+        let with_code = QuoteRequest {
+            promo_code: Some("HALF".to_string()),
+            ..request("subscription_tier1", None)
+        };
+
+        // The contract checks EIP-712 quotes, whatever their discount
+        let signed = engine.generate_quote(with_code.clone()).await.unwrap();
+        assert_eq!(signed.quote.amount, "500000");
+        assert_eq!(signed.quote.tier_id, Some(1));
+
+        // Legacy quotes are paid without a signature check, at the on-chain price
+        let legacy = QuoteEngine {
+            pricing: Arc::new(PricingConfig { signature_scheme: SignatureScheme::Legacy, ..PricingConfig::default() }),
+            ..engine.clone()
+        };
+        assert!(matches!(legacy.generate_quote(with_code).await, Err(QuoteError::NotOffered(_))));
+        let mut book = PricingConfig::default();
+        book.services.get_mut("subscription_tier1").unwrap().amount = Some("800000".to_string());
+        legacy.catalog.set_price_book(&book);
+        assert!(matches!(
+            legacy.generate_quote(request("subscription_tier1", None)).await,
+            Err(QuoteError::NotOffered(_))
+        ));
+        legacy.catalog.set_price_book(&PricingConfig::default());
+        assert!(legacy.generate_quote(request("subscription_tier1", None)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_verify_quote() {
+        let engine = test_engine();
+        let signed = engine.generate_quote(request("subscription_tier1", None)).await.unwrap();
+        assert_eq!(signed.scheme, SignatureScheme::Eip712);
+
+        let result = engine.verify_quote(&signed);
+        assert!(result.valid, "{:?}", result.reason);
+        assert_eq!(result.signer, Some(engine.wallet.address()));
+
+        // Tampering with an unsigned-before field is caught
+        let mut tampered = signed.clone();
+        tampered.quote.expiry += 3600;
+        assert!(!engine.verify_quote(&tampered).valid);
+
+        // So is presenting it to another deployment
+        let other = QuoteEngine::new(
+            test_wallet(),
+            engine.catalog.clone(),
+            engine.subscriptions.clone(),
+            PricingConfig::default(),
+            Arc::new(PriceOracle::new()),
+            Address::with_last_byte(1),
+            Arc::new(QuoteRegistry::default()),
+            Arc::new(PromoBook::new()),
+        );
+        assert!(!other.verify_quote(&signed).valid);
+
+        let mut expired = signed.clone();
+        expired.quote.expiry = 1;
+        let resigned = engine.sign_quote(expired.quote).await.unwrap();
+        let result = engine.verify_quote(&resigned);
+        assert!(result.expired && !result.valid);
+    }
+
+    #[tokio::test]
+    async fn test_chain_selection() {
+        let engine = test_engine().with_chain(10, Address::with_last_byte(2));
+
+        let primary = engine.generate_quote(request("subscription_tier1", None)).await.unwrap();
+        assert_eq!(primary.quote.chain_id, Some(31337));
+        let on_chain = |chain_id| QuoteRequest { chain_id: Some(chain_id), ..request("subscription_tier1", None) };
+        let other = engine.generate_quote(on_chain(10)).await.unwrap();
+        assert_eq!(other.quote.chain_id, Some(10));
+        assert!(engine.verify_quote(&other).valid);
+
+        // The chain is bound by the signature
+        let mut moved = other.clone();
+        moved.quote.chain_id = Some(31337);
+        assert!(!engine.verify_quote(&moved).valid);
+        moved.quote.chain_id = Some(5);
+        assert_eq!(engine.verify_quote(&moved).reason.as_deref(), Some("not payable on chain 5"));
+
+        assert!(matches!(engine.generate_quote(on_chain(5)).await, Err(QuoteError::UnsupportedChain(5))));
+
+        // Native prices do not carry over to a chain paying in another currency
+        engine.catalog.set_unpriced_chains([10].into());
+        assert!(matches!(engine.generate_quote(on_chain(10)).await, Err(QuoteError::NotOffered(_))));
+    }
+
+    #[tokio::test]
+    async fn test_legacy_signatures() {
+        let engine = test_engine_with(PricingConfig {
+            signature_scheme: SignatureScheme::Legacy,
+            ..PricingConfig::default()
+        });
+        let signed = engine.generate_quote(request("subscription_tier1", None)).await.unwrap();
+        assert_eq!(signed.scheme, SignatureScheme::Legacy);
+        assert!(engine.verify_quote(&signed).valid);
+
+        // Quotes serialized before the scheme field existed are legacy, and
+        // only accepted while migrating
+        let mut json = serde_json::to_value(&signed).unwrap();
+        json.as_object_mut().unwrap().remove("scheme");
+        let old: SignedQuote = serde_json::from_value(json).unwrap();
+        assert_eq!(test_engine().verify_quote(&old).reason.as_deref(), Some("legacy signatures are not accepted"));
+        let migrating = test_engine_with(PricingConfig {
+            accept_legacy_signatures: true,
+            ..PricingConfig::default()
+        });
+        assert!(migrating.verify_quote(&old).valid);
+    }
+
+    #[tokio::test]
+    async fn test_configured_service() {
+        let mut pricing = PricingConfig::default();
+        pricing.services.insert("api_credits".to_string(), ServicePrice {
+            amount: Some("2500000".to_string()),
+            currency: "USDC".to_string(),
+            token_address: Some("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string()),
+            min_duration_secs: 60,
+            max_duration_secs: 600,
+            quote_validity_secs: 120,
+            ..ServicePrice::default()
+        });
+        let engine = test_engine_with(pricing);
+
+        let signed = engine.generate_quote(request("api_credits", Some(300))).await.unwrap();
+        assert_eq!(signed.quote.amount, "2500000");
+        assert_eq!(signed.quote.currency, "USDC");
+        assert!(signed.quote.token_address.is_some());
+        assert_eq!(signed.quote.duration_seconds, None);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert!(signed.quote.expiry <= now + 120);
+
+        // Duration bounds come from the price book
+        assert!(matches!(
+            engine.generate_quote(request("api_credits", Some(30))).await,
+            Err(QuoteError::InvalidDuration(_))
+        ));
+        assert!(matches!(
+            engine.generate_quote(request("proof_generation", Some(3600))).await,
+            Err(QuoteError::InvalidDuration(_))
+        ));
+        assert!(matches!(
+            engine.generate_quote(request("nope", None)).await,
+            Err(QuoteError::UnknownService(_))
+        ));
     }
 }
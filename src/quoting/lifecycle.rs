@@ -0,0 +1,488 @@
+//! Quote lifecycle tracking.
+//!
+//! ```text
+//! issued ──(matching payment before expiry)──> paid
+//!    └────────────(expiry passes)────────────> expired ──> evicted
+//! ```
+//!
+//! The payment monitor redeems quotes: a confirmed payment is matched by
+//! the quote id it carries or, failing that, by user, tier, duration and
+//! amount. A quote can be redeemed once, by a payment in a block
+//! timestamped before its expiry. Quotes are evicted
+//! [`RETENTION_SECS`] after they expire so clients can still poll the
+//! outcome.
+
+use alloy::primitives::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::config::{PromoCode, QuoteStoreConfig};
+use crate::payments::types::PaymentEvent;
+use crate::quoting::store::{MemoryQuoteStore, PromoRedemptions, QuoteStore, RedbQuoteStore};
+use crate::store::{unix_now, StoreError, StoreResult};
+use crate::quoting::types::{QuoteError, QuoteResult, SignedQuote};
+
+/// How long quotes are kept after expiring.
+pub const RETENTION_SECS: u64 = 3600;
+
+/// Minimum interval between eviction sweeps.
+const SWEEP_INTERVAL_SECS: u64 = 60;
+
+/// Where a quote is in its lifecycle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum QuoteStatus {
+    /// Signed and awaiting payment.
+    Issued,
+    /// Redeemed by an on-chain payment.
+    Paid {
+        tx_hash: String,
+        block_number: u64,
+        paid_at: u64,
+    },
+    /// Expired without being paid.
+    Expired,
+}
+
+/// A quote with its lifecycle state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedQuote {
+    #[serde(flatten)]
+    pub signed: SignedQuote,
+    /// Subscription tier the quote sells, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier_id: Option<u8>,
+    #[serde(flatten)]
+    pub status: QuoteStatus,
+}
+
+/// Issued quotes, shared by the quote engine and the payment monitor.
+///
+/// Outlives config reloads; the backing [`QuoteStore`] decides whether
+/// quotes also outlive restarts.
+pub struct QuoteRegistry {
+    store: Arc<dyn QuoteStore>,
+    max_quotes: usize,
+    max_outstanding_per_user: usize,
+    last_sweep: AtomicU64,
+    /// Serializes the limit checks in [`insert`](Self::insert) with the
+    /// insert itself.
+    insert_lock: Mutex<()>,
+}
+
+impl Default for QuoteRegistry {
+    /// In-memory registry with default limits.
+    fn default() -> Self {
+        Self::new(Arc::new(MemoryQuoteStore::new()), &QuoteStoreConfig::default())
+    }
+}
+
+impl QuoteRegistry {
+    pub fn new(store: Arc<dyn QuoteStore>, config: &QuoteStoreConfig) -> Self {
+        Self {
+            store,
+            max_quotes: config.max_quotes,
+            max_outstanding_per_user: config.max_outstanding_per_user,
+            last_sweep: AtomicU64::new(0),
+            insert_lock: Mutex::new(()),
+        }
+    }
+
+    /// Open the store configured by `config`.
+    ///
+    /// Fails if the database cannot be opened, for example because another
+    /// instance holds it: quotes kept apart from it could be redeemed twice.
+    pub fn open(config: &QuoteStoreConfig) -> StoreResult<Self> {
+        let store: Arc<dyn QuoteStore> = if config.path.is_empty() {
+            Arc::new(MemoryQuoteStore::new())
+        } else {
+            Arc::new(RedbQuoteStore::open(&config.path)?)
+        };
+        Ok(Self::new(store, config))
+    }
+
+    /// The backing store.
+    pub fn store(&self) -> Arc<dyn QuoteStore> {
+        self.store.clone()
+    }
+
+    /// Track a newly issued quote.
+    ///
+    /// Fails if the user already holds `max_outstanding_per_user` open
+    /// quotes, the store is full of unexpired quotes, or the quote would
+    /// take its promo code past the limits of `promo`.
+    pub fn insert(&self, signed: SignedQuote, tier_id: Option<u8>, promo: Option<&PromoCode>) -> QuoteResult<()> {
+        let now = unix_now();
+        if now.saturating_sub(self.last_sweep.swap(now, Ordering::Relaxed)) >= SWEEP_INTERVAL_SECS {
+            self.evict_expired(now)?;
+        }
+
+        let _guard = self.insert_lock.lock().unwrap_or_else(|e| e.into_inner());
+        if self.outstanding(signed.quote.user_address)?.len() >= self.max_outstanding_per_user {
+            return Err(QuoteError::TooManyQuotes(self.max_outstanding_per_user));
+        }
+        if let (Some(code), Some(promo)) = (&signed.quote.promo_code, promo) {
+            let invalid = |reason: &str| QuoteError::InvalidPromo(format!("{}: {}", code, reason));
+            let usage = self.promo_usage(code, signed.quote.user_address)?;
+            if promo.max_redemptions != 0 && usage.total >= promo.max_redemptions {
+                return Err(invalid("fully redeemed"));
+            }
+            if promo.max_per_address != 0 && usage.by_user >= promo.max_per_address {
+                return Err(invalid("already used by this address"));
+            }
+        }
+        if self.store.count()? >= self.max_quotes {
+            // Make room from expired quotes before refusing
+            self.store.evict_expired_before(now)?;
+            if self.store.count()? >= self.max_quotes {
+                return Err(StoreError::Full.into());
+            }
+        }
+
+        self.store.insert(&TrackedQuote {
+            signed,
+            tier_id,
+            status: QuoteStatus::Issued,
+        })?;
+        Ok(())
+    }
+
+    /// Get a quote with its current status.
+    pub fn get(&self, id: Uuid) -> QuoteResult<Option<TrackedQuote>> {
+        let now = unix_now();
+        Ok(self.store.get(id)?.map(|mut q| {
+            expire_if_due(&mut q, now);
+            q
+        }))
+    }
+
+    /// Unpaid, unexpired quotes issued to `user`, soonest expiry first.
+    pub fn outstanding(&self, user: Address) -> QuoteResult<Vec<TrackedQuote>> {
+        self.outstanding_at(user, unix_now())
+    }
+
+    /// Quotes issued to `user` that were still open at `now`.
+    fn outstanding_at(&self, user: Address, now: u64) -> QuoteResult<Vec<TrackedQuote>> {
+        let mut quotes: Vec<_> = self
+            .store
+            .by_user(user)?
+            .into_iter()
+            .filter(|q| q.status == QuoteStatus::Issued && q.signed.quote.expiry > now)
+            .collect();
+        quotes.sort_by_key(|q| q.signed.quote.expiry);
+        Ok(quotes)
+    }
+
+    /// Redeem the quote a confirmed payment settles, if any.
+    ///
+    /// Returns the quote as it was before being marked paid, also when the
+    /// same transaction redeems it again. Payments that
+    /// do not cover the quoted amount, or were mined after expiry, redeem
+    /// nothing. Expiry is judged by the block timestamp, so a payment
+    /// confirmed late still redeems.
+    pub fn redeem(&self, event: &PaymentEvent) -> QuoteResult<Option<TrackedQuote>> {
+        let now = match event.block_timestamp {
+            0 => unix_now(),
+            mined_at => mined_at,
+        };
+        let id = match event.quote_id {
+            Some(id) => id,
+            None => match self.find_match(event, now)? {
+                Some(id) => id,
+                None => return Ok(None),
+            },
+        };
+
+        let mut redeemed = None;
+        let mut replayed = false;
+        self.store.update(id, &mut |quote| {
+            // The same payment seen again, e.g. rescanned after a restart
+            if matches!(&quote.status, QuoteStatus::Paid { tx_hash, .. } if *tx_hash == event.tx_hash) {
+                redeemed = Some(quote.clone());
+                replayed = true;
+                return false;
+            }
+            expire_if_due(quote, now);
+            if quote.status != QuoteStatus::Issued || !matches(quote, event) {
+                return false;
+            }
+            redeemed = Some(quote.clone());
+            quote.status = QuoteStatus::Paid {
+                tx_hash: event.tx_hash.clone(),
+                block_number: event.block_number,
+                paid_at: now,
+            };
+            true
+        })?;
+
+        match &redeemed {
+            Some(_) if replayed => {}
+            Some(quote) => {
+                tracing::info!(quote = %id, user = %event.user, "Quote paid");
+                if let Some(code) = &quote.signed.quote.promo_code {
+                    // The quote is paid either way; a lost count only loosens the limits
+                    if let Err(e) = self.store.record_promo_redemption(code, event.user) {
+                        tracing::error!(quote = %id, code = %code, "Failed to record promo redemption: {}", e);
+                    }
+                }
+            }
+            None => tracing::warn!(quote = %id, tx = %event.tx_hash, "Payment does not redeem quote"),
+        }
+        Ok(redeemed)
+    }
+
+    /// Paid redemptions of a promo code.
+    pub fn promo_redemptions(&self, code: &str) -> QuoteResult<u64> {
+        Ok(self.store.promo_redemptions(code, Address::ZERO)?.total)
+    }
+
+    /// Redemptions of a promo code counted against its limits: paid ones
+    /// plus outstanding quotes carrying the code, in total and by `user`.
+    pub fn promo_usage(&self, code: &str, user: Address) -> QuoteResult<PromoRedemptions> {
+        let mut usage = self.store.promo_redemptions(code, user)?;
+        usage.total += self.store.promo_outstanding(code, unix_now())?;
+        usage.by_user += self
+            .outstanding(user)?
+            .iter()
+            .filter(|q| q.signed.quote.promo_code.as_deref() == Some(code))
+            .count() as u64;
+        Ok(usage)
+    }
+
+    /// Oldest quote open at `now` the payment settles, for payments
+    /// without a quote id, or the quote this payment already redeemed.
+    fn find_match(&self, event: &PaymentEvent, now: u64) -> QuoteResult<Option<Uuid>> {
+        let paid_by_event = self.store.by_user(event.user)?.into_iter().find(|q| {
+            matches!(&q.status, QuoteStatus::Paid { tx_hash, .. } if *tx_hash == event.tx_hash)
+        });
+        if let Some(quote) = paid_by_event {
+            return Ok(Some(quote.signed.quote.id));
+        }
+        Ok(self
+            .outstanding_at(event.user, now)?
+            .into_iter()
+            .find(|q| matches(q, event))
+            .map(|q| q.signed.quote.id))
+    }
+
+    /// Drop quotes that expired more than [`RETENTION_SECS`] ago.
+    pub fn evict_expired(&self, now: u64) -> QuoteResult<()> {
+        let evicted = self.store.evict_expired_before(now.saturating_sub(RETENTION_SECS))?;
+        if evicted > 0 {
+            tracing::debug!(evicted, "Evicted expired quotes");
+        }
+        Ok(())
+    }
+}
+
+/// Whether `event` pays for `quote`.
+///
+/// Quotes that name no chain predate multi-chain support and match a
+/// payment on any chain.
+fn matches(quote: &TrackedQuote, event: &PaymentEvent) -> bool {
+    let q = &quote.signed.quote;
+    let amount = U256::from_str_radix(&q.amount, 10).unwrap_or(U256::MAX);
+    q.user_address == event.user
+        && q.chain_id.is_none_or(|chain_id| chain_id == event.chain_id)
+        && quote.tier_id == Some(event.tier_id)
+        && event.amount >= amount
+        && event.duration_secs.is_none_or(|d| Some(d) == q.duration_seconds)
+}
+
+fn expire_if_due(quote: &mut TrackedQuote, now: u64) {
+    if quote.status == QuoteStatus::Issued && quote.signed.quote.expiry <= now {
+        quote.status = QuoteStatus::Expired;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloy::primitives::{Signature, B256};
+    use crate::config::SignatureScheme;
+    use crate::quoting::types::{Quote, ServiceType};
+
+    pub(crate) fn quote(expiry: u64, amount: u64) -> SignedQuote {
+        SignedQuote {
+            quote: Quote {
+                id: Uuid::new_v4(),
+                service_type: ServiceType::new("subscription_tier1"),
+                amount: amount.to_string(),
+                currency: "ETH".to_string(),
+                token_address: None,
+                duration_seconds: Some(3600),
+                tier_id: Some(1),
+                usd_amount: None,
+                exchange_rate: None,
+                promo_code: None,
+                discount: None,
+                expiry,
+                nonce: 1,
+                user_address: Address::with_last_byte(1),
+                chain_id: Some(1),
+                tier_change: None,
+            },
+            signature: Signature::new(U256::from(1), U256::from(1), false),
+            hash: B256::ZERO,
+            scheme: SignatureScheme::Eip712,
+        }
+    }
+
+    fn payment(amount: u64, quote_id: Option<Uuid>) -> PaymentEvent {
+        PaymentEvent {
+            chain_id: 1,
+            tx_hash: "0xabc".to_string(),
+            log_index: 0,
+            block_number: 10,
+            block_timestamp: 0,
+            user: Address::with_last_byte(1),
+            amount: U256::from(amount),
+            tier_id: 1,
+            duration_secs: Some(3600),
+            quote_id,
+        }
+    }
+
+    #[test]
+    fn test_redeem_by_id_is_single_use() {
+        let registry = QuoteRegistry::default();
+        let signed = quote(unix_now() + 600, 100);
+        let id = signed.quote.id;
+        registry.insert(signed, Some(1), None).unwrap();
+
+        // Underpayment redeems nothing
+        assert!(registry.redeem(&payment(99, Some(id))).unwrap().is_none());
+        assert_eq!(registry.get(id).unwrap().unwrap().status, QuoteStatus::Issued);
+
+        assert!(registry.redeem(&payment(100, Some(id))).unwrap().is_some());
+        assert!(matches!(registry.get(id).unwrap().unwrap().status, QuoteStatus::Paid { block_number: 10, .. }));
+        // Seeing the same payment again returns the quote it redeemed
+        assert!(registry.redeem(&payment(100, Some(id))).unwrap().is_some());
+        assert!(registry.redeem(&payment(100, None)).unwrap().is_some());
+        let mut other = payment(100, Some(id));
+        other.tx_hash = "0xdef".to_string();
+        assert!(registry.redeem(&other).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_redeem_by_details() {
+        let registry = QuoteRegistry::default();
+        let signed = quote(unix_now() + 600, 100);
+        let id = signed.quote.id;
+        registry.insert(signed, Some(1), None).unwrap();
+
+        // Different duration or tier does not match
+        let mut other = payment(100, None);
+        other.duration_secs = Some(60);
+        assert!(registry.redeem(&other).unwrap().is_none());
+        let mut other = payment(100, None);
+        other.tier_id = 2;
+        assert!(registry.redeem(&other).unwrap().is_none());
+        // Nor does paying on another chain, even with the quote id
+        let mut other = payment(100, Some(id));
+        other.chain_id = 10;
+        assert!(registry.redeem(&other).unwrap().is_none());
+
+        assert_eq!(registry.redeem(&payment(150, None)).unwrap().unwrap().signed.quote.id, id);
+        assert!(registry.outstanding(Address::with_last_byte(1)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_promo_usage() {
+        let registry = QuoteRegistry::default();
+        let user = Address::with_last_byte(1);
+        let mut signed = quote(unix_now() + 600, 100);
+        signed.quote.promo_code = Some("SPRING".to_string());
+        let id = signed.quote.id;
+        registry.insert(signed, Some(1), None).unwrap();
+
+        // Outstanding quotes count as redemptions until paid
+        assert_eq!(registry.promo_usage("SPRING", user).unwrap(), PromoRedemptions { total: 1, by_user: 1 });
+        registry.redeem(&payment(100, Some(id))).unwrap().unwrap();
+        assert_eq!(registry.promo_usage("SPRING", user).unwrap(), PromoRedemptions { total: 1, by_user: 1 });
+    }
+
+    #[test]
+    fn test_expiry_by_block_time() {
+        let registry = QuoteRegistry::default();
+        let now = unix_now();
+        let late = quote(now - 10, 100);
+        let early = quote(now + 600, 100);
+        let (late_id, early_id) = (late.quote.id, early.quote.id);
+        registry.insert(late, Some(1), None).unwrap();
+        registry.insert(early, Some(1), None).unwrap();
+
+        // Mined before expiry but confirmed after it
+        let mut paid = payment(100, Some(late_id));
+        paid.block_timestamp = now - 20;
+        assert!(registry.redeem(&paid).unwrap().is_some());
+        assert!(matches!(registry.get(late_id).unwrap().unwrap().status, QuoteStatus::Paid { paid_at, .. } if paid_at == now - 20));
+
+        // Mined after expiry, even if the clock here is behind
+        let mut paid = payment(100, None);
+        paid.tx_hash = "0xdef".to_string();
+        paid.block_timestamp = now + 600;
+        assert!(registry.redeem(&paid).unwrap().is_none());
+        paid.quote_id = Some(early_id);
+        assert!(registry.redeem(&paid).unwrap().is_none());
+        assert_eq!(registry.get(early_id).unwrap().unwrap().status, QuoteStatus::Issued);
+    }
+
+    #[test]
+    fn test_expiry_and_eviction() {
+        let registry = QuoteRegistry::default();
+        let now = unix_now();
+        let expired = quote(now - 10, 100);
+        let stale = quote(now - RETENTION_SECS - 10, 100);
+        let (expired_id, stale_id) = (expired.quote.id, stale.quote.id);
+        registry.insert(expired, Some(1), None).unwrap();
+        registry.insert(stale, Some(1), None).unwrap();
+
+        assert_eq!(registry.get(expired_id).unwrap().unwrap().status, QuoteStatus::Expired);
+        assert!(registry.redeem(&payment(100, Some(expired_id))).unwrap().is_none());
+
+        registry.evict_expired(now).unwrap();
+        assert!(registry.get(stale_id).unwrap().is_none());
+        assert!(registry.get(expired_id).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_limits() {
+        let config = QuoteStoreConfig {
+            path: String::new(),
+            max_quotes: 3,
+            max_outstanding_per_user: 2,
+        };
+        let registry = QuoteRegistry::new(Arc::new(MemoryQuoteStore::new()), &config);
+        let now = unix_now();
+
+        registry.insert(quote(now + 600, 100), Some(1), None).unwrap();
+        registry.insert(quote(now + 600, 100), Some(1), None).unwrap();
+        assert!(matches!(registry.insert(quote(now + 600, 100), Some(1), None), Err(QuoteError::TooManyQuotes(2))));
+
+        // Expired quotes do not count against the user and make room when full
+        let mut expired = quote(now - 10, 100);
+        expired.quote.user_address = Address::with_last_byte(3);
+        registry.insert(expired, Some(1), None).unwrap();
+        let mut other = quote(now + 600, 100);
+        other.quote.user_address = Address::with_last_byte(2);
+        registry.insert(other.clone(), Some(1), None).unwrap();
+        other.quote.id = Uuid::new_v4();
+        assert!(matches!(registry.insert(other, Some(1), None), Err(QuoteError::Store(StoreError::Full))));
+
+        // Concurrent requests cannot overshoot the per-user limit
+        let registry = Arc::new(QuoteRegistry::new(Arc::new(MemoryQuoteStore::new()), &QuoteStoreConfig {
+            max_quotes: 100,
+            ..config
+        }));
+        let inserted = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| registry.insert(quote(now + 600, 100), Some(1), None).is_ok()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).filter(|ok| *ok).count()
+        });
+        assert_eq!(inserted, 2);
+    }
+}
@@ -1,7 +1,20 @@
 //! Quote generation module.
 
 pub mod engine;
+pub mod lifecycle;
+pub mod oracle;
+pub mod promo;
+pub mod signing;
+pub mod store;
 pub mod types;
 
 pub use engine::QuoteEngine;
-pub use types::{Quote, QuoteRequest, ServiceType, SignedQuote};
+pub use lifecycle::{QuoteRegistry, QuoteStatus, TrackedQuote};
+pub use oracle::{FeedRate, PriceOracle};
+pub use promo::{PromoBook, PromoError, PromoSource};
+pub use signing::ChainDomains;
+pub use store::{MemoryQuoteStore, PromoRedemptions, QuoteStore, RedbQuoteStore, StoreError, StoreResult};
+pub use types::{
+    Quote, QuoteError, QuoteRequest, QuoteResult, QuoteVerification, ServiceType, SignedQuote,
+    TierChange,
+};
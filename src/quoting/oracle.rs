@@ -0,0 +1,323 @@
+//! USD price feed for fiat-denominated prices.
+//!
+//! Reads a Chainlink-style aggregator (`latestRoundData`) through
+//! [`BlockchainClient`] and guards the result:
+//! - readings older than `max_staleness_secs` are rejected
+//! - readings moving more than `max_deviation_bps` from the last accepted
+//!   price are rejected while that price is still fresh
+//! - on rejection or RPC failure the last good price is used until it goes
+//!   stale itself
+//!
+//! The oracle outlives config reloads so the last good price is kept.
+
+use alloy::network::TransactionBuilder;
+use alloy::primitives::{Address, U256};
+use alloy::rpc::types::TransactionRequest;
+use alloy::sol;
+use alloy::sol_types::SolCall;
+use arc_swap::ArcSwapOption;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use crate::blockchain::client::BlockchainClient;
+use crate::config::PriceFeedConfig;
+use crate::quoting::types::{QuoteError, QuoteResult};
+use crate::store::unix_now;
+
+/// Decimals of configured USD amounts.
+pub const USD_DECIMALS: u32 = 8;
+
+sol! {
+    /// `AggregatorV3Interface.latestRoundData`.
+    function latestRoundData() external view returns (
+        uint80 roundId,
+        int256 answer,
+        uint256 startedAt,
+        uint256 updatedAt,
+        uint80 answeredInRound
+    );
+
+    /// `AggregatorV3Interface.decimals`.
+    function decimals() external view returns (uint8);
+}
+
+/// USD price of the payment currency as reported by the feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedRate {
+    /// Price of one whole currency unit in USD, with `decimals` decimals.
+    pub answer: U256,
+    /// Decimals of `answer`.
+    pub decimals: u8,
+    /// Feed round the price was read from.
+    pub round_id: u128,
+    /// Unix timestamp of the round.
+    pub updated_at: u64,
+}
+
+impl FeedRate {
+    /// Convert a USD amount (scaled by [`USD_DECIMALS`]) into the smallest
+    /// unit of a currency with `token_decimals` decimals. Rounds up.
+    pub fn to_token_amount(&self, usd: U256, token_decimals: u8) -> U256 {
+        let ten = U256::from(10);
+        let numerator = usd * ten.pow(U256::from(token_decimals)) * ten.pow(U256::from(self.decimals));
+        let denominator = self.answer * ten.pow(U256::from(USD_DECIMALS));
+        numerator.div_ceil(denominator)
+    }
+}
+
+/// Parse a decimal USD amount such as `"5"` or `"4.99"` into an integer
+/// scaled by [`USD_DECIMALS`].
+pub fn parse_usd(amount: &str) -> Option<U256> {
+    let (whole, frac) = amount.split_once('.').unwrap_or((amount, ""));
+    if whole.is_empty() || frac.len() > USD_DECIMALS as usize {
+        return None;
+    }
+    if !whole.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let padded = format!("{}{:0<width$}", whole, frac, width = USD_DECIMALS as usize);
+    U256::from_str_radix(&padded, 10).ok()
+}
+
+/// Last accepted reading and when it was fetched.
+#[derive(Debug, Clone, Copy)]
+struct LastGood {
+    rate: FeedRate,
+    fetched_at: u64,
+}
+
+/// Guarded, cached reader of the configured price feed.
+#[derive(Default)]
+pub struct PriceOracle {
+    client: ArcSwapOption<BlockchainClient>,
+    config: ArcSwapOption<(Address, PriceFeedConfig)>,
+    last_good: Mutex<Option<LastGood>>,
+}
+
+impl PriceOracle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach the RPC client once it is connected.
+    pub fn attach(&self, client: BlockchainClient) {
+        self.client.store(Some(Arc::new(client)));
+    }
+
+    /// Apply the feed configuration after a (re)load. Switching aggregator
+    /// discards the cached price.
+    pub fn configure(&self, config: Option<&PriceFeedConfig>) {
+        let parsed = config.and_then(|c| {
+            let address = c.aggregator_address.parse::<Address>().ok()?;
+            Some(Arc::new((address, c.clone())))
+        });
+        let previous = self.config.swap(parsed.clone());
+        if previous.map(|p| p.0) != parsed.map(|p| p.0) {
+            *self.last_good.lock().expect("oracle mutex poisoned") = None;
+        }
+    }
+
+    /// Decimals of the payment currency, if a feed is configured.
+    pub fn token_decimals(&self) -> Option<u8> {
+        self.config.load().as_ref().map(|c| c.1.token_decimals)
+    }
+
+    /// Current USD rate, read from the feed or served from cache.
+    pub async fn latest_rate(&self) -> QuoteResult<FeedRate> {
+        let config = self.config.load_full().ok_or_else(|| {
+            QuoteError::PriceFeed("USD pricing requires pricing.price_feed".to_string())
+        })?;
+        let (aggregator, feed) = (config.0, &config.1);
+        let now = unix_now();
+
+        // Serve recent readings without another round trip
+        if let Some(last) = self.last_good() {
+            if now.saturating_sub(last.fetched_at) < feed.refresh_secs
+                && now.saturating_sub(last.rate.updated_at) <= feed.max_staleness_secs
+            {
+                return Ok(last.rate);
+            }
+        }
+
+        let result = match self.read(aggregator).await {
+            Ok(reading) => self.accept(reading, feed, now),
+            Err(e) => Err(e),
+        };
+        result.or_else(|e| {
+            match self.last_good() {
+                Some(last) if now.saturating_sub(last.rate.updated_at) <= feed.max_staleness_secs => {
+                    tracing::warn!(error = %e, "Price feed rejected, using last good price");
+                    Ok(last.rate)
+                }
+                _ => Err(e),
+            }
+        })
+    }
+
+    /// Run the guards on a reading and cache it if it passes.
+    fn accept(&self, rate: FeedRate, feed: &PriceFeedConfig, now: u64) -> QuoteResult<FeedRate> {
+        if rate.answer.is_zero() {
+            return Err(QuoteError::PriceFeed("non-positive price".to_string()));
+        }
+        let age = now.saturating_sub(rate.updated_at);
+        if age > feed.max_staleness_secs {
+            return Err(QuoteError::PriceFeed(format!("price is {}s old", age)));
+        }
+
+        let mut last_good = self.last_good.lock().expect("oracle mutex poisoned");
+        if let Some(last) = *last_good {
+            let last_fresh = now.saturating_sub(last.rate.updated_at) <= feed.max_staleness_secs;
+            let (new, old) = (normalize(&rate), normalize(&last.rate));
+            let moved = if new > old { new - old } else { old - new };
+            if last_fresh && moved * U256::from(10_000) > old * U256::from(feed.max_deviation_bps) {
+                return Err(QuoteError::PriceFeed(format!(
+                    "price moved more than {} bps since round {}",
+                    feed.max_deviation_bps, last.rate.round_id
+                )));
+            }
+        }
+
+        *last_good = Some(LastGood { rate, fetched_at: now });
+        Ok(rate)
+    }
+
+    /// Read the latest round from the aggregator.
+    async fn read(&self, aggregator: Address) -> QuoteResult<FeedRate> {
+        let client = self
+            .client
+            .load_full()
+            .ok_or_else(|| QuoteError::PriceFeed("blockchain client not connected".to_string()))?;
+
+        let call = |input: Vec<u8>| TransactionRequest::default().with_to(aggregator).with_input(input);
+        let invalid = |e: alloy::sol_types::Error| QuoteError::PriceFeed(format!("invalid feed response: {}", e));
+
+        let output = client.call(call(decimalsCall {}.abi_encode())).await?;
+        let decimals = decimalsCall::abi_decode_returns(&output).map_err(invalid)?;
+
+        let output = client.call(call(latestRoundDataCall {}.abi_encode())).await?;
+        let round = latestRoundDataCall::abi_decode_returns(&output).map_err(invalid)?;
+
+        if round.answeredInRound < round.roundId {
+            return Err(QuoteError::PriceFeed(format!("round {} is incomplete", round.roundId)));
+        }
+        Ok(FeedRate {
+            // Negative answers become zero and are rejected by the guards
+            answer: U256::try_from(round.answer).unwrap_or_default(),
+            decimals,
+            round_id: round.roundId.to::<u128>(),
+            updated_at: u64::try_from(round.updatedAt).unwrap_or(u64::MAX),
+        })
+    }
+
+    fn last_good(&self) -> Option<LastGood> {
+        *self.last_good.lock().expect("oracle mutex poisoned")
+    }
+
+    /// Seed the cache as if `rate` had just been read.
+    #[cfg(test)]
+    pub(crate) fn seed(&self, rate: FeedRate) {
+        *self.last_good.lock().unwrap() = Some(LastGood { rate, fetched_at: unix_now() });
+    }
+}
+
+/// Answer scaled to 18 decimals so readings with different decimals compare.
+fn normalize(rate: &FeedRate) -> U256 {
+    let ten = U256::from(10);
+    match rate.decimals.cmp(&18) {
+        std::cmp::Ordering::Less => rate.answer * ten.pow(U256::from(18 - rate.decimals)),
+        std::cmp::Ordering::Equal => rate.answer,
+        std::cmp::Ordering::Greater => rate.answer / ten.pow(U256::from(rate.decimals - 18)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(dollars: u64, updated_at: u64) -> FeedRate {
+        FeedRate {
+            answer: U256::from(dollars) * U256::from(100_000_000u64),
+            decimals: 8,
+            round_id: updated_at as u128,
+            updated_at,
+        }
+    }
+
+    fn feed() -> PriceFeedConfig {
+        PriceFeedConfig {
+            aggregator_address: Address::with_last_byte(1).to_string(),
+            ..PriceFeedConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_usd() {
+        assert_eq!(parse_usd("5"), Some(U256::from(500_000_000u64)));
+        assert_eq!(parse_usd("4.99"), Some(U256::from(499_000_000u64)));
+        assert_eq!(parse_usd("0.00000001"), Some(U256::from(1)));
+        assert_eq!(parse_usd("0.000000001"), None);
+        assert_eq!(parse_usd(".5"), None);
+        assert_eq!(parse_usd("-5"), None);
+        assert_eq!(parse_usd("5$"), None);
+    }
+
+    #[test]
+    fn test_conversion() {
+        // $5 at $2000/ETH is 0.0025 ETH
+        let usd = parse_usd("5").unwrap();
+        assert_eq!(rate(2000, 0).to_token_amount(usd, 18), U256::from(2_500_000_000_000_000u64));
+        // $1 at $3/token with 6 decimals rounds up
+        assert_eq!(rate(3, 0).to_token_amount(parse_usd("1").unwrap(), 6), U256::from(333_334));
+    }
+
+    #[test]
+    fn test_staleness_guard() {
+        let oracle = PriceOracle::new();
+        let feed = feed();
+        let now = 10_000;
+
+        assert!(oracle.accept(rate(2000, now - 60), &feed, now).is_ok());
+        let err = oracle.accept(rate(2000, now - feed.max_staleness_secs - 1), &feed, now);
+        assert!(matches!(err, Err(QuoteError::PriceFeed(_))));
+        assert!(oracle.accept(rate(0, now), &feed, now).is_err());
+    }
+
+    #[test]
+    fn test_deviation_guard() {
+        let oracle = PriceOracle::new();
+        let feed = PriceFeedConfig { max_deviation_bps: 1000, ..feed() };
+        let now = 10_000;
+
+        oracle.accept(rate(2000, now), &feed, now).unwrap();
+        // 5% moves pass, 50% moves do not while the last price is fresh
+        oracle.accept(rate(2100, now), &feed, now).unwrap();
+        assert!(oracle.accept(rate(1000, now), &feed, now).is_err());
+        assert_eq!(oracle.last_good().unwrap().rate.answer, rate(2100, 0).answer);
+
+        // Once the last good price is stale, a fresh reading is trusted
+        let later = now + feed.max_staleness_secs + 1;
+        assert!(oracle.accept(rate(1000, later), &feed, later).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unconfigured_feed() {
+        let oracle = PriceOracle::new();
+        assert!(matches!(oracle.latest_rate().await, Err(QuoteError::PriceFeed(_))));
+
+        // Configured but not connected: no cached price to fall back on
+        oracle.configure(Some(&feed()));
+        assert!(oracle.latest_rate().await.is_err());
+
+        // A cached fresh price is served instead
+        let now = unix_now();
+        oracle.accept(rate(2000, now), &feed(), now).unwrap();
+        assert_eq!(oracle.latest_rate().await.unwrap().answer, rate(2000, 0).answer);
+
+        // Switching aggregator drops it
+        oracle.configure(Some(&PriceFeedConfig {
+            aggregator_address: Address::with_last_byte(2).to_string(),
+            ..feed()
+        }));
+        assert!(oracle.latest_rate().await.is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Promotional codes.
+//!
+//! Codes come from `pricing.promo_codes` (replaced on every reload) or the
+//! admin API (kept in the quote store). Codes are matched
+//! case-insensitively. Outstanding quotes carrying a code count as
+//! redemptions until they expire; paid ones are counted by the quote store.
+
+use alloy::primitives::U256;
+use arc_swap::ArcSwap;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::config::PromoCode;
+use crate::quoting::store::{MemoryQuoteStore, QuoteStore};
+use crate::store::{StoreError, StoreResult};
+
+/// Where a promo code was defined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromoSource {
+    Config,
+    Admin,
+}
+
+/// Errors changing runtime promo codes.
+#[derive(Debug, thiserror::Error)]
+pub enum PromoError {
+    /// The code is defined in the config file, which takes precedence.
+    #[error("Promo code {0} is defined in config")]
+    Configured(String),
+
+    /// The runtime code could not be persisted.
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/// Configured and runtime promo codes, shared across reloads.
+pub struct PromoBook {
+    configured: ArcSwap<BTreeMap<String, PromoCode>>,
+    runtime: DashMap<String, PromoCode>,
+    store: Arc<dyn QuoteStore>,
+}
+
+impl Default for PromoBook {
+    /// Runtime codes kept in memory only.
+    fn default() -> Self {
+        Self {
+            configured: ArcSwap::default(),
+            runtime: DashMap::new(),
+            store: Arc::new(MemoryQuoteStore::new()),
+        }
+    }
+}
+
+impl PromoBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep runtime codes in `store`, loading those already there.
+    pub fn open(store: Arc<dyn QuoteStore>) -> StoreResult<Self> {
+        let runtime = store.promo_codes()?.into_iter().collect();
+        Ok(Self { configured: ArcSwap::default(), runtime, store })
+    }
+
+    /// Replace the configured codes.
+    pub fn set_configured(&self, codes: &BTreeMap<String, PromoCode>) {
+        let codes = codes.iter().map(|(code, promo)| (normalize(code), promo.clone())).collect();
+        self.configured.store(Arc::new(codes));
+    }
+
+    /// Look up a code.
+    pub fn get(&self, code: &str) -> Option<(PromoSource, PromoCode)> {
+        let code = normalize(code);
+        if let Some(promo) = self.configured.load().get(&code) {
+            return Some((PromoSource::Config, promo.clone()));
+        }
+        self.runtime.get(&code).map(|p| (PromoSource::Admin, p.clone()))
+    }
+
+    /// Create or replace a runtime code.
+    pub fn put(&self, code: &str, promo: PromoCode) -> Result<(), PromoError> {
+        let code = normalize(code);
+        if self.configured.load().contains_key(&code) {
+            return Err(PromoError::Configured(code));
+        }
+        self.store.put_promo_code(&code, &promo)?;
+        self.runtime.insert(code, promo);
+        Ok(())
+    }
+
+    /// Remove a runtime code. Returns whether it existed.
+    pub fn remove(&self, code: &str) -> Result<bool, PromoError> {
+        let code = normalize(code);
+        self.store.remove_promo_code(&code)?;
+        Ok(self.runtime.remove(&code).is_some())
+    }
+
+    /// All codes, configured ones first.
+    pub fn all(&self) -> Vec<(String, PromoSource, PromoCode)> {
+        let mut codes: Vec<_> = self
+            .configured
+            .load()
+            .iter()
+            .map(|(code, promo)| (code.clone(), PromoSource::Config, promo.clone()))
+            .collect();
+        let mut runtime: Vec<_> = self
+            .runtime
+            .iter()
+            .map(|p| (p.key().clone(), PromoSource::Admin, p.value().clone()))
+            .collect();
+        runtime.sort_by(|a, b| a.0.cmp(&b.0));
+        codes.extend(runtime);
+        codes
+    }
+}
+
+/// Canonical form of a code.
+pub fn normalize(code: &str) -> String {
+    code.trim().to_ascii_uppercase()
+}
+
+/// Amount taken off `amount` by `promo`, never more than `amount`.
+pub fn discount(promo: &PromoCode, amount: U256) -> U256 {
+    let off = match (promo.discount_bps, &promo.discount_amount) {
+        (Some(bps), _) => amount * U256::from(bps) / U256::from(10_000),
+        (None, Some(fixed)) => U256::from_str_radix(fixed, 10).unwrap_or_default(),
+        (None, None) => U256::ZERO,
+    };
+    off.min(amount)
+}
+
+/// Whether `promo` is within its validity window at `now`.
+pub fn is_active(promo: &PromoCode, now: u64) -> bool {
+    now >= promo.valid_from && (promo.valid_until == 0 || now < promo.valid_until)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discount() {
+        let percent = PromoCode { discount_bps: Some(2500), ..PromoCode::default() };
+        assert_eq!(discount(&percent, U256::from(1000)), U256::from(250));
+
+        // Fixed discounts never make the price negative
+        let fixed = PromoCode { discount_amount: Some("300".to_string()), ..PromoCode::default() };
+        assert_eq!(discount(&fixed, U256::from(1000)), U256::from(300));
+        assert_eq!(discount(&fixed, U256::from(100)), U256::from(100));
+    }
+
+    #[test]
+    fn test_window() {
+        let promo = PromoCode { valid_from: 100, valid_until: 200, ..PromoCode::default() };
+        assert!(!is_active(&promo, 99));
+        assert!(is_active(&promo, 100));
+        assert!(!is_active(&promo, 200));
+        assert!(is_active(&PromoCode::default(), 0));
+    }
+
+    #[test]
+    fn test_book_sources() {
+        let book = PromoBook::new();
+        let promo = PromoCode { discount_bps: Some(100), ..PromoCode::default() };
+        book.set_configured(&BTreeMap::from([("spring".to_string(), promo.clone())]));
+
+        assert_eq!(book.get(" Spring ").unwrap().0, PromoSource::Config);
+        assert!(book.put("SPRING", promo.clone()).is_err());
+
+        book.put("partner", promo).unwrap();
+        assert_eq!(book.get("PARTNER").unwrap().0, PromoSource::Admin);
+        assert_eq!(book.all().len(), 2);
+        assert!(book.remove("Partner").unwrap());
+        assert!(book.get("partner").is_none());
+    }
+
+    #[test]
+    fn test_runtime_codes_persist() {
+        let store: Arc<dyn QuoteStore> = Arc::new(MemoryQuoteStore::new());
+        let promo = PromoCode { discount_bps: Some(100), ..PromoCode::default() };
+        let book = PromoBook::open(store.clone()).unwrap();
+        book.put("partner", promo.clone()).unwrap();
+        book.put("gone", promo.clone()).unwrap();
+        book.remove("gone").unwrap();
+
+        let reopened = PromoBook::open(store).unwrap();
+        assert_eq!(reopened.get("partner"), Some((PromoSource::Admin, promo)));
+        assert!(reopened.get("gone").is_none());
+    }
+}
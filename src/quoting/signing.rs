@@ -0,0 +1,297 @@
+//! Quote hashing for signatures.
+//!
+//! Quotes are signed as EIP-712 typed data under a domain bound to the chain
+//! id and the PaymentProcessor contract, so a signature cannot be replayed on
+//! another chain or deployment and covers every field of the quote,
+//! including its expiry. The PaymentProcessor checks the signature before
+//! crediting a quoted price. The legacy hash is kept for clients still
+//! migrating; the contract cannot check it.
+//! When payments are accepted on several chains, each quote is signed under
+//! the domain of the chain it names.
+
+use alloy::dyn_abi::TypedData;
+use alloy::primitives::{keccak256, Address, FixedBytes, B256, U256};
+use alloy::sol_types::{Eip712Domain, SolStruct};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::blockchain::wallet::Signable;
+use crate::config::SignatureScheme;
+use crate::quoting::types::Quote;
+
+/// EIP-712 domain name.
+pub const DOMAIN_NAME: &str = "Seidar";
+/// EIP-712 domain version.
+pub const DOMAIN_VERSION: &str = "1";
+
+mod typed {
+    alloy::sol! {
+        /// EIP-712 representation of a quote.
+        ///
+        /// `token` is zero for the native currency; `tierId`, `duration`,
+        /// `rate`, `rateUpdatedAt`, `discount` and the tier change fields are
+        /// zero and `promoCode` empty when not applicable.
+        #[derive(serde::Serialize)]
+        struct Quote {
+            bytes16 id;
+            string serviceType;
+            uint8 tierId;
+            address user;
+            uint256 amount;
+            string currency;
+            address token;
+            uint64 duration;
+            uint64 expiry;
+            uint64 nonce;
+            uint256 rate;
+            uint64 rateUpdatedAt;
+            string promoCode;
+            uint256 discount;
+            uint8 fromTier;
+            uint64 fromExpiry;
+            uint256 credit;
+            uint64 bonusDuration;
+        }
+    }
+}
+
+/// Signing domain for quotes verified by `payment_processor` on `chain_id`.
+pub fn quote_domain(chain_id: u64, payment_processor: Address) -> Eip712Domain {
+    Eip712Domain::new(
+        Some(Cow::Borrowed(DOMAIN_NAME)),
+        Some(Cow::Borrowed(DOMAIN_VERSION)),
+        Some(U256::from(chain_id)),
+        Some(payment_processor),
+        None,
+    )
+}
+
+/// PaymentProcessor of each chain payments are accepted on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainDomains {
+    default_chain: u64,
+    processors: BTreeMap<u64, Address>,
+}
+
+impl ChainDomains {
+    /// Domains with `payment_processor` on `chain_id` as the default chain.
+    pub fn new(chain_id: u64, payment_processor: Address) -> Self {
+        Self { default_chain: chain_id, processors: BTreeMap::from([(chain_id, payment_processor)]) }
+    }
+
+    /// Also accept payments to `payment_processor` on `chain_id`.
+    pub fn with_chain(mut self, chain_id: u64, payment_processor: Address) -> Self {
+        self.processors.insert(chain_id, payment_processor);
+        self
+    }
+
+    /// Chain used when a quote or payment does not name one.
+    pub fn default_chain(&self) -> u64 {
+        self.default_chain
+    }
+
+    /// PaymentProcessor on `chain_id`, if payments are accepted there.
+    pub fn processor(&self, chain_id: u64) -> Option<Address> {
+        self.processors.get(&chain_id).copied()
+    }
+
+    /// Signing domain of `chain_id`, if payments are accepted there.
+    pub fn domain(&self, chain_id: u64) -> Option<Eip712Domain> {
+        self.processor(chain_id).map(|processor| quote_domain(chain_id, processor))
+    }
+
+    /// Ids of all accepted chains.
+    pub fn chain_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.processors.keys().copied()
+    }
+}
+
+/// Hash to sign for `quote` under `scheme`.
+///
+/// Returns `None` if the quote amount, discount or tier change credit is
+/// not a decimal integer.
+pub fn quote_hash(quote: &Quote, scheme: SignatureScheme, domain: &Eip712Domain) -> Option<B256> {
+    let (amount, discount, credit) = quote_amounts(quote)?;
+    Some(match scheme {
+        SignatureScheme::Eip712 => typed_quote(quote, amount, discount, credit).eip712_signing_hash(domain),
+        SignatureScheme::Legacy => keccak256(legacy_preimage(quote, amount, discount, credit)),
+    })
+}
+
+/// What the wallet signs for `quote` under `scheme`, hashing to
+/// [`quote_hash`].
+pub fn quote_signable(quote: &Quote, scheme: SignatureScheme, domain: &Eip712Domain) -> Option<Signable> {
+    let (amount, discount, credit) = quote_amounts(quote)?;
+    Some(match scheme {
+        SignatureScheme::Eip712 => Signable::TypedData(Box::new(TypedData::from_struct(
+            &typed_quote(quote, amount, discount, credit),
+            Some(domain.clone()),
+        ))),
+        SignatureScheme::Legacy => Signable::Keccak(legacy_preimage(quote, amount, discount, credit).into()),
+    })
+}
+
+/// Amount, discount and tier change credit of `quote`.
+fn quote_amounts(quote: &Quote) -> Option<(U256, U256, U256)> {
+    let amount = U256::from_str_radix(&quote.amount, 10).ok()?;
+    let discount = match &quote.discount {
+        Some(discount) => U256::from_str_radix(discount, 10).ok()?,
+        None => U256::ZERO,
+    };
+    let credit = match &quote.tier_change {
+        Some(change) => U256::from_str_radix(&change.credit, 10).ok()?,
+        None => U256::ZERO,
+    };
+    Some((amount, discount, credit))
+}
+
+fn typed_quote(quote: &Quote, amount: U256, discount: U256, credit: U256) -> typed::Quote {
+    let change = quote.tier_change.as_ref();
+    typed::Quote {
+        id: FixedBytes(*quote.id.as_bytes()),
+        serviceType: quote.service_type.to_string(),
+        tierId: quote.tier_id.unwrap_or_default(),
+        user: quote.user_address,
+        amount,
+        currency: quote.currency.clone(),
+        token: quote.token_address.unwrap_or_default(),
+        duration: quote.duration_seconds.unwrap_or_default(),
+        expiry: quote.expiry,
+        nonce: quote.nonce,
+        rate: quote.exchange_rate.map(|r| r.answer).unwrap_or_default(),
+        rateUpdatedAt: quote.exchange_rate.map(|r| r.updated_at).unwrap_or_default(),
+        promoCode: quote.promo_code.clone().unwrap_or_default(),
+        discount,
+        fromTier: change.map(|c| c.from_tier).unwrap_or_default(),
+        fromExpiry: change.map(|c| c.from_expiry).unwrap_or_default(),
+        credit,
+        bonusDuration: change.map(|c| c.bonus_seconds).unwrap_or_default(),
+    }
+}
+
+/// Pre-EIP-712 hash: keccak of selected fields, without domain separation.
+fn legacy_preimage(quote: &Quote, amount: U256, discount: U256, credit: U256) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(quote.id.as_bytes());
+    data.extend_from_slice(&amount.to_be_bytes::<32>());
+    data.extend_from_slice(&quote.nonce.to_be_bytes());
+    data.extend_from_slice(&quote.duration_seconds.unwrap_or_default().to_be_bytes());
+    data.extend_from_slice(quote.user_address.as_slice());
+    if let Some(rate) = &quote.exchange_rate {
+        data.extend_from_slice(&rate.answer.to_be_bytes::<32>());
+        data.extend_from_slice(&rate.updated_at.to_be_bytes());
+    }
+    if let Some(code) = &quote.promo_code {
+        // Length-prefixed so the code cannot run into the fields after it
+        data.extend_from_slice(&(code.len() as u32).to_be_bytes());
+        data.extend_from_slice(code.as_bytes());
+        data.extend_from_slice(&discount.to_be_bytes::<32>());
+    }
+    if let Some(change) = &quote.tier_change {
+        data.push(change.from_tier);
+        data.extend_from_slice(&change.from_expiry.to_be_bytes());
+        data.extend_from_slice(&credit.to_be_bytes::<32>());
+        data.extend_from_slice(&change.bonus_seconds.to_be_bytes());
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quoting::types::ServiceType;
+    use uuid::Uuid;
+
+    fn quote() -> Quote {
+        Quote {
+            id: Uuid::from_u128(1),
+            service_type: ServiceType::new("subscription_tier1"),
+            amount: "1000".to_string(),
+            currency: "ETH".to_string(),
+            token_address: None,
+            duration_seconds: Some(3600),
+            tier_id: Some(1),
+            usd_amount: None,
+            exchange_rate: None,
+            promo_code: None,
+            discount: None,
+            expiry: 2_000_000_000,
+            nonce: 7,
+            user_address: Address::with_last_byte(9),
+            chain_id: Some(1),
+            tier_change: None,
+        }
+    }
+
+    #[test]
+    fn test_domain_binding() {
+        let processor = Address::with_last_byte(1);
+        let hash = |domain: &Eip712Domain| quote_hash(&quote(), SignatureScheme::Eip712, domain).unwrap();
+
+        let base = hash(&quote_domain(1, processor));
+        assert_ne!(base, hash(&quote_domain(10, processor)));
+        assert_ne!(base, hash(&quote_domain(1, Address::with_last_byte(2))));
+        assert_eq!(base, hash(&quote_domain(1, processor)));
+    }
+
+    #[test]
+    fn test_chain_domains() {
+        let domains = ChainDomains::new(1, Address::with_last_byte(1)).with_chain(10, Address::with_last_byte(2));
+        assert_eq!(domains.default_chain(), 1);
+        assert_eq!(domains.domain(10), Some(quote_domain(10, Address::with_last_byte(2))));
+        assert_eq!(domains.processor(5), None);
+        assert_eq!(domains.chain_ids().collect::<Vec<_>>(), vec![1, 10]);
+    }
+
+    #[test]
+    fn test_eip712_covers_all_fields() {
+        let domain = quote_domain(1, Address::ZERO);
+        let base = quote_hash(&quote(), SignatureScheme::Eip712, &domain).unwrap();
+
+        let mut expiry = quote();
+        expiry.expiry += 1;
+        let mut currency = quote();
+        currency.currency = "USDC".to_string();
+        let mut service = quote();
+        service.service_type = ServiceType::new("subscription_tier2");
+        let mut tier = quote();
+        tier.tier_id = Some(2);
+        for changed in [expiry, currency, service, tier] {
+            assert_ne!(quote_hash(&changed, SignatureScheme::Eip712, &domain).unwrap(), base);
+            // The legacy hash did not cover these
+            assert_eq!(
+                quote_hash(&changed, SignatureScheme::Legacy, &domain),
+                quote_hash(&quote(), SignatureScheme::Legacy, &domain)
+            );
+        }
+
+        // Tier change credits are covered by both schemes
+        let mut change = quote();
+        change.tier_change = Some(crate::quoting::types::TierChange {
+            from_tier: 1,
+            from_expiry: 1_900_000_000,
+            credit: "500".to_string(),
+            bonus_seconds: 0,
+        });
+        assert_ne!(quote_hash(&change, SignatureScheme::Eip712, &domain).unwrap(), base);
+        assert_ne!(
+            quote_hash(&change, SignatureScheme::Legacy, &domain),
+            quote_hash(&quote(), SignatureScheme::Legacy, &domain)
+        );
+
+        let mut malformed = quote();
+        malformed.amount = "1e18".to_string();
+        assert!(quote_hash(&malformed, SignatureScheme::Eip712, &domain).is_none());
+    }
+    #[test]
+    fn test_signable_hashes_to_quote_hash() {
+        let domain = quote_domain(1, Address::with_last_byte(1));
+        let mut quote = quote();
+        quote.promo_code = Some("SPRING".to_string());
+        quote.discount = Some("100".to_string());
+        for scheme in [SignatureScheme::Eip712, SignatureScheme::Legacy] {
+            let signable = quote_signable(&quote, scheme, &domain).unwrap();
+            assert_eq!(signable.hash().unwrap(), quote_hash(&quote, scheme, &domain).unwrap());
+        }
+    }
+}
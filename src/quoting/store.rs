@@ -0,0 +1,454 @@
+//! Quote storage.
+//!
+//! Issued quotes are kept behind [`QuoteStore`] so they outlive the quote
+//! engine (which is rebuilt on every config reload) and, with
+//! [`RedbQuoteStore`], process restarts. [`MemoryQuoteStore`] keeps quotes in
+//! memory only. Lifecycle rules live in
+//! [`QuoteRegistry`](crate::quoting::lifecycle::QuoteRegistry); stores only
+//! persist and index quotes.
+
+use alloy::primitives::Address;
+use dashmap::DashMap;
+use redb::{Database, MultimapTableDefinition, ReadableTable, ReadableTableMetadata, TableDefinition};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::config::PromoCode;
+use crate::quoting::lifecycle::{QuoteStatus, TrackedQuote};
+use crate::store::backend;
+pub use crate::store::{StoreError, StoreResult};
+
+/// Persistent storage for issued quotes.
+///
+/// Implementations must apply [`update`](QuoteStore::update) atomically so a
+/// quote cannot be redeemed twice.
+pub trait QuoteStore: Send + Sync {
+    /// Get a quote by id.
+    fn get(&self, id: Uuid) -> StoreResult<Option<TrackedQuote>>;
+
+    /// Store a new quote.
+    fn insert(&self, quote: &TrackedQuote) -> StoreResult<()>;
+
+    /// Apply `f` to a stored quote, persisting the result if `f` returns
+    /// `true`. Returns whether the quote was updated.
+    fn update(&self, id: Uuid, f: &mut dyn FnMut(&mut TrackedQuote) -> bool) -> StoreResult<bool>;
+
+    /// All stored quotes issued to `user`.
+    fn by_user(&self, user: Address) -> StoreResult<Vec<TrackedQuote>>;
+
+    /// Number of stored quotes.
+    fn count(&self) -> StoreResult<usize>;
+
+    /// Remove quotes that expired before `cutoff`. Returns how many were removed.
+    fn evict_expired_before(&self, cutoff: u64) -> StoreResult<usize>;
+
+    /// Paid redemptions of a promo code, in total and by `user`.
+    fn promo_redemptions(&self, code: &str, user: Address) -> StoreResult<PromoRedemptions>;
+
+    /// Count a paid redemption of a promo code by `user`.
+    fn record_promo_redemption(&self, code: &str, user: Address) -> StoreResult<()>;
+
+    /// Unpaid quotes carrying a promo code that expire after `now`.
+    fn promo_outstanding(&self, code: &str, now: u64) -> StoreResult<u64>;
+
+    /// Promo codes created at runtime.
+    fn promo_codes(&self) -> StoreResult<Vec<(String, PromoCode)>>;
+
+    /// Create or replace a runtime promo code.
+    fn put_promo_code(&self, code: &str, promo: &PromoCode) -> StoreResult<()>;
+
+    /// Remove a runtime promo code. Returns whether it existed.
+    fn remove_promo_code(&self, code: &str) -> StoreResult<bool>;
+}
+
+/// Paid redemptions of a promo code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PromoRedemptions {
+    pub total: u64,
+    pub by_user: u64,
+}
+
+/// In-memory store. Quotes are lost on restart.
+#[derive(Default)]
+pub struct MemoryQuoteStore {
+    quotes: DashMap<Uuid, TrackedQuote>,
+    promo_totals: DashMap<String, u64>,
+    promo_by_user: DashMap<(String, Address), u64>,
+    promo_codes: DashMap<String, PromoCode>,
+}
+
+impl MemoryQuoteStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl QuoteStore for MemoryQuoteStore {
+    fn get(&self, id: Uuid) -> StoreResult<Option<TrackedQuote>> {
+        Ok(self.quotes.get(&id).map(|q| q.clone()))
+    }
+
+    fn insert(&self, quote: &TrackedQuote) -> StoreResult<()> {
+        self.quotes.insert(quote.signed.quote.id, quote.clone());
+        Ok(())
+    }
+
+    fn update(&self, id: Uuid, f: &mut dyn FnMut(&mut TrackedQuote) -> bool) -> StoreResult<bool> {
+        let Some(mut entry) = self.quotes.get_mut(&id) else {
+            return Ok(false);
+        };
+        let mut updated = entry.clone();
+        if !f(&mut updated) {
+            return Ok(false);
+        }
+        *entry = updated;
+        Ok(true)
+    }
+
+    fn by_user(&self, user: Address) -> StoreResult<Vec<TrackedQuote>> {
+        Ok(self
+            .quotes
+            .iter()
+            .filter(|q| q.signed.quote.user_address == user)
+            .map(|q| q.clone())
+            .collect())
+    }
+
+    fn count(&self) -> StoreResult<usize> {
+        Ok(self.quotes.len())
+    }
+
+    fn evict_expired_before(&self, cutoff: u64) -> StoreResult<usize> {
+        let before = self.quotes.len();
+        self.quotes.retain(|_, q| q.signed.quote.expiry >= cutoff);
+        Ok(before.saturating_sub(self.quotes.len()))
+    }
+
+    fn promo_redemptions(&self, code: &str, user: Address) -> StoreResult<PromoRedemptions> {
+        Ok(PromoRedemptions {
+            total: self.promo_totals.get(code).map(|n| *n).unwrap_or_default(),
+            by_user: self.promo_by_user.get(&(code.to_string(), user)).map(|n| *n).unwrap_or_default(),
+        })
+    }
+
+    fn record_promo_redemption(&self, code: &str, user: Address) -> StoreResult<()> {
+        *self.promo_totals.entry(code.to_string()).or_default() += 1;
+        *self.promo_by_user.entry((code.to_string(), user)).or_default() += 1;
+        Ok(())
+    }
+
+    fn promo_outstanding(&self, code: &str, now: u64) -> StoreResult<u64> {
+        Ok(self.quotes.iter().filter(|q| is_outstanding_with(q, code, now)).count() as u64)
+    }
+
+    fn promo_codes(&self) -> StoreResult<Vec<(String, PromoCode)>> {
+        Ok(self.promo_codes.iter().map(|p| (p.key().clone(), p.value().clone())).collect())
+    }
+
+    fn put_promo_code(&self, code: &str, promo: &PromoCode) -> StoreResult<()> {
+        self.promo_codes.insert(code.to_string(), promo.clone());
+        Ok(())
+    }
+
+    fn remove_promo_code(&self, code: &str) -> StoreResult<bool> {
+        Ok(self.promo_codes.remove(code).is_some())
+    }
+}
+
+/// Whether `quote` is unpaid, carries `code` and expires after `now`.
+fn is_outstanding_with(quote: &TrackedQuote, code: &str, now: u64) -> bool {
+    quote.status == QuoteStatus::Issued
+        && quote.signed.quote.expiry > now
+        && quote.signed.quote.promo_code.as_deref() == Some(code)
+}
+
+/// Quote JSON keyed by quote id.
+const QUOTES: TableDefinition<u128, &[u8]> = TableDefinition::new("quotes");
+/// Quote ids by user address.
+const BY_USER: MultimapTableDefinition<[u8; 20], u128> = MultimapTableDefinition::new("quotes_by_user");
+/// (expiry, quote id) to user address, for eviction.
+const BY_EXPIRY: TableDefinition<(u64, u128), [u8; 20]> = TableDefinition::new("quotes_by_expiry");
+/// Paid redemptions by promo code.
+const PROMO_TOTALS: TableDefinition<&str, u64> = TableDefinition::new("promo_redemptions");
+/// Paid redemptions by (promo code, user address).
+const PROMO_BY_USER: TableDefinition<(&str, [u8; 20]), u64> = TableDefinition::new("promo_redemptions_by_user");
+/// (promo code, expiry, quote id) of quotes carrying a promo code.
+const PROMO_QUOTES: TableDefinition<(&str, u64, u128), ()> = TableDefinition::new("promo_quotes");
+/// Runtime promo code JSON keyed by code.
+const PROMO_CODES: TableDefinition<&str, &[u8]> = TableDefinition::new("promo_codes");
+
+/// Embedded on-disk store backed by [redb](https://docs.rs/redb).
+///
+/// The database file is locked by the process that opens it; instances that
+/// must share quotes need a shared [`QuoteStore`] backend.
+pub struct RedbQuoteStore {
+    db: Database,
+}
+
+impl RedbQuoteStore {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        let db = Database::create(path).map_err(backend)?;
+
+        // Create the tables so reads never see them missing
+        let txn = db.begin_write().map_err(backend)?;
+        txn.open_table(QUOTES).map_err(backend)?;
+        txn.open_multimap_table(BY_USER).map_err(backend)?;
+        txn.open_table(BY_EXPIRY).map_err(backend)?;
+        txn.open_table(PROMO_TOTALS).map_err(backend)?;
+        txn.open_table(PROMO_BY_USER).map_err(backend)?;
+        txn.open_table(PROMO_QUOTES).map_err(backend)?;
+        txn.open_table(PROMO_CODES).map_err(backend)?;
+        txn.commit().map_err(backend)?;
+
+        Ok(Self { db })
+    }
+}
+
+impl QuoteStore for RedbQuoteStore {
+    fn get(&self, id: Uuid) -> StoreResult<Option<TrackedQuote>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let quotes = txn.open_table(QUOTES).map_err(backend)?;
+        let stored = quotes.get(id.as_u128()).map_err(backend)?;
+        Ok(stored.map(|v| serde_json::from_slice(v.value())).transpose()?)
+    }
+
+    fn insert(&self, quote: &TrackedQuote) -> StoreResult<()> {
+        let q = &quote.signed.quote;
+        let id = q.id.as_u128();
+        let user = q.user_address.into_array();
+        let json = serde_json::to_vec(quote)?;
+
+        let txn = self.db.begin_write().map_err(backend)?;
+        {
+            txn.open_table(QUOTES).map_err(backend)?.insert(id, json.as_slice()).map_err(backend)?;
+            txn.open_multimap_table(BY_USER).map_err(backend)?.insert(user, id).map_err(backend)?;
+            txn.open_table(BY_EXPIRY).map_err(backend)?.insert((q.expiry, id), user).map_err(backend)?;
+            if let Some(code) = &q.promo_code {
+                txn.open_table(PROMO_QUOTES).map_err(backend)?.insert((code.as_str(), q.expiry, id), ()).map_err(backend)?;
+            }
+        }
+        txn.commit().map_err(backend)
+    }
+
+    fn update(&self, id: Uuid, f: &mut dyn FnMut(&mut TrackedQuote) -> bool) -> StoreResult<bool> {
+        // Write transactions are serialized, so the read-modify-write is atomic
+        let txn = self.db.begin_write().map_err(backend)?;
+        {
+            let mut quotes = txn.open_table(QUOTES).map_err(backend)?;
+            let stored = quotes.get(id.as_u128()).map_err(backend)?;
+            let Some(mut quote) = stored.map(|v| serde_json::from_slice::<TrackedQuote>(v.value())).transpose()? else {
+                return Ok(false);
+            };
+            if !f(&mut quote) {
+                return Ok(false);
+            }
+            // Indexed fields (user, expiry) are immutable
+            let json = serde_json::to_vec(&quote)?;
+            quotes.insert(id.as_u128(), json.as_slice()).map_err(backend)?;
+        }
+        txn.commit().map_err(backend)?;
+        Ok(true)
+    }
+
+    fn by_user(&self, user: Address) -> StoreResult<Vec<TrackedQuote>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let quotes = txn.open_table(QUOTES).map_err(backend)?;
+        let index = txn.open_multimap_table(BY_USER).map_err(backend)?;
+
+        let mut found = Vec::new();
+        for id in index.get(user.into_array()).map_err(backend)? {
+            let id = id.map_err(backend)?.value();
+            if let Some(stored) = quotes.get(id).map_err(backend)? {
+                found.push(serde_json::from_slice(stored.value())?);
+            }
+        }
+        Ok(found)
+    }
+
+    fn count(&self) -> StoreResult<usize> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let quotes = txn.open_table(QUOTES).map_err(backend)?;
+        Ok(quotes.len().map_err(backend)? as usize)
+    }
+
+    fn evict_expired_before(&self, cutoff: u64) -> StoreResult<usize> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        let evicted = {
+            let mut quotes = txn.open_table(QUOTES).map_err(backend)?;
+            let mut by_user = txn.open_multimap_table(BY_USER).map_err(backend)?;
+            let mut by_expiry = txn.open_table(BY_EXPIRY).map_err(backend)?;
+            let mut promo_quotes = txn.open_table(PROMO_QUOTES).map_err(backend)?;
+
+            let mut expired = Vec::new();
+            for entry in by_expiry.range(..(cutoff, 0)).map_err(backend)? {
+                let (key, user) = entry.map_err(backend)?;
+                expired.push((key.value(), user.value()));
+            }
+            for &((expiry, id), user) in &expired {
+                let removed = quotes.remove(id).map_err(backend)?;
+                let code = removed.map(|v| serde_json::from_slice::<TrackedQuote>(v.value())).transpose()?;
+                if let Some(code) = code.and_then(|q| q.signed.quote.promo_code) {
+                    promo_quotes.remove((code.as_str(), expiry, id)).map_err(backend)?;
+                }
+                by_user.remove(user, id).map_err(backend)?;
+                by_expiry.remove((expiry, id)).map_err(backend)?;
+            }
+            expired.len()
+        };
+        txn.commit().map_err(backend)?;
+        Ok(evicted)
+    }
+
+    fn promo_redemptions(&self, code: &str, user: Address) -> StoreResult<PromoRedemptions> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let totals = txn.open_table(PROMO_TOTALS).map_err(backend)?;
+        let by_user = txn.open_table(PROMO_BY_USER).map_err(backend)?;
+        Ok(PromoRedemptions {
+            total: totals.get(code).map_err(backend)?.map(|n| n.value()).unwrap_or_default(),
+            by_user: by_user
+                .get((code, user.into_array()))
+                .map_err(backend)?
+                .map(|n| n.value())
+                .unwrap_or_default(),
+        })
+    }
+
+    fn record_promo_redemption(&self, code: &str, user: Address) -> StoreResult<()> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        {
+            let mut totals = txn.open_table(PROMO_TOTALS).map_err(backend)?;
+            let total = totals.get(code).map_err(backend)?.map(|n| n.value()).unwrap_or_default();
+            totals.insert(code, total + 1).map_err(backend)?;
+
+            let mut by_user = txn.open_table(PROMO_BY_USER).map_err(backend)?;
+            let key = (code, user.into_array());
+            let count = by_user.get(key).map_err(backend)?.map(|n| n.value()).unwrap_or_default();
+            by_user.insert(key, count + 1).map_err(backend)?;
+        }
+        txn.commit().map_err(backend)
+    }
+
+    fn promo_outstanding(&self, code: &str, now: u64) -> StoreResult<u64> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let quotes = txn.open_table(QUOTES).map_err(backend)?;
+        let promo_quotes = txn.open_table(PROMO_QUOTES).map_err(backend)?;
+
+        let mut outstanding = 0;
+        for entry in promo_quotes.range((code, now + 1, 0)..=(code, u64::MAX, u128::MAX)).map_err(backend)? {
+            let (_, _, id) = entry.map_err(backend)?.0.value();
+            if let Some(stored) = quotes.get(id).map_err(backend)? {
+                if is_outstanding_with(&serde_json::from_slice(stored.value())?, code, now) {
+                    outstanding += 1;
+                }
+            }
+        }
+        Ok(outstanding)
+    }
+
+    fn promo_codes(&self) -> StoreResult<Vec<(String, PromoCode)>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let codes = txn.open_table(PROMO_CODES).map_err(backend)?;
+        let mut found = Vec::new();
+        for entry in codes.iter().map_err(backend)? {
+            let (code, promo) = entry.map_err(backend)?;
+            found.push((code.value().to_string(), serde_json::from_slice(promo.value())?));
+        }
+        Ok(found)
+    }
+
+    fn put_promo_code(&self, code: &str, promo: &PromoCode) -> StoreResult<()> {
+        let json = serde_json::to_vec(promo)?;
+        let txn = self.db.begin_write().map_err(backend)?;
+        txn.open_table(PROMO_CODES).map_err(backend)?.insert(code, json.as_slice()).map_err(backend)?;
+        txn.commit().map_err(backend)
+    }
+
+    fn remove_promo_code(&self, code: &str) -> StoreResult<bool> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        let removed = txn.open_table(PROMO_CODES).map_err(backend)?.remove(code).map_err(backend)?.is_some();
+        txn.commit().map_err(backend)?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quoting::lifecycle::tests::quote;
+    use crate::quoting::lifecycle::QuoteStatus;
+    use crate::store::conformance::check_backends;
+
+    fn tracked(user: u8, expiry: u64) -> TrackedQuote {
+        let mut signed = quote(expiry, 100);
+        signed.quote.user_address = Address::with_last_byte(user);
+        TrackedQuote { signed, tier_id: Some(1), status: QuoteStatus::Issued }
+    }
+
+    fn exercise(store: &(dyn QuoteStore + 'static)) {
+        let (a, b, stale) = (tracked(1, 1000), tracked(2, 1000), tracked(1, 10));
+        for q in [&a, &b, &stale] {
+            store.insert(q).unwrap();
+        }
+        assert_eq!(store.count().unwrap(), 3);
+        assert_eq!(store.by_user(Address::with_last_byte(1)).unwrap().len(), 2);
+
+        let id = a.signed.quote.id;
+        assert!(!store.update(id, &mut |_| false).unwrap());
+        assert!(store.update(id, &mut |q| { q.status = QuoteStatus::Expired; true }).unwrap());
+        assert_eq!(store.get(id).unwrap().unwrap().status, QuoteStatus::Expired);
+        assert_eq!(store.get(id).unwrap().unwrap().tier_id, Some(1));
+        assert!(!store.update(Uuid::new_v4(), &mut |_| true).unwrap());
+
+        assert_eq!(store.evict_expired_before(500).unwrap(), 1);
+        assert!(store.get(stale.signed.quote.id).unwrap().is_none());
+        assert_eq!(store.by_user(Address::with_last_byte(1)).unwrap().len(), 1);
+        assert_eq!(store.count().unwrap(), 2);
+
+        let (alice, bob) = (Address::with_last_byte(1), Address::with_last_byte(2));
+        store.record_promo_redemption("SPRING", alice).unwrap();
+        store.record_promo_redemption("SPRING", bob).unwrap();
+        store.record_promo_redemption("SPRING", alice).unwrap();
+        assert_eq!(
+            store.promo_redemptions("SPRING", alice).unwrap(),
+            PromoRedemptions { total: 3, by_user: 2 }
+        );
+        assert_eq!(store.promo_redemptions("OTHER", alice).unwrap(), PromoRedemptions::default());
+
+        // Only unpaid, unexpired quotes carrying the code are outstanding
+        let mut promo = tracked(1, 1000);
+        promo.signed.quote.promo_code = Some("SPRING".to_string());
+        store.insert(&promo).unwrap();
+        let mut expired = tracked(1, 100);
+        expired.signed.quote.promo_code = Some("SPRING".to_string());
+        store.insert(&expired).unwrap();
+        assert_eq!(store.promo_outstanding("SPRING", 500).unwrap(), 1);
+        store.update(promo.signed.quote.id, &mut |q| { q.status = QuoteStatus::Expired; true }).unwrap();
+        assert_eq!(store.promo_outstanding("SPRING", 500).unwrap(), 0);
+        assert_eq!(store.evict_expired_before(500).unwrap(), 1);
+
+        let code = PromoCode { discount_bps: Some(100), ..PromoCode::default() };
+        store.put_promo_code("PARTNER", &code).unwrap();
+        store.put_promo_code("GONE", &code).unwrap();
+        assert!(store.remove_promo_code("GONE").unwrap());
+        assert!(!store.remove_promo_code("GONE").unwrap());
+    }
+
+    #[test]
+    fn test_backends() {
+        check_backends::<dyn QuoteStore>(
+            "quotes",
+            Box::new(MemoryQuoteStore::new()),
+            |path| Box::new(RedbQuoteStore::open(path).unwrap()),
+            exercise,
+            |store| {
+                // Reopening sees the same quotes and codes
+                assert_eq!(store.count().unwrap(), 3);
+                assert_eq!(store.promo_codes().unwrap().len(), 1);
+                let quotes = store.by_user(Address::with_last_byte(2)).unwrap();
+                assert_eq!(quotes[0].signed.signature, tracked(2, 1000).signed.signature);
+                assert_eq!(store.promo_redemptions("SPRING", Address::with_last_byte(1)).unwrap().total, 3);
+            },
+        );
+    }
+}
@@ -3,20 +3,87 @@
 use alloy::primitives::{Address, B256};
 use alloy::signers::Signature;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use uuid::Uuid;
 
-/// Supported service types for quoting.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum ServiceType {
-    /// Standard subscription tier.
-    SubscriptionTier1,
-    /// Premium subscription tier.
-    SubscriptionTier2,
-    /// Single proof generation request.
-    ProofGeneration,
+use crate::blockchain::types::BlockchainError;
+use crate::config::SignatureScheme;
+use crate::quoting::oracle::FeedRate;
+use crate::quoting::store::StoreError;
+
+/// Identifier of a quotable service, as defined in the price book
+/// (e.g. `subscription_tier1`, `proof_generation`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ServiceType(pub String);
+
+impl ServiceType {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
+impl std::fmt::Display for ServiceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Errors that can occur while quoting.
+#[derive(Debug, Error)]
+pub enum QuoteError {
+    /// The service type is not in the price book.
+    #[error("Unknown service type: {0}")]
+    UnknownService(String),
+
+    /// The service exists but cannot currently be purchased.
+    #[error("Service not offered: {0}")]
+    NotOffered(String),
+
+    /// The requested duration is outside the service's bounds.
+    #[error("Invalid duration: {0}")]
+    InvalidDuration(String),
+
+    /// No usable USD rate for a USD-priced service.
+    #[error("Price feed unavailable: {0}")]
+    PriceFeed(String),
+
+    /// The promo code does not exist or cannot be used for this quote.
+    #[error("Invalid promo code: {0}")]
+    InvalidPromo(String),
+
+    /// The user has no subscription that can be switched to the requested tier.
+    #[error("Invalid tier change: {0}")]
+    InvalidTierChange(String),
+
+    /// Payments are not accepted on the requested chain.
+    #[error("Unsupported chain: {0}")]
+    UnsupportedChain(u64),
+
+    /// Tier prices have not been loaded from chain yet.
+    #[error("Tier prices are not loaded yet")]
+    CatalogUnavailable,
+
+    /// The user holds the maximum number of open quotes.
+    #[error("Too many outstanding quotes (limit {0})")]
+    TooManyQuotes(usize),
+
+    /// The quote could not be stored or read.
+    #[error(transparent)]
+    Store(#[from] StoreError),
+
+    /// Signing or chain access failed.
+    #[error(transparent)]
+    Blockchain(#[from] BlockchainError),
+}
+
+/// Result type for quoting operations.
+pub type QuoteResult<T> = Result<T, QuoteError>;
+
 /// Request payload for generating a quote.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QuoteRequest {
@@ -26,6 +93,34 @@ pub struct QuoteRequest {
     pub user_address: Address,
     /// Optional duration in seconds (for subscriptions).
     pub duration_seconds: Option<u64>,
+    /// Promotional code to apply.
+    #[serde(default)]
+    pub promo_code: Option<String>,
+    /// Chain to pay on; the primary chain when absent.
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Switch the user's active subscription to this tier, crediting the
+    /// time left on it.
+    #[serde(default)]
+    pub change_tier: bool,
+}
+
+/// How a tier change quote credits the subscription it replaces.
+///
+/// The unused time of the current subscription is valued at its tier's
+/// price. A credit below the new tier's price is deducted from `amount`;
+/// a larger one is paid out as `bonus_seconds` on the new tier instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TierChange {
+    /// Tier being replaced.
+    pub from_tier: u8,
+    /// Expiry of the replaced subscription when quoted. The credit only
+    /// applies if the subscription is unchanged when the quote is paid.
+    pub from_expiry: u64,
+    /// Value of the unused time.
+    pub credit: String,
+    /// Time credited on top of `duration_seconds`.
+    pub bonus_seconds: u64,
 }
 
 /// A pricing quote for a service.
@@ -39,12 +134,40 @@ pub struct Quote {
     pub amount: String, // String to handle large numbers safely in JSON
     /// Currency symbol (e.g., "ETH", "LIT").
     pub currency: String,
+    /// ERC-20 token to pay with; native currency when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_address: Option<Address>,
+    /// Subscribed duration in seconds, for subscription services.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<u64>,
+    /// Tier subscribed to, for subscription services.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier_id: Option<u8>,
+    /// Configured USD price the amount was converted from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usd_amount: Option<String>,
+    /// Feed rate used for the conversion.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exchange_rate: Option<FeedRate>,
+    /// Promo code applied to the quote.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promo_code: Option<String>,
+    /// Amount taken off by the promo code, already deducted from `amount`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discount: Option<String>,
     /// Unix timestamp when this quote expires.
     pub expiry: u64,
     /// Random nonce to prevent replay attacks.
     pub nonce: u64,
     /// Address who requested the quote.
     pub user_address: Address,
+    /// Chain the quote is payable on. Quotes issued before multi-chain
+    /// support carry none and are payable on the primary chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    /// Set for quotes that switch an active subscription to another tier.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier_change: Option<TierChange>,
 }
 
 /// A quote signed by the service provider (Reverse Proxy).
@@ -56,4 +179,29 @@ pub struct SignedQuote {
     pub signature: Signature,
     /// The hash that was signed.
     pub hash: B256,
+    /// How `hash` was derived. Quotes issued before EIP-712 signing carry
+    /// no scheme and are legacy.
+    #[serde(default = "legacy_scheme")]
+    pub scheme: SignatureScheme,
+}
+
+fn legacy_scheme() -> SignatureScheme {
+    SignatureScheme::Legacy
+}
+
+/// Result of checking a signed quote.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QuoteVerification {
+    /// Signed by this proxy, untampered and not expired.
+    pub valid: bool,
+    /// Address recovered from the signature.
+    pub signer: Option<Address>,
+    /// Address quotes are signed with.
+    pub expected_signer: Address,
+    /// Hash recomputed from the quote fields.
+    pub hash: Option<B256>,
+    pub expired: bool,
+    /// Why the quote is invalid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
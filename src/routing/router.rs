@@ -85,7 +85,7 @@ impl Router {
 
         // Sort each bucket by priority (descending)
         for routes in routes_by_host.values_mut() {
-            routes.sort_by(|a, b| b.priority.cmp(&a.priority));
+            routes.sort_by_key(|r| std::cmp::Reverse(r.priority));
         }
 
         Self { routes_by_host }
@@ -1,17 +1,26 @@
 //! Access Control Middleware.
 //! Enforces subscription requirements.
+//!
+//! Members of an organization are served under the owner's subscription
+//! unless they hold an active one of their own.
 
 use axum::{
     body::Body,
-    extract::State,
-    http::{Request, StatusCode},
+    extract::{ConnectInfo, State},
+    http::{header, Extensions, HeaderMap, HeaderValue, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use alloy::primitives::Address;
 
-use crate::payments::cache::SubscriptionCache;
+use crate::payments::cache::{SubscriptionCache, SubscriptionInfo, SubscriptionStatus};
+use crate::security::orgs::Organizations;
+use crate::security::trial::{self, TrialDenial, TrialGate};
+
+pub const SUBSCRIPTION_TIER_HEADER: &str = "X-Subscription-Tier";
+pub const SUBSCRIPTION_EXPIRES_HEADER: &str = "X-Subscription-Expires";
 
 /// State required for access control.
 #[derive(Clone)]
@@ -19,13 +28,27 @@ pub struct AccessControlState {
     pub cache: Arc<SubscriptionCache>,
     pub enabled: bool,
     pub grace_period_secs: u64,
+    /// Admits unknown addresses as trial users.
+    pub trial: Arc<TrialGate>,
+    /// Resolves members to their organization's subscription.
+    pub orgs: Arc<Organizations>,
 }
 
 /// Context attached to authenticated requests.
+///
+/// Limits apply to `address`; SLA credits go to `subscriber`.
 #[derive(Clone, Debug)]
 pub struct UserContext {
     pub address: Address,
+    /// Holder of the subscription the request is served under.
+    pub subscriber: Address,
+    /// Owner of the organization `address` belongs to or owns.
+    pub org: Option<Address>,
     pub tier_id: u8,
+    /// Subscription expiry (seconds since epoch). 0 for trial users.
+    pub expiry: u64,
+    /// Served under the free trial rather than a subscription.
+    pub trial: bool,
 }
 
 pub async fn access_control_middleware(
@@ -54,13 +77,18 @@ pub async fn access_control_middleware(
     };
 
     // 3. Verify subscription in cache
-    match state.cache.get_subscription(&address) {
-        Some(sub) => {
+    let org = state.orgs.org_of(address);
+    match subscription_for(&state.cache, address, org, state.grace_period_secs) {
+        Some((subscriber, sub)) => {
             if sub.is_active_with_grace(state.grace_period_secs) {
                 // Attach context
                 let ctx = UserContext {
                     address,
+                    subscriber,
+                    org,
                     tier_id: sub.tier_id,
+                    expiry: sub.expiry,
+                    trial: false,
                 };
                 req.extensions_mut().insert(ctx);
                 next.run(req).await
@@ -68,10 +96,275 @@ pub async fn access_control_middleware(
                 (StatusCode::FORBIDDEN, "Subscription expired").into_response()
             }
         }
-        None => {
-            // Optional: for dev, maybe allow strict mode?
-            // For now, deny.
-            (StatusCode::FORBIDDEN, "No active subscription found").into_response()
+        None => match admit_trial(&state.trial, address, req.headers(), req.extensions(), req.uri().path()).await {
+            Ok(ctx) => {
+                req.extensions_mut().insert(ctx);
+                next.run(req).await
+            }
+            Err(rejection) => rejection.into_response(),
+        },
+    }
+}
+
+/// The subscription `address` is served under, with its holder: its own
+/// while active or in grace, otherwise that of `org`'s owner, if any.
+pub(crate) fn subscription_for(
+    cache: &SubscriptionCache,
+    address: Address,
+    org: Option<Address>,
+    grace_period_secs: u64,
+) -> Option<(Address, SubscriptionInfo)> {
+    let own = cache.get_subscription(&address);
+    if own.as_ref().is_some_and(|sub| sub.is_active_with_grace(grace_period_secs)) {
+        return own.map(|sub| (address, sub));
+    }
+    match org.filter(|owner| *owner != address).and_then(|owner| cache.get_subscription(&owner).map(|sub| (owner, sub))) {
+        Some(shared) => Some(shared),
+        None => own.map(|sub| (address, sub)),
+    }
+}
+
+/// Trial context for an unknown `address`, if it signed a fresh access
+/// message and is within the trial limits.
+async fn admit_trial(
+    gate: &Arc<TrialGate>,
+    address: Address,
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    path: &str,
+) -> Result<UserContext, (StatusCode, &'static str)> {
+    let config = gate.config();
+    if !config.enabled {
+        return Err((StatusCode::FORBIDDEN, "No active subscription found"));
+    }
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(signature), Some(timestamp)) = (header(trial::SIGNATURE_HEADER), header(trial::TIMESTAMP_HEADER)) else {
+        return Err((StatusCode::FORBIDDEN, "No active subscription found"));
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let signed = timestamp
+        .parse()
+        .is_ok_and(|timestamp| trial::verify_access(address, timestamp, signature, now, config.signature_max_age_secs));
+    if !signed {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid or expired X-User-Signature"));
+    }
+
+    // Per-IP limits cannot hold without the client's IP
+    let Some(ip) = client_ip(headers, extensions, &config.client_ip_header) else {
+        return Err((StatusCode::BAD_REQUEST, "Client IP unknown"));
+    };
+    let (gate, path) = (gate.clone(), path.to_string());
+    // Counting writes to the trial store, which blocks
+    tokio::task::spawn_blocking(move || gate.admit(address, &ip, &path, now))
+        .await
+        .unwrap_or(Err(TrialDenial::Unavailable))
+        .map_err(|denial| denial.rejection())?;
+    Ok(UserContext { address, subscriber: address, org: None, tier_id: config.tier_id, expiry: 0, trial: true })
+}
+
+/// The client's IP: the last entry of `header`, which the trusted proxy in
+/// front appends, if one is configured, otherwise the peer address.
+fn client_ip(headers: &HeaderMap, extensions: &Extensions, header: &str) -> Option<String> {
+    let ip: IpAddr = if header.is_empty() {
+        extensions.get::<ConnectInfo<SocketAddr>>()?.0.ip()
+    } else {
+        headers.get(header)?.to_str().ok()?.rsplit(',').next()?.trim().parse().ok()?
+    };
+    Some(ip.to_string())
+}
+
+/// Tell subscribers about their subscription on every proxied response:
+/// `X-Subscription-Tier`, `X-Subscription-Expires` and, once expired but
+/// within the grace period, a `Warning` with the time left.
+///
+/// Must run inside access control, which attaches the [`UserContext`].
+pub async fn subscription_headers_middleware(
+    State(grace_period_secs): State<u64>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let ctx = req.extensions().get::<UserContext>().cloned();
+    let mut response = next.run(req).await;
+    if let Some(ctx) = ctx {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let headers = response.headers_mut();
+        headers.insert(SUBSCRIPTION_TIER_HEADER, HeaderValue::from(u16::from(ctx.tier_id)));
+        if ctx.trial {
+            return response;
+        }
+        headers.insert(SUBSCRIPTION_EXPIRES_HEADER, HeaderValue::from(ctx.expiry));
+        let sub = SubscriptionInfo { tier_id: ctx.tier_id, expiry: ctx.expiry };
+        if sub.status(grace_period_secs, now) == SubscriptionStatus::Grace {
+            let remaining = ctx.expiry + grace_period_secs - now;
+            let warning = format!("299 - \"Subscription expired, access ends in {}s. Renew to keep access.\"", remaining);
+            if let Ok(value) = HeaderValue::from_str(&warning) {
+                headers.insert(header::WARNING, value);
+            }
         }
     }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn headers_for(sub: Option<SubscriptionInfo>) -> axum::http::HeaderMap {
+        let cache = Arc::new(SubscriptionCache::new(None));
+        let user = Address::with_last_byte(1);
+        if let Some(sub) = sub {
+            cache.update_subscription(user, sub.tier_id, sub.expiry);
+        }
+        let access = AccessControlState {
+            cache,
+            enabled: true,
+            grace_period_secs: 600,
+            trial: Arc::new(TrialGate::default()),
+            orgs: Arc::new(Organizations::default()),
+        };
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(600u64, subscription_headers_middleware))
+            .layer(middleware::from_fn_with_state(access, access_control_middleware));
+        let req = Request::builder().uri("/").header("X-User-Address", user.to_string()).body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap().headers().clone()
+    }
+
+    #[tokio::test]
+    async fn test_subscription_headers() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let active = headers_for(Some(SubscriptionInfo { tier_id: 2, expiry: now + 3600 })).await;
+        assert_eq!(active[SUBSCRIPTION_TIER_HEADER], "2");
+        assert_eq!(active[SUBSCRIPTION_EXPIRES_HEADER], (now + 3600).to_string().as_str());
+        assert!(!active.contains_key(header::WARNING));
+
+        let grace = headers_for(Some(SubscriptionInfo { tier_id: 1, expiry: now - 60 })).await;
+        assert_eq!(grace[SUBSCRIPTION_TIER_HEADER], "1");
+        let warning = grace[header::WARNING].to_str().unwrap();
+        assert!(warning.starts_with("299 - \"Subscription expired"), "{}", warning);
+
+        // Rejected requests carry no subscription headers
+        let denied = headers_for(None).await;
+        assert!(!denied.contains_key(SUBSCRIPTION_TIER_HEADER));
+    }
+
+    #[tokio::test]
+    async fn test_trial_access() {
+        use alloy::signers::{local::PrivateKeySigner, SignerSync};
+        use crate::config::TrialConfig;
+
+        let signer: PrivateKeySigner = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80".parse().unwrap();
+        let config = TrialConfig { enabled: true, lifetime_requests: 1, store_path: String::new(), ..TrialConfig::default() };
+        let access = AccessControlState {
+            cache: Arc::new(SubscriptionCache::new(None)),
+            enabled: true,
+            grace_period_secs: 0,
+            trial: Arc::new(TrialGate::open(&config).unwrap()),
+            orgs: Arc::new(Organizations::default()),
+        };
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(0u64, subscription_headers_middleware))
+            .layer(middleware::from_fn_with_state(access, access_control_middleware));
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let signature = signer.sign_message_sync(trial::access_message(signer.address(), now).as_bytes()).unwrap();
+        let request = |signature: &str| {
+            Request::builder()
+                .uri("/")
+                .header("X-User-Address", signer.address().to_string())
+                .header(trial::SIGNATURE_HEADER, signature)
+                .header(trial::TIMESTAMP_HEADER, now.to_string())
+                .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 40000))))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let res = app.clone().oneshot(request("0x00")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = app.clone().oneshot(request(&signature.to_string())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[SUBSCRIPTION_TIER_HEADER], "0");
+        assert!(!res.headers().contains_key(SUBSCRIPTION_EXPIRES_HEADER));
+
+        // The lifetime cap of one request is used up
+        let res = app.clone().oneshot(request(&signature.to_string())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // Without the client's IP there is no trial
+        let mut unknown = request(&signature.to_string());
+        unknown.extensions_mut().remove::<ConnectInfo<SocketAddr>>();
+        let res = app.oneshot(unknown).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let peer = ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 40000)));
+        let req = Request::builder()
+            .header("X-Forwarded-For", "1.1.1.1, 203.0.113.7")
+            .extension(peer)
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(client_ip(req.headers(), req.extensions(), "").as_deref(), Some("10.0.0.1"));
+        // The entry appended by the trusted proxy, not one the client sent
+        assert_eq!(client_ip(req.headers(), req.extensions(), "X-Forwarded-For").as_deref(), Some("203.0.113.7"));
+        assert_eq!(client_ip(req.headers(), req.extensions(), "X-Real-IP"), None);
+
+        let bare = Request::builder().header("X-Real-IP", "garbage").body(Body::empty()).unwrap();
+        assert_eq!(client_ip(bare.headers(), bare.extensions(), ""), None);
+        assert_eq!(client_ip(bare.headers(), bare.extensions(), "X-Real-IP"), None);
+    }
+
+    #[tokio::test]
+    async fn test_org_member_access() {
+        use crate::config::OrgConfig;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let (owner, member, outsider) = (Address::with_last_byte(1), Address::with_last_byte(2), Address::with_last_byte(3));
+        let cache = Arc::new(SubscriptionCache::new(None));
+        cache.update_subscription(owner, 2, now + 3600);
+        let orgs = Arc::new(Organizations::open(&OrgConfig { enabled: true, store_path: String::new(), ..OrgConfig::default() }).unwrap());
+        orgs.add_member(owner, member).unwrap();
+        let access = AccessControlState {
+            cache,
+            enabled: true,
+            grace_period_secs: 0,
+            trial: Arc::new(TrialGate::default()),
+            orgs: orgs.clone(),
+        };
+        let app = Router::new()
+            .route("/", get(|ctx: axum::Extension<UserContext>| async move { format!("{} {}", ctx.address, ctx.subscriber) }))
+            .layer(middleware::from_fn_with_state(access, access_control_middleware));
+        let request = |user: Address| Request::builder().uri("/").header("X-User-Address", user.to_string()).body(Body::empty()).unwrap();
+
+        let res = app.clone().oneshot(request(member)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), 1024).await.unwrap();
+        assert_eq!(body, format!("{} {}", member, owner).as_bytes());
+
+        let res = app.clone().oneshot(request(owner)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app.oneshot(request(outsider)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
 }
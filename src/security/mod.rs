@@ -19,3 +19,5 @@ pub mod limits;
 pub mod rate_limit;
 pub mod access_control;
 pub mod qos;
+pub mod trial;
+pub mod orgs;
@@ -0,0 +1,592 @@
+//! Organizations sharing one subscription.
+//!
+//! An owner's subscription also grants access to the owner's members. A
+//! member belongs to at most one organization and cannot own one. Rate and
+//! connection limits stay per member address; requests are additionally
+//! counted per organization so owners can see how their team uses it.
+//!
+//! Member lists are persisted and indexed in memory, so resolving a member
+//! costs no store read on the request path. Request counts are kept in
+//! memory and added to the store in batches by [`Organizations::run_usage_flush`].
+//! Signed changes are claimed in the store once, so a captured request
+//! cannot be replayed while its timestamp is still fresh.
+
+use alloy::primitives::{keccak256, Address};
+use arc_swap::ArcSwap;
+use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
+use dashmap::DashMap;
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast;
+
+use crate::config::OrgConfig;
+use crate::security::access_control::UserContext;
+use crate::store::{backend, StoreError, StoreResult};
+
+/// How often request counts are added to the store.
+const USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Member list change an owner signs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberChange {
+    Add,
+    Remove,
+}
+
+/// Message owners sign (EIP-191) to add or remove `member`. Members sign the
+/// same message to accept an invitation or to leave.
+pub fn change_message(change: MemberChange, owner: Address, member: Address, timestamp: u64) -> String {
+    let action = match change {
+        MemberChange::Add => "add",
+        MemberChange::Remove => "remove",
+    };
+    format!("Seidar org {} {} for {} at {}", action, member, owner, timestamp)
+}
+
+/// Errors changing a member list.
+#[derive(Debug, Error)]
+pub enum OrgError {
+    #[error("Organizations are disabled")]
+    Disabled,
+
+    #[error("An owner cannot be a member of their own organization")]
+    SelfMember,
+
+    /// The address is in another organization.
+    #[error("{0} already belongs to an organization")]
+    AlreadyMember(Address),
+
+    /// The address owns an organization, so it cannot join one.
+    #[error("{0} owns an organization")]
+    IsOwner(Address),
+
+    #[error("{0} is not a member of this organization")]
+    NotMember(Address),
+
+    #[error("Organization has the maximum of {0} members")]
+    TooManyMembers(usize),
+
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/// Persistent storage for member lists.
+pub trait OrgStore: Send + Sync {
+    /// All memberships as (member, owner).
+    fn memberships(&self) -> StoreResult<Vec<(Address, Address)>>;
+
+    /// Record `member` as a member of `owner`'s organization.
+    fn add(&self, owner: Address, member: Address) -> StoreResult<()>;
+
+    /// Forget `member`'s membership.
+    fn remove(&self, member: Address) -> StoreResult<()>;
+
+    /// Requests counted so far, by address.
+    fn usage(&self) -> StoreResult<Vec<(Address, u64)>>;
+
+    /// Add `counts` to the stored request counts.
+    fn add_usage(&self, counts: &[(Address, u64)]) -> StoreResult<()>;
+
+    /// Record `key` as used until `expires`. Returns false if it already
+    /// was. Keys expired at `now` are forgotten.
+    fn claim(&self, key: [u8; 32], expires: u64, now: u64) -> StoreResult<bool>;
+}
+
+/// In-memory store. Member lists are lost on restart.
+#[derive(Default)]
+pub struct MemoryOrgStore {
+    owners: DashMap<Address, Address>,
+    usage: DashMap<Address, u64>,
+    claimed: Mutex<std::collections::HashMap<[u8; 32], u64>>,
+}
+
+impl OrgStore for MemoryOrgStore {
+    fn memberships(&self) -> StoreResult<Vec<(Address, Address)>> {
+        Ok(self.owners.iter().map(|e| (*e.key(), *e.value())).collect())
+    }
+
+    fn add(&self, owner: Address, member: Address) -> StoreResult<()> {
+        self.owners.insert(member, owner);
+        Ok(())
+    }
+
+    fn remove(&self, member: Address) -> StoreResult<()> {
+        self.owners.remove(&member);
+        Ok(())
+    }
+
+    fn usage(&self) -> StoreResult<Vec<(Address, u64)>> {
+        Ok(self.usage.iter().map(|e| (*e.key(), *e.value())).collect())
+    }
+
+    fn add_usage(&self, counts: &[(Address, u64)]) -> StoreResult<()> {
+        for (address, count) in counts {
+            *self.usage.entry(*address).or_default() += count;
+        }
+        Ok(())
+    }
+
+    fn claim(&self, key: [u8; 32], expires: u64, now: u64) -> StoreResult<bool> {
+        let mut claimed = self.claimed.lock().unwrap_or_else(|e| e.into_inner());
+        claimed.retain(|_, expiry| *expiry >= now);
+        Ok(claimed.insert(key, expires).is_none())
+    }
+}
+
+/// Owner by member address.
+const MEMBERS: TableDefinition<[u8; 20], [u8; 20]> = TableDefinition::new("org_members");
+/// Requests by address.
+const USAGE: TableDefinition<[u8; 20], u64> = TableDefinition::new("org_usage");
+/// Expiry by hash of a used signed message.
+const CLAIMED: TableDefinition<[u8; 32], u64> = TableDefinition::new("org_claimed");
+
+/// Embedded on-disk store backed by redb.
+pub struct RedbOrgStore {
+    db: Database,
+}
+
+impl RedbOrgStore {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        let db = Database::create(path).map_err(backend)?;
+        let txn = db.begin_write().map_err(backend)?;
+        txn.open_table(MEMBERS).map_err(backend)?;
+        txn.open_table(USAGE).map_err(backend)?;
+        txn.open_table(CLAIMED).map_err(backend)?;
+        txn.commit().map_err(backend)?;
+        Ok(Self { db })
+    }
+}
+
+impl OrgStore for RedbOrgStore {
+    fn memberships(&self) -> StoreResult<Vec<(Address, Address)>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let members = txn.open_table(MEMBERS).map_err(backend)?;
+        let mut memberships = Vec::new();
+        for entry in members.iter().map_err(backend)? {
+            let (member, owner) = entry.map_err(backend)?;
+            memberships.push((Address::from(member.value()), Address::from(owner.value())));
+        }
+        Ok(memberships)
+    }
+
+    fn add(&self, owner: Address, member: Address) -> StoreResult<()> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        txn.open_table(MEMBERS)
+            .map_err(backend)?
+            .insert(member.into_array(), owner.into_array())
+            .map_err(backend)?;
+        txn.commit().map_err(backend)
+    }
+
+    fn remove(&self, member: Address) -> StoreResult<()> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        txn.open_table(MEMBERS).map_err(backend)?.remove(member.into_array()).map_err(backend)?;
+        txn.commit().map_err(backend)
+    }
+
+    fn usage(&self) -> StoreResult<Vec<(Address, u64)>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let table = txn.open_table(USAGE).map_err(backend)?;
+        let mut usage = Vec::new();
+        for entry in table.iter().map_err(backend)? {
+            let (address, requests) = entry.map_err(backend)?;
+            usage.push((Address::from(address.value()), requests.value()));
+        }
+        Ok(usage)
+    }
+
+    fn add_usage(&self, counts: &[(Address, u64)]) -> StoreResult<()> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        {
+            let mut table = txn.open_table(USAGE).map_err(backend)?;
+            for (address, count) in counts {
+                let key = address.into_array();
+                let stored = table.get(key).map_err(backend)?.map(|v| v.value()).unwrap_or_default();
+                table.insert(key, stored + count).map_err(backend)?;
+            }
+        }
+        txn.commit().map_err(backend)
+    }
+
+    fn claim(&self, key: [u8; 32], expires: u64, now: u64) -> StoreResult<bool> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        let fresh = {
+            let mut table = txn.open_table(CLAIMED).map_err(backend)?;
+            table.retain(|_, expiry| expiry >= now).map_err(backend)?;
+            let previous = table.insert(key, expires).map_err(backend)?;
+            previous.is_none()
+        };
+        txn.commit().map_err(backend)?;
+        Ok(fresh)
+    }
+}
+
+/// An organization with the requests each address made through it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrgUsage {
+    pub owner: Address,
+    /// Requests of all members and the owner.
+    pub total_requests: u64,
+    /// The owner first, then the members.
+    pub members: Vec<MemberUsage>,
+}
+
+/// Requests of one address through its organization.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemberUsage {
+    pub address: Address,
+    pub requests: u64,
+}
+
+/// Member lists and per-member usage, shared across reloads.
+pub struct Organizations {
+    config: ArcSwap<OrgConfig>,
+    store: Arc<dyn OrgStore>,
+    /// Owner by member.
+    owners: DashMap<Address, Address>,
+    /// Members by owner.
+    members: DashMap<Address, BTreeSet<Address>>,
+    /// Proxied requests by address, including those not yet flushed.
+    usage: DashMap<Address, u64>,
+    /// Requests counted since the last flush to the store.
+    unflushed: DashMap<Address, u64>,
+    /// Serializes member list changes so limits cannot be overrun.
+    lock: Mutex<()>,
+}
+
+impl Default for Organizations {
+    fn default() -> Self {
+        Self::new(&OrgConfig::default(), Arc::new(MemoryOrgStore::default()))
+    }
+}
+
+impl Organizations {
+    /// Index the member lists in `store`.
+    pub fn new(config: &OrgConfig, store: Arc<dyn OrgStore>) -> Self {
+        let orgs = Self {
+            config: ArcSwap::from_pointee(config.clone()),
+            store,
+            owners: DashMap::new(),
+            members: DashMap::new(),
+            usage: DashMap::new(),
+            unflushed: DashMap::new(),
+            lock: Mutex::new(()),
+        };
+        match orgs.store.memberships() {
+            Ok(memberships) => {
+                for (member, owner) in memberships {
+                    orgs.index(owner, member);
+                }
+            }
+            Err(e) => tracing::error!("Failed to load organization members: {}", e),
+        }
+        match orgs.store.usage() {
+            Ok(usage) => {
+                for (address, requests) in usage {
+                    orgs.usage.insert(address, requests);
+                }
+            }
+            Err(e) => tracing::error!("Failed to load organization usage: {}", e),
+        }
+        orgs
+    }
+
+    /// Open the store at `config.store_path`, or keep member lists in
+    /// memory if the path is empty.
+    ///
+    /// Fails if the database cannot be opened: members added to a memory
+    /// store would lose access on the next restart.
+    pub fn open(config: &OrgConfig) -> StoreResult<Self> {
+        let store: Arc<dyn OrgStore> = if config.store_path.is_empty() {
+            Arc::new(MemoryOrgStore::default())
+        } else {
+            Arc::new(RedbOrgStore::open(&config.store_path)?)
+        };
+        Ok(Self::new(config, store))
+    }
+
+    /// Replace the policy on config reload.
+    pub fn set_config(&self, config: &OrgConfig) {
+        self.config.store(Arc::new(config.clone()));
+    }
+
+    pub fn config(&self) -> Arc<OrgConfig> {
+        self.config.load_full()
+    }
+
+    /// Owner of the organization `address` belongs to, or `address` itself
+    /// if it owns one. `None` when organizations are disabled.
+    pub fn org_of(&self, address: Address) -> Option<Address> {
+        if !self.config.load().enabled {
+            return None;
+        }
+        match self.owners.get(&address) {
+            Some(owner) => Some(*owner),
+            None => self.members.contains_key(&address).then_some(address),
+        }
+    }
+
+    /// Members of `owner`'s organization.
+    pub fn members(&self, owner: Address) -> Vec<Address> {
+        self.members.get(&owner).map(|m| m.iter().copied().collect()).unwrap_or_default()
+    }
+
+    /// Add `member` to `owner`'s organization. Adding a current member is a
+    /// no-op.
+    pub fn add_member(&self, owner: Address, member: Address) -> Result<(), OrgError> {
+        let config = self.config.load();
+        if !config.enabled {
+            return Err(OrgError::Disabled);
+        }
+        if owner == member {
+            return Err(OrgError::SelfMember);
+        }
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        match self.owners.get(&member).map(|o| *o) {
+            Some(current) if current == owner => return Ok(()),
+            Some(_) => return Err(OrgError::AlreadyMember(member)),
+            None => {}
+        }
+        if self.members.contains_key(&member) {
+            return Err(OrgError::IsOwner(member));
+        }
+        if self.owners.contains_key(&owner) {
+            return Err(OrgError::AlreadyMember(owner));
+        }
+        if self.members.get(&owner).is_some_and(|m| m.len() >= config.max_members) {
+            return Err(OrgError::TooManyMembers(config.max_members));
+        }
+
+        self.store.add(owner, member)?;
+        self.index(owner, member);
+        tracing::info!(owner = %owner, member = %member, "Organization member added");
+        Ok(())
+    }
+
+    /// Remove `member` from `owner`'s organization.
+    pub fn remove_member(&self, owner: Address, member: Address) -> Result<(), OrgError> {
+        if !self.config.load().enabled {
+            return Err(OrgError::Disabled);
+        }
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if self.owners.get(&member).map(|o| *o) != Some(owner) {
+            return Err(OrgError::NotMember(member));
+        }
+        self.store.remove(member)?;
+        self.owners.remove(&member);
+        let emptied = self.members.get_mut(&owner).is_some_and(|mut m| {
+            m.remove(&member);
+            m.is_empty()
+        });
+        if emptied {
+            self.members.remove(&owner);
+        }
+        tracing::info!(owner = %owner, member = %member, "Organization member removed");
+        Ok(())
+    }
+
+    /// Mark the signed `message` as used until `timestamp` is too old to
+    /// be accepted. Returns false if it was used before.
+    pub fn claim_message(&self, message: &str, timestamp: u64, now: u64) -> StoreResult<bool> {
+        let expires = timestamp.saturating_add(self.config.load().signature_max_age_secs);
+        self.store.claim(keccak256(message.as_bytes()).0, expires, now)
+    }
+
+    /// Count a proxied request of `address`, a member or owner of an organization.
+    pub fn record_request(&self, address: Address) {
+        *self.usage.entry(address).or_default() += 1;
+        *self.unflushed.entry(address).or_default() += 1;
+    }
+
+    /// Add the requests counted since the last flush to the store. Counts
+    /// that fail to persist are kept for the next flush.
+    pub fn flush_usage(&self) {
+        let addresses: Vec<Address> = self.unflushed.iter().map(|e| *e.key()).collect();
+        let counts: Vec<(Address, u64)> = addresses.into_iter().filter_map(|a| self.unflushed.remove(&a)).collect();
+        if counts.is_empty() {
+            return;
+        }
+        if let Err(e) = self.store.add_usage(&counts) {
+            tracing::warn!("Failed to persist organization usage: {}", e);
+            for (address, count) in counts {
+                *self.unflushed.entry(address).or_default() += count;
+            }
+        }
+    }
+
+    /// Flush request counts periodically and once more on shutdown.
+    pub async fn run_usage_flush(self: Arc<Self>, mut shutdown: broadcast::Receiver<()>) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(USAGE_FLUSH_INTERVAL) => self.flush_usage(),
+                _ = shutdown.recv() => {
+                    self.flush_usage();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// `owner`'s organization and its usage, if it has members.
+    pub fn usage(&self, owner: Address) -> Option<OrgUsage> {
+        let members = self.members.get(&owner)?.clone();
+        let requests = |address: Address| MemberUsage {
+            address,
+            requests: self.usage.get(&address).map(|u| *u).unwrap_or_default(),
+        };
+        let members: Vec<_> = std::iter::once(owner).chain(members).map(requests).collect();
+        Some(OrgUsage {
+            owner,
+            total_requests: members.iter().map(|m| m.requests).sum(),
+            members,
+        })
+    }
+
+    fn index(&self, owner: Address, member: Address) {
+        self.owners.insert(member, owner);
+        self.members.entry(owner).or_default().insert(member);
+    }
+}
+
+/// Count proxied requests of organization members and owners. Runs inside
+/// rate limiting and QoS, so rejected requests are not counted.
+pub async fn org_usage_middleware(
+    State(orgs): State<Arc<Organizations>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if let Some(ctx) = req.extensions().get::<UserContext>() {
+        if ctx.org.is_some() {
+            orgs.record_request(ctx.address);
+        }
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled(max_members: usize) -> OrgConfig {
+        OrgConfig { enabled: true, max_members, store_path: String::new(), ..OrgConfig::default() }
+    }
+
+    #[test]
+    fn test_member_rules() {
+        let orgs = Organizations::open(&enabled(2)).unwrap();
+        let (owner, a, b, c) = (Address::with_last_byte(1), Address::with_last_byte(2), Address::with_last_byte(3), Address::with_last_byte(4));
+
+        orgs.add_member(owner, a).unwrap();
+        orgs.add_member(owner, a).unwrap();
+        orgs.add_member(owner, b).unwrap();
+        assert!(matches!(orgs.add_member(owner, c), Err(OrgError::TooManyMembers(2))));
+        assert!(matches!(orgs.add_member(owner, owner), Err(OrgError::SelfMember)));
+        // Memberships do not nest or overlap
+        assert!(matches!(orgs.add_member(c, a), Err(OrgError::AlreadyMember(_))));
+        assert!(matches!(orgs.add_member(c, owner), Err(OrgError::IsOwner(_))));
+        assert!(matches!(orgs.add_member(a, c), Err(OrgError::AlreadyMember(_))));
+
+        assert_eq!(orgs.org_of(a), Some(owner));
+        assert_eq!(orgs.org_of(owner), Some(owner));
+        assert_eq!(orgs.org_of(c), None);
+
+        assert!(matches!(orgs.remove_member(c, a), Err(OrgError::NotMember(_))));
+        orgs.remove_member(owner, a).unwrap();
+        orgs.remove_member(owner, b).unwrap();
+        assert_eq!(orgs.org_of(a), None);
+        assert_eq!(orgs.org_of(owner), None);
+
+        orgs.set_config(&OrgConfig::default());
+        assert!(matches!(orgs.add_member(owner, a), Err(OrgError::Disabled)));
+    }
+
+    #[test]
+    fn test_usage_is_aggregated() {
+        let orgs = Organizations::open(&enabled(5)).unwrap();
+        let (owner, member) = (Address::with_last_byte(1), Address::with_last_byte(2));
+        orgs.add_member(owner, member).unwrap();
+        orgs.record_request(member);
+        orgs.record_request(member);
+        orgs.record_request(owner);
+
+        let usage = orgs.usage(owner).unwrap();
+        assert_eq!(usage.total_requests, 3);
+        assert_eq!(usage.members, vec![
+            MemberUsage { address: owner, requests: 1 },
+            MemberUsage { address: member, requests: 2 },
+        ]);
+        assert!(orgs.usage(member).is_none());
+    }
+
+    #[test]
+    fn test_claimed_messages_expire() {
+        let orgs = Organizations::open(&OrgConfig { signature_max_age_secs: 300, ..enabled(5) }).unwrap();
+        assert!(orgs.claim_message("add", 1000, 1000).unwrap());
+        assert!(!orgs.claim_message("add", 1000, 1300).unwrap());
+        assert!(orgs.claim_message("remove", 1000, 1000).unwrap());
+        // Forgotten once the timestamp would be rejected anyway
+        assert!(orgs.claim_message("add", 1000, 1301).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_middleware_counts_org_requests() {
+        use axum::{middleware, routing::get, Router};
+        use tower::ServiceExt;
+
+        let orgs = Arc::new(Organizations::open(&enabled(5)).unwrap());
+        let (owner, member, outsider) = (Address::with_last_byte(1), Address::with_last_byte(2), Address::with_last_byte(3));
+        orgs.add_member(owner, member).unwrap();
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(orgs.clone(), org_usage_middleware));
+        let request = |address: Address, org: Option<Address>| {
+            let mut req = Request::builder().uri("/").body(Body::empty()).unwrap();
+            req.extensions_mut().insert(UserContext { address, subscriber: owner, org, tier_id: 1, expiry: 0, trial: false });
+            req
+        };
+
+        app.clone().oneshot(request(member, Some(owner))).await.unwrap();
+        app.clone().oneshot(request(owner, Some(owner))).await.unwrap();
+        app.oneshot(request(outsider, None)).await.unwrap();
+
+        let usage = orgs.usage(owner).unwrap();
+        assert_eq!(usage.total_requests, 2);
+        assert_eq!(usage.members[1].requests, 1);
+        assert!(orgs.usage.get(&outsider).is_none());
+    }
+
+    #[test]
+    fn test_redb_members_persist() {
+        let path = std::env::temp_dir().join(format!("orgs-{}.redb", uuid::Uuid::new_v4()));
+        let config = OrgConfig { store_path: path.to_string_lossy().into_owned(), ..enabled(5) };
+        let (owner, member) = (Address::with_last_byte(1), Address::with_last_byte(2));
+        {
+            let orgs = Organizations::open(&config).unwrap();
+            orgs.add_member(owner, member).unwrap();
+            orgs.add_member(owner, Address::with_last_byte(3)).unwrap();
+            orgs.remove_member(owner, Address::with_last_byte(3)).unwrap();
+            orgs.record_request(member);
+            orgs.flush_usage();
+            orgs.record_request(member);
+            orgs.flush_usage();
+            assert!(orgs.claim_message("signed", 1000, 1000).unwrap());
+            // The database is held by the running instance
+            assert!(Organizations::open(&config).is_err());
+        }
+        let orgs = Organizations::open(&config).unwrap();
+        assert_eq!(orgs.members(owner), vec![member]);
+        assert_eq!(orgs.org_of(member), Some(owner));
+        assert_eq!(orgs.usage(owner).unwrap().total_requests, 2);
+        assert!(!orgs.claim_message("signed", 1000, 1200).unwrap());
+        drop(orgs);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,53 +1,377 @@
-//! Connection tracking and QoS enforcement for long-lived connections.
+//! Connection tracking and QoS enforcement.
+//!
+//! # Responsibilities
+//! - Cap long-lived connections (WebSocket/SSE) per subscriber
+//! - Cap in-flight requests and body size per subscriber, per tier policy
+//! - Admit requests by tier priority when the proxy is saturated
 
-use std::collections::HashMap;
-use std::sync::Mutex;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex};
 use alloy::primitives::Address;
-use crate::config::QosConfig;
+use axum::{
+    body::{Body, HttpBody},
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::oneshot;
+
+use crate::observability::metrics;
+use crate::payments::catalog::TierCatalog;
+use crate::security::access_control::UserContext;
 
 /// State for tracking active long-lived connections.
 pub struct ConnectionTracker {
     /// active connections per user: address -> count
     counts: Mutex<HashMap<Address, usize>>,
-    _config: QosConfig,
+    catalog: Arc<TierCatalog>,
 }
 
 impl ConnectionTracker {
-    pub fn new(config: QosConfig) -> Self {
+    pub fn new(catalog: Arc<TierCatalog>) -> Self {
         Self {
             counts: Mutex::new(HashMap::new()),
-            _config: config,
+            catalog,
         }
     }
 
     /// Try to increment connection count for a user.
-    /// Returns true if allowed, false if limit reached.
+    /// Returns true if allowed, false if limit reached or the tier has no policy.
     pub fn try_increment(&self, address: Address, tier_id: u8) -> bool {
-        let limit = match tier_id {
-            1 => self._config.tier_1_max_conns,
-            2 => self._config.tier_2_max_conns,
-            3 => self._config.tier_3_max_conns,
-            _ => self._config.tier_1_max_conns, // Default to lowest
-        };
+        let limit = self
+            .catalog
+            .policy(tier_id)
+            .map(|p| p.max_conns)
+            .unwrap_or(0);
 
         let mut counts = self.counts.lock().expect("connection tracker mutex poisoned");
-        let current = counts.entry(address).or_insert(0);
-        
-        if *current < limit {
-            *current += 1;
+        let current = counts.get(&address).copied().unwrap_or(0);
+
+        if current < limit {
+            counts.insert(address, current + 1);
             true
         } else {
             false
         }
     }
 
-    /// Decrement connection count for a user.
+    /// Decrement connection count for a user, forgetting users left with none.
     pub fn decrement(&self, address: Address) {
         let mut counts = self.counts.lock().expect("connection tracker mutex poisoned");
         if let Some(count) = counts.get_mut(&address) {
-            if *count > 0 {
-                *count -= 1;
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                counts.remove(&address);
+            }
+        }
+    }
+}
+
+/// A request waiting for an in-flight slot.
+struct Waiter {
+    priority: u8,
+    /// Arrival order; earlier wins among equal priorities.
+    seq: u64,
+    tx: oneshot::Sender<SchedulerPermit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    in_flight: usize,
+    next_seq: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+/// Global in-flight limit that admits waiting requests by priority.
+pub struct PriorityScheduler {
+    /// Maximum in-flight requests; 0 means unlimited.
+    capacity: usize,
+    state: Arc<Mutex<SchedulerState>>,
+}
+
+/// Slot held for the lifetime of a request.
+pub struct SchedulerPermit {
+    state: Option<Arc<Mutex<SchedulerState>>>,
+}
+
+impl PriorityScheduler {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Arc::new(Mutex::new(SchedulerState::default())),
+        }
+    }
+
+    /// Wait for an in-flight slot.
+    pub async fn acquire(&self, priority: u8) -> SchedulerPermit {
+        if self.capacity == 0 {
+            return SchedulerPermit { state: None };
+        }
+
+        let rx = {
+            let mut state = self.state.lock().expect("scheduler mutex poisoned");
+            if state.in_flight < self.capacity && state.waiters.is_empty() {
+                state.in_flight += 1;
+                return SchedulerPermit { state: Some(self.state.clone()) };
+            }
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiters.push(Waiter { priority, seq, tx });
+            rx
+        };
+
+        // The releasing permit hands over its slot. If this future is
+        // dropped after the handover, the permit is dropped with `rx` and
+        // the slot moves on.
+        rx.await.unwrap_or(SchedulerPermit { state: None })
+    }
+}
+
+impl Drop for SchedulerPermit {
+    fn drop(&mut self) {
+        let Some(shared) = self.state.take() else { return };
+        let mut state = shared.lock().expect("scheduler mutex poisoned");
+        // Hand the slot to the highest priority waiter still listening
+        while let Some(waiter) = state.waiters.pop() {
+            match waiter.tx.send(SchedulerPermit { state: Some(shared.clone()) }) {
+                Ok(()) => return,
+                // Returned unused; disarm it so the slot is not released twice
+                Err(mut permit) => permit.state = None,
+            }
+        }
+        state.in_flight = state.in_flight.saturating_sub(1);
+    }
+}
+
+/// State for the per-tier QoS middleware.
+#[derive(Clone)]
+pub struct QosState {
+    pub catalog: Arc<TierCatalog>,
+    pub scheduler: Arc<PriorityScheduler>,
+    /// In-flight requests per subscriber.
+    pub in_flight: Arc<Mutex<HashMap<Address, usize>>>,
+    /// Body size limit for requests without a tier policy override.
+    pub default_max_body_size: usize,
+}
+
+/// Releases a subscriber's in-flight slot when the request completes.
+struct InFlightGuard {
+    counts: Arc<Mutex<HashMap<Address, usize>>>,
+    address: Address,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().expect("in-flight mutex poisoned");
+        if let Some(count) = counts.get_mut(&self.address) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                counts.remove(&self.address);
             }
         }
     }
 }
+
+/// Middleware enforcing the tier policy of authenticated requests.
+pub async fn qos_middleware(
+    State(state): State<QosState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let ctx = request.extensions().get::<UserContext>().cloned();
+
+    let (priority, max_body_size, _in_flight) = match ctx {
+        Some(ctx) => {
+            let Some(policy) = state.catalog.policy(ctx.tier_id) else {
+                tracing::warn!(user = %ctx.address, tier = ctx.tier_id, "No QoS policy for tier");
+                return (StatusCode::FORBIDDEN, "Subscription tier not provisioned").into_response();
+            };
+
+            let guard = {
+                let mut counts = state.in_flight.lock().expect("in-flight mutex poisoned");
+                let current = counts.entry(ctx.address).or_insert(0);
+                if policy.max_concurrent_requests.is_some_and(|max| *current >= max) {
+                    drop(counts);
+                    metrics::record_rate_limited("concurrency_limit");
+                    return (StatusCode::TOO_MANY_REQUESTS, "Concurrent request limit reached").into_response();
+                }
+                *current += 1;
+                InFlightGuard { counts: state.in_flight.clone(), address: ctx.address }
+            };
+
+            let max_body = policy.max_body_size.unwrap_or(state.default_max_body_size);
+            (policy.priority, max_body, Some(guard))
+        }
+        None => (0, state.default_max_body_size, None),
+    };
+
+    let declared_len = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared_len.is_some_and(|len| len > max_body_size) {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "Request body too large").into_response();
+    }
+
+    // The server stops a declared body at its length. Streamed bodies are
+    // read up to the limit here, so nothing oversized reaches the upstream.
+    let request = if declared_len.is_none() && !request.body().is_end_stream() {
+        let (parts, body) = request.into_parts();
+        match axum::body::to_bytes(body, max_body_size).await {
+            Ok(bytes) => Request::from_parts(parts, Body::from(bytes)),
+            Err(_) => return (StatusCode::PAYLOAD_TOO_LARGE, "Request body too large").into_response(),
+        }
+    } else {
+        request
+    };
+
+    let _permit = state.scheduler.acquire(priority).await;
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QosConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_connection_limits_follow_policy() {
+        let catalog = Arc::new(TierCatalog::new(&QosConfig::default()));
+        let tracker = ConnectionTracker::new(catalog.clone());
+        let user = Address::ZERO;
+
+        // Tier 1 allows a single long-lived connection
+        assert!(tracker.try_increment(user, 1));
+        assert!(!tracker.try_increment(user, 1));
+        tracker.decrement(user);
+        assert!(tracker.try_increment(user, 1));
+
+        // Tiers without a policy get nothing
+        assert!(!tracker.try_increment(Address::with_last_byte(1), 9));
+
+        // Users without connections are forgotten
+        tracker.decrement(user);
+        assert!(tracker.counts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_prefers_priority() {
+        let scheduler = Arc::new(PriorityScheduler::new(1));
+        let held = scheduler.acquire(0).await;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for priority in [1u8, 5, 3] {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            handles.push(tokio::spawn(async move {
+                let _permit = scheduler.acquire(priority).await;
+                order.lock().unwrap().push(priority);
+            }));
+            // Make arrival order deterministic
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        drop(held);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![5, 3, 1]);
+        assert_eq!(scheduler.state.lock().unwrap().in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_handover_releases_slot() {
+        let scheduler = PriorityScheduler::new(1);
+        let held = scheduler.acquire(0).await;
+
+        let mut waiting = Box::pin(scheduler.acquire(1));
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut waiting).await.is_err());
+        // The slot is handed to the waiter, which goes away before taking it
+        drop(held);
+        drop(waiting);
+
+        assert_eq!(scheduler.state.lock().unwrap().in_flight, 0);
+        let next = tokio::time::timeout(Duration::from_millis(100), scheduler.acquire(0)).await;
+        assert!(next.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_streamed_body_over_limit_is_rejected() {
+        use axum::{routing::post, Router};
+        use tower::ServiceExt;
+
+        let state = QosState {
+            catalog: Arc::new(TierCatalog::new(&QosConfig::default())),
+            scheduler: Arc::new(PriorityScheduler::new(0)),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            default_max_body_size: 8,
+        };
+        // Counts the requests forwarded, like the proxy handler would
+        let forwarded = Arc::new(AtomicUsize::new(0));
+        let seen = forwarded.clone();
+        let app = Router::new()
+            .route("/", post(move |body: Body| async move {
+                seen.fetch_add(1, Ordering::SeqCst);
+                match axum::body::to_bytes(body, usize::MAX).await {
+                    Ok(bytes) => bytes.len().to_string(),
+                    Err(_) => String::new(),
+                }
+            }))
+            .layer(axum::middleware::from_fn_with_state(state, qos_middleware));
+
+        let chunks = futures_util::stream::iter(["12345", "67890"].map(Ok::<_, std::io::Error>));
+        let request = Request::post("/").body(Body::from_stream(chunks)).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let request = Request::post("/").header(header::CONTENT_LENGTH, "10").body(Body::from("1234567890")).unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+        // Neither reached the handler
+        assert_eq!(forwarded.load(Ordering::SeqCst), 0);
+
+        // Streamed bodies within the limit are forwarded whole
+        let chunks = futures_util::stream::iter(["1234", "5678"].map(Ok::<_, std::io::Error>));
+        let request = Request::post("/").body(Body::from_stream(chunks)).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(&axum::body::to_bytes(response.into_body(), 16).await.unwrap()[..], b"8");
+        assert_eq!(forwarded.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_scheduler() {
+        let scheduler = PriorityScheduler::new(0);
+        let _a = scheduler.acquire(0).await;
+        let _b = scheduler.acquire(0).await;
+        assert_eq!(scheduler.state.lock().unwrap().in_flight, 0);
+    }
+}
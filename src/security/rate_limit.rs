@@ -12,7 +12,7 @@ use axum::{
 };
 use std::net::SocketAddr;
 
-use crate::config::QosConfig;
+use crate::payments::catalog::TierCatalog;
 use crate::security::access_control::UserContext;
 use crate::observability::metrics;
 
@@ -50,28 +50,26 @@ impl TokenBucket {
 /// State for the Tiered Rate Limiter.
 pub struct RateLimiterState {
     buckets: Mutex<HashMap<String, TokenBucket>>,
-    config: QosConfig,
+    catalog: Arc<TierCatalog>,
     // Fallback/Default limits
     default_rps: f64,
     default_burst: f64,
 }
 
 impl RateLimiterState {
-    pub fn new(qos_config: QosConfig, default_rps: u32, default_burst: u32) -> Self {
+    pub fn new(catalog: Arc<TierCatalog>, default_rps: u32, default_burst: u32) -> Self {
         Self {
             buckets: Mutex::new(HashMap::new()),
-            config: qos_config,
+            catalog,
             default_rps: default_rps as f64,
             default_burst: default_burst as f64,
         }
     }
 
     fn check(&self, key: String, tier_id: Option<u8>) -> bool {
-        let (rps, burst) = match tier_id {
-            Some(1) => (self.config.tier_1_rps as f64, self.config.tier_1_rps as f64 * 2.0), // Burst 2x RPS
-            Some(2) => (self.config.tier_2_rps as f64, self.config.tier_2_rps as f64 * 2.0),
-            Some(3) => (self.config.tier_3_rps as f64, self.config.tier_3_rps as f64 * 2.0),
-            _ => (self.default_rps, self.default_burst),
+        let (rps, burst) = match tier_id.and_then(|id| self.catalog.policy(id)) {
+            Some(policy) => (policy.rps as f64, policy.burst() as f64),
+            None => (self.default_rps, self.default_burst),
         };
 
         let mut buckets = self.buckets.lock().expect("rate limiter mutex poisoned");
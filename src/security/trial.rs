@@ -0,0 +1,425 @@
+//! Free trial for addresses without a subscription.
+//!
+//! Unknown addresses that sign a fresh access message are served under the
+//! trial tier until they hit a lifetime or daily request cap. Usage is
+//! counted per address and per client IP, and persisted so a restart does
+//! not hand out new trials. Each IP may only start trials for a few
+//! addresses, which keeps one client from cycling through fresh keys.
+
+use alloy::primitives::{Address, Signature};
+use arc_swap::ArcSwap;
+use axum::http::StatusCode;
+use dashmap::DashMap;
+use redb::{Database, MultimapTableDefinition, TableDefinition};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::config::TrialConfig;
+use crate::observability::metrics;
+use crate::store::{backend, StoreResult};
+
+pub const SIGNATURE_HEADER: &str = "X-User-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-User-Timestamp";
+
+const DAY_SECS: u64 = 24 * 3600;
+
+/// Message trial users sign (EIP-191) to prove they own `address`.
+pub fn access_message(address: Address, timestamp: u64) -> String {
+    format!("Seidar access for {} at {}", address, timestamp)
+}
+
+/// Whether `signature` is `address`'s signature of the access message for
+/// `timestamp`, and `timestamp` is at most `max_age_secs` away from `now`.
+pub fn verify_access(address: Address, timestamp: u64, signature: &str, now: u64, max_age_secs: u64) -> bool {
+    now.abs_diff(timestamp) <= max_age_secs && signed_by(address, &access_message(address, timestamp), signature)
+}
+
+/// Whether `signature` is `address`'s EIP-191 signature of `message`.
+pub fn signed_by(address: Address, message: &str, signature: &str) -> bool {
+    let Ok(signature) = signature.parse::<Signature>() else {
+        return false;
+    };
+    signature
+        .recover_address_from_msg(message)
+        .is_ok_and(|signer| signer == address)
+}
+
+/// Requests counted for one address or IP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrialUsage {
+    pub total: u64,
+    /// UTC day (days since epoch) `today` counts.
+    pub day: u64,
+    pub today: u64,
+}
+
+impl TrialUsage {
+    /// Requests made on `day`.
+    fn on(&self, day: u64) -> u64 {
+        if self.day == day {
+            self.today
+        } else {
+            0
+        }
+    }
+
+    fn count(&mut self, day: u64) {
+        self.today = self.on(day) + 1;
+        self.day = day;
+        self.total += 1;
+    }
+}
+
+/// Usage relevant to admitting `address` from `ip`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrialRecord {
+    pub address: TrialUsage,
+    pub ip: TrialUsage,
+    /// Addresses that used the trial from the IP.
+    pub ip_addresses: Vec<Address>,
+}
+
+/// Persistent storage for trial usage.
+pub trait TrialStore: Send + Sync {
+    fn load(&self, address: Address, ip: &str) -> StoreResult<TrialRecord>;
+
+    /// Store both usages and add `address` to the IP's addresses.
+    fn save(&self, address: Address, ip: &str, record: &TrialRecord) -> StoreResult<()>;
+}
+
+/// In-memory store. Usage is lost on restart.
+#[derive(Default)]
+pub struct MemoryTrialStore {
+    addresses: DashMap<Address, TrialUsage>,
+    ips: DashMap<String, (TrialUsage, Vec<Address>)>,
+}
+
+impl TrialStore for MemoryTrialStore {
+    fn load(&self, address: Address, ip: &str) -> StoreResult<TrialRecord> {
+        let (ip_usage, ip_addresses) = self.ips.get(ip).map(|e| e.clone()).unwrap_or_default();
+        Ok(TrialRecord {
+            address: self.addresses.get(&address).map(|u| *u).unwrap_or_default(),
+            ip: ip_usage,
+            ip_addresses,
+        })
+    }
+
+    fn save(&self, address: Address, ip: &str, record: &TrialRecord) -> StoreResult<()> {
+        self.addresses.insert(address, record.address);
+        let mut entry = self.ips.entry(ip.to_string()).or_default();
+        entry.0 = record.ip;
+        if !entry.1.contains(&address) {
+            entry.1.push(address);
+        }
+        Ok(())
+    }
+}
+
+/// (total, day, today) by address.
+const ADDRESSES: TableDefinition<[u8; 20], (u64, u64, u64)> = TableDefinition::new("trial_addresses");
+/// (total, day, today) by client IP.
+const IPS: TableDefinition<&str, (u64, u64, u64)> = TableDefinition::new("trial_ips");
+/// Trial addresses used from each IP.
+const IP_ADDRESSES: MultimapTableDefinition<&str, [u8; 20]> = MultimapTableDefinition::new("trial_ip_addresses");
+
+/// Embedded on-disk store backed by redb.
+pub struct RedbTrialStore {
+    db: Database,
+}
+
+impl RedbTrialStore {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        let db = Database::create(path).map_err(backend)?;
+        let txn = db.begin_write().map_err(backend)?;
+        txn.open_table(ADDRESSES).map_err(backend)?;
+        txn.open_table(IPS).map_err(backend)?;
+        txn.open_multimap_table(IP_ADDRESSES).map_err(backend)?;
+        txn.commit().map_err(backend)?;
+        Ok(Self { db })
+    }
+}
+
+impl TrialStore for RedbTrialStore {
+    fn load(&self, address: Address, ip: &str) -> StoreResult<TrialRecord> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let usage = |(total, day, today)| TrialUsage { total, day, today };
+        let addresses = txn.open_table(ADDRESSES).map_err(backend)?;
+        let ips = txn.open_table(IPS).map_err(backend)?;
+        let ip_addresses = txn.open_multimap_table(IP_ADDRESSES).map_err(backend)?;
+
+        let mut record = TrialRecord {
+            address: addresses.get(address.into_array()).map_err(backend)?.map(|v| usage(v.value())).unwrap_or_default(),
+            ip: ips.get(ip).map_err(backend)?.map(|v| usage(v.value())).unwrap_or_default(),
+            ip_addresses: Vec::new(),
+        };
+        for entry in ip_addresses.get(ip).map_err(backend)? {
+            record.ip_addresses.push(Address::from(entry.map_err(backend)?.value()));
+        }
+        Ok(record)
+    }
+
+    fn save(&self, address: Address, ip: &str, record: &TrialRecord) -> StoreResult<()> {
+        let row = |u: &TrialUsage| (u.total, u.day, u.today);
+        let txn = self.db.begin_write().map_err(backend)?;
+        {
+            txn.open_table(ADDRESSES)
+                .map_err(backend)?
+                .insert(address.into_array(), row(&record.address))
+                .map_err(backend)?;
+            txn.open_table(IPS).map_err(backend)?.insert(ip, row(&record.ip)).map_err(backend)?;
+            txn.open_multimap_table(IP_ADDRESSES)
+                .map_err(backend)?
+                .insert(ip, address.into_array())
+                .map_err(backend)?;
+        }
+        txn.commit().map_err(backend)
+    }
+}
+
+/// Why a trial request was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrialDenial {
+    Disabled,
+    RouteNotAllowed,
+    LifetimeCap,
+    DailyCap,
+    TooManyAddresses,
+    IpDailyCap,
+    /// Usage could not be read or recorded.
+    Unavailable,
+}
+
+impl TrialDenial {
+    /// Response for the refused request.
+    pub fn rejection(self) -> (StatusCode, &'static str) {
+        match self {
+            Self::Disabled => (StatusCode::FORBIDDEN, "No active subscription found"),
+            Self::RouteNotAllowed => (StatusCode::FORBIDDEN, "Route not available on the free trial"),
+            Self::LifetimeCap => (StatusCode::FORBIDDEN, "Free trial used up, subscribe to continue"),
+            Self::DailyCap => (StatusCode::TOO_MANY_REQUESTS, "Daily free trial requests used up"),
+            Self::TooManyAddresses => (StatusCode::FORBIDDEN, "Too many trial addresses from this IP"),
+            Self::IpDailyCap => (StatusCode::TOO_MANY_REQUESTS, "Daily free trial requests used up for this IP"),
+            Self::Unavailable => (StatusCode::SERVICE_UNAVAILABLE, "Free trial temporarily unavailable"),
+        }
+    }
+
+    fn outcome(self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::RouteNotAllowed => "route_not_allowed",
+            Self::LifetimeCap => "lifetime_cap",
+            Self::DailyCap => "daily_cap",
+            Self::TooManyAddresses => "too_many_addresses",
+            Self::IpDailyCap => "ip_daily_cap",
+            Self::Unavailable => "unavailable",
+        }
+    }
+}
+
+/// Trial policy and usage, shared across reloads.
+///
+/// [`admit`](Self::admit) reads and writes the store under a lock, so async
+/// callers run it on the blocking pool.
+pub struct TrialGate {
+    config: ArcSwap<TrialConfig>,
+    store: Arc<dyn TrialStore>,
+    /// Serializes check-and-count so concurrent requests cannot overrun a cap.
+    lock: Mutex<()>,
+}
+
+impl Default for TrialGate {
+    fn default() -> Self {
+        Self::new(&TrialConfig::default(), Arc::new(MemoryTrialStore::default()))
+    }
+}
+
+impl TrialGate {
+    pub fn new(config: &TrialConfig, store: Arc<dyn TrialStore>) -> Self {
+        Self {
+            config: ArcSwap::from_pointee(config.clone()),
+            store,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Open the store at `config.store_path`, or count usage in memory if
+    /// the path is empty.
+    ///
+    /// Fails if the database cannot be opened: counting in memory instead
+    /// would hand out a fresh allowance on every restart.
+    pub fn open(config: &TrialConfig) -> StoreResult<Self> {
+        let store: Arc<dyn TrialStore> = if config.store_path.is_empty() {
+            Arc::new(MemoryTrialStore::default())
+        } else {
+            Arc::new(RedbTrialStore::open(&config.store_path)?)
+        };
+        Ok(Self::new(config, store))
+    }
+
+    /// Replace the trial policy on config reload.
+    pub fn set_config(&self, config: &TrialConfig) {
+        self.config.store(Arc::new(config.clone()));
+    }
+
+    pub fn config(&self) -> Arc<TrialConfig> {
+        self.config.load_full()
+    }
+
+    /// Count a request of `address` from `ip` for `path` at `now`, unless a
+    /// limit refuses it.
+    pub fn admit(&self, address: Address, ip: &str, path: &str, now: u64) -> Result<(), TrialDenial> {
+        let result = self.check_and_count(address, ip, path, now);
+        metrics::record_trial_request(match result {
+            Ok(()) => "admitted",
+            Err(denial) => denial.outcome(),
+        });
+        result
+    }
+
+    fn check_and_count(&self, address: Address, ip: &str, path: &str, now: u64) -> Result<(), TrialDenial> {
+        let config = self.config.load();
+        if !config.enabled {
+            return Err(TrialDenial::Disabled);
+        }
+        if !config.allowed_paths.is_empty() && !config.allowed_paths.iter().any(|p| path.starts_with(p.as_str())) {
+            return Err(TrialDenial::RouteNotAllowed);
+        }
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let day = now / DAY_SECS;
+        let mut record = self.store.load(address, ip).map_err(|e| {
+            tracing::error!("Failed to read trial usage of {}: {}", address, e);
+            TrialDenial::Unavailable
+        })?;
+
+        let capped = |limit: u64, used: u64| limit > 0 && used >= limit;
+        if capped(config.lifetime_requests, record.address.total) {
+            return Err(TrialDenial::LifetimeCap);
+        }
+        if capped(config.daily_requests, record.address.on(day)) {
+            return Err(TrialDenial::DailyCap);
+        }
+        if config.max_addresses_per_ip > 0
+            && !record.ip_addresses.contains(&address)
+            && record.ip_addresses.len() >= config.max_addresses_per_ip
+        {
+            return Err(TrialDenial::TooManyAddresses);
+        }
+        if capped(config.daily_requests_per_ip, record.ip.on(day)) {
+            return Err(TrialDenial::IpDailyCap);
+        }
+
+        record.address.count(day);
+        record.ip.count(day);
+        self.store.save(address, ip, &record).map_err(|e| {
+            tracing::error!("Failed to record trial usage of {}: {}", address, e);
+            TrialDenial::Unavailable
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::signers::{local::PrivateKeySigner, SignerSync};
+
+    const NOW: u64 = 100 * DAY_SECS + 3600;
+
+    fn config() -> TrialConfig {
+        TrialConfig {
+            enabled: true,
+            lifetime_requests: 5,
+            daily_requests: 3,
+            max_addresses_per_ip: 2,
+            daily_requests_per_ip: 4,
+            ..TrialConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_verify_access() {
+        let signer: PrivateKeySigner = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80".parse().unwrap();
+        let address = signer.address();
+        let signature = signer.sign_message_sync(access_message(address, NOW).as_bytes()).unwrap().to_string();
+
+        assert!(verify_access(address, NOW, &signature, NOW + 10, 300));
+        // Stale, for another address or timestamp, or malformed
+        assert!(!verify_access(address, NOW, &signature, NOW + 301, 300));
+        assert!(!verify_access(Address::with_last_byte(1), NOW, &signature, NOW, 300));
+        assert!(!verify_access(address, NOW + 1, &signature, NOW, 300));
+        assert!(!verify_access(address, NOW, "0x1234", NOW, 300));
+    }
+
+    #[test]
+    fn test_address_caps() {
+        let gate = TrialGate::new(&config(), Arc::new(MemoryTrialStore::default()));
+        let user = Address::with_last_byte(1);
+        for _ in 0..3 {
+            assert_eq!(gate.admit(user, "10.0.0.1", "/", NOW), Ok(()));
+        }
+        assert_eq!(gate.admit(user, "10.0.0.2", "/", NOW), Err(TrialDenial::DailyCap));
+
+        // The daily cap resets the next day, the lifetime cap does not
+        let tomorrow = NOW + DAY_SECS;
+        assert_eq!(gate.admit(user, "10.0.0.2", "/", tomorrow), Ok(()));
+        assert_eq!(gate.admit(user, "10.0.0.2", "/", tomorrow), Ok(()));
+        assert_eq!(gate.admit(user, "10.0.0.2", "/", tomorrow), Err(TrialDenial::LifetimeCap));
+    }
+
+    #[test]
+    fn test_ip_limits() {
+        let gate = TrialGate::new(&config(), Arc::new(MemoryTrialStore::default()));
+        let ip = "10.0.0.1";
+        let user = |b| Address::with_last_byte(b);
+        assert_eq!(gate.admit(user(1), ip, "/", NOW), Ok(()));
+        assert_eq!(gate.admit(user(2), ip, "/", NOW), Ok(()));
+        assert_eq!(gate.admit(user(3), ip, "/", NOW), Err(TrialDenial::TooManyAddresses));
+        assert_eq!(gate.admit(user(1), ip, "/", NOW), Ok(()));
+        assert_eq!(gate.admit(user(2), ip, "/", NOW), Ok(()));
+        assert_eq!(gate.admit(user(2), ip, "/", NOW), Err(TrialDenial::IpDailyCap));
+    }
+
+    #[test]
+    fn test_route_allowlist() {
+        let config = TrialConfig { allowed_paths: vec!["/api/public".to_string()], ..config() };
+        let gate = TrialGate::new(&config, Arc::new(MemoryTrialStore::default()));
+        let user = Address::with_last_byte(1);
+        assert_eq!(gate.admit(user, "10.0.0.1", "/api/public/items", NOW), Ok(()));
+        assert_eq!(gate.admit(user, "10.0.0.1", "/api/private", NOW), Err(TrialDenial::RouteNotAllowed));
+
+        gate.set_config(&TrialConfig { enabled: false, ..config });
+        assert_eq!(gate.admit(user, "10.0.0.1", "/api/public", NOW), Err(TrialDenial::Disabled));
+    }
+
+    #[test]
+    fn test_usage_survives_restart() {
+        let path = std::env::temp_dir().join(format!("trials-{}.redb", uuid::Uuid::new_v4()));
+        let user = Address::with_last_byte(1);
+        {
+            let gate = TrialGate::new(&config(), Arc::new(RedbTrialStore::open(&path).unwrap()));
+            for _ in 0..3 {
+                assert_eq!(gate.admit(user, "10.0.0.1", "/", NOW), Ok(()));
+            }
+        }
+        let store = RedbTrialStore::open(&path).unwrap();
+        let record = store.load(user, "10.0.0.1").unwrap();
+        assert_eq!(record.address, TrialUsage { total: 3, day: 100, today: 3 });
+        assert_eq!(record.ip_addresses, vec![user]);
+        let gate = TrialGate::new(&config(), Arc::new(store));
+        assert_eq!(gate.admit(user, "10.0.0.1", "/", NOW), Err(TrialDenial::DailyCap));
+        drop(gate);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_fails_on_held_store() {
+        let path = std::env::temp_dir().join(format!("trials-{}.redb", uuid::Uuid::new_v4()));
+        let store = RedbTrialStore::open(&path).unwrap();
+        let config = TrialConfig { store_path: path.to_string_lossy().into_owned(), ..config() };
+        assert!(TrialGate::open(&config).is_err());
+        drop(store);
+        assert!(TrialGate::open(&config).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
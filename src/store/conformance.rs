@@ -0,0 +1,22 @@
+//! Checks every store backend must pass.
+
+use std::path::Path;
+use uuid::Uuid;
+
+/// Run `exercise` against `memory` and against a fresh redb database opened
+/// with `open`, then reopen that database and hand it to `reopened` to check
+/// what survived.
+pub(crate) fn check_backends<S: ?Sized>(
+    name: &str,
+    memory: Box<S>,
+    open: impl Fn(&Path) -> Box<S>,
+    exercise: impl Fn(&S),
+    reopened: impl Fn(&S),
+) {
+    exercise(&memory);
+
+    let path = std::env::temp_dir().join(format!("{}-{}.redb", name, Uuid::new_v4()));
+    exercise(&open(&path));
+    reopened(&open(&path));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,43 @@
+//! Helpers shared by the embedded stores.
+//!
+//! Quotes, billing records, webhook deliveries, trial counters,
+//! organizations and wallet nonces each keep a memory and a redb backend;
+//! they share the error type here and, in tests, the
+//! [`conformance`] check run against both backends.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+pub(crate) mod conformance;
+
+/// Errors from a store.
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    /// The store holds `max_quotes` unexpired quotes.
+    #[error("Quote store is full")]
+    Full,
+
+    /// The backend failed to read or write.
+    #[error("Store backend error: {0}")]
+    Backend(String),
+
+    /// A stored record could not be encoded or decoded.
+    #[error("Store codec error: {0}")]
+    Codec(#[from] serde_json::Error),
+}
+
+/// Result type for store operations.
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// Map a redb error to [`StoreError::Backend`].
+pub(crate) fn backend(e: impl Into<redb::Error>) -> StoreError {
+    StoreError::Backend(e.into().to_string())
+}
+
+/// Seconds since the Unix epoch.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
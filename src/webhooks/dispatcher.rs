@@ -0,0 +1,482 @@
+//! Webhook fan-out and delivery.
+//!
+//! [`Webhooks::emit`] enqueues one delivery per endpoint subscribed to the
+//! event. The delivery worker posts due deliveries and reschedules failures
+//! with [`calculate_backoff`] until they succeed or run out of attempts. The
+//! expiry scanner turns the subscription cache into `expiring` and `expired`
+//! events.
+
+use alloy::primitives::Address;
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, Notify};
+
+use crate::config::{WebhookConfig, WebhookEndpoint, WebhookEventKind};
+use crate::observability::metrics;
+use crate::payments::cache::SubscriptionCache;
+use crate::quoting::StoreResult;
+use crate::resilience::backoff::calculate_backoff;
+use crate::webhooks::events::{self, SubscriptionEvent};
+use crate::webhooks::outbox::{Delivery, MemoryOutbox, OutboxStore, RedbOutbox};
+
+/// Deliveries attempted per round.
+const BATCH_SIZE: usize = 64;
+
+/// How often the delivery worker looks for retries that became due.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Subscriptions that lapsed longer ago than this are not reported as
+/// expired, so enabling webhooks does not flood endpoints with old expiries.
+const EXPIRED_LOOKBACK_SECS: u64 = 24 * 3600;
+
+/// Webhook state shared across reloads.
+pub struct Webhooks {
+    config: ArcSwap<WebhookConfig>,
+    outbox: Arc<dyn OutboxStore>,
+    wake: Notify,
+    client: reqwest::Client,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self::new(&WebhookConfig::default(), Arc::new(MemoryOutbox::new()))
+    }
+}
+
+/// Endpoint as shown in the admin API.
+#[derive(Debug, Serialize)]
+pub struct EndpointStatus {
+    pub name: String,
+    pub url: String,
+    pub events: Vec<WebhookEventKind>,
+}
+
+/// Webhook delivery state for the admin API.
+#[derive(Debug, Serialize)]
+pub struct WebhookStatus {
+    pub enabled: bool,
+    pub endpoints: Vec<EndpointStatus>,
+    pub pending: usize,
+    pub dead_letters: Vec<Delivery>,
+}
+
+impl Webhooks {
+    pub fn new(config: &WebhookConfig, outbox: Arc<dyn OutboxStore>) -> Self {
+        Self {
+            config: ArcSwap::from_pointee(config.clone()),
+            outbox,
+            wake: Notify::new(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Open the outbox at `config.outbox_path`, or keep deliveries in memory
+    /// if the path is empty.
+    ///
+    /// Fails if the outbox cannot be opened, rather than dropping the
+    /// undelivered events on the next restart.
+    pub fn open(config: &WebhookConfig) -> StoreResult<Self> {
+        let outbox: Arc<dyn OutboxStore> = if config.outbox_path.is_empty() {
+            Arc::new(MemoryOutbox::new())
+        } else {
+            Arc::new(RedbOutbox::open(&config.outbox_path)?)
+        };
+        Ok(Self::new(config, outbox))
+    }
+
+    /// Replace endpoints and retry policy on config reload.
+    pub fn set_config(&self, config: &WebhookConfig) {
+        self.config.store(Arc::new(config.clone()));
+    }
+
+    /// Queue `event` for every endpoint subscribed to it. Emitting an event
+    /// again replaces its pending deliveries rather than adding more.
+    pub fn emit(&self, event: SubscriptionEvent) {
+        let config = self.config.load();
+        if !config.enabled {
+            return;
+        }
+        let now = now_ms();
+        for endpoint in config.endpoints.iter().filter(|e| subscribed(e, event.event)) {
+            let delivery = Delivery {
+                id: events::delivery_id(event.id, &endpoint.name),
+                endpoint: endpoint.name.clone(),
+                event: event.clone(),
+                attempts: 0,
+                next_attempt_ms: now,
+                last_error: None,
+            };
+            if let Err(e) = self.outbox.put(&delivery) {
+                tracing::error!("Failed to queue {:?} webhook for {}: {}", event.event, endpoint.name, e);
+            }
+        }
+        self.wake.notify_one();
+    }
+
+    /// Deliver queued webhooks until shutdown.
+    pub async fn run_delivery(self: Arc<Self>, mut shutdown: broadcast::Receiver<()>) {
+        tracing::info!("Webhook delivery worker starting");
+        loop {
+            self.deliver_due().await;
+            tokio::select! {
+                _ = self.wake.notified() => {}
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = shutdown.recv() => {
+                    tracing::info!("Webhook delivery worker received shutdown signal, exiting loop");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Attempt all deliveries that are due.
+    pub async fn deliver_due(&self) {
+        loop {
+            let due = match self.outbox.due(now_ms(), BATCH_SIZE) {
+                Ok(due) => due,
+                Err(e) => {
+                    tracing::error!("Failed to read webhook outbox: {}", e);
+                    return;
+                }
+            };
+            let batch = due.len();
+            futures_util::future::join_all(due.into_iter().map(|d| self.attempt(d))).await;
+            if batch < BATCH_SIZE {
+                return;
+            }
+        }
+    }
+
+    async fn attempt(&self, mut delivery: Delivery) {
+        let config = self.config.load();
+        let Some(endpoint) = config.endpoints.iter().find(|e| e.name == delivery.endpoint) else {
+            tracing::warn!(
+                "Dropping webhook {} for removed endpoint {}",
+                delivery.id,
+                delivery.endpoint
+            );
+            self.settle(self.outbox.complete(delivery.id));
+            return;
+        };
+
+        match self.post(endpoint, &delivery, config.timeout_secs).await {
+            Ok(()) => {
+                metrics::record_webhook_delivery("delivered");
+                self.settle(self.outbox.complete(delivery.id));
+            }
+            Err(error) => {
+                delivery.attempts += 1;
+                delivery.last_error = Some(error);
+                if delivery.attempts >= config.max_attempts {
+                    tracing::error!(
+                        "Webhook {} to {} failed {} times, giving up: {}",
+                        delivery.id,
+                        endpoint.name,
+                        delivery.attempts,
+                        delivery.last_error.as_deref().unwrap_or_default()
+                    );
+                    metrics::record_webhook_delivery("dead_lettered");
+                    self.settle(self.outbox.dead_letter(&delivery));
+                } else {
+                    let backoff = calculate_backoff(delivery.attempts, config.base_backoff_ms, config.max_backoff_ms);
+                    delivery.next_attempt_ms = now_ms().saturating_add(backoff.as_millis() as u64);
+                    tracing::warn!(
+                        "Webhook {} to {} failed (attempt {}), retrying in {:?}: {}",
+                        delivery.id,
+                        endpoint.name,
+                        delivery.attempts,
+                        backoff,
+                        delivery.last_error.as_deref().unwrap_or_default()
+                    );
+                    metrics::record_webhook_delivery("failed");
+                    self.settle(self.outbox.put(&delivery));
+                }
+            }
+        }
+    }
+
+    async fn post(&self, endpoint: &WebhookEndpoint, delivery: &Delivery, timeout_secs: u64) -> Result<(), String> {
+        let body = serde_json::to_vec(&delivery.event).map_err(|e| e.to_string())?;
+        let signature = events::signature(&endpoint.secret, now_ms() / 1000, &body);
+        let response = self
+            .client
+            .post(&endpoint.url)
+            .timeout(Duration::from_secs(timeout_secs))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(events::EVENT_HEADER, event_name(delivery.event.event))
+            .header(events::DELIVERY_HEADER, delivery.id.to_string())
+            .header(events::SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("HTTP {}", response.status()))
+        }
+    }
+
+    fn settle(&self, result: StoreResult<()>) {
+        if let Err(e) = result {
+            tracing::error!("Failed to update webhook outbox: {}", e);
+        }
+    }
+
+    /// Report approaching and past expiries until shutdown.
+    pub async fn run_expiry_scanner(self: Arc<Self>, cache: Arc<SubscriptionCache>, mut shutdown: broadcast::Receiver<()>) {
+        tracing::info!("Webhook expiry scanner starting");
+        loop {
+            self.scan_expiries(&cache, now_ms() / 1000);
+            // Read on every round so reloads take effect
+            let interval = Duration::from_secs(self.config.load().scan_interval_secs);
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = shutdown.recv() => {
+                    tracing::info!("Webhook expiry scanner received shutdown signal, exiting loop");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Emit `expiring` and `expired` for the subscriptions in `cache` at
+    /// `now`. Each is sent once per expiry, so a renewal re-arms both.
+    pub fn scan_expiries(&self, cache: &SubscriptionCache, now: u64) {
+        let warning = {
+            let config = self.config.load();
+            if !config.enabled {
+                return;
+            }
+            config.expiry_warning_secs
+        };
+        for (user, sub) in cache.subscriptions() {
+            let kind = if sub.expiry > now {
+                if sub.expiry - now > warning {
+                    continue;
+                }
+                WebhookEventKind::Expiring
+            } else if now - sub.expiry <= EXPIRED_LOOKBACK_SECS {
+                WebhookEventKind::Expired
+            } else {
+                continue;
+            };
+            self.notify_once(user, kind, sub.tier_id, sub.expiry, now);
+        }
+    }
+
+    fn notify_once(&self, user: Address, kind: WebhookEventKind, tier_id: u8, expiry: u64, now: u64) {
+        match self.outbox.notified(user, kind) {
+            Ok(Some(sent)) if sent == expiry => return,
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Failed to read webhook notices for {}: {}", user, e);
+                return;
+            }
+        }
+        self.emit(SubscriptionEvent::new(kind, user, tier_id, expiry, now));
+        if let Err(e) = self.outbox.set_notified(user, kind, expiry) {
+            tracing::error!("Failed to record webhook notice for {}: {}", user, e);
+        }
+    }
+
+    /// Endpoints, queue depth and abandoned deliveries.
+    pub fn status(&self) -> StoreResult<WebhookStatus> {
+        let config = self.config.load();
+        let mut dead_letters = self.outbox.dead_letters()?;
+        dead_letters.sort_by_key(|d| std::cmp::Reverse(d.next_attempt_ms));
+        Ok(WebhookStatus {
+            enabled: config.enabled,
+            endpoints: config
+                .endpoints
+                .iter()
+                .map(|e| EndpointStatus { name: e.name.clone(), url: e.url.clone(), events: e.events.clone() })
+                .collect(),
+            pending: self.outbox.pending()?,
+            dead_letters,
+        })
+    }
+}
+
+fn subscribed(endpoint: &WebhookEndpoint, kind: WebhookEventKind) -> bool {
+    endpoint.events.is_empty() || endpoint.events.contains(&kind)
+}
+
+/// Wire name of `kind`, as in the payload.
+fn event_name(kind: WebhookEventKind) -> &'static str {
+    match kind {
+        WebhookEventKind::Created => "created",
+        WebhookEventKind::Renewed => "renewed",
+        WebhookEventKind::Expiring => "expiring",
+        WebhookEventKind::Expired => "expired",
+        WebhookEventKind::Cancelled => "cancelled",
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::Mutex;
+
+    const SECRET: &str = "whsec";
+
+    #[derive(Clone, Default)]
+    struct Receiver {
+        received: Arc<Mutex<Vec<SubscriptionEvent>>>,
+        /// Requests to reject before accepting.
+        failures: Arc<AtomicUsize>,
+    }
+
+    async fn receive(State(rx): State<Receiver>, headers: HeaderMap, body: axum::body::Bytes) -> StatusCode {
+        let signature = headers[events::SIGNATURE_HEADER].to_str().unwrap();
+        let timestamp: u64 = signature[2..signature.find(',').unwrap()].parse().unwrap();
+        assert_eq!(signature, events::signature(SECRET, timestamp, &body));
+        assert!(headers.contains_key(events::DELIVERY_HEADER));
+
+        if rx.failures.load(Ordering::SeqCst) > 0 {
+            rx.failures.fetch_sub(1, Ordering::SeqCst);
+            return StatusCode::SERVICE_UNAVAILABLE;
+        }
+        let event: SubscriptionEvent = serde_json::from_slice(&body).unwrap();
+        assert_eq!(headers[events::EVENT_HEADER], event_name(event.event));
+        rx.received.lock().await.push(event);
+        StatusCode::NO_CONTENT
+    }
+
+    async fn spawn_receiver(rx: Receiver) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/hook", post(receive)).with_state(rx);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/hook", addr)
+    }
+
+    fn config(url: &str, events: Vec<WebhookEventKind>) -> WebhookConfig {
+        WebhookConfig {
+            enabled: true,
+            outbox_path: String::new(),
+            max_attempts: 2,
+            base_backoff_ms: 0,
+            max_backoff_ms: 0,
+            endpoints: vec![WebhookEndpoint {
+                name: "crm".to_string(),
+                url: url.to_string(),
+                secret: SECRET.to_string(),
+                events,
+            }],
+            ..WebhookConfig::default()
+        }
+    }
+
+    fn event(kind: WebhookEventKind) -> SubscriptionEvent {
+        SubscriptionEvent::new(kind, Address::with_last_byte(1), 1, 1000, 0)
+    }
+
+    #[tokio::test]
+    async fn test_signed_delivery_with_retry() {
+        let rx = Receiver::default();
+        rx.failures.store(1, Ordering::SeqCst);
+        let url = spawn_receiver(rx.clone()).await;
+        let webhooks = Webhooks::open(&config(&url, vec![])).unwrap();
+
+        webhooks.emit(event(WebhookEventKind::Created));
+        webhooks.deliver_due().await;
+        assert!(rx.received.lock().await.is_empty());
+        assert_eq!(webhooks.status().unwrap().pending, 1);
+
+        // The retry is due immediately with zero backoff
+        webhooks.deliver_due().await;
+        let received = rx.received.lock().await.clone();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].event, WebhookEventKind::Created);
+        assert_eq!(webhooks.status().unwrap().pending, 0);
+    }
+
+    #[tokio::test]
+    async fn test_dead_letter_after_max_attempts() {
+        let rx = Receiver::default();
+        rx.failures.store(usize::MAX, Ordering::SeqCst);
+        let url = spawn_receiver(rx.clone()).await;
+        let webhooks = Webhooks::open(&config(&url, vec![])).unwrap();
+
+        webhooks.emit(event(WebhookEventKind::Renewed));
+        webhooks.deliver_due().await;
+        webhooks.deliver_due().await;
+
+        let status = webhooks.status().unwrap();
+        assert_eq!(status.pending, 0);
+        assert_eq!(status.dead_letters.len(), 1);
+        assert_eq!(status.dead_letters[0].attempts, 2);
+        assert_eq!(status.dead_letters[0].last_error.as_deref(), Some("HTTP 503 Service Unavailable"));
+    }
+
+    #[test]
+    fn test_event_filter() {
+        let webhooks = Webhooks::open(&config("http://localhost/", vec![WebhookEventKind::Expired])).unwrap();
+        webhooks.emit(event(WebhookEventKind::Created));
+        assert_eq!(webhooks.status().unwrap().pending, 0);
+        webhooks.emit(event(WebhookEventKind::Expired));
+        assert_eq!(webhooks.status().unwrap().pending, 1);
+
+        // Nothing is queued while disabled
+        webhooks.set_config(&WebhookConfig { enabled: false, ..config("http://localhost/", vec![]) });
+        webhooks.emit(event(WebhookEventKind::Expired));
+        assert_eq!(webhooks.status().unwrap().pending, 1);
+    }
+
+    #[test]
+    fn test_expiry_scan_notifies_once() {
+        let webhooks = Webhooks::open(&config("http://localhost/", vec![])).unwrap();
+        let cache = SubscriptionCache::new(None);
+        let now = 100_000_000;
+        let (soon, later, lapsed, old) = (
+            Address::with_last_byte(1),
+            Address::with_last_byte(2),
+            Address::with_last_byte(3),
+            Address::with_last_byte(4),
+        );
+        cache.update_subscription(soon, 1, now + 3600);
+        cache.update_subscription(later, 1, now + 30 * 24 * 3600);
+        cache.update_subscription(lapsed, 1, now - 60);
+        cache.update_subscription(old, 1, now - 30 * 24 * 3600);
+
+        webhooks.scan_expiries(&cache, now);
+        let mut queued: Vec<_> = webhooks
+            .outbox
+            .due(u64::MAX, 10)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.event.user, d.event.event))
+            .collect();
+        queued.sort_by_key(|(user, _)| *user);
+        assert_eq!(queued, vec![(soon, WebhookEventKind::Expiring), (lapsed, WebhookEventKind::Expired)]);
+
+        // Repeated scans do not resend, a renewal re-arms the notice
+        webhooks.scan_expiries(&cache, now + 60);
+        assert_eq!(webhooks.status().unwrap().pending, 2);
+        cache.update_subscription(soon, 1, now + 7200);
+        webhooks.scan_expiries(&cache, now + 60);
+        assert_eq!(webhooks.status().unwrap().pending, 3);
+    }
+
+    #[test]
+    fn test_open_fails_on_held_outbox() {
+        let path = std::env::temp_dir().join(format!("outbox-{}.redb", uuid::Uuid::new_v4()));
+        let config = WebhookConfig { outbox_path: path.to_string_lossy().into_owned(), ..config("http://localhost/", vec![]) };
+        let held = Webhooks::open(&config).unwrap();
+        assert!(Webhooks::open(&config).is_err());
+        drop(held);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+//! Webhook payloads and signatures.
+//!
+//! Each delivery is a JSON [`SubscriptionEvent`] with these headers:
+//! - `X-Seidar-Event`: the event kind
+//! - `X-Seidar-Delivery`: delivery id, stable across retries
+//!
+//! Events caused by a payment take their id from the chain, transaction and
+//! log index, so a payment processed twice yields the same event id and
+//! receivers can drop the duplicate.
+//! - `X-Seidar-Signature`: `t=<unix seconds>,v1=<hex HMAC-SHA256>` over
+//!   `"<t>.<body>"` keyed with the endpoint secret
+//!
+//! Receivers should recompute the HMAC and reject stale timestamps.
+
+use alloy::primitives::{hex, Address};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::config::WebhookEventKind;
+
+pub const EVENT_HEADER: &str = "X-Seidar-Event";
+pub const DELIVERY_HEADER: &str = "X-Seidar-Delivery";
+pub const SIGNATURE_HEADER: &str = "X-Seidar-Signature";
+
+/// A change to a user's subscription.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubscriptionEvent {
+    /// Unique per event; shared by the deliveries to every endpoint. Stable
+    /// for events caused by a payment.
+    pub id: Uuid,
+    pub event: WebhookEventKind,
+    pub created_at: u64,
+    pub user: Address,
+    pub tier_id: u8,
+    /// Expiry of the subscription the event is about.
+    pub expiry: u64,
+    /// Payment that caused the event, for `created` and `renewed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+}
+
+impl SubscriptionEvent {
+    pub fn new(event: WebhookEventKind, user: Address, tier_id: u8, expiry: u64, created_at: u64) -> Self {
+        Self { id: Uuid::new_v4(), event, created_at, user, tier_id, expiry, tx_hash: None }
+    }
+
+    pub fn with_tx_hash(mut self, tx_hash: &str) -> Self {
+        self.tx_hash = Some(tx_hash.to_string());
+        self
+    }
+
+    /// Derive the id from the payment `payment_id` (see
+    /// [`record_id`](crate::payments::billing::record_id)) on `chain_id`.
+    pub fn caused_by(mut self, chain_id: u64, payment_id: &str) -> Self {
+        self.id = derived_id(&[&chain_id.to_be_bytes(), payment_id.as_bytes(), &[self.event as u8]]);
+        self
+    }
+}
+
+/// Deterministic id of a delivery of `event` to `endpoint`.
+pub fn delivery_id(event: Uuid, endpoint: &str) -> Uuid {
+    derived_id(&[event.as_bytes(), endpoint.as_bytes()])
+}
+
+/// Version 8 UUID from the SHA-256 of length-prefixed `parts`.
+fn derived_id(parts: &[&[u8]]) -> Uuid {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u32).to_be_bytes());
+        hasher.update(part);
+    }
+    let digest = hasher.finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+/// `X-Seidar-Signature` value for `body` sent at `timestamp`.
+pub fn signature(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // HMAC-SHA256("secret", "1700000000.{}")
+        let sig = signature("secret", 1_700_000_000, b"{}");
+        assert!(sig.starts_with("t=1700000000,v1="));
+        assert_eq!(sig.len(), "t=1700000000,v1=".len() + 64);
+        assert_eq!(sig, signature("secret", 1_700_000_000, b"{}"));
+        assert_ne!(sig, signature("other", 1_700_000_000, b"{}"));
+        assert_ne!(sig, signature("secret", 1_700_000_001, b"{}"));
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = SubscriptionEvent::new(WebhookEventKind::Renewed, Address::ZERO, 2, 100, 50).with_tx_hash("0xab");
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "renewed");
+        assert_eq!(json["tx_hash"], "0xab");
+        let decoded: SubscriptionEvent = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, event);
+    }
+
+    #[test]
+    fn test_payment_event_ids() {
+        let event = |kind| SubscriptionEvent::new(kind, Address::ZERO, 2, 100, 50);
+        let renewed = event(WebhookEventKind::Renewed).caused_by(1, "0xab-0");
+        assert_eq!(renewed.id, event(WebhookEventKind::Renewed).caused_by(1, "0xab-0").id);
+        assert_ne!(renewed.id, event(WebhookEventKind::Renewed).caused_by(1, "0xab-1").id);
+        assert_ne!(renewed.id, event(WebhookEventKind::Renewed).caused_by(10, "0xab-0").id);
+        assert_ne!(renewed.id, event(WebhookEventKind::Cancelled).caused_by(1, "0xab-0").id);
+
+        assert_eq!(delivery_id(renewed.id, "crm"), delivery_id(renewed.id, "crm"));
+        assert_ne!(delivery_id(renewed.id, "crm"), delivery_id(renewed.id, "billing"));
+    }
+}
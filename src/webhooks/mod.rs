@@ -0,0 +1,18 @@
+//! Webhooks for subscription lifecycle events.
+//!
+//! # Data Flow
+//! ```text
+//! process_payment (created, renewed, cancelled)
+//! expiry scanner over SubscriptionCache (expiring, expired)
+//!     → dispatcher.rs (filter by endpoint, enqueue)
+//!     → outbox.rs (durable queue of pending deliveries)
+//!     → dispatcher.rs (HMAC-signed POST, retries with backoff)
+//! ```
+
+pub mod dispatcher;
+pub mod events;
+pub mod outbox;
+
+pub use dispatcher::Webhooks;
+pub use events::SubscriptionEvent;
+pub use outbox::{Delivery, MemoryOutbox, OutboxStore, RedbOutbox};
@@ -0,0 +1,320 @@
+//! Durable webhook outbox.
+//!
+//! Deliveries are written to the outbox before the first attempt and
+//! removed only once an endpoint accepts them, so webhooks survive restarts
+//! and endpoint outages. Deliveries that exhaust their attempts move to the
+//! dead letters. The outbox also remembers which expiry notices were sent,
+//! so a restart does not repeat them.
+//!
+//! The on-disk outbox indexes pending deliveries by their next attempt, so
+//! polling for due deliveries reads only those.
+
+use alloy::primitives::Address;
+use dashmap::DashMap;
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::config::WebhookEventKind;
+use crate::store::{backend, StoreResult};
+use crate::webhooks::events::SubscriptionEvent;
+
+/// An event queued for one endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: Uuid,
+    /// Name of the receiving endpoint.
+    pub endpoint: String,
+    pub event: SubscriptionEvent,
+    /// Failed attempts so far.
+    pub attempts: u32,
+    /// Earliest time of the next attempt, in unix milliseconds.
+    pub next_attempt_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Persistent storage for pending deliveries.
+pub trait OutboxStore: Send + Sync {
+    /// Add or replace a pending delivery.
+    fn put(&self, delivery: &Delivery) -> StoreResult<()>;
+
+    /// Up to `limit` pending deliveries due at `now_ms`, earliest due first.
+    fn due(&self, now_ms: u64, limit: usize) -> StoreResult<Vec<Delivery>>;
+
+    /// Remove a delivered delivery.
+    fn complete(&self, id: Uuid) -> StoreResult<()>;
+
+    /// Move a delivery that will not be retried to the dead letters.
+    fn dead_letter(&self, delivery: &Delivery) -> StoreResult<()>;
+
+    /// Number of pending deliveries.
+    fn pending(&self) -> StoreResult<usize>;
+
+    /// All dead letters.
+    fn dead_letters(&self) -> StoreResult<Vec<Delivery>>;
+
+    /// Expiry for which `kind` was last sent to `user`.
+    fn notified(&self, user: Address, kind: WebhookEventKind) -> StoreResult<Option<u64>>;
+
+    /// Remember that `kind` was sent to `user` for `expiry`.
+    fn set_notified(&self, user: Address, kind: WebhookEventKind, expiry: u64) -> StoreResult<()>;
+}
+
+/// In-memory outbox. Pending deliveries are lost on restart.
+#[derive(Default)]
+pub struct MemoryOutbox {
+    pending: DashMap<Uuid, Delivery>,
+    dead: DashMap<Uuid, Delivery>,
+    notified: DashMap<(Address, WebhookEventKind), u64>,
+}
+
+impl MemoryOutbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OutboxStore for MemoryOutbox {
+    fn put(&self, delivery: &Delivery) -> StoreResult<()> {
+        self.pending.insert(delivery.id, delivery.clone());
+        Ok(())
+    }
+
+    fn due(&self, now_ms: u64, limit: usize) -> StoreResult<Vec<Delivery>> {
+        let mut due: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|d| d.next_attempt_ms <= now_ms)
+            .map(|d| d.clone())
+            .collect();
+        due.sort_by_key(|d| (d.next_attempt_ms, d.event.created_at));
+        due.truncate(limit);
+        Ok(due)
+    }
+
+    fn complete(&self, id: Uuid) -> StoreResult<()> {
+        self.pending.remove(&id);
+        Ok(())
+    }
+
+    fn dead_letter(&self, delivery: &Delivery) -> StoreResult<()> {
+        self.pending.remove(&delivery.id);
+        self.dead.insert(delivery.id, delivery.clone());
+        Ok(())
+    }
+
+    fn pending(&self) -> StoreResult<usize> {
+        Ok(self.pending.len())
+    }
+
+    fn dead_letters(&self) -> StoreResult<Vec<Delivery>> {
+        Ok(self.dead.iter().map(|d| d.clone()).collect())
+    }
+
+    fn notified(&self, user: Address, kind: WebhookEventKind) -> StoreResult<Option<u64>> {
+        Ok(self.notified.get(&(user, kind)).map(|e| *e))
+    }
+
+    fn set_notified(&self, user: Address, kind: WebhookEventKind, expiry: u64) -> StoreResult<()> {
+        self.notified.insert((user, kind), expiry);
+        Ok(())
+    }
+}
+
+/// Pending delivery JSON keyed by delivery id.
+const PENDING: TableDefinition<u128, &[u8]> = TableDefinition::new("webhook_outbox");
+/// Index of pending deliveries by (next attempt in unix ms, delivery id).
+const DUE: TableDefinition<(u64, u128), ()> = TableDefinition::new("webhook_outbox_due");
+/// Delivery JSON of abandoned deliveries.
+const DEAD: TableDefinition<u128, &[u8]> = TableDefinition::new("webhook_dead_letters");
+/// Notified expiry by (user address, event kind).
+const NOTIFIED: TableDefinition<([u8; 20], u8), u64> = TableDefinition::new("webhook_notified");
+
+/// Embedded on-disk outbox backed by redb.
+pub struct RedbOutbox {
+    db: Database,
+}
+
+impl RedbOutbox {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        let db = Database::create(path).map_err(backend)?;
+        let txn = db.begin_write().map_err(backend)?;
+        {
+            let pending = txn.open_table(PENDING).map_err(backend)?;
+            let mut due = txn.open_table(DUE).map_err(backend)?;
+            // Outboxes written before the index existed
+            if due.is_empty().map_err(backend)? {
+                for entry in pending.iter().map_err(backend)? {
+                    let (id, json) = entry.map_err(backend)?;
+                    let delivery: Delivery = serde_json::from_slice(json.value())?;
+                    due.insert((delivery.next_attempt_ms, id.value()), ()).map_err(backend)?;
+                }
+            }
+        }
+        txn.open_table(DEAD).map_err(backend)?;
+        txn.open_table(NOTIFIED).map_err(backend)?;
+        txn.commit().map_err(backend)?;
+        Ok(Self { db })
+    }
+
+    /// Remove the pending delivery `id` and its index entry, if any.
+    fn remove_pending(txn: &redb::WriteTransaction, id: u128) -> StoreResult<()> {
+        let mut pending = txn.open_table(PENDING).map_err(backend)?;
+        let removed = pending.remove(id).map_err(backend)?;
+        if let Some(json) = removed {
+            let delivery: Delivery = serde_json::from_slice(json.value())?;
+            txn.open_table(DUE).map_err(backend)?.remove((delivery.next_attempt_ms, id)).map_err(backend)?;
+        }
+        Ok(())
+    }
+
+    fn read_all(&self, table: TableDefinition<u128, &[u8]>) -> StoreResult<Vec<Delivery>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let table = txn.open_table(table).map_err(backend)?;
+        let mut deliveries = Vec::new();
+        for entry in table.iter().map_err(backend)? {
+            let (_, json) = entry.map_err(backend)?;
+            deliveries.push(serde_json::from_slice(json.value())?);
+        }
+        Ok(deliveries)
+    }
+}
+
+impl OutboxStore for RedbOutbox {
+    fn put(&self, delivery: &Delivery) -> StoreResult<()> {
+        let json = serde_json::to_vec(delivery)?;
+        let id = delivery.id.as_u128();
+        let txn = self.db.begin_write().map_err(backend)?;
+        Self::remove_pending(&txn, id)?;
+        txn.open_table(PENDING).map_err(backend)?.insert(id, json.as_slice()).map_err(backend)?;
+        txn.open_table(DUE).map_err(backend)?.insert((delivery.next_attempt_ms, id), ()).map_err(backend)?;
+        txn.commit().map_err(backend)
+    }
+
+    fn due(&self, now_ms: u64, limit: usize) -> StoreResult<Vec<Delivery>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let index = txn.open_table(DUE).map_err(backend)?;
+        let pending = txn.open_table(PENDING).map_err(backend)?;
+        let mut due = Vec::new();
+        for entry in index.range(..=(now_ms, u128::MAX)).map_err(backend)?.take(limit) {
+            let (key, _) = entry.map_err(backend)?;
+            if let Some(json) = pending.get(key.value().1).map_err(backend)? {
+                due.push(serde_json::from_slice(json.value())?);
+            }
+        }
+        Ok(due)
+    }
+
+    fn complete(&self, id: Uuid) -> StoreResult<()> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        Self::remove_pending(&txn, id.as_u128())?;
+        txn.commit().map_err(backend)
+    }
+
+    fn dead_letter(&self, delivery: &Delivery) -> StoreResult<()> {
+        let json = serde_json::to_vec(delivery)?;
+        let txn = self.db.begin_write().map_err(backend)?;
+        {
+            Self::remove_pending(&txn, delivery.id.as_u128())?;
+            txn.open_table(DEAD)
+                .map_err(backend)?
+                .insert(delivery.id.as_u128(), json.as_slice())
+                .map_err(backend)?;
+        }
+        txn.commit().map_err(backend)
+    }
+
+    fn pending(&self) -> StoreResult<usize> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let pending = txn.open_table(PENDING).map_err(backend)?;
+        Ok(pending.len().map_err(backend)? as usize)
+    }
+
+    fn dead_letters(&self) -> StoreResult<Vec<Delivery>> {
+        self.read_all(DEAD)
+    }
+
+    fn notified(&self, user: Address, kind: WebhookEventKind) -> StoreResult<Option<u64>> {
+        let txn = self.db.begin_read().map_err(backend)?;
+        let notified = txn.open_table(NOTIFIED).map_err(backend)?;
+        let expiry = notified.get((user.into_array(), kind as u8)).map_err(backend)?;
+        Ok(expiry.map(|e| e.value()))
+    }
+
+    fn set_notified(&self, user: Address, kind: WebhookEventKind, expiry: u64) -> StoreResult<()> {
+        let txn = self.db.begin_write().map_err(backend)?;
+        txn.open_table(NOTIFIED)
+            .map_err(backend)?
+            .insert((user.into_array(), kind as u8), expiry)
+            .map_err(backend)?;
+        txn.commit().map_err(backend)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::conformance::check_backends;
+
+    fn delivery(next_attempt_ms: u64) -> Delivery {
+        Delivery {
+            id: Uuid::new_v4(),
+            endpoint: "crm".to_string(),
+            event: SubscriptionEvent::new(WebhookEventKind::Created, Address::ZERO, 1, 100, 0),
+            attempts: 0,
+            next_attempt_ms,
+            last_error: None,
+        }
+    }
+
+    fn exercise(outbox: &dyn OutboxStore) {
+        let (now, later) = (delivery(10), delivery(1000));
+        outbox.put(&later).unwrap();
+        outbox.put(&now).unwrap();
+        assert_eq!(outbox.pending().unwrap(), 2);
+        assert_eq!(outbox.due(500, 10).unwrap(), vec![now.clone()]);
+        assert_eq!(outbox.due(2000, 1).unwrap(), vec![now.clone()]);
+
+        // Rescheduling moves a delivery in the due order
+        let mut retried = now.clone();
+        retried.attempts = 1;
+        retried.next_attempt_ms = 1500;
+        outbox.put(&retried).unwrap();
+        assert_eq!(outbox.pending().unwrap(), 2);
+        assert_eq!(outbox.due(1200, 10).unwrap(), vec![later.clone()]);
+        assert_eq!(outbox.due(2000, 10).unwrap(), vec![later.clone(), retried]);
+
+        outbox.complete(now.id).unwrap();
+        outbox.dead_letter(&later).unwrap();
+        assert_eq!(outbox.pending().unwrap(), 0);
+        assert_eq!(outbox.dead_letters().unwrap(), vec![later]);
+
+        let user = Address::with_last_byte(1);
+        assert_eq!(outbox.notified(user, WebhookEventKind::Expiring).unwrap(), None);
+        outbox.set_notified(user, WebhookEventKind::Expiring, 42).unwrap();
+        assert_eq!(outbox.notified(user, WebhookEventKind::Expiring).unwrap(), Some(42));
+        assert_eq!(outbox.notified(user, WebhookEventKind::Expired).unwrap(), None);
+    }
+
+    #[test]
+    fn test_backends() {
+        check_backends::<dyn OutboxStore>(
+            "webhooks",
+            Box::new(MemoryOutbox::new()),
+            |path| Box::new(RedbOutbox::open(path).unwrap()),
+            |outbox| {
+                exercise(outbox);
+                outbox.put(&delivery(5)).unwrap();
+            },
+            |outbox| {
+                assert_eq!(outbox.due(5, 10).unwrap().len(), 1);
+                assert_eq!(outbox.dead_letters().unwrap().len(), 1);
+                let user = Address::with_last_byte(1);
+                assert_eq!(outbox.notified(user, WebhookEventKind::Expiring).unwrap(), Some(42));
+            },
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Subscriber account API integration tests.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use reverse_proxy::config::ProxyConfig;
+use reverse_proxy::http::HttpServer;
+use reverse_proxy::lifecycle::Shutdown;
+
+#[tokio::test]
+async fn test_account_outside_access_control() {
+    let proxy_addr: SocketAddr = "127.0.0.1:28391".parse().unwrap();
+
+    let mut config = ProxyConfig::default();
+    config.listener.bind_address = proxy_addr.to_string();
+    config.health_check.enabled = false;
+    config.payments.enabled = true;
+
+    let shutdown = Shutdown::new();
+    let (_, config_updates) = mpsc::unbounded_channel();
+    let server = HttpServer::new(config);
+    let listener = tokio::net::TcpListener::bind(proxy_addr).await.unwrap();
+    let server_shutdown = shutdown.subscribe();
+
+    tokio::spawn(async move {
+        let _ = server.run(listener, config_updates, server_shutdown).await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let client = reqwest::Client::builder().no_proxy().build().unwrap();
+    let user = "0x00000000000000000000000000000000000000aa";
+
+    // Proxied requests need a subscription, account status does not
+    let res = client.get(format!("http://{}/rpc", proxy_addr)).header("X-User-Address", user).send().await.unwrap();
+    assert_eq!(res.status(), 403);
+    let res = client
+        .get(format!("http://{}/api/v1/account", proxy_addr))
+        .header("X-User-Address", user)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 404);
+    let res = client
+        .get(format!("http://{}/api/v1/account/payments", proxy_addr))
+        .header("X-User-Address", user)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 200);
+
+    shutdown.trigger();
+}
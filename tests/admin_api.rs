@@ -0,0 +1,108 @@
+//! Admin API integration tests.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use reverse_proxy::config::ProxyConfig;
+use reverse_proxy::http::HttpServer;
+use reverse_proxy::lifecycle::Shutdown;
+
+#[tokio::test]
+async fn test_admin_tiers_requires_auth() {
+    let proxy_addr: SocketAddr = "127.0.0.1:28381".parse().unwrap();
+    let admin_addr: SocketAddr = "127.0.0.1:28382".parse().unwrap();
+
+    let mut config = ProxyConfig::default();
+    config.listener.bind_address = proxy_addr.to_string();
+    config.health_check.enabled = false;
+    config.admin.enabled = true;
+    config.admin.bind_address = admin_addr.to_string();
+    config.admin.api_key = "test-key".to_string();
+
+    let shutdown = Shutdown::new();
+    let (_, config_updates) = mpsc::unbounded_channel();
+    let server = HttpServer::new(config);
+    let listener = tokio::net::TcpListener::bind(proxy_addr).await.unwrap();
+    let server_shutdown = shutdown.subscribe();
+
+    tokio::spawn(async move {
+        let _ = server.run(listener, config_updates, server_shutdown).await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let client = reqwest::Client::builder().no_proxy().build().unwrap();
+    let url = format!("http://{}/admin/tiers", admin_addr);
+
+    let res = client.get(&url).send().await.expect("Admin unreachable");
+    assert_eq!(res.status(), 401);
+
+    let res = client.get(&url).bearer_auth("test-key").send().await.unwrap();
+    assert_eq!(res.status(), 200);
+    let tiers: serde_json::Value = res.json().await.unwrap();
+    let tiers = tiers.as_array().unwrap();
+    assert_eq!(tiers.len(), 3);
+    assert_eq!(tiers[0]["tier_id"], 1);
+    assert_eq!(tiers[0]["policy"]["rps"], 10);
+    assert!(tiers[2]["pricing"].is_null());
+
+    shutdown.trigger();
+}
+
+#[tokio::test]
+async fn test_admin_promo_codes() {
+    let proxy_addr: SocketAddr = "127.0.0.1:28383".parse().unwrap();
+    let admin_addr: SocketAddr = "127.0.0.1:28384".parse().unwrap();
+
+    let mut config = ProxyConfig::default();
+    config.listener.bind_address = proxy_addr.to_string();
+    config.health_check.enabled = false;
+    config.admin.enabled = true;
+    config.admin.bind_address = admin_addr.to_string();
+    config.admin.api_key = "test-key".to_string();
+    config.pricing.promo_codes.insert("LAUNCH".to_string(), reverse_proxy::config::PromoCode {
+        discount_bps: Some(1000),
+        ..Default::default()
+    });
+
+    let shutdown = Shutdown::new();
+    let (_, config_updates) = mpsc::unbounded_channel();
+    let server = HttpServer::new(config);
+    let listener = tokio::net::TcpListener::bind(proxy_addr).await.unwrap();
+    let server_shutdown = shutdown.subscribe();
+
+    tokio::spawn(async move {
+        let _ = server.run(listener, config_updates, server_shutdown).await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let client = reqwest::Client::builder().no_proxy().build().unwrap();
+    let url = |path: &str| format!("http://{}/admin/promos{}", admin_addr, path);
+
+    let partner = serde_json::json!({ "discount_amount": "1000", "max_per_address": 1 });
+    let res = client.put(url("/partner")).bearer_auth("test-key").json(&partner).send().await.unwrap();
+    assert_eq!(res.status(), 204);
+
+    // Configured codes cannot be overridden, and codes are validated
+    let res = client.put(url("/launch")).bearer_auth("test-key").json(&partner).send().await.unwrap();
+    assert_eq!(res.status(), 409);
+    let invalid = serde_json::json!({ "discount_bps": 20000 });
+    let res = client.put(url("/bad")).bearer_auth("test-key").json(&invalid).send().await.unwrap();
+    assert_eq!(res.status(), 400);
+
+    let res = client.get(url("")).bearer_auth("test-key").send().await.unwrap();
+    let promos: serde_json::Value = res.json().await.unwrap();
+    let promos = promos.as_array().unwrap();
+    assert_eq!(promos.len(), 2);
+    assert_eq!((&promos[0]["code"], &promos[0]["source"]), (&"LAUNCH".into(), &"config".into()));
+    assert_eq!((&promos[1]["code"], &promos[1]["source"]), (&"PARTNER".into(), &"admin".into()));
+    assert_eq!(promos[1]["redemptions"], 0);
+
+    let res = client.delete(url("/partner")).bearer_auth("test-key").send().await.unwrap();
+    assert_eq!(res.status(), 204);
+    let res = client.delete(url("/partner")).bearer_auth("test-key").send().await.unwrap();
+    assert_eq!(res.status(), 404);
+
+    shutdown.trigger();
+}
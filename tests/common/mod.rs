@@ -11,22 +11,17 @@ pub async fn start_mock_backend(addr: SocketAddr, response: &'static str) {
     let listener = TcpListener::bind(addr).await.unwrap();
     
     tokio::spawn(async move {
-        loop {
-            match listener.accept().await {
-                Ok((mut socket, _)) => {
-                    tokio::spawn(async move {
-                        let response_str = format!(
-                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-                            response.len(),
-                            response
-                        );
-                        let _ = socket.write_all(response_str.as_bytes()).await;
-                        let _ = socket.shutdown().await;
-                        tokio::time::sleep(Duration::from_millis(10)).await;
-                    });
-                }
-                Err(_) => break,
-            }
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let response_str = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                );
+                let _ = socket.write_all(response_str.as_bytes()).await;
+                let _ = socket.shutdown().await;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            });
         }
     });
 }
@@ -42,35 +37,30 @@ where
     let f = std::sync::Arc::new(f);
     
     tokio::spawn(async move {
-        loop {
-            match listener.accept().await {
-                Ok((mut socket, _)) => {
-                    let f = f.clone();
-                    tokio::spawn(async move {
-                        let (status, body) = f().await;
-                        let status_text = match status {
-                            200 => "200 OK",
-                            404 => "404 Not Found",
-                            429 => "429 Too Many Requests",
-                            500 => "500 Internal Server Error",
-                            502 => "502 Bad Gateway",
-                            503 => "503 Service Unavailable",
-                            _ => "200 OK",
-                        };
-                        
-                        let response_str = format!(
-                            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-                            status_text,
-                            body.len(),
-                            body
-                        );
-                        let _ = socket.write_all(response_str.as_bytes()).await;
-                        let _ = socket.shutdown().await;
-                        tokio::time::sleep(Duration::from_millis(10)).await;
-                    });
-                }
-                Err(_) => break,
-            }
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let f = f.clone();
+            tokio::spawn(async move {
+                let (status, body) = f().await;
+                let status_text = match status {
+                    200 => "200 OK",
+                    404 => "404 Not Found",
+                    429 => "429 Too Many Requests",
+                    500 => "500 Internal Server Error",
+                    502 => "502 Bad Gateway",
+                    503 => "503 Service Unavailable",
+                    _ => "200 OK",
+                };
+                
+                let response_str = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status_text,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response_str.as_bytes()).await;
+                let _ = socket.shutdown().await;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            });
         }
     });
 }
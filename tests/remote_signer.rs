@@ -0,0 +1,182 @@
+//! Remote signer integration tests against a local stub signing service.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use alloy::consensus::transaction::SignerRecoverable;
+use alloy::consensus::{Transaction, TxEnvelope};
+use alloy::eips::eip2718::{Decodable2718, Encodable2718};
+use alloy::hex;
+use alloy::network::{EthereumWallet, TransactionBuilder};
+use alloy::dyn_abi::TypedData;
+use alloy::primitives::{keccak256, Address, Bytes, U256};
+use alloy::rpc::types::TransactionRequest;
+use alloy::signers::local::PrivateKeySigner;
+use alloy::signers::SignerSync;
+use alloy::sol_types::{eip712_domain, SolStruct};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use zeroize::Zeroizing;
+use reverse_proxy::blockchain::remote_signer::RemoteSigner;
+use reverse_proxy::blockchain::wallet::{Signable, WalletSigner};
+use reverse_proxy::blockchain::{BlockchainError, Wallet};
+use reverse_proxy::config::RemoteSignerConfig;
+
+// Anvil's first account
+const TEST_PRIVATE_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+const TOKEN: &str = "signer-token";
+
+alloy::sol! {
+    #[derive(serde::Serialize)]
+    struct Mail {
+        address to;
+        string contents;
+    }
+}
+
+/// Web3Signer-style stub holding the test key.
+#[derive(Clone)]
+struct Stub {
+    key: PrivateKeySigner,
+    /// Raise the gas limit of transactions it signs.
+    tamper: Arc<AtomicBool>,
+}
+
+async fn spawn_stub() -> (SocketAddr, Stub) {
+    let stub = Stub { key: TEST_PRIVATE_KEY.parse().unwrap(), tamper: Arc::default() };
+    let app = Router::new()
+        .route("/upcheck", get(|| async { "OK" }))
+        .route("/api/v1/eth1/sign/{address}", post(sign))
+        .route("/", post(rpc))
+        .with_state(stub.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (addr, stub)
+}
+
+fn authorized(headers: &HeaderMap) -> Result<(), StatusCode> {
+    match headers.get("authorization").and_then(|v| v.to_str().ok()) {
+        Some(value) if value == format!("Bearer {}", TOKEN) => Ok(()),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+/// Signs the keccak-256 hash of `data`, as Web3Signer does.
+async fn sign(State(stub): State<Stub>, headers: HeaderMap, Json(body): Json<Value>) -> Result<String, StatusCode> {
+    authorized(&headers)?;
+    let data: Bytes = serde_json::from_value(body["data"].clone()).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let signature = stub.key.sign_hash_sync(&keccak256(&data)).unwrap();
+    Ok(hex::encode_prefixed(signature.as_bytes()))
+}
+
+async fn rpc(State(stub): State<Stub>, headers: HeaderMap, Json(request): Json<Value>) -> Result<Json<Value>, StatusCode> {
+    authorized(&headers)?;
+    let key = stub.key;
+    let result = match request["method"].as_str() {
+        Some("eth_sign") => {
+            let message = hex::decode(request["params"][1].as_str().unwrap()).unwrap();
+            hex::encode_prefixed(key.sign_message(&message).await.unwrap().as_bytes())
+        }
+        Some("eth_signTypedData") => {
+            let typed_data: TypedData = serde_json::from_value(request["params"][1].clone()).unwrap();
+            let hash = typed_data.eip712_signing_hash().unwrap();
+            hex::encode_prefixed(key.sign_hash_sync(&hash).unwrap().as_bytes())
+        }
+        Some("eth_signTransaction") => {
+            let mut tx: TransactionRequest = serde_json::from_value(request["params"][0].clone()).unwrap();
+            if stub.tamper.load(Ordering::SeqCst) {
+                tx.gas = tx.gas.map(|gas| gas * 10);
+            }
+            let envelope = tx.build(&EthereumWallet::from(key)).await.unwrap();
+            hex::encode_prefixed(envelope.encoded_2718())
+        }
+        _ => return Ok(Json(json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": -32601, "message": "Method not found" } }))),
+    };
+    Ok(Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })))
+}
+
+fn signer_config(addr: SocketAddr, address: Address) -> RemoteSignerConfig {
+    RemoteSignerConfig {
+        url: format!("http://{}/", addr),
+        address: address.to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_remote_signer_signs() {
+    let (addr, stub) = spawn_stub().await;
+    let key = stub.key;
+    let signer = RemoteSigner::new(&signer_config(addr, key.address()), Some(Zeroizing::new(TOKEN.to_string()))).unwrap();
+    signer.check_health().await.unwrap();
+    let wallet = Wallet::with_signer(Arc::new(signer), 31337);
+    assert_eq!(wallet.address(), key.address());
+
+    // Typed data is signed over its EIP-712 hash
+    let mail = Mail { to: Address::with_last_byte(2), contents: "hello".to_string() };
+    let domain = eip712_domain! { name: "Test", version: "1", chain_id: 31337, };
+    let typed_data = Signable::TypedData(Box::new(TypedData::from_struct(&mail, Some(domain.clone()))));
+    let signature = wallet.sign(&typed_data).await.unwrap();
+    let hash = mail.eip712_signing_hash(&domain);
+    assert_eq!(signature.recover_address_from_prehash(&hash).unwrap(), key.address());
+
+    // The service hashes what it is given
+    let preimage = Signable::Keccak(Bytes::from_static(b"legacy quote"));
+    let signature = wallet.sign(&preimage).await.unwrap();
+    assert_eq!(signature.recover_address_from_prehash(&keccak256(b"legacy quote")).unwrap(), key.address());
+
+    let signature = wallet.sign_message(b"Hello, World!").await.unwrap();
+    assert_eq!(signature.recover_address_from_msg(b"Hello, World!").unwrap(), key.address());
+
+    let tx = TransactionRequest::default()
+        .with_to(Address::with_last_byte(1))
+        .with_value(U256::from(1000))
+        .with_nonce(7)
+        .with_gas_price(1_000_000_000)
+        .with_gas_limit(21000)
+        .with_chain_id(31337);
+    let raw = wallet.sign_transaction(tx).await.unwrap();
+    let envelope = TxEnvelope::decode_2718(&mut raw.as_ref()).unwrap();
+    assert_eq!(envelope.recover_signer().unwrap(), key.address());
+    assert_eq!((envelope.nonce(), envelope.value()), (7, U256::from(1000)));
+}
+
+#[tokio::test]
+async fn test_remote_signer_rejects() {
+    let (addr, stub) = spawn_stub().await;
+    let key = stub.key.clone();
+
+    // Missing token
+    let signer = RemoteSigner::new(&signer_config(addr, key.address()), None).unwrap();
+    let err = signer.sign(&Signable::Keccak(Bytes::new())).await.unwrap_err();
+    assert!(matches!(&err, BlockchainError::RemoteSigner(msg) if msg.contains("401")), "{}", err);
+
+    // Signatures from another key than configured
+    let other = Address::with_last_byte(9);
+    let signer = RemoteSigner::new(&signer_config(addr, other), Some(Zeroizing::new(TOKEN.to_string()))).unwrap();
+    let err = signer.sign_message(b"hi").await.unwrap_err();
+    assert!(err.to_string().contains("instead of"), "{}", err);
+
+    // A transaction signed with another gas limit than requested
+    stub.tamper.store(true, Ordering::SeqCst);
+    let signer = RemoteSigner::new(&signer_config(addr, key.address()), Some(Zeroizing::new(TOKEN.to_string()))).unwrap();
+    let tx = TransactionRequest::default()
+        .with_to(Address::with_last_byte(1))
+        .with_nonce(7)
+        .with_gas_price(1_000_000_000)
+        .with_gas_limit(21000)
+        .with_chain_id(31337);
+    let err = signer.sign_transaction(tx).await.unwrap_err();
+    assert!(err.to_string().contains("different transaction"), "{}", err);
+
+    // Nothing listening
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let signer = RemoteSigner::new(&signer_config(closed, key.address()), None).unwrap();
+    assert!(signer.check_health().await.is_err());
+}